@@ -0,0 +1,99 @@
+use crate::types::LineRange;
+use std::collections::HashSet;
+
+/// Lines this short (fences, rules, lone bullets) match too many places to
+/// say where a segment came from
+const MIN_LINE_CHARS: usize = 4;
+
+/// The lines of a note a segment's content came from, numbered from
+/// `first_line`. Lines are compared ignoring case, spacing and list or header
+/// markers. Longer lines are matched in order, so a repeated line is found
+/// where the segment's earlier lines were; short ones only widen the range to
+/// the lines next to it, unless the segment has nothing else. None when no
+/// line matches, e.g. when the categorizer rewrote the text.
+pub fn line_range(note: &str, first_line: usize, content: &str) -> Option<LineRange> {
+    let note_lines: Vec<String> = note.lines().map(normalize).collect();
+    let lines: Vec<String> = content
+        .lines()
+        .map(normalize)
+        .filter(|l| !l.is_empty())
+        .collect();
+    let (long, short): (Vec<&String>, HashSet<&String>) = (
+        lines.iter().filter(|l| !is_short(l)).collect(),
+        lines.iter().filter(|l| is_short(l)).collect(),
+    );
+    // A segment of nothing but short lines has to be found by them
+    let anchors: Vec<&String> = if long.is_empty() {
+        lines.iter().collect()
+    } else {
+        long
+    };
+
+    let mut next = 0;
+    let mut range: Option<(usize, usize)> = None;
+    for line in anchors {
+        // Look after the last match first, then anywhere
+        let found = (next..note_lines.len())
+            .chain(0..next)
+            .find(|&i| note_lines[i] == **line);
+        let Some(i) = found else {
+            continue;
+        };
+        if i >= next {
+            next = i + 1;
+        }
+        range = Some(range.map_or((i, i), |(start, end)| (start.min(i), end.max(i))));
+    }
+
+    let (mut start, mut end) = range?;
+    let widens = |i: usize| !note_lines[i].is_empty() && short.contains(&note_lines[i]);
+    // Blank lines between are crossed, but don't widen the range themselves
+    let mut i = start;
+    while i > 0 && (note_lines[i - 1].is_empty() || widens(i - 1)) {
+        i -= 1;
+        if widens(i) {
+            start = i;
+        }
+    }
+    let mut i = end;
+    while i + 1 < note_lines.len() && (note_lines[i + 1].is_empty() || widens(i + 1)) {
+        i += 1;
+        if widens(i) {
+            end = i;
+        }
+    }
+    Some(LineRange {
+        start: first_line + start,
+        end: first_line + end,
+    })
+}
+
+fn is_short(line: &str) -> bool {
+    line.chars().count() < MIN_LINE_CHARS
+}
+
+/// Lowercase a line and drop its indentation, list, quote and header markers
+/// and repeated spaces
+fn normalize(line: &str) -> String {
+    let mut line = line.trim();
+    loop {
+        let stripped = line
+            .strip_prefix(['-', '*', '+', '>', '#'])
+            .or_else(|| {
+                let digits =
+                    line.len() - line.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+                (digits > 0)
+                    .then(|| line[digits..].strip_prefix(['.', ')']))
+                    .flatten()
+            })
+            .map(str::trim_start);
+        match stripped {
+            Some(rest) if rest.len() < line.len() => line = rest,
+            _ => break,
+        }
+    }
+    line.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
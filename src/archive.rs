@@ -0,0 +1,103 @@
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use walkdir::WalkDir;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+#[derive(Error, Debug)]
+pub enum ArchiveError {
+    #[error("could not write archive {0}: {1}")]
+    Io(PathBuf, std::io::Error),
+    #[error("could not write archive {0}: {1}")]
+    Zip(PathBuf, zip::result::ZipError),
+}
+
+/// Archive formats, told apart by file extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+impl Format {
+    /// The format for an archive's file name, if it names a known one
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_string_lossy().to_lowercase();
+        if name.ends_with(".zip") {
+            Some(Format::Zip)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(Format::TarGz)
+        } else if name.ends_with(".tar") {
+            Some(Format::Tar)
+        } else {
+            None
+        }
+    }
+}
+
+/// Pack every file under `dir` into `archive`, except hidden ones such as
+/// `.runs.jsonl` and `.backups/`, and the archive itself. Returns the number
+/// of files packed.
+pub fn write(dir: &Path, archive: &Path, format: Format) -> Result<usize, ArchiveError> {
+    let io_error = |e| ArchiveError::Io(archive.to_path_buf(), e);
+    if let Some(parent) = archive.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent).map_err(io_error)?;
+    }
+    let own_path = archive.canonicalize().ok();
+    let files: Vec<(PathBuf, String)> = WalkDir::new(dir)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|e| e.depth() == 0 || !e.file_name().to_string_lossy().starts_with('.'))
+        .filter_map(Result::ok)
+        .filter(|e| e.file_type().is_file())
+        .filter(|e| own_path.is_none() || e.path().canonicalize().ok() != own_path)
+        .filter_map(|e| {
+            let rel = e.path().strip_prefix(dir).ok()?;
+            let name = rel
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            Some((e.path().to_path_buf(), name))
+        })
+        .collect();
+
+    let out = BufWriter::new(File::create(archive).map_err(io_error)?);
+    match format {
+        Format::Zip => {
+            write_zip(out, &files).map_err(|e| ArchiveError::Zip(archive.to_path_buf(), e))?
+        }
+        Format::Tar => write_tar(out, &files)
+            .and_then(|mut out| out.flush())
+            .map_err(io_error)?,
+        Format::TarGz => write_tar(GzEncoder::new(out, Compression::default()), &files)
+            .and_then(|gz| gz.finish())
+            .and_then(|mut out| out.flush())
+            .map_err(io_error)?,
+    }
+    Ok(files.len())
+}
+
+fn write_zip(out: BufWriter<File>, files: &[(PathBuf, String)]) -> zip::result::ZipResult<()> {
+    let mut zip = ZipWriter::new(out);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    for (path, name) in files {
+        zip.start_file(name.as_str(), options)?;
+        std::io::copy(&mut File::open(path)?, &mut zip)?;
+    }
+    zip.finish()?.flush()?;
+    Ok(())
+}
+
+fn write_tar<W: Write>(out: W, files: &[(PathBuf, String)]) -> std::io::Result<W> {
+    let mut tar = tar::Builder::new(out);
+    for (path, name) in files {
+        tar.append_path_with_name(path, name)?;
+    }
+    tar.into_inner()
+}
@@ -0,0 +1,137 @@
+use crate::types::BackupMode;
+use chrono::Local;
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use tracing::{debug, warn};
+
+/// Snapshot directory of `--backup tree`, inside the output directory (hidden,
+/// so indexing and reorganization skip it)
+pub const BACKUP_DIR: &str = ".backups";
+
+/// Extension added to the copies of `--backup bak`
+const BAK_EXTENSION: &str = "bak";
+
+static BACKUPS: OnceLock<Backups> = OnceLock::new();
+
+struct Backups {
+    mode: BackupMode,
+    /// Output directories, `-o` and the `[roots]` entries, each with its own
+    /// `.backups/`
+    roots: Vec<PathBuf>,
+    /// Name of this run's snapshot, `.backups/<timestamp>/`
+    snapshot: String,
+    keep: usize,
+    /// Files already backed up this run; later writes keep the pre-run copy
+    saved: Mutex<HashSet<PathBuf>>,
+}
+
+/// Keep a copy of every output file replaced during this run (`--backup`).
+///
+/// With `tree`, copies go to `.backups/<timestamp>/` in the output directory
+/// they were written under, and only the newest `keep` snapshots are kept.
+pub fn init_backups(mode: BackupMode, roots: &[&Path], keep: usize) {
+    BACKUPS.get_or_init(|| Backups {
+        mode,
+        roots: roots.iter().map(|root| root.to_path_buf()).collect(),
+        snapshot: Local::now().format("%Y%m%d-%H%M%S").to_string(),
+        keep: keep.max(1),
+        saved: Mutex::new(HashSet::new()),
+    });
+}
+
+/// Whether a file is a `--backup bak` copy rather than a note
+pub fn is_backup(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == BAK_EXTENSION)
+}
+
+/// Replace a file without ever leaving it half-written: the content goes to a
+/// temporary file next to it, which is then renamed over the original
+pub fn write(path: &Path, contents: impl AsRef<[u8]>) -> Result<(), std::io::Error> {
+    if path.exists() {
+        backup(path)?;
+    }
+
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let tmp = path.with_file_name(format!(".{}.tmp", name));
+    let written = File::create(&tmp).and_then(|mut file| {
+        file.write_all(contents.as_ref())?;
+        file.sync_all()
+    });
+    if let Err(e) = written.and_then(|()| fs::rename(&tmp, path)) {
+        let _ = fs::remove_file(&tmp);
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// Copy a file about to be replaced, once per run
+fn backup(path: &Path) -> Result<(), std::io::Error> {
+    let Some(backups) = BACKUPS.get() else {
+        return Ok(());
+    };
+    let dest = match backups.mode {
+        BackupMode::Off => return Ok(()),
+        BackupMode::Bak => {
+            let mut name = path.as_os_str().to_os_string();
+            name.push(format!(".{}", BAK_EXTENSION));
+            PathBuf::from(name)
+        }
+        BackupMode::Tree => {
+            // The innermost root holding the file, in case roots are nested
+            let root = backups
+                .roots
+                .iter()
+                .filter(|root| path.starts_with(root))
+                .max_by_key(|root| root.components().count());
+            let Some(root) = root else {
+                warn!(
+                    "Not backing up {}: it is outside every output directory",
+                    path.display()
+                );
+                return Ok(());
+            };
+            let snapshot = root.join(BACKUP_DIR).join(&backups.snapshot);
+            let rel = path.strip_prefix(root).expect("the root holds the file");
+            if !snapshot.exists() {
+                prune_snapshots(&root.join(BACKUP_DIR), backups.keep);
+            }
+            snapshot.join(rel)
+        }
+    };
+    if !backups.saved.lock().unwrap().insert(path.to_path_buf()) {
+        return Ok(());
+    }
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::copy(path, &dest)?;
+    debug!("Backed up {} to {}", path.display(), dest.display());
+    Ok(())
+}
+
+/// Remove the oldest snapshots in a `.backups/` folder so that, with this
+/// run's, `keep` remain
+fn prune_snapshots(dir: &Path, keep: usize) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let mut snapshots: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.is_dir())
+        .collect();
+    // Timestamped names sort oldest first
+    snapshots.sort();
+    let excess = (snapshots.len() + 1).saturating_sub(keep);
+    for old in snapshots.into_iter().take(excess) {
+        if let Err(e) = fs::remove_dir_all(&old) {
+            warn!("Could not remove old backup {}: {}", old.display(), e);
+        }
+    }
+}
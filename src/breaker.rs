@@ -0,0 +1,118 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::time::sleep;
+use tracing::{info, warn};
+
+/// How often requests waiting on a probe check whether it has finished
+const PROBE_POLL: Duration = Duration::from_millis(500);
+
+/// Stops sending requests to an endpoint that keeps refusing connections.
+///
+/// Shared by every copy of an `LlmClient`. After `threshold` connection
+/// failures in a row the breaker opens: requests then fail at once, or with
+/// a probe interval wait while one request every interval checks whether the
+/// endpoint is back. Any response from the server closes it again.
+#[derive(Debug, Default)]
+pub struct CircuitBreaker {
+    /// Consecutive connection failures that open the breaker; 0 disables it
+    threshold: usize,
+    probe: Option<Duration>,
+    state: Mutex<State>,
+}
+
+/// Handed to an admitted request and passed back with its outcome
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Ticket {
+    /// This request is the probe, so its outcome ends the probing
+    probe: bool,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    failures: usize,
+    open: bool,
+    /// A probe request is in flight
+    probing: bool,
+    next_probe: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: usize, probe: Option<Duration>) -> Self {
+        Self {
+            threshold,
+            probe,
+            state: Mutex::default(),
+        }
+    }
+
+    pub fn threshold(&self) -> usize {
+        self.threshold
+    }
+
+    /// Whether a request may be sent. Returns `None` when the breaker is open
+    /// and not probing; otherwise waits until it closes or this request is
+    /// picked as the next probe.
+    pub async fn admit(&self) -> Option<Ticket> {
+        if self.threshold == 0 {
+            return Some(Ticket::default());
+        }
+        loop {
+            let wait = {
+                let mut state = self.state.lock().unwrap();
+                if !state.open {
+                    return Some(Ticket::default());
+                }
+                let interval = self.probe?;
+                let now = Instant::now();
+                match state.next_probe {
+                    _ if state.probing => PROBE_POLL,
+                    Some(at) if at > now => (at - now).min(PROBE_POLL),
+                    _ => {
+                        state.probing = true;
+                        state.next_probe = Some(now + interval);
+                        info!("Probing whether the LLM endpoint is back");
+                        return Some(Ticket { probe: true });
+                    }
+                }
+            };
+            sleep(wait).await;
+        }
+    }
+
+    /// Record the outcome of a request: whether the server could be reached
+    /// at all, whatever it answered. Requests sent before the breaker opened
+    /// may still finish while a probe is in flight; only the probe's own
+    /// outcome lets the next one go.
+    pub fn record(&self, ticket: Ticket, reachable: bool) {
+        if self.threshold == 0 {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        if ticket.probe {
+            state.probing = false;
+        }
+        if reachable {
+            if state.open {
+                info!("LLM endpoint is reachable again, resuming requests");
+            }
+            *state = State::default();
+            return;
+        }
+
+        state.failures += 1;
+        if !state.open && state.failures >= self.threshold {
+            state.open = true;
+            state.next_probe = self.probe.map(|interval| Instant::now() + interval);
+            match self.probe {
+                Some(interval) => warn!(
+                    "LLM endpoint unreachable after {} connection failures; pausing requests and probing every {:?}",
+                    state.failures, interval
+                ),
+                None => warn!(
+                    "LLM endpoint unreachable after {} connection failures; failing the remaining requests",
+                    state.failures
+                ),
+            }
+        }
+    }
+}
@@ -1,6 +1,15 @@
+use crate::chunking::{chunk_text, split_pieces};
+use crate::clarify::{self, CLARIFY_INSTRUCTION};
 use crate::client::{ClientError, LlmClient};
-use crate::types::{CategorizationResponse, RawNote, Segment};
+use crate::config::CategorizeExample;
+use crate::frontmatter::SourceMeta;
+use crate::heuristics::default_path;
+use crate::tokens::count_tokens;
+use crate::types::{CategorizationResponse, Category, ClarifyMode, RawNote, Segment, SplitOn};
+use std::borrow::Cow;
+use std::path::Path;
 use thiserror::Error;
+use tracing::{debug, warn};
 
 #[derive(Error, Debug)]
 pub enum CategorizationError {
@@ -8,6 +17,380 @@ pub enum CategorizationError {
     Client(#[from] ClientError),
     #[error("Failed to parse categorization response: {0}")]
     Parse(#[from] serde_json::Error),
+    #[error("LLM kept using categories that are not allowed: {0}")]
+    Disallowed(String),
+    #[error("LLM still could not categorize the note after a clarification: {0}")]
+    Unclear(String),
+}
+
+/// Times a response using disallowed categories is sent back for correction
+const CATEGORY_RETRIES: usize = 2;
+
+/// Added to the request for each `--split-on` piece
+const PIECE_INSTRUCTION: &str = "This piece was split from the note mechanically and covers one topic. Return it as exactly one segment. Do not repeat the piece: set the segment's content to a short title for it.";
+
+/// Line opening the category list in the categorization prompt
+const CATEGORY_LIST_START: &str = "Available categories (use these exact values):\n";
+
+/// Names models commonly use for the built-in categories
+const CATEGORY_ALIASES: &[(&str, Category)] = &[
+    ("math", Category::Mathematics),
+    ("maths", Category::Mathematics),
+    ("stats", Category::Statistics),
+    ("probability", Category::Statistics),
+    ("cs", Category::ComputerScience),
+    ("programming", Category::ComputerScience),
+    ("software", Category::ComputerScience),
+    ("coding", Category::ComputerScience),
+    ("ml", Category::MachineLearning),
+    ("ai", Category::MachineLearning),
+    ("deep_learning", Category::MachineLearning),
+    ("artificial_intelligence", Category::MachineLearning),
+    ("bio", Category::Biology),
+    ("chem", Category::Chemistry),
+    ("phys", Category::Physics),
+    ("diary", Category::Journal),
+    ("tasks", Category::Todo),
+    ("todos", Category::Todo),
+    ("to_do", Category::Todo),
+    ("papers", Category::Articles),
+    ("bookmarks", Category::Links),
+    ("references", Category::Reference),
+    ("linguistics", Category::Languages),
+    ("misc", Category::Uncategorized),
+    ("other", Category::Uncategorized),
+    ("general", Category::Uncategorized),
+];
+
+/// Categories allowed in a run (`--only-categories` / `--never-categories`)
+/// and the custom ones the LLM may use
+#[derive(Debug, Clone, Default)]
+pub struct CategoryFilter {
+    only: Vec<Category>,
+    never: Vec<Category>,
+    /// Custom categories named in the config file
+    custom: Vec<Category>,
+    /// Keep categories the LLM invents instead of filing them as uncategorized
+    allow_new: bool,
+}
+
+impl CategoryFilter {
+    pub fn new(only: &[String], never: &[String]) -> Self {
+        Self {
+            only: only.iter().map(|c| Category::from_name(c)).collect(),
+            never: never.iter().map(|c| Category::from_name(c)).collect(),
+            ..Self::default()
+        }
+    }
+
+    /// Accept these custom categories from the LLM, or any it comes up with
+    /// when `allow_new` is set (`--new-categories`)
+    pub fn with_custom(mut self, custom: &[String], allow_new: bool) -> Self {
+        self.custom = custom
+            .iter()
+            .map(|c| Category::from_name(c))
+            .filter(|c| !c.is_builtin())
+            .collect();
+        self.allow_new = allow_new;
+        self
+    }
+
+    pub fn is_active(&self) -> bool {
+        !self.only.is_empty() || !self.never.is_empty()
+    }
+
+    pub fn allows(&self, category: &Category) -> bool {
+        (self.only.is_empty() || self.only.contains(category)) && !self.never.contains(category)
+    }
+
+    /// Categorization prompt offering only the allowed categories
+    fn system_prompt(&self) -> Cow<'static, str> {
+        if !self.is_active() {
+            return Cow::Borrowed(CATEGORIZATION_SYSTEM_PROMPT);
+        }
+
+        let (head, rest) = CATEGORIZATION_SYSTEM_PROMPT
+            .split_once(CATEGORY_LIST_START)
+            .expect("prompt lists the categories");
+        let (list, tail) = rest
+            .split_once("\n\n")
+            .expect("category list ends with a blank line");
+
+        let mut allowed = String::new();
+        for line in list.lines() {
+            let names: Vec<&str> = line
+                .trim_start_matches("- ")
+                .split(", ")
+                .filter(|name| self.allows(&Category::from_name(name)))
+                .collect();
+            if !names.is_empty() {
+                allowed.push_str(&format!("- {}\n", names.join(", ")));
+            }
+        }
+        // Custom categories named in --only-categories
+        let custom: Vec<String> = self
+            .only
+            .iter()
+            .filter(|c| !c.is_builtin() && self.allows(c))
+            .map(Category::dir_name)
+            .collect();
+        if !custom.is_empty() {
+            allowed.push_str(&format!("- {}\n", custom.join(", ")));
+        }
+
+        let rule = if self.only.is_empty() {
+            let never: Vec<String> = self.never.iter().map(Category::dir_name).collect();
+            format!("NEVER use these categories: {}", never.join(", "))
+        } else {
+            "Use ONLY the categories listed above; do not invent new ones".to_string()
+        };
+
+        Cow::Owned(format!(
+            "{}{}{}{}\n\n{}",
+            head, CATEGORY_LIST_START, allowed, rule, tail
+        ))
+    }
+
+    /// Disallowed categories used by a set of segments
+    fn violations(&self, segments: &[Segment]) -> Vec<String> {
+        let mut names: Vec<String> = segments
+            .iter()
+            .filter(|s| !self.allows(&s.category))
+            .map(|s| s.category.dir_name())
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// Move segments with a disallowed category to `uncategorized`, or fail if
+    /// that is not allowed either
+    pub fn enforce(&self, segments: &mut [Segment]) -> Result<(), CategorizationError> {
+        let violations = self.violations(segments);
+        if violations.is_empty() {
+            return Ok(());
+        }
+        if !self.allows(&Category::Uncategorized) {
+            return Err(CategorizationError::Disallowed(violations.join(", ")));
+        }
+
+        for segment in segments.iter_mut().filter(|s| !self.allows(&s.category)) {
+            warn!(
+                "Category {} is not allowed; filing the segment as uncategorized",
+                segment.category.dir_name()
+            );
+            let old_dir = segment.category.dir_name();
+            segment.paths = segment
+                .paths
+                .iter()
+                .map(|p| {
+                    let rest = p
+                        .strip_prefix(&old_dir)
+                        .and_then(|r| r.strip_prefix('/'))
+                        .unwrap_or(p);
+                    format!("{}/{}", Category::Uncategorized.dir_name(), rest)
+                })
+                .collect();
+            segment.category = Category::Uncategorized;
+            segment.subcategory = None;
+        }
+        Ok(())
+    }
+
+    /// The category a name from the LLM stands for: a built-in one matched by
+    /// name, alias or a close spelling, or a configured custom one
+    fn canonical(&self, name: &str) -> Option<Category> {
+        let category = Category::from_name(name);
+        if category.is_builtin() || self.custom.contains(&category) || self.only.contains(&category)
+        {
+            return Some(category);
+        }
+        let name = category.dir_name();
+        if let Some((_, category)) = CATEGORY_ALIASES.iter().find(|(alias, _)| *alias == name) {
+            return Some(category.clone());
+        }
+
+        // Misspellings and singulars, e.g. `machine_lerning` or `book`
+        let allowed = match name.chars().count() {
+            0..=3 => return None,
+            4..=5 => 1,
+            _ => 2,
+        };
+        let mut close: Vec<(usize, &str)> = builtin_names()
+            .map(|builtin| (edit_distance(&name, builtin), builtin))
+            .filter(|(distance, _)| *distance <= allowed)
+            .collect();
+        close.sort();
+        match close.as_slice() {
+            [(best, builtin), rest @ ..] if rest.first().is_none_or(|(d, _)| d > best) => {
+                Some(Category::from_name(builtin))
+            }
+            _ => None,
+        }
+    }
+
+    /// Canonicalize the category, subcategory and paths the LLM gave each
+    /// segment, so `Machine Learning`, `ml` and `machine-learning` share one
+    /// folder. Unknown categories are filed as uncategorized unless
+    /// `--new-categories` is set.
+    pub fn normalize(&self, path: &Path, segments: &mut [Segment]) {
+        for segment in segments.iter_mut() {
+            let raw = segment.category.dir_name();
+            let (name, sub) = match raw.split_once('/') {
+                Some((name, sub)) => (name.to_string(), Some(sub.to_string())),
+                None => (raw.clone(), None),
+            };
+            let category = match self.canonical(&name) {
+                Some(category) => category,
+                None if self.allow_new => Category::from_name(&slug(&name)),
+                None => {
+                    warn!(
+                        "Unknown category {} for a segment of {}; filing it as uncategorized",
+                        raw,
+                        path.display()
+                    );
+                    Category::Uncategorized
+                }
+            };
+            if category.dir_name() != raw {
+                debug!("Category {} normalized to {}", raw, category.dir_name());
+            }
+
+            segment.subcategory = segment
+                .subcategory
+                .take()
+                .or(sub)
+                .map(|s| slug(&s))
+                .filter(|s| !s.is_empty());
+
+            let dir = category.dir_name();
+            segment.paths = segment
+                .paths
+                .iter()
+                .map(|p| {
+                    let p = p.trim().trim_matches('/');
+                    match p.split_once('/') {
+                        // A bare file name goes in the category folder
+                        None => format!("{}/{}", dir, p),
+                        Some((first, rest))
+                            if Category::from_name(first).dir_name() == slug(&name) =>
+                        {
+                            format!("{}/{}", dir, rest)
+                        }
+                        Some((first, rest)) => match self.canonical(first) {
+                            Some(other) => format!("{}/{}", other.dir_name(), rest),
+                            // The model started the path at the subcategory
+                            None => format!("{}/{}", dir, p),
+                        },
+                    }
+                })
+                .collect();
+
+            segment.cross_file_to = segment
+                .cross_file_to
+                .iter()
+                .filter_map(|p| {
+                    let p = p.trim().trim_matches('/');
+                    let (first, rest) = p.split_once('/')?;
+                    match self.canonical(first) {
+                        Some(other) => Some(format!("{}/{}", other.dir_name(), rest)),
+                        None if self.allow_new => Some(format!("{}/{}", slug(first), rest)),
+                        None => {
+                            warn!(
+                                "Not cross-filing a segment of {} to {}: unknown category",
+                                path.display(),
+                                p
+                            );
+                            None
+                        }
+                    }
+                })
+                .collect();
+            segment.category = category;
+        }
+    }
+}
+
+/// The built-in category names listed in the categorization prompt
+fn builtin_names() -> impl Iterator<Item = &'static str> {
+    let (_, rest) = CATEGORIZATION_SYSTEM_PROMPT
+        .split_once(CATEGORY_LIST_START)
+        .expect("prompt lists the categories");
+    let (list, _) = rest
+        .split_once("\n\n")
+        .expect("category list ends with a blank line");
+    list.lines()
+        .flat_map(|line| line.trim_start_matches("- ").split(", "))
+}
+
+/// Lower-case snake_case form of a name
+fn slug(name: &str) -> String {
+    name.trim()
+        .to_lowercase()
+        .split(|c: char| c.is_whitespace() || c == '-' || c == '_')
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+/// Levenshtein distance between two names
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut prev = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let current = row[j + 1];
+            row[j + 1] = if ca == *cb {
+                prev
+            } else {
+                1 + prev.min(row[j]).min(row[j + 1])
+            };
+            prev = current;
+        }
+    }
+    row[b.len()]
+}
+
+/// What the categorization prompt says besides the categories: who the user
+/// is and worked examples from the config file
+#[derive(Debug, Clone, Default)]
+pub struct PromptContext {
+    pub profile: Option<String>,
+    pub examples: Vec<CategorizeExample>,
+    /// Whether the categorizer may ask about ambiguous notes, and who answers
+    pub clarify: ClarifyMode,
+}
+
+impl PromptContext {
+    fn append_to(&self, prompt: &mut String) {
+        if let Some(profile) = &self.profile {
+            prompt.push_str(&format!(
+                "\n\nAbout the user whose notes these are (use it to judge subjects and subcategories):\n{}",
+                profile
+            ));
+        }
+        if self.clarify != ClarifyMode::Off {
+            prompt.push_str("\n\n");
+            prompt.push_str(CLARIFY_INSTRUCTION);
+        }
+        if self.examples.is_empty() {
+            return;
+        }
+
+        prompt.push_str("\n\nExamples of notes and the JSON to return for them:");
+        for (i, example) in self.examples.iter().enumerate() {
+            let response = serde_json::json!({ "segments": example.segments });
+            prompt.push_str(&format!(
+                "\n\nExample {} note:\n{}\n\nExample {} response:\n{}",
+                i + 1,
+                example.note.trim(),
+                i + 1,
+                response
+            ));
+        }
+    }
 }
 
 const CATEGORIZATION_SYSTEM_PROMPT: &str = r#"You are a note categorization assistant. Given a note, extract distinct segments and categorize each.
@@ -26,6 +409,7 @@ For each segment you identify:
 3. Optionally add a subcategory for more specific organization (e.g., "topology" for mathematics)
 4. Suggest output path(s) using format: category/subcategory.md or category/topic.md
 5. If content fits multiple subjects, add cross_file_to paths
+6. Rate your confidence in the category from 0.0 (guess) to 1.0 (certain)
 
 Return JSON in this exact format:
 {
@@ -35,7 +419,8 @@ Return JSON in this exact format:
       "category": "mathematics",
       "subcategory": "topology",
       "paths": ["mathematics/topology.md"],
-      "cross_file_to": []
+      "cross_file_to": [],
+      "confidence": 0.9
     }
   ]
 }
@@ -51,26 +436,271 @@ Rules:
 pub async fn categorize_note(
     client: &LlmClient,
     note: &RawNote,
+    max_tokens: usize,
+    overlap_tokens: usize,
+    split: &SplitOn,
+    filter: &CategoryFilter,
+    context: &PromptContext,
 ) -> Result<Vec<Segment>, CategorizationError> {
+    let pieces = split_pieces(&note.content, split);
+    let mut segments = if pieces.len() > 1 {
+        debug!(
+            "Split {} into {} pieces before categorization",
+            note.path.display(),
+            pieces.len()
+        );
+        let mut segments = Vec::new();
+        for (i, piece) in pieces.iter().enumerate() {
+            // Pieces too long for one request are chunked like whole notes
+            let piece_segments = if count_tokens(piece) > max_tokens {
+                split_and_categorize(
+                    client,
+                    &note.path,
+                    piece,
+                    max_tokens,
+                    overlap_tokens,
+                    filter,
+                    context,
+                )
+                .await?
+            } else {
+                let part = (i + 1, pieces.len());
+                vec![categorize_piece(client, &note.path, piece, part, filter, context).await?]
+            };
+            segments.extend(piece_segments);
+        }
+        merge_adjacent(segments)
+    } else {
+        split_and_categorize(
+            client,
+            &note.path,
+            &note.content,
+            max_tokens,
+            overlap_tokens,
+            filter,
+            context,
+        )
+        .await?
+    };
+    filter.normalize(&note.path, &mut segments);
+    filter.enforce(&mut segments)?;
+
+    // Cross-filing must not sneak content into a disallowed category
+    for segment in &mut segments {
+        segment.cross_file_to.retain(|path| {
+            let category = path.split('/').next().unwrap_or(path);
+            filter.allows(&Category::from_name(category))
+        });
+    }
+    Ok(segments)
+}
+
+/// Categorize one `--split-on` piece of a note as a single segment holding
+/// the piece unchanged.
+///
+/// The model is only asked where the piece goes; when its answer can't be
+/// parsed the piece is kept as uncategorized rather than lost.
+async fn categorize_piece(
+    client: &LlmClient,
+    path: &Path,
+    piece: &str,
+    (number, count): (usize, usize),
+    filter: &CategoryFilter,
+    context: &PromptContext,
+) -> Result<Segment, CategorizationError> {
     let user_prompt = format!(
-        "Original file path: {}\n\nNote content:\n{}",
-        note.path.display(),
-        note.content
+        "Original file path: {} (piece {} of {})\n\n{}\n\nNote content:\n{}",
+        path.display(),
+        number,
+        count,
+        PIECE_INSTRUCTION,
+        piece
     );
+    let placed = match request_segments(client, path, &user_prompt, filter, context).await {
+        Ok(segments) => segments.into_iter().next(),
+        Err(CategorizationError::Parse(e) | CategorizationError::Client(ClientError::Parse(e))) => {
+            warn!(
+                "Could not parse the category of piece {} of {}, keeping it uncategorized: {}",
+                number,
+                path.display(),
+                e
+            );
+            None
+        }
+        Err(e) => return Err(e),
+    };
+    // The piece stays whole whatever the model did with it
+    Ok(match placed {
+        Some(segment) => Segment {
+            content: piece.to_string(),
+            ..segment
+        },
+        None => Segment {
+            content: piece.to_string(),
+            category: Category::Uncategorized,
+            subcategory: None,
+            paths: vec![default_path(&Category::Uncategorized, None, path)],
+            cross_file_to: Vec::new(),
+            confidence: None,
+            skip_enhance: false,
+            source: SourceMeta::default(),
+            lines: None,
+            duplicates: Vec::new(),
+        },
+    })
+}
 
-    let response = client
-        .chat_json(CATEGORIZATION_SYSTEM_PROMPT, &user_prompt)
-        .await?;
+/// Categorize a note's text, in chunks if it is too long for one request
+async fn split_and_categorize(
+    client: &LlmClient,
+    path: &Path,
+    content: &str,
+    max_tokens: usize,
+    overlap_tokens: usize,
+    filter: &CategoryFilter,
+    context: &PromptContext,
+) -> Result<Vec<Segment>, CategorizationError> {
+    let chunks = chunk_text(content, max_tokens, overlap_tokens);
+
+    if chunks.len() == 1 {
+        let user_prompt = format!(
+            "Original file path: {}\n\nNote content:\n{}",
+            path.display(),
+            content
+        );
+        return request_segments(client, path, &user_prompt, filter, context).await;
+    }
+
+    debug!(
+        "Splitting {} into {} chunks for categorization",
+        path.display(),
+        chunks.len()
+    );
+
+    let mut segments = Vec::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let previous = if chunk.context.is_empty() {
+            String::new()
+        } else {
+            format!(
+                "Context from the previous part (for reference only, do NOT extract segments from it):\n{}\n\n",
+                chunk.context
+            )
+        };
+        let user_prompt = format!(
+            "Original file path: {} (part {} of {})\n\n{}Note content:\n{}",
+            path.display(),
+            i + 1,
+            chunks.len(),
+            previous,
+            chunk.body
+        );
+        segments.extend(request_segments(client, path, &user_prompt, filter, context).await?);
+    }
+
+    Ok(merge_adjacent(segments))
+}
+
+/// Send one categorization request and parse the segments, asking again
+/// when the response uses categories that are not allowed
+async fn request_segments(
+    client: &LlmClient,
+    path: &Path,
+    user_prompt: &str,
+    filter: &CategoryFilter,
+    context: &PromptContext,
+) -> Result<Vec<Segment>, CategorizationError> {
+    let mut system_prompt = filter.system_prompt();
+    context.append_to(system_prompt.to_mut());
+    let mut user_prompt = Cow::Borrowed(user_prompt);
+    let mut prompt = user_prompt.clone();
 
-    // Try to extract JSON from response (handle potential markdown code blocks)
-    let json_str = extract_json(&response);
+    let mut attempt = 0;
+    let mut clarified = false;
+    loop {
+        let categorization: CategorizationResponse =
+            client.chat_json_parsed(&system_prompt, &prompt).await?;
+        if let Some(question) = categorization.needs_clarification {
+            if clarified {
+                return Err(CategorizationError::Unclear(question));
+            }
+            let answer = match context.clarify {
+                ClarifyMode::Off => None,
+                mode => {
+                    clarify::answer(client, mode, context.profile.as_deref(), path, &question).await
+                }
+            };
+            user_prompt = Cow::Owned(format!(
+                "{}\n\nYou asked: {}\nAnswer: {}\nCategorize the note now, without asking again.",
+                user_prompt,
+                question,
+                answer.as_deref().unwrap_or(
+                    "No more information is available. Choose the most likely category."
+                )
+            ));
+            prompt = user_prompt.clone();
+            clarified = true;
+            continue;
+        }
+        if !categorization.rejected.is_empty() {
+            warn!(
+                "Dropped {} malformed segments from the categorization of {}: {}",
+                categorization.rejected.len(),
+                path.display(),
+                categorization.rejected.join("; ")
+            );
+        }
+        let violations = filter.violations(&categorization.segments);
+        if violations.is_empty() || attempt == CATEGORY_RETRIES {
+            return Ok(categorization.segments);
+        }
+
+        debug!(
+            "Response used disallowed categories ({}), asking again",
+            violations.join(", ")
+        );
+        prompt = Cow::Owned(format!(
+            "{}\n\nA previous answer used categories that are not allowed: {}. Choose only from the available categories.",
+            user_prompt,
+            violations.join(", ")
+        ));
+        attempt += 1;
+    }
+}
+
+/// Merge neighbouring segments that were split across chunk boundaries
+fn merge_adjacent(segments: Vec<Segment>) -> Vec<Segment> {
+    let mut merged: Vec<Segment> = Vec::new();
+
+    for segment in segments {
+        match merged.last_mut() {
+            Some(prev)
+                if prev.category == segment.category
+                    && prev.subcategory == segment.subcategory
+                    && prev.paths == segment.paths =>
+            {
+                prev.content.push_str("\n\n");
+                prev.content.push_str(&segment.content);
+                // The merged segment is only as certain as its least certain part
+                prev.confidence = match (prev.confidence, segment.confidence) {
+                    (Some(a), Some(b)) => Some(a.min(b)),
+                    (a, b) => a.or(b),
+                };
+                for path in segment.cross_file_to {
+                    if !prev.cross_file_to.contains(&path) {
+                        prev.cross_file_to.push(path);
+                    }
+                }
+            }
+            _ => merged.push(segment),
+        }
+    }
 
-    let categorization: CategorizationResponse = serde_json::from_str(json_str)?;
-    Ok(categorization.segments)
+    merged
 }
 
 /// Extract JSON from response, handling potential markdown code blocks
-fn extract_json(response: &str) -> &str {
+pub fn extract_json(response: &str) -> &str {
     let trimmed = response.trim();
 
     // Check for markdown code blocks
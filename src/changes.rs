@@ -0,0 +1,109 @@
+use crate::types::EnhancedSegment;
+use similar::{ChangeTag, TextDiff};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use walkdir::DirEntry;
+
+/// Output folder with the word diffs of `--show-changes`
+pub const CHANGES_DIR: &str = "changes";
+
+/// Whether a walk of the output directory has reached the diffs folder
+pub fn is_changes(entry: &DirEntry) -> bool {
+    entry.depth() == 1 && entry.file_name() == CHANGES_DIR
+}
+
+/// Longest a diff may take before the rest is shown as one replacement
+const DIFF_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Word-level diff of a segment's enhancement: unchanged text as is, removed
+/// words as `[-...-]` and added ones as `{+...+}`, after a line counting both
+pub fn word_diff(original: &str, enhanced: &str) -> String {
+    let diff = TextDiff::configure()
+        .timeout(DIFF_TIMEOUT)
+        .diff_words(original, enhanced);
+
+    let mut body = String::new();
+    let (mut added, mut removed) = (0, 0);
+    let mut open: Option<ChangeTag> = None;
+    // Whitespace between two changes of the same kind stays inside the markers
+    let mut space = String::new();
+    for change in diff.iter_all_changes() {
+        let tag = change.tag();
+        let word = change.value();
+        let blank = word.trim().is_empty();
+        match tag {
+            ChangeTag::Insert if !blank => added += 1,
+            ChangeTag::Delete if !blank => removed += 1,
+            ChangeTag::Equal if blank && open != Some(ChangeTag::Equal) => {
+                space.push_str(word);
+                continue;
+            }
+            _ => {}
+        }
+
+        if open != Some(tag) {
+            close(&mut body, open);
+            body.push_str(&std::mem::take(&mut space));
+            match tag {
+                ChangeTag::Delete => body.push_str("[-"),
+                ChangeTag::Insert => body.push_str("{+"),
+                ChangeTag::Equal => {}
+            }
+            open = Some(tag);
+        }
+        body.push_str(&std::mem::take(&mut space));
+        body.push_str(word);
+    }
+    close(&mut body, open);
+    body.push_str(&space);
+
+    format!("+{} -{} words\n\n{}", added, removed, body)
+}
+
+fn close(body: &mut String, open: Option<ChangeTag>) {
+    match open {
+        Some(ChangeTag::Delete) => body.push_str("-]"),
+        Some(ChangeTag::Insert) => body.push_str("+}"),
+        _ => {}
+    }
+}
+
+/// Write the diffs of a run's segments to `changes/`, one file per source
+/// note with its segments in order. Returns how many files were written.
+pub fn write_changes(
+    output_dir: &Path,
+    input_dir: &Path,
+    segments: &[EnhancedSegment],
+) -> std::io::Result<usize> {
+    let mut by_source: BTreeMap<&Path, Vec<&EnhancedSegment>> = BTreeMap::new();
+    for segment in segments.iter().filter(|s| s.changes.is_some()) {
+        by_source
+            .entry(segment.original_path.as_path())
+            .or_default()
+            .push(segment);
+    }
+
+    let files = by_source.len();
+    for (source, mut segments) in by_source {
+        segments.sort_by_key(|s| s.position);
+        let rel = source.strip_prefix(input_dir).unwrap_or(source);
+        let mut out = format!("Changes to {}\n", rel.display());
+        for segment in segments {
+            out.push_str(&format!(
+                "\n=== Segment {} -> {}\n\n{}\n",
+                segment.position + 1,
+                segment.output_paths.join(", "),
+                segment.changes.as_deref().unwrap_or_default().trim_end()
+            ));
+        }
+
+        let path = output_dir.join(CHANGES_DIR).join(rel).with_extension("txt");
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, out)?;
+    }
+    Ok(files)
+}
@@ -0,0 +1,178 @@
+use crate::tokens::count_tokens;
+use crate::types::SplitOn;
+
+/// A piece of a long text plus the tail of the previous piece for context
+#[derive(Debug, Clone)]
+pub struct Chunk {
+    /// End of the previous chunk, given to the model for continuity only
+    pub context: String,
+    pub body: String,
+}
+
+/// Split text into blocks at markdown headers and blank lines
+fn split_blocks(text: &str) -> Vec<String> {
+    let mut blocks = Vec::new();
+    let mut current: Vec<&str> = Vec::new();
+    let mut in_fence = false;
+
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") {
+            in_fence = !in_fence;
+        }
+
+        let starts_header = !in_fence && trimmed.starts_with('#');
+        let is_blank = !in_fence && line.trim().is_empty();
+
+        if (starts_header || is_blank) && !current.is_empty() {
+            blocks.push(current.join("\n"));
+            current.clear();
+        }
+        if !is_blank {
+            current.push(line);
+        }
+    }
+    if !current.is_empty() {
+        blocks.push(current.join("\n"));
+    }
+
+    blocks
+}
+
+/// Hard-split a block that alone exceeds the budget, by lines and then by characters
+fn split_oversized(block: &str, max_tokens: usize) -> Vec<String> {
+    let max_chars = max_tokens * 4;
+    let mut pieces = Vec::new();
+    let mut current = String::new();
+
+    for line in block.lines() {
+        let mut line = line.to_string();
+        while line.chars().count() > max_chars {
+            let head: String = line.chars().take(max_chars).collect();
+            line = line.chars().skip(max_chars).collect();
+            if !current.is_empty() {
+                pieces.push(std::mem::take(&mut current));
+            }
+            pieces.push(head);
+        }
+        if !current.is_empty() && count_tokens(&current) + count_tokens(&line) > max_tokens {
+            pieces.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push('\n');
+        }
+        current.push_str(&line);
+    }
+    if !current.is_empty() {
+        pieces.push(current);
+    }
+
+    pieces
+}
+
+/// Take the last blocks of a chunk that fit in the overlap budget
+fn tail_context(blocks: &[String], overlap_tokens: usize) -> String {
+    let mut taken = Vec::new();
+    let mut tokens = 0;
+
+    for block in blocks.iter().rev() {
+        let cost = count_tokens(block);
+        if tokens + cost > overlap_tokens {
+            break;
+        }
+        tokens += cost;
+        taken.push(block.as_str());
+    }
+
+    taken.reverse();
+    taken.join("\n\n")
+}
+
+/// Split text into chunks of at most `max_tokens`, breaking at headers and paragraphs.
+///
+/// Each chunk after the first carries up to `overlap_tokens` of the previous
+/// chunk's tail as context. Text that fits the budget comes back as one chunk.
+pub fn chunk_text(text: &str, max_tokens: usize, overlap_tokens: usize) -> Vec<Chunk> {
+    let max_tokens = max_tokens.max(1);
+    if count_tokens(text) <= max_tokens {
+        return vec![Chunk {
+            context: String::new(),
+            body: text.to_string(),
+        }];
+    }
+
+    let blocks: Vec<String> = split_blocks(text)
+        .into_iter()
+        .flat_map(|b| {
+            if count_tokens(&b) > max_tokens {
+                split_oversized(&b, max_tokens)
+            } else {
+                vec![b]
+            }
+        })
+        .collect();
+
+    let mut chunks = Vec::new();
+    let mut current: Vec<String> = Vec::new();
+    let mut current_tokens = 0;
+    let mut context = String::new();
+
+    for block in blocks {
+        let cost = count_tokens(&block);
+        if !current.is_empty() && current_tokens + cost > max_tokens {
+            let next_context = tail_context(&current, overlap_tokens);
+            chunks.push(Chunk {
+                context: std::mem::replace(&mut context, next_context),
+                body: current.join("\n\n"),
+            });
+            current.clear();
+            current_tokens = 0;
+        }
+        current_tokens += cost;
+        current.push(block);
+    }
+    if !current.is_empty() {
+        chunks.push(Chunk {
+            context,
+            body: current.join("\n\n"),
+        });
+    }
+
+    chunks
+}
+
+/// Split a note into pieces for `--split-on`, each starting at a header or a
+/// line matching the pattern (text before the first one is a piece of its
+/// own). Lines in code fences never start a piece; empty pieces are dropped.
+pub fn split_pieces(text: &str, split: &SplitOn) -> Vec<String> {
+    let starts_piece = |line: &str| match split {
+        SplitOn::None => false,
+        SplitOn::Headers => {
+            let hashes = line.len() - line.trim_start_matches('#').len();
+            (1..=6).contains(&hashes) && line[hashes..].starts_with([' ', '\t'])
+        }
+        SplitOn::Regex(pattern) => pattern.is_match(line),
+    };
+
+    let mut pieces = Vec::new();
+    let mut current: Vec<&str> = Vec::new();
+    let mut in_fence = false;
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+        }
+        if !in_fence && !current.is_empty() && starts_piece(line) {
+            pieces.push(current.join("\n"));
+            current.clear();
+        }
+        current.push(line);
+    }
+    pieces.push(current.join("\n"));
+
+    pieces
+        .into_iter()
+        .map(|piece| piece.trim().to_string())
+        .filter(|piece| !piece.is_empty())
+        .collect()
+}
@@ -0,0 +1,113 @@
+use crate::client::LlmClient;
+use crate::types::ClarifyMode;
+use std::io::{BufRead, IsTerminal, Write};
+use std::path::Path;
+use tokio::sync::Mutex;
+use tracing::{debug, info, warn};
+
+/// Other notes in the same folder listed as context for an automatic answer
+const MAX_SIBLINGS: usize = 20;
+
+/// Added to the categorization prompt with `--clarify`
+pub const CLARIFY_INSTRUCTION: &str = r#"If a note is too short or ambiguous to categorize with any confidence (for example an abbreviation or fragment that could belong to several subjects), do not guess. Respond instead with one short question whose answer would decide it:
+{"needs_clarification": "Is 'ML' here machine learning or maximum likelihood?"}"#;
+
+const ANSWER_SYSTEM_PROMPT: &str = r#"You answer a question a note categorizer asked about one of the user's notes. Use only the context given: where the note is stored, the notes next to it and what the user says about themselves.
+
+Answer in one or two sentences. If the context does not answer the question, reply with exactly UNKNOWN."#;
+
+/// One terminal question at a time while notes are categorized in parallel
+static TERMINAL: Mutex<()> = Mutex::const_new(());
+
+/// Answer a categorizer's question about a note, or None when there is no
+/// answer. `Ask` asks on the terminal and falls back to `Auto` when there is
+/// no terminal or the user just presses enter.
+pub async fn answer(
+    client: &LlmClient,
+    mode: ClarifyMode,
+    profile: Option<&str>,
+    path: &Path,
+    question: &str,
+) -> Option<String> {
+    if mode == ClarifyMode::Ask && std::io::stdin().is_terminal() {
+        if let Some(answer) = ask(path, question).await {
+            return Some(answer);
+        }
+    }
+    let user_prompt = format!("Question: {}\n\n{}", question, note_context(path, profile));
+    match client
+        .for_phase("clarify")
+        .chat(ANSWER_SYSTEM_PROMPT, &user_prompt)
+        .await
+    {
+        Ok(answer) => {
+            let answer = answer.trim();
+            if answer.is_empty() || answer.trim_end_matches('.') == "UNKNOWN" {
+                debug!("No answer to \"{}\" for {}", question, path.display());
+                None
+            } else {
+                info!("Clarified {}: {} {}", path.display(), question, answer);
+                Some(answer.to_string())
+            }
+        }
+        Err(e) => {
+            warn!(
+                "Could not answer \"{}\" for {}: {}",
+                question,
+                path.display(),
+                e
+            );
+            None
+        }
+    }
+}
+
+/// Ask the user; None if they give no answer
+async fn ask(path: &Path, question: &str) -> Option<String> {
+    let _terminal = TERMINAL.lock().await;
+    let prompt = format!("\n{}\n  {}\n> ", path.display(), question);
+    let line = tokio::task::spawn_blocking(move || {
+        let mut stderr = std::io::stderr();
+        let _ = write!(stderr, "{}", prompt);
+        let _ = stderr.flush();
+        let mut line = String::new();
+        std::io::stdin().lock().read_line(&mut line).ok()?;
+        Some(line)
+    })
+    .await
+    .ok()??;
+
+    let answer = line.trim();
+    (!answer.is_empty()).then(|| answer.to_string())
+}
+
+/// Where the note is, what is next to it and who wrote it
+fn note_context(path: &Path, profile: Option<&str>) -> String {
+    let mut context = format!("Note: {}", path.display());
+
+    let folder = path.parent().filter(|p| !p.as_os_str().is_empty());
+    if let Some(folder) = folder {
+        let mut siblings: Vec<String> = std::fs::read_dir(folder)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .filter(|name| {
+                !name.starts_with('.')
+                    && Some(name.as_str()) != path.file_name().and_then(|n| n.to_str())
+            })
+            .collect();
+        siblings.sort();
+        siblings.truncate(MAX_SIBLINGS);
+        if !siblings.is_empty() {
+            context.push_str(&format!(
+                "\nOther notes in the same folder: {}",
+                siblings.join(", ")
+            ));
+        }
+    }
+    if let Some(profile) = profile {
+        context.push_str(&format!("\n\nAbout the user:\n{}", profile));
+    }
+    context
+}
@@ -1,24 +1,240 @@
+use crate::breaker::CircuitBreaker;
+use crate::categorizer::extract_json;
+use crate::http::HttpSettings;
+use crate::metrics;
+use crate::tokens::count_tokens;
+use crate::types::PromptCache;
 use async_openai::{
-    config::OpenAIConfig,
+    config::{Config, OpenAIConfig},
+    error::{ApiError, OpenAIError},
     types::{
-        ChatCompletionRequestMessage, ChatCompletionRequestSystemMessageArgs,
-        ChatCompletionRequestUserMessageArgs, CreateChatCompletionRequestArgs,
+        ChatCompletionRequestAssistantMessageArgs, ChatCompletionRequestMessage,
+        ChatCompletionRequestMessageContentPartImage, ChatCompletionRequestMessageContentPartText,
+        ChatCompletionRequestSystemMessageArgs, ChatCompletionRequestUserMessageArgs,
+        ChatCompletionRequestUserMessageContent, ChatCompletionRequestUserMessageContentPart,
+        CreateChatCompletionRequest, CreateChatCompletionRequestArgs, CreateChatCompletionResponse,
+        CreateEmbeddingRequestArgs, CreateTranscriptionRequestArgs, EmbeddingInput, ImageUrl,
     },
     Client,
 };
-use std::time::Duration;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use thiserror::Error;
 use tokio::time::sleep;
-use tracing::{debug, warn};
+use tracing::{debug, field, info_span, warn, Instrument};
+
+/// Times a response that is not valid JSON is sent back to be fixed
+const JSON_REPAIRS: usize = 2;
+
+/// Sampling used when neither the command line nor the config file sets it:
+/// categorization should be consistent, enhancement may write more freely
+const PHASE_DEFAULTS: &[(&str, Sampling)] = &[
+    (
+        "categorize",
+        Sampling {
+            temperature: Some(0.2),
+            top_p: None,
+            max_tokens: None,
+            frequency_penalty: None,
+        },
+    ),
+    (
+        "enhance",
+        Sampling {
+            temperature: Some(0.7),
+            top_p: None,
+            max_tokens: None,
+            frequency_penalty: None,
+        },
+    ),
+];
+
+/// Sampling parameters sent with chat requests; unset ones are left to the server
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Sampling {
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub max_tokens: Option<u32>,
+    pub frequency_penalty: Option<f32>,
+}
+
+impl Sampling {
+    /// These parameters, with unset ones taken from `fallback`
+    pub fn or(self, fallback: Sampling) -> Sampling {
+        Sampling {
+            temperature: self.temperature.or(fallback.temperature),
+            top_p: self.top_p.or(fallback.top_p),
+            max_tokens: self.max_tokens.or(fallback.max_tokens),
+            frequency_penalty: self.frequency_penalty.or(fallback.frequency_penalty),
+        }
+    }
+}
+
+/// Sampling for every phase of a run
+#[derive(Debug, Clone, Default)]
+pub struct SamplingSettings {
+    /// From the command line, for every phase
+    pub global: Sampling,
+    /// From the config file's `[sampling.<phase>]` tables
+    pub phases: HashMap<String, Sampling>,
+    /// Sent with every request for providers that support reproducible sampling
+    pub seed: Option<i64>,
+}
+
+impl SamplingSettings {
+    /// Sampling for a phase: its config table, then the command line, then the
+    /// built-in default (temperature 0 everywhere when a seed is set)
+    pub fn for_phase(&self, phase: &str) -> Sampling {
+        let default = match self.seed {
+            Some(_) => Sampling {
+                temperature: Some(0.0),
+                ..Sampling::default()
+            },
+            None => PHASE_DEFAULTS
+                .iter()
+                .find(|(name, _)| *name == phase)
+                .map(|(_, sampling)| *sampling)
+                .unwrap_or_default(),
+        };
+        self.phases
+            .get(phase)
+            .copied()
+            .unwrap_or_default()
+            .or(self.global)
+            .or(default)
+    }
+}
+
+/// Words in an API error's type, code or message that mean the server is
+/// busy or broken rather than the request being wrong
+const TRANSIENT_API_ERRORS: &[&str] = &[
+    "rate_limit",
+    "rate limit",
+    "server_error",
+    "overloaded",
+    "timeout",
+    "timed out",
+    "unavailable",
+    "try again",
+];
+
+/// Words in an API error's type, code or message that mean the request
+/// itself was refused, for errors that come without an HTTP status
+const REJECTED_API_ERRORS: &[&str] = &[
+    "invalid_request",
+    "invalid_api_key",
+    "authentication",
+    "permission",
+    "insufficient_quota",
+    "not_found",
+    "context_length",
+];
+
+/// How long to wait between retries: exponential from `base`, capped at
+/// `cap`, with jitter so parallel requests don't retry in lockstep
+#[derive(Debug, Clone, Copy)]
+pub struct Backoff {
+    pub base: Duration,
+    pub cap: Duration,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            base: Duration::from_secs(1),
+            cap: Duration::from_secs(60),
+        }
+    }
+}
+
+impl Backoff {
+    /// Delay after a failed attempt (1-based): half the exponential delay
+    /// plus a random part of the other half
+    pub fn delay(&self, attempt: usize) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(31) as u32;
+        let delay = self.base.saturating_mul(1 << exponent).min(self.cap);
+        delay / 2 + delay.mul_f64(rand::random_range(0.0..=0.5))
+    }
+}
 
 #[derive(Error, Debug)]
 pub enum ClientError {
     #[error("OpenAI API error: {0}")]
-    OpenAI(#[from] async_openai::error::OpenAIError),
+    OpenAI(#[from] OpenAIError),
+    #[error("API error (HTTP {status}): {error}")]
+    Api { status: u16, error: ApiError },
     #[error("No response content from LLM")]
     NoContent,
+    #[error("Failed to parse LLM response: {0}")]
+    Parse(serde_json::Error),
     #[error("Max retries exceeded after {0} attempts")]
     MaxRetries(usize),
+    #[error("LLM endpoint unreachable, circuit breaker open after {0} connection failures")]
+    CircuitOpen(usize),
+}
+
+impl ClientError {
+    /// Whether the same request might succeed later: timeouts, dropped
+    /// connections, rate limits, server errors and garbled responses, such as
+    /// a proxy's error page. Rejected requests fail the same way every time.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            ClientError::OpenAI(OpenAIError::Reqwest(e)) => !e.is_builder(),
+            ClientError::OpenAI(OpenAIError::ApiError(e)) => {
+                is_transient_api_error(e) || !is_rejected_request(e)
+            }
+            ClientError::OpenAI(OpenAIError::JSONDeserialize(_)) => true,
+            ClientError::OpenAI(OpenAIError::StreamError(_)) => true,
+            ClientError::OpenAI(_) => false,
+            ClientError::Api { status, error } => match status {
+                408 | 409 | 425 | 500.. => true,
+                429 => !is_rejected_request(error),
+                _ => is_transient_api_error(error),
+            },
+            ClientError::NoContent => true,
+            ClientError::Parse(_) | ClientError::MaxRetries(_) | ClientError::CircuitOpen(_) => {
+                false
+            }
+        }
+    }
+
+    /// Whether the server could not be reached at all
+    pub fn is_connection_failure(&self) -> bool {
+        matches!(self, ClientError::OpenAI(OpenAIError::Reqwest(e)) if e.is_connect() || e.is_timeout())
+    }
+}
+
+/// Error body of an OpenAI-compatible API
+#[derive(Deserialize)]
+struct ErrorBody {
+    error: ApiError,
+}
+
+fn is_transient_api_error(error: &ApiError) -> bool {
+    mentions(error, TRANSIENT_API_ERRORS)
+}
+
+fn is_rejected_request(error: &ApiError) -> bool {
+    mentions(error, REJECTED_API_ERRORS)
+}
+
+/// Whether an API error's type, code or message contains any of `words`
+fn mentions(error: &ApiError, words: &[&str]) -> bool {
+    let text = format!(
+        "{} {} {}",
+        error.r#type.as_deref().unwrap_or(""),
+        error.code.as_deref().unwrap_or(""),
+        error.message
+    )
+    .to_lowercase();
+    words.iter().any(|word| text.contains(word))
 }
 
 /// Wrapper around async-openai client with custom base URL support
@@ -27,6 +243,15 @@ pub struct LlmClient {
     client: Client<OpenAIConfig>,
     model: String,
     max_retries: usize,
+    backoff: Backoff,
+    breaker: Arc<CircuitBreaker>,
+    /// For chat requests, which async-openai can't send with prompt caching
+    /// fields or report the HTTP status of
+    http: reqwest::Client,
+    prompt_cache: PromptCache,
+    context_size: Option<usize>,
+    sampling: Arc<SamplingSettings>,
+    phase: &'static str,
 }
 
 impl LlmClient {
@@ -40,28 +265,161 @@ impl LlmClient {
             client: Client::with_config(config),
             model: model.to_string(),
             max_retries,
+            backoff: Backoff::default(),
+            breaker: Arc::default(),
+            http: reqwest::Client::new(),
+            prompt_cache: PromptCache::Off,
+            context_size: None,
+            sampling: Arc::default(),
+            phase: "other",
         }
     }
 
-    /// Send a chat completion request with retry logic
-    async fn chat_internal(&self, system: &str, user: &str) -> Result<String, ClientError> {
-        let messages: Vec<ChatCompletionRequestMessage> = vec![
-            ChatCompletionRequestSystemMessageArgs::default()
-                .content(system)
-                .build()?
-                .into(),
-            ChatCompletionRequestUserMessageArgs::default()
-                .content(user)
-                .build()?
-                .into(),
-        ];
+    /// A copy of this client whose requests are attributed to a pipeline phase
+    /// in metrics and traces
+    pub fn for_phase(&self, phase: &'static str) -> Self {
+        Self {
+            phase,
+            ..self.clone()
+        }
+    }
+
+    /// Warn about prompts that exceed the model's context window
+    pub fn with_context_size(mut self, context_size: Option<usize>) -> Self {
+        self.context_size = context_size;
+        self
+    }
+
+    /// Delays between retries. Also used by async-openai, which retries rate
+    /// limited (429) transcription and embedding requests itself before an
+    /// error reaches `with_retry`.
+    pub fn with_backoff(mut self, backoff: Backoff) -> Self {
+        self.client = self.client.with_backoff(backoff::ExponentialBackoff {
+            initial_interval: backoff.base,
+            current_interval: backoff.base,
+            randomization_factor: 0.5,
+            multiplier: 2.0,
+            max_interval: backoff.cap,
+            max_elapsed_time: Some(backoff.cap.saturating_mul(self.max_retries as u32)),
+            ..Default::default()
+        });
+        self.backoff = backoff;
+        self
+    }
+
+    /// Send requests with a client that uses the proxy and certificates given
+    pub fn with_http(mut self, http: &HttpSettings) -> Self {
+        match http.builder().build() {
+            Ok(client) => {
+                self.client = self.client.with_http_client(client.clone());
+                self.http = client;
+            }
+            Err(e) => warn!(
+                "Could not set up the HTTP client, using the default one: {}",
+                e
+            ),
+        }
+        self
+    }
+
+    /// Ask the provider to cache the system prompt; `Auto` picks the mode from
+    /// the model name and URL
+    pub fn with_prompt_cache(mut self, mode: PromptCache) -> Self {
+        self.prompt_cache = match mode {
+            PromptCache::Auto if self.model.to_lowercase().contains("claude") => {
+                PromptCache::Anthropic
+            }
+            PromptCache::Auto if self.client.config().api_base().contains("api.openai.com") => {
+                PromptCache::Openai
+            }
+            PromptCache::Auto => PromptCache::Off,
+            mode => mode,
+        };
+        self
+    }
+
+    /// Fail fast, or wait, when the endpoint stops accepting connections
+    pub fn with_breaker(mut self, breaker: CircuitBreaker) -> Self {
+        self.breaker = Arc::new(breaker);
+        self
+    }
+
+    /// Sampling parameters to send, per phase
+    pub fn with_sampling(mut self, sampling: SamplingSettings) -> Self {
+        self.sampling = Arc::new(sampling);
+        self
+    }
+
+    /// Log a warning if a prompt will not fit the context window
+    fn check_context(&self, system: &str, user: &str) {
+        if let Some(limit) = self.context_size {
+            let tokens = count_tokens(system) + count_tokens(user);
+            if tokens > limit {
+                warn!(
+                    "Prompt is {} tokens, over the {}-token context window; the server may truncate it",
+                    tokens, limit
+                );
+            }
+        }
+    }
+
+    /// Send a single chat completion request
+    async fn complete(
+        &self,
+        messages: Vec<ChatCompletionRequestMessage>,
+    ) -> Result<String, ClientError> {
+        let sampling = self.sampling.for_phase(self.phase);
+        let mut args = CreateChatCompletionRequestArgs::default();
+        args.model(&self.model).messages(messages);
+        if let Some(temperature) = sampling.temperature {
+            args.temperature(temperature);
+        }
+        if let Some(top_p) = sampling.top_p {
+            args.top_p(top_p);
+        }
+        if let Some(max_tokens) = sampling.max_tokens {
+            // `max_completion_tokens` is newer, but local servers only know `max_tokens`
+            #[allow(deprecated)]
+            args.max_tokens(max_tokens);
+        }
+        if let Some(penalty) = sampling.frequency_penalty {
+            args.frequency_penalty(penalty);
+        }
+        if let Some(seed) = self.sampling.seed {
+            args.seed(seed);
+        }
+        let request = args.build()?;
 
-        let request = CreateChatCompletionRequestArgs::default()
-            .model(&self.model)
-            .messages(messages)
-            .build()?;
+        let span = info_span!(
+            "llm.request",
+            phase = self.phase,
+            model = %self.model,
+            prompt_tokens = field::Empty,
+            completion_tokens = field::Empty,
+            cached_tokens = field::Empty,
+        );
+        let started = Instant::now();
+        let result = self.create(request).instrument(span.clone()).await;
+        metrics::global().record_request(self.phase, started.elapsed(), result.is_ok());
+        let response = result?;
 
-        let response = self.client.chat().create(request).await?;
+        if let Some(usage) = &response.usage {
+            span.record("prompt_tokens", usage.prompt_tokens);
+            span.record("completion_tokens", usage.completion_tokens);
+            metrics::global().record_tokens(
+                self.phase,
+                usage.prompt_tokens,
+                usage.completion_tokens,
+            );
+            let cached = usage
+                .prompt_tokens_details
+                .as_ref()
+                .and_then(|details| details.cached_tokens);
+            if let Some(cached) = cached {
+                span.record("cached_tokens", cached);
+                metrics::global().record_cached_tokens(self.phase, cached);
+            }
+        }
 
         response
             .choices
@@ -70,25 +428,105 @@ impl LlmClient {
             .ok_or(ClientError::NoContent)
     }
 
-    /// Send a chat completion request with automatic retry
-    pub async fn chat(&self, system: &str, user: &str) -> Result<String, ClientError> {
+    /// Send a chat request, with the `--prompt-cache` fields added to the body.
+    /// Unlike async-openai, errors keep the HTTP status so they are classified
+    /// for retrying by it.
+    async fn create(
+        &self,
+        request: CreateChatCompletionRequest,
+    ) -> Result<CreateChatCompletionResponse, ClientError> {
+        let mut body = serde_json::to_value(request).map_err(OpenAIError::JSONDeserialize)?;
+        match self.prompt_cache {
+            PromptCache::Anthropic => {
+                let system = &mut body["messages"][0];
+                if system["role"] == "system" {
+                    if let Some(text) = system["content"].as_str() {
+                        system["content"] = serde_json::json!([{
+                            "type": "text",
+                            "text": text,
+                            "cache_control": { "type": "ephemeral" },
+                        }]);
+                    }
+                }
+            }
+            PromptCache::Openai => {
+                body["prompt_cache_key"] = format!("notex-{}", self.phase).into();
+            }
+            PromptCache::Llama => body["cache_prompt"] = true.into(),
+            PromptCache::Auto | PromptCache::Off => {}
+        }
+
+        let config = self.client.config();
+        let response = self
+            .http
+            .post(config.url("/chat/completions"))
+            .headers(config.headers())
+            .query(&config.query())
+            .json(&body)
+            .send()
+            .await
+            .map_err(OpenAIError::Reqwest)?;
+        let status = response.status();
+        let bytes = response.bytes().await.map_err(OpenAIError::Reqwest)?;
+        if status.is_success() {
+            return Ok(serde_json::from_slice(&bytes).map_err(OpenAIError::JSONDeserialize)?);
+        }
+
+        let error = match serde_json::from_slice::<ErrorBody>(&bytes) {
+            Ok(body) => body.error,
+            // Proxies answer with HTML or plain text
+            Err(_) => ApiError {
+                message: String::from_utf8_lossy(&bytes).chars().take(200).collect(),
+                r#type: None,
+                param: None,
+                code: None,
+            },
+        };
+        Err(ClientError::Api {
+            status: status.as_u16(),
+            error,
+        })
+    }
+
+    /// Run a request, retrying transient errors with jittered exponential backoff
+    async fn with_retry<T, F, Fut>(&self, mut request: F) -> Result<T, ClientError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, ClientError>>,
+    {
         let mut last_error = None;
 
         for attempt in 1..=self.max_retries {
-            match self.chat_internal(system, user).await {
+            let Some(ticket) = self.breaker.admit().await else {
+                return Err(ClientError::CircuitOpen(self.breaker.threshold()));
+            };
+            let result = request().await;
+            self.breaker.record(
+                ticket,
+                !matches!(&result, Err(e) if e.is_connection_failure()),
+            );
+
+            match result {
                 Ok(response) => {
                     if attempt > 1 {
                         debug!("Succeeded on attempt {}", attempt);
                     }
                     return Ok(response);
                 }
+                Err(e) if !e.is_transient() => {
+                    warn!(
+                        "Attempt {}/{} failed, not retrying: {}",
+                        attempt, self.max_retries, e
+                    );
+                    return Err(e);
+                }
                 Err(e) => {
                     warn!("Attempt {}/{} failed: {}", attempt, self.max_retries, e);
                     last_error = Some(e);
 
                     if attempt < self.max_retries {
-                        // Exponential backoff: 1s, 2s, 4s, ...
-                        let delay = Duration::from_secs(1 << (attempt - 1));
+                        metrics::global().record_retry(self.phase);
+                        let delay = self.backoff.delay(attempt);
                         debug!("Retrying in {:?}...", delay);
                         sleep(delay).await;
                     }
@@ -99,12 +537,185 @@ impl LlmClient {
         Err(last_error.unwrap_or(ClientError::MaxRetries(self.max_retries)))
     }
 
+    /// Send a chat completion request with automatic retry
+    async fn complete_with_retry(
+        &self,
+        messages: Vec<ChatCompletionRequestMessage>,
+    ) -> Result<String, ClientError> {
+        self.with_retry(|| self.complete(messages.clone())).await
+    }
+
+    /// Send a chat completion request with automatic retry
+    pub async fn chat(&self, system: &str, user: &str) -> Result<String, ClientError> {
+        self.check_context(system, user);
+
+        let messages: Vec<ChatCompletionRequestMessage> = vec![
+            ChatCompletionRequestSystemMessageArgs::default()
+                .content(system)
+                .build()?
+                .into(),
+            ChatCompletionRequestUserMessageArgs::default()
+                .content(user)
+                .build()?
+                .into(),
+        ];
+
+        self.complete_with_retry(messages).await
+    }
+
+    /// Send a chat completion request with an attached image (vision models only)
+    pub async fn chat_with_image(
+        &self,
+        system: &str,
+        user: &str,
+        image: &[u8],
+        mime: &str,
+    ) -> Result<String, ClientError> {
+        self.check_context(system, user);
+        let data_url = format!("data:{};base64,{}", mime, BASE64.encode(image));
+
+        let parts = vec![
+            ChatCompletionRequestUserMessageContentPart::Text(
+                ChatCompletionRequestMessageContentPartText {
+                    text: user.to_string(),
+                },
+            ),
+            ChatCompletionRequestUserMessageContentPart::ImageUrl(
+                ChatCompletionRequestMessageContentPartImage {
+                    image_url: ImageUrl {
+                        url: data_url,
+                        detail: None,
+                    },
+                },
+            ),
+        ];
+
+        let messages: Vec<ChatCompletionRequestMessage> = vec![
+            ChatCompletionRequestSystemMessageArgs::default()
+                .content(system)
+                .build()?
+                .into(),
+            ChatCompletionRequestUserMessageArgs::default()
+                .content(ChatCompletionRequestUserMessageContent::Array(parts))
+                .build()?
+                .into(),
+        ];
+
+        self.complete_with_retry(messages).await
+    }
+
+    /// Transcribe an audio file via the OpenAI-compatible transcription endpoint
+    pub async fn transcribe(&self, path: &Path) -> Result<String, ClientError> {
+        self.with_retry(|| async {
+            let request = CreateTranscriptionRequestArgs::default()
+                .file(path)
+                .model(&self.model)
+                .build()?;
+            let span = info_span!("llm.transcribe", phase = self.phase, model = %self.model);
+            let started = Instant::now();
+            let result = self
+                .client
+                .audio()
+                .transcribe(request)
+                .instrument(span)
+                .await;
+            metrics::global().record_request(self.phase, started.elapsed(), result.is_ok());
+            Ok(result?.text)
+        })
+        .await
+    }
+
+    /// Embed texts via the OpenAI-compatible embeddings endpoint, one vector
+    /// per text, in order
+    pub async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, ClientError> {
+        self.with_retry(|| async {
+            let request = CreateEmbeddingRequestArgs::default()
+                .model(&self.model)
+                .input(EmbeddingInput::StringArray(texts.to_vec()))
+                .build()?;
+            let span = info_span!("llm.embed", phase = self.phase, model = %self.model);
+            let started = Instant::now();
+            let result = self
+                .client
+                .embeddings()
+                .create(request)
+                .instrument(span)
+                .await;
+            metrics::global().record_request(self.phase, started.elapsed(), result.is_ok());
+            let mut data = result?.data;
+            data.sort_by_key(|e| e.index);
+            Ok(data.into_iter().map(|e| e.embedding).collect())
+        })
+        .await
+    }
+
     /// Send a chat completion request expecting JSON response
     pub async fn chat_json(&self, system: &str, user: &str) -> Result<String, ClientError> {
-        let system_with_json = format!(
-            "{}\n\nIMPORTANT: Respond with valid JSON only. No markdown code blocks, no explanations outside the JSON.",
-            system
-        );
-        self.chat(&system_with_json, user).await
+        self.chat(&json_system_prompt(system), user).await
     }
+
+    /// Send a chat completion request expecting JSON and parse the response.
+    ///
+    /// A response that does not parse is sent back with the parse error, asking
+    /// for corrected JSON, up to `JSON_REPAIRS` times before giving up.
+    pub async fn chat_json_parsed<T: DeserializeOwned>(
+        &self,
+        system: &str,
+        user: &str,
+    ) -> Result<T, ClientError> {
+        self.check_context(system, user);
+
+        let mut messages: Vec<ChatCompletionRequestMessage> = vec![
+            ChatCompletionRequestSystemMessageArgs::default()
+                .content(json_system_prompt(system))
+                .build()?
+                .into(),
+            ChatCompletionRequestUserMessageArgs::default()
+                .content(user)
+                .build()?
+                .into(),
+        ];
+
+        let mut attempt = 0;
+        loop {
+            let response = self.complete_with_retry(messages.clone()).await?;
+            let error = match serde_json::from_str(extract_json(&response)) {
+                Ok(parsed) => {
+                    if attempt > 0 {
+                        debug!("Repaired JSON after {} attempts", attempt);
+                    }
+                    return Ok(parsed);
+                }
+                Err(e) if attempt == JSON_REPAIRS => return Err(ClientError::Parse(e)),
+                Err(e) => e,
+            };
+
+            debug!("Response was not valid JSON ({}), asking for a fix", error);
+            metrics::global().record_json_repair(self.phase);
+            messages.push(
+                ChatCompletionRequestAssistantMessageArgs::default()
+                    .content(response)
+                    .build()?
+                    .into(),
+            );
+            messages.push(
+                ChatCompletionRequestUserMessageArgs::default()
+                    .content(format!(
+                        "That response could not be parsed: {}. Reply with the corrected JSON only, keeping the same content and format.",
+                        error
+                    ))
+                    .build()?
+                    .into(),
+            );
+            attempt += 1;
+        }
+    }
+}
+
+/// System prompt with the instruction to answer in JSON only
+fn json_system_prompt(system: &str) -> String {
+    format!(
+        "{}\n\nIMPORTANT: Respond with valid JSON only. No markdown code blocks, no explanations outside the JSON.",
+        system
+    )
 }
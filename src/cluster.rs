@@ -0,0 +1,95 @@
+/// Rounds of k-means before the clusters are taken as they are
+const MAX_ROUNDS: usize = 25;
+
+/// Group vectors by cosine similarity into clusters of at most `max_size`,
+/// returning the indices in each. Runs k-means with enough clusters for the
+/// average to fit, seeded with vectors far apart, then cuts up any cluster
+/// that still came out too big.
+pub fn cluster(vectors: &[Vec<f32>], max_size: usize) -> Vec<Vec<usize>> {
+    let max_size = max_size.max(1);
+    let vectors: Vec<Vec<f32>> = vectors.iter().map(|v| unit(v)).collect();
+    if vectors.len() <= max_size {
+        return vec![(0..vectors.len()).collect()];
+    }
+    let k = vectors.len().div_ceil(max_size);
+
+    // Each seed is the vector least like the seeds before it; `closest` holds
+    // every vector's similarity to its most similar seed so far
+    let mut centroids = vec![vectors[0].clone()];
+    let mut closest: Vec<f32> = vectors.iter().map(|v| dot(v, &centroids[0])).collect();
+    while centroids.len() < k {
+        let farthest = (0..vectors.len())
+            .min_by(|&a, &b| closest[a].total_cmp(&closest[b]))
+            .expect("there are vectors");
+        let seed = vectors[farthest].clone();
+        for (similarity, vector) in closest.iter_mut().zip(&vectors) {
+            *similarity = similarity.max(dot(vector, &seed));
+        }
+        centroids.push(seed);
+    }
+
+    let mut assignment = vec![usize::MAX; vectors.len()];
+    for _ in 0..MAX_ROUNDS {
+        let mut changed = false;
+        for (i, vector) in vectors.iter().enumerate() {
+            let best = nearest(vector, &centroids);
+            if assignment[i] != best {
+                assignment[i] = best;
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+        for (c, centroid) in centroids.iter_mut().enumerate() {
+            let members: Vec<&Vec<f32>> = vectors
+                .iter()
+                .zip(&assignment)
+                .filter(|(_, a)| **a == c)
+                .map(|(v, _)| v)
+                .collect();
+            // A centroid nothing is nearest to stays where it is
+            if let Some(mean) = mean(&members) {
+                *centroid = unit(&mean);
+            }
+        }
+    }
+
+    let mut clusters = Vec::new();
+    for (c, centroid) in centroids.iter().enumerate() {
+        let mut members: Vec<usize> = (0..vectors.len()).filter(|&i| assignment[i] == c).collect();
+        // Most typical first, so the pieces of a big cluster stay coherent
+        members.sort_by(|&a, &b| dot(&vectors[b], centroid).total_cmp(&dot(&vectors[a], centroid)));
+        clusters.extend(members.chunks(max_size).map(<[usize]>::to_vec));
+    }
+    clusters
+}
+
+fn nearest(vector: &[f32], centroids: &[Vec<f32>]) -> usize {
+    (0..centroids.len())
+        .max_by(|&a, &b| dot(vector, &centroids[a]).total_cmp(&dot(vector, &centroids[b])))
+        .expect("there are centroids")
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+fn unit(vector: &[f32]) -> Vec<f32> {
+    let norm = dot(vector, vector).sqrt();
+    if norm == 0.0 {
+        return vector.to_vec();
+    }
+    vector.iter().map(|x| x / norm).collect()
+}
+
+fn mean(vectors: &[&Vec<f32>]) -> Option<Vec<f32>> {
+    let first = vectors.first()?;
+    let mut sum = vec![0.0; first.len()];
+    for vector in vectors {
+        for (s, x) in sum.iter_mut().zip(vector.iter()) {
+            *s += x;
+        }
+    }
+    Some(sum.iter().map(|s| s / vectors.len() as f32).collect())
+}
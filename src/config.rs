@@ -1,15 +1,150 @@
-use crate::types::OutputFormat;
-use clap::Parser;
-use std::path::PathBuf;
+use crate::archive;
+use crate::breaker::CircuitBreaker;
+use crate::client::{Backoff, Sampling, SamplingSettings};
+use crate::hooks::Hooks;
+use crate::http::HttpSettings;
+use crate::local;
+use crate::path_rules::{PathRule, PathRules};
+use crate::roots::OutputRoots;
+use crate::types::{
+    BackupMode, Category, ClarifyMode, CollisionPolicy, DriftGuard, EnhanceLevel, GraphFormat,
+    Layout, OutputFormat, ProgressMode, PromptCache, PublishTarget, ReaderKind, Segment,
+    SegmentMerge, SplitOn,
+};
+use clap::{error::ErrorKind, CommandFactory, Parser, Subcommand};
+use ipnet::IpNet;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::net::SocketAddr;
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("Could not read config file {0}: {1}")]
+    Io(PathBuf, std::io::Error),
+    #[error("Invalid config file {0}: {1}")]
+    Parse(PathBuf, toml::de::Error),
+    #[error("Invalid source glob {0} in [[paths]]: {1}")]
+    Glob(String, glob::PatternError),
+}
+
+/// Settings loaded from the TOML file given with `--config`
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    /// A few sentences about the user, added to the categorization and
+    /// enhancement prompts
+    pub profile: Option<String>,
+    /// Reader to use per file extension (without the dot), e.g. `html = "html"`;
+    /// the `else` entry applies to every extension not listed
+    pub extensions: HashMap<String, ReaderKind>,
+    /// Rules for `--no-llm-categorize` and examples for the LLM
+    pub categorize: CategorizeRules,
+    /// Per-category enhancement settings
+    pub enhance: EnhanceSettings,
+    /// Sampling parameters keyed by phase, e.g. `[sampling.enhance]`
+    pub sampling: HashMap<String, Sampling>,
+    /// Shell commands run at the pipeline's boundaries
+    pub hooks: Hooks,
+    /// `[[paths]]` rules overriding where segments are written
+    pub paths: Vec<PathRule>,
+    /// Output directories by category, e.g. `journal = "~/private/journal"`;
+    /// other categories are written under `-o`
+    pub roots: BTreeMap<String, PathBuf>,
+}
+
+/// The `[categorize]` table: heuristic rules keyed by category, and examples
+/// for the LLM
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CategorizeRules {
+    /// Notes mentioning these words get the category; the most matches wins
+    pub keywords: BTreeMap<String, Vec<String>>,
+    /// Notes whose file name matches these glob patterns get the category
+    pub filenames: BTreeMap<String, Vec<String>>,
+    /// Worked examples shown to the LLM in the categorization prompt
+    pub examples: Vec<CategorizeExample>,
+}
+
+/// A note and the segments it should be split into, from `[[categorize.examples]]`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CategorizeExample {
+    pub note: String,
+    pub segments: Vec<Segment>,
+}
+
+/// The `[enhance]` table
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EnhanceSettings {
+    /// `--enhance-level` overrides keyed by category, e.g. `journal = "light"`
+    pub levels: HashMap<String, EnhanceLevel>,
+    /// Extra prompt instructions keyed by category, appended to the enhancement prompt
+    pub instructions: HashMap<String, String>,
+}
+
+impl Settings {
+    /// Reader configured for an extension, falling back to the `else` entry
+    pub fn reader_for(&self, ext: &str) -> Option<ReaderKind> {
+        self.extensions
+            .get(ext)
+            .or_else(|| self.extensions.get("else"))
+            .copied()
+    }
+
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let text =
+            std::fs::read_to_string(path).map_err(|e| ConfigError::Io(path.to_path_buf(), e))?;
+        let mut settings: Settings =
+            toml::from_str(&text).map_err(|e| ConfigError::Parse(path.to_path_buf(), e))?;
+
+        // Extensions are matched lower-cased and without a leading dot
+        settings.extensions = settings
+            .extensions
+            .into_iter()
+            .map(|(ext, kind)| (ext.trim_start_matches('.').to_lowercase(), kind))
+            .collect();
+        // Categories are matched by their output directory name
+        settings.enhance.levels = settings
+            .enhance
+            .levels
+            .into_iter()
+            .map(|(category, level)| (Category::from_name(&category).dir_name(), level))
+            .collect();
+        settings.enhance.instructions = settings
+            .enhance
+            .instructions
+            .into_iter()
+            .map(|(category, text)| (Category::from_name(&category).dir_name(), text))
+            .collect();
+
+        Ok(settings)
+    }
+}
 
 /// Notex - AI-powered note compressor and enhancer
 #[derive(Parser, Debug, Clone)]
 #[command(name = "notex")]
 #[command(author, version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 pub struct Config {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// Input directory containing notes to process
-    #[arg(value_name = "INPUT_DIR")]
-    pub input: PathBuf,
+    #[arg(value_name = "INPUT_DIR", required_unless_present = "apply_reorg")]
+    pub input: Option<PathBuf>,
+
+    /// TOML config file with additional settings
+    #[arg(short = 'c', long = "config", value_name = "FILE")]
+    pub config_file: Option<PathBuf>,
+
+    /// Settings loaded from the config file
+    #[arg(skip)]
+    pub settings: Settings,
 
     /// Output directory for processed notes
     #[arg(short, long, default_value = "./compressed")]
@@ -27,41 +162,851 @@ pub struct Config {
     #[arg(short = 'k', long, default_value = "sk-no-key-required")]
     pub api_key: String,
 
+    /// Let the categorizer ask about ambiguous notes: off, auto (answered from the
+    /// note's folder and the config profile) or ask (on the terminal)
+    #[arg(long, value_enum, default_value = "off")]
+    pub clarify: ClarifyMode,
+
+    /// Ask the provider to cache the system prompt: auto, off, anthropic, openai or llama
+    #[arg(long, value_enum, default_value = "auto")]
+    pub prompt_cache: PromptCache,
+
+    /// Skip checking the server, API key and model before starting
+    #[arg(long)]
+    pub no_preflight: bool,
+
+    /// Send all HTTP requests through this proxy (default: HTTPS_PROXY and friends)
+    #[arg(long, value_name = "URL")]
+    pub proxy: Option<String>,
+
+    /// Also trust the certificates in this PEM file, e.g. an internal gateway's CA
+    #[arg(long, value_name = "PEM")]
+    pub ca_cert: Option<PathBuf>,
+
+    /// Refuse to start unless every endpoint is on this machine or a private
+    /// network, and turn off environment proxies and link fetching
+    #[arg(long, conflicts_with = "expand_links")]
+    pub local_only: bool,
+
+    /// Also count this range as local with --local-only, e.g. 100.64.0.0/10; repeatable
+    #[arg(long = "local-network", value_name = "CIDR")]
+    pub local_networks: Vec<IpNet>,
+
+    /// Proxy and certificates loaded from `--proxy` and `--ca-cert`
+    #[arg(skip)]
+    pub http: HttpSettings,
+
     /// Maximum concurrent LLM requests (match your server's -np value)
     #[arg(short, long, default_value = "8")]
     pub parallel: usize,
 
+    /// Maximum concurrent categorization requests (defaults to --parallel)
+    #[arg(long, value_name = "N")]
+    pub parallel_categorize: Option<usize>,
+
+    /// Maximum concurrent enhancement requests (defaults to --parallel)
+    #[arg(long, value_name = "N")]
+    pub parallel_enhance: Option<usize>,
+
     /// Output format
     #[arg(short, long, value_enum, default_value = "markdown")]
     pub format: OutputFormat,
 
+    /// Progress reporting: terminal bars or JSON events on stdout
+    #[arg(long, value_enum, default_value = "bars")]
+    pub progress: ProgressMode,
+
+    /// Also write DEBUG-level JSON logs to this file (rotated daily)
+    #[arg(long, value_name = "PATH")]
+    pub log_file: Option<PathBuf>,
+
+    /// Write Prometheus metrics (requests, retries, latency, tokens, failures) to this file
+    #[arg(long, value_name = "PATH")]
+    pub metrics_file: Option<PathBuf>,
+
+    /// Export a trace span for every LLM call to this OTLP/HTTP collector
+    #[arg(long, value_name = "URL")]
+    pub otlp_endpoint: Option<String>,
+
     /// Verbose output
     #[arg(short, long)]
     pub verbose: bool,
 
+    /// Arrange output by category, by date (YYYY/MM/), or by category then date
+    #[arg(long, value_enum, default_value = "by-category")]
+    pub layout: Layout,
+
+    /// Most folders deep an output file may be; deeper folders are folded
+    /// into the file name (`a/b/c.md` becomes `a/b-c.md` at depth 1)
+    #[arg(long, value_name = "N")]
+    pub max_depth: Option<usize>,
+
+    /// Write every file into the output directory itself, with folders folded
+    /// into file names and the category kept in the frontmatter (--max-depth 0)
+    #[arg(long, conflicts_with = "max_depth")]
+    pub flat: bool,
+
+    /// What to do when suggested paths collide after normalization (e.g. differ only in case)
+    #[arg(long, value_enum, default_value = "merge")]
+    pub on_collision: CollisionPolicy,
+
+    /// Keep a copy of every output file a run replaces: next to it (.bak) or in .backups/<timestamp>/
+    #[arg(long, value_enum, default_value = "off")]
+    pub backup: BackupMode,
+
+    /// Backup snapshots kept with --backup tree (older ones are removed)
+    #[arg(long, default_value_t = 5, value_name = "N")]
+    pub backup_keep: usize,
+
+    /// Handlebars template for output files (frontmatter, segment wrapper, footer)
+    #[arg(long, value_name = "FILE")]
+    pub template: Option<PathBuf>,
+
+    /// How much enhancement rewrites notes (per-category overrides go in the config file)
+    #[arg(long, value_enum, default_value = "standard")]
+    pub enhance_level: EnhanceLevel,
+
+    /// Skip enhancement: file segments with their original text verbatim
+    #[arg(long, conflicts_with = "enhance_level")]
+    pub no_enhance: bool,
+
+    /// Tag each segment with 3-7 topics (written to frontmatter) and write tags/index.md
+    #[arg(long)]
+    pub tags: bool,
+
+    /// Close each file with review questions (Q/A and cloze prompts) for spaced repetition
+    #[arg(long)]
+    pub srs: bool,
+
+    /// Check each enhanced segment against its original for dropped facts and
+    /// list the segments that lost some in verification.json
+    #[arg(long)]
+    pub verify: bool,
+
+    /// Flag a segment when more than this many facts are missing
+    #[arg(long, default_value_t = 0, requires = "verify")]
+    pub verify_threshold: usize,
+
+    /// Re-enhance flagged segments once, telling the model which facts to keep
+    #[arg(long, requires = "verify")]
+    pub verify_retry: bool,
+
+    /// Catch enhanced segments that drift from their original (length, lost or
+    /// new names and numbers): off, fallback (keep the original) or retry
+    /// (enhance again with a stricter prompt, then keep the original)
+    #[arg(long, value_enum, default_value = "off")]
+    pub drift_guard: DriftGuard,
+
+    /// Drift score from 0 to 1 above which --drift-guard steps in
+    #[arg(long, default_value_t = 0.5, value_parser = parse_fraction)]
+    pub drift_threshold: f32,
+
+    /// Write a word-level diff of every enhanced segment against its original
+    /// to changes/ (also printed with --verbose)
+    #[arg(long)]
+    pub show_changes: bool,
+
+    /// File journal entries by date under journal/YYYY/MM/YYYY-MM-DD.md, in chronological order
+    #[arg(long)]
+    pub journal: bool,
+
+    /// Copy source notes into _originals/ in the output and link to them
+    #[arg(long)]
+    pub keep_originals: bool,
+
+    /// Commit the run's changes when the output directory is a git repository
+    #[arg(long)]
+    pub git_commit: bool,
+
+    /// Also pack the output directory into this archive after the run:
+    /// `.zip`, `.tar`, `.tar.gz` or `.tgz`
+    #[arg(long, value_name = "FILE", value_parser = parse_archive)]
+    pub archive: Option<PathBuf>,
+
+    /// Keep only the --archive: the output directory is removed once it is
+    /// packed, so it must not exist beforehand
+    #[arg(long, requires = "archive")]
+    pub archive_only: bool,
+
     /// Dry run - only categorize and show plan, don't enhance or write
     #[arg(long)]
     pub dry_run: bool,
 
+    /// Only file notes under these categories (comma-separated)
+    #[arg(long, value_name = "CATEGORIES", value_delimiter = ',')]
+    pub only_categories: Vec<String>,
+
+    /// Never file notes under these categories (comma-separated)
+    #[arg(long, value_name = "CATEGORIES", value_delimiter = ',')]
+    pub never_categories: Vec<String>,
+
+    /// Keep categories the LLM invents instead of filing them as uncategorized
+    #[arg(long)]
+    pub new_categories: bool,
+
+    /// Categorize with local rules (frontmatter, file names, folders, keywords) instead of the LLM
+    #[arg(long)]
+    pub no_llm_categorize: bool,
+
+    /// File segments categorized with lower confidence (0-1) under review/ instead
+    #[arg(long, value_name = "SCORE")]
+    pub confidence_threshold: Option<f32>,
+
+    /// Exit with an error if more than this percentage of notes or segments fail
+    #[arg(long, value_name = "PCT", value_parser = parse_percent)]
+    pub fail_threshold: Option<f64>,
+
+    /// Stop at the first note or segment that fails
+    #[arg(long)]
+    pub fail_fast: bool,
+
+    /// Reprocess only the notes and segments listed in a failure report
+    #[arg(long, value_name = "REPORT")]
+    pub retry_failed: Option<PathBuf>,
+
+    /// Only process notes git lists as changed since this commit, tag or branch
+    /// (the input directory must be in a git repository)
+    #[arg(long, value_name = "REF", conflicts_with = "retry_failed")]
+    pub since: Option<String>,
+
+    /// Only process files matching these patterns (glob syntax, can be specified multiple times)
+    #[arg(short = 'i', long = "include", value_name = "PATTERN")]
+    pub include: Vec<String>,
+
     /// Exclude patterns (glob syntax, can be specified multiple times)
     #[arg(short = 'x', long = "exclude", value_name = "PATTERN")]
     pub exclude: Vec<String>,
 
+    /// Follow symbolic links while discovering notes
+    #[arg(long)]
+    pub follow_symlinks: bool,
+
+    /// Allow followed symlinks to point outside the input directory
+    #[arg(long, requires = "follow_symlinks")]
+    pub allow_outside: bool,
+
+    /// Skip notes larger than this many megabytes
+    #[arg(long, value_name = "MB", default_value = "10")]
+    pub max_file_size: u64,
+
+    /// Extract text from PDF files in the input directory
+    #[arg(long)]
+    pub include_pdf: bool,
+
+    /// Transcribe images (whiteboard photos, screenshots) with a vision model
+    #[arg(long)]
+    pub include_images: bool,
+
+    /// Describe images embedded in markdown notes with the vision model, under
+    /// each image
+    #[arg(long)]
+    pub describe_images: bool,
+
+    /// Skip images larger than this many megabytes
+    #[arg(long, value_name = "MB", default_value = "10")]
+    pub max_image_size: u64,
+
+    /// API base URL of the vision model (defaults to --url)
+    #[arg(long, value_name = "URL")]
+    pub vision_url: Option<String>,
+
+    /// Vision model name (defaults to --model)
+    #[arg(long, value_name = "MODEL")]
+    pub vision_model: Option<String>,
+
+    /// API key for the vision endpoint (defaults to --api-key)
+    #[arg(long, value_name = "KEY")]
+    pub vision_api_key: Option<String>,
+
+    /// Transcribe audio notes (.m4a/.mp3/.wav) with a transcription endpoint
+    #[arg(long)]
+    pub include_audio: bool,
+
+    /// Skip audio files larger than this many megabytes
+    #[arg(long, value_name = "MB", default_value = "25")]
+    pub max_audio_size: u64,
+
+    /// API base URL of the transcription endpoint (defaults to --url)
+    #[arg(long, value_name = "URL")]
+    pub transcribe_url: Option<String>,
+
+    /// Transcription model name
+    #[arg(long, value_name = "MODEL", default_value = "whisper-1")]
+    pub transcribe_model: String,
+
+    /// API key for the transcription endpoint (defaults to --api-key)
+    #[arg(long, value_name = "KEY")]
+    pub transcribe_api_key: Option<String>,
+
+    /// Fetch URLs in `links` notes and add LLM summaries of the pages
+    #[arg(long)]
+    pub expand_links: bool,
+
+    /// Only expand links on this domain (and its subdomains); repeatable, default allows all
+    #[arg(long = "expand-domain", value_name = "DOMAIN")]
+    pub expand_domains: Vec<String>,
+
+    /// Timeout in seconds for fetching a linked page
+    #[arg(long, value_name = "SECS", default_value = "15")]
+    pub fetch_timeout: u64,
+
+    /// Model context window in tokens; prompts larger than this trigger a warning
+    #[arg(long, value_name = "TOKENS")]
+    pub context_size: Option<usize>,
+
+    /// Process paragraphs repeated across notes once: later copies are
+    /// dropped before categorization and listed under the first
+    #[arg(long)]
+    pub dedupe: bool,
+
+    /// Split notes into pieces before categorization, at markdown headers or
+    /// at lines matching a pattern (`regex:<pattern>`); each piece is
+    /// categorized as one segment
+    #[arg(long, value_name = "MODE", default_value = "none", value_parser = parse_split_on)]
+    pub split_on: SplitOn,
+
+    /// Segments shorter than this many characters are merged into another
+    /// segment of the same note after categorization
+    #[arg(long, value_name = "CHARS", default_value = "0")]
+    pub min_segment_chars: usize,
+
+    /// Notes categorized into more segments than this have their smallest
+    /// segments merged until they fit
+    #[arg(long, value_name = "N")]
+    pub max_segments_per_note: Option<usize>,
+
+    /// Which segment a too-short or excess segment is merged into
+    #[arg(long, value_name = "POLICY", default_value = "neighbor")]
+    pub segment_merge: SegmentMerge,
+
+    /// Notes above this many tokens are split into chunks for categorization
+    #[arg(long, value_name = "TOKENS", default_value = "6000")]
+    pub max_note_tokens: usize,
+
+    /// Segments above this many tokens are enhanced in parts and stitched together
+    #[arg(long, value_name = "TOKENS", default_value = "3000")]
+    pub max_segment_tokens: usize,
+
+    /// Tokens of the previous chunk passed along as context
+    #[arg(long, value_name = "TOKENS", default_value = "200")]
+    pub chunk_overlap: usize,
+
     /// Number of retries for failed LLM calls
     #[arg(long, default_value = "3")]
     pub retries: usize,
 
+    /// Delay before the first retry, doubling with each further attempt
+    #[arg(long, value_name = "SECS", default_value = "1", value_parser = parse_seconds)]
+    pub retry_delay: Duration,
+
+    /// Longest delay between retries
+    #[arg(long, value_name = "SECS", default_value = "60", value_parser = parse_seconds)]
+    pub retry_max_delay: Duration,
+
+    /// Stop sending requests after this many connection failures in a row (0 = never)
+    #[arg(long, value_name = "N", default_value = "5")]
+    pub breaker_threshold: usize,
+
+    /// Once the breaker trips, wait for the endpoint instead of failing the
+    /// remaining work, trying one request every SECS
+    #[arg(long, value_name = "SECS", value_parser = parse_seconds)]
+    pub breaker_probe: Option<Duration>,
+
+    /// Sampling temperature for every phase (categorization defaults to 0.2, enhancement to 0.7)
+    #[arg(long, value_name = "T")]
+    pub temperature: Option<f32>,
+
+    /// Nucleus sampling cutoff for every phase
+    #[arg(long, value_name = "P")]
+    pub top_p: Option<f32>,
+
+    /// Most tokens the model may generate per response
+    #[arg(long, value_name = "TOKENS")]
+    pub max_tokens: Option<u32>,
+
+    /// Frequency penalty for every phase (-2 to 2)
+    #[arg(long, value_name = "PENALTY")]
+    pub frequency_penalty: Option<f32>,
+
+    /// Sampling seed for reproducible runs; also makes temperature default to 0
+    #[arg(long, value_name = "N")]
+    pub seed: Option<i64>,
+
     /// Run reorganization pass to optimize file structure
     #[arg(long)]
     pub reorganize: bool,
 
+    /// Run the reorganization pass but only print and save the suggested plan
+    #[arg(long)]
+    pub reorganize_dry_run: bool,
+
+    /// Embedding model used to cluster files for the reorganization pass, so
+    /// outputs too big for one prompt are reorganized a cluster at a time
+    #[arg(long, value_name = "MODEL")]
+    pub embedding_model: Option<String>,
+
+    /// Most files in one reorganization prompt when clustering with --embedding-model
+    #[arg(long, value_name = "N", default_value = "40")]
+    pub reorg_cluster_size: usize,
+
+    /// Apply a reviewed reorganization plan to the output directory, then exit
+    #[arg(long, value_name = "PLAN")]
+    pub apply_reorg: Option<PathBuf>,
+
     /// Add cross-references between related notes
     #[arg(long)]
     pub cross_ref: bool,
+
+    /// Run the cross-referencing pass but only print the links it would add
+    #[arg(long)]
+    pub cross_ref_dry_run: bool,
+
+    /// Files per cross-referencing request; larger sets are split into overlapping batches
+    #[arg(long, value_name = "N", default_value = "40")]
+    pub cross_ref_batch_size: usize,
+
+    /// Open files assembled from several segments with an LLM-written summary
+    #[arg(long)]
+    pub summaries: bool,
+
+    /// Segments a file needs before --summaries adds a summary to it
+    #[arg(long, default_value_t = 3, value_name = "N")]
+    pub summary_min_segments: usize,
+
+    /// Translate the enhanced notes into this language, keeping code, equations and links
+    #[arg(long, value_name = "LANG")]
+    pub translate_to: Option<String>,
+
+    /// Write translations to a parallel <lang>/ tree instead of replacing the notes
+    #[arg(long, requires = "translate_to")]
+    pub translate_subtree: bool,
+
+    /// Add a linked table of contents to long Markdown and Obsidian files
+    #[arg(long)]
+    pub toc: bool,
+
+    /// Headings that make a file long enough for --toc
+    #[arg(long, default_value_t = 5, value_name = "N")]
+    pub toc_min_headers: usize,
+
+    /// Lines that make a file long enough for --toc
+    #[arg(long, default_value_t = 150, value_name = "N")]
+    pub toc_min_lines: usize,
+
+    /// Extract key terms per category into category/glossary.md, merged with earlier runs
+    #[arg(long)]
+    pub glossary: bool,
+
+    /// Collect the questions answered during enhancement into questions/<category>.md
+    #[arg(long)]
+    pub questions: bool,
+
+    /// Aggregate open tasks from every note into todo/index.md with inferred due dates and priorities
+    #[arg(long)]
+    pub todos: bool,
+
+    /// Generate a described index.md per category and a top-level INDEX.md
+    #[arg(long)]
+    pub index: bool,
+
+    /// Write a README.md in every output folder summarizing it and describing its files
+    #[arg(long)]
+    pub readmes: bool,
+
+    /// Export all enhanced segments as JSONL to this file
+    #[arg(long, value_name = "FILE")]
+    pub export_json: Option<PathBuf>,
+}
+
+/// Standalone commands that operate on existing output instead of processing notes
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Publish a processed output directory as an mdBook or a static site
+    Publish {
+        /// Processed notes directory to publish
+        #[arg(value_name = "DIR", default_value = "./compressed")]
+        source: PathBuf,
+
+        /// Destination directory for the book or site
+        #[arg(short, long, default_value = "./site")]
+        dest: PathBuf,
+
+        /// What to generate
+        #[arg(short, long, value_enum, default_value = "mdbook")]
+        target: PublishTarget,
+
+        /// Title of the book or site
+        #[arg(long, default_value = "Notes")]
+        title: String,
+    },
+
+    /// Export the note graph (cross-references and shared sources) as DOT or JSON
+    Graph {
+        /// Processed notes directory to graph
+        #[arg(value_name = "DIR", default_value = "./compressed")]
+        source: PathBuf,
+
+        /// Graph format
+        #[arg(short, long, value_enum, default_value = "dot")]
+        format: GraphFormat,
+
+        /// JSONL export from --export-json, adds shared-source edges
+        #[arg(short, long, value_name = "FILE")]
+        export: Option<PathBuf>,
+
+        /// Write the graph to this file instead of stdout
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+
+    /// Check a processed output directory for broken links, unbalanced math,
+    /// empty or truncated files and duplicate headings
+    Lint {
+        /// Processed notes directory to check
+        #[arg(value_name = "DIR", default_value = "./compressed")]
+        source: PathBuf,
+    },
+
+    /// Report on a processed output directory: categories, compression, largest
+    /// and orphaned files, unanswered questions and token spend per run
+    Stats {
+        /// Processed notes directory to report on
+        #[arg(value_name = "DIR", default_value = "./compressed")]
+        source: PathBuf,
+
+        /// Print the report as JSON instead of tables
+        #[arg(long)]
+        json: bool,
+
+        /// How many of the largest files to list
+        #[arg(long, default_value_t = 10)]
+        top: usize,
+    },
+
+    /// Serve an HTTP API to submit notes or directories for processing, follow
+    /// the runs and fetch what they wrote
+    Serve {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:7878")]
+        listen: SocketAddr,
+
+        /// Require `Authorization: Bearer <TOKEN>` on every request; needed
+        /// to listen on anything but a loopback address
+        #[arg(long, value_name = "TOKEN")]
+        token: Option<String>,
+
+        /// Folder submitted notes are saved to, one subfolder per run
+        #[arg(long, value_name = "DIR", default_value = "./inbox")]
+        inbox: PathBuf,
+
+        /// Options for every run, after `--`, e.g. `-- -o ./compressed --url URL`
+        #[arg(last = true, value_name = "OPTIONS")]
+        options: Vec<String>,
+    },
+
+    /// Serve tools to file notes, search the processed notes and list open
+    /// questions over the Model Context Protocol on stdin and stdout
+    Mcp {
+        /// Processed notes directory to search and file notes into
+        #[arg(value_name = "DIR", default_value = "./compressed")]
+        source: PathBuf,
+
+        /// Folder filed notes are saved to, one subfolder per note
+        #[arg(long, value_name = "DIR", default_value = "./inbox")]
+        inbox: PathBuf,
+
+        /// Options for the runs that file notes, after `--`, e.g. `-- --url URL`
+        #[arg(last = true, value_name = "OPTIONS")]
+        options: Vec<String>,
+    },
 }
 
 impl Config {
     pub fn parse_args() -> Self {
-        Config::parse()
+        let mut config = Config::parse();
+
+        if let Some(path) = &config.config_file {
+            match Settings::load(path) {
+                Ok(settings) => config.settings = settings,
+                Err(e) => Config::command().error(ErrorKind::Io, e).exit(),
+            }
+            // Subcommands take no input directory and write no notes
+            if config.command.is_none() {
+                if let Err(e) = PathRules::new(&config) {
+                    Config::command()
+                        .error(ErrorKind::ValueValidation, e)
+                        .exit();
+                }
+            }
+        }
+        if config.local_only {
+            if let Err(e) = local::check(&config) {
+                Config::command()
+                    .error(ErrorKind::ValueValidation, e)
+                    .exit();
+            }
+        }
+        match HttpSettings::load(config.proxy.as_deref(), config.ca_cert.as_deref()) {
+            Ok(http) if config.local_only => config.http = http.without_env_proxy(),
+            Ok(http) => config.http = http,
+            Err(e) => Config::command()
+                .error(ErrorKind::ValueValidation, e)
+                .exit(),
+        }
+        if let Some(Command::Serve {
+            listen,
+            token: None,
+            ..
+        }) = &config.command
+        {
+            if !listen.ip().is_loopback() {
+                Config::command()
+                    .error(
+                        ErrorKind::ValueValidation,
+                        format!("listening on {} needs --token", listen),
+                    )
+                    .exit();
+            }
+        }
+        if config.archive_only && config.output.exists() {
+            Config::command()
+                .error(
+                    ErrorKind::ValueValidation,
+                    format!(
+                        "--archive-only removes the output directory, and {} already exists",
+                        config.output.display()
+                    ),
+                )
+                .exit();
+        }
+        if config.archive.is_some() {
+            let output = normalized(&config.output);
+            let roots = OutputRoots::new(&config);
+            if let Some(root) = roots
+                .all()
+                .into_iter()
+                .find(|root| !normalized(root).starts_with(&output))
+            {
+                Config::command()
+                    .error(
+                        ErrorKind::ArgumentConflict,
+                        format!(
+                            "--archive only packs the output directory, and the [roots] entry {} is outside it",
+                            root.display()
+                        ),
+                    )
+                    .exit();
+            }
+        }
+        if let Some(path) = config.archive.as_deref().filter(|_| config.archive_only) {
+            if normalized(path).starts_with(normalized(&config.output)) {
+                Config::command()
+                    .error(
+                        ErrorKind::ValueValidation,
+                        "--archive-only needs the archive outside the output directory",
+                    )
+                    .exit();
+            }
+        }
+
+        config
+    }
+
+    /// Delays between retries of failed LLM calls
+    pub fn backoff(&self) -> Backoff {
+        Backoff {
+            base: self.retry_delay,
+            cap: self.retry_max_delay.max(self.retry_delay),
+        }
+    }
+
+    /// A circuit breaker for one LLM endpoint
+    pub fn circuit_breaker(&self) -> CircuitBreaker {
+        CircuitBreaker::new(self.breaker_threshold, self.breaker_probe)
+    }
+
+    /// Sampling parameters for each phase, from the command line and config file
+    pub fn sampling(&self) -> SamplingSettings {
+        SamplingSettings {
+            global: Sampling {
+                temperature: self.temperature,
+                top_p: self.top_p,
+                max_tokens: self.max_tokens,
+                frequency_penalty: self.frequency_penalty,
+            },
+            phases: self.settings.sampling.clone(),
+            seed: self.seed,
+        }
+    }
+
+    /// Custom categories named in the config file's rule and enhancement tables
+    pub fn configured_categories(&self) -> Vec<String> {
+        let settings = &self.settings;
+        settings
+            .categorize
+            .keywords
+            .keys()
+            .chain(settings.categorize.filenames.keys())
+            .chain(settings.enhance.levels.keys())
+            .chain(settings.enhance.instructions.keys())
+            .cloned()
+            .collect()
+    }
+
+    /// Folder depth output paths are limited to, from `--max-depth` or `--flat`
+    pub fn max_depth(&self) -> Option<usize> {
+        if self.flat {
+            Some(0)
+        } else {
+            self.max_depth
+        }
+    }
+
+    /// Whether the run calls the LLM at all, so the server is worth checking
+    pub fn uses_llm(&self) -> bool {
+        let enhances = !self.no_enhance
+            && (self.enhance_level != EnhanceLevel::Off
+                || self
+                    .settings
+                    .enhance
+                    .levels
+                    .values()
+                    .any(|level| *level != EnhanceLevel::Off));
+        !self.no_llm_categorize
+            || enhances
+            || self.include_images
+            || self.include_audio
+            || self.expand_links
+            || self.reorganize
+            || self.reorganize_dry_run
+            || self.cross_ref
+            || self.cross_ref_dry_run
+            || self.summaries
+            || self.translate_to.is_some()
+            || self.glossary
+            || self.todos
+            || self.index
+            || self.readmes
+            || self.tags
+            || self.srs
+            || self.verify
+    }
+
+    /// Concurrency limit for the categorization phase
+    pub fn categorize_parallelism(&self) -> usize {
+        self.parallel_categorize.unwrap_or(self.parallel)
+    }
+
+    /// Concurrency limit for the enhancement phase
+    pub fn enhance_parallelism(&self) -> usize {
+        self.parallel_enhance.unwrap_or(self.parallel)
+    }
+
+    /// Enhancement level for a category: its `[enhance.levels]` entry or
+    /// `--enhance-level`, and always `Off` with `--no-enhance`
+    pub fn enhance_level(&self, category: &Category) -> EnhanceLevel {
+        if self.no_enhance {
+            return EnhanceLevel::Off;
+        }
+        self.settings
+            .enhance
+            .levels
+            .get(&category.dir_name())
+            .copied()
+            .unwrap_or(self.enhance_level)
+    }
+
+    /// Extra enhancement instructions for a category from `[enhance.instructions]`
+    pub fn enhance_instructions(&self, category: &Category) -> Option<&str> {
+        self.settings
+            .enhance
+            .instructions
+            .get(&category.dir_name())
+            .map(|text| text.trim())
+            .filter(|text| !text.is_empty())
+    }
+
+    /// The `profile` from the config file, if it says anything
+    pub fn profile(&self) -> Option<&str> {
+        self.settings
+            .profile
+            .as_deref()
+            .map(str::trim)
+            .filter(|text| !text.is_empty())
+    }
+
+    /// Input directory of a processing run (clap requires it unless a subcommand or
+    /// `--apply-reorg` is given)
+    pub fn input_dir(&self) -> &Path {
+        self.input
+            .as_deref()
+            .expect("INPUT_DIR is required when no subcommand is given")
+    }
+}
+
+/// Parse `--split-on`: `none`, `headers` or `regex:<pattern>`
+fn parse_split_on(value: &str) -> Result<SplitOn, String> {
+    match value {
+        "none" => Ok(SplitOn::None),
+        "headers" => Ok(SplitOn::Headers),
+        _ => match value.strip_prefix("regex:") {
+            Some(pattern) => regex::Regex::new(pattern)
+                .map(SplitOn::Regex)
+                .map_err(|e| e.to_string()),
+            None => Err("expected none, headers or regex:<pattern>".to_string()),
+        },
+    }
+}
+
+/// A path made absolute with `.` and `..` folded away, for comparing paths
+/// that may not exist yet
+pub fn normalized(path: &Path) -> PathBuf {
+    let absolute = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let mut normalized = PathBuf::new();
+    for component in absolute.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
     }
+    normalized
+}
+
+/// Parse `--archive`: a file name ending in `.zip`, `.tar`, `.tar.gz` or `.tgz`
+fn parse_archive(value: &str) -> Result<PathBuf, String> {
+    let path = PathBuf::from(value);
+    match archive::Format::from_path(&path) {
+        Some(_) => Ok(path),
+        None => Err("expected a .zip, .tar, .tar.gz or .tgz file name".to_string()),
+    }
+}
+
+/// Parse a score from 0 to 1
+fn parse_fraction(value: &str) -> Result<f32, String> {
+    let fraction: f32 = value.parse().map_err(|e| format!("{}", e))?;
+    if (0.0..=1.0).contains(&fraction) {
+        Ok(fraction)
+    } else {
+        Err(format!("{} is not between 0 and 1", value))
+    }
+}
+
+/// Parse a percentage from 0 to 100, fractions allowed
+fn parse_percent(value: &str) -> Result<f64, String> {
+    let percent: f64 = value.parse().map_err(|e| format!("{}", e))?;
+    if (0.0..=100.0).contains(&percent) {
+        Ok(percent)
+    } else {
+        Err(format!("{} is not between 0 and 100", value))
+    }
+}
+
+/// Parse a non-negative number of seconds, fractions allowed
+fn parse_seconds(value: &str) -> Result<Duration, String> {
+    let seconds: f64 = value.parse().map_err(|e| format!("{}", e))?;
+    Duration::try_from_secs_f64(seconds)
+        .map_err(|_| format!("{} is not a valid number of seconds", value))
 }
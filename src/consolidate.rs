@@ -0,0 +1,90 @@
+use crate::types::{Segment, SegmentMerge};
+use std::path::Path;
+use tracing::debug;
+
+/// Merge a note's segments until none is shorter than `min_chars` and there
+/// are at most `max_segments`, smallest first, into a segment chosen by
+/// `policy`. The content keeps the order it had in the note.
+pub fn consolidate(
+    path: &Path,
+    segments: &mut Vec<Segment>,
+    min_chars: usize,
+    max_segments: Option<usize>,
+    policy: SegmentMerge,
+) {
+    let before = segments.len();
+    while segments.len() > 1 {
+        let chars: Vec<usize> = segments.iter().map(|s| s.content.chars().count()).collect();
+        let smallest = (0..segments.len())
+            .min_by_key(|&i| chars[i])
+            .expect("more than one segment");
+        let too_many = max_segments.is_some_and(|max| segments.len() > max);
+        if chars[smallest] >= min_chars && !too_many {
+            break;
+        }
+        let into = target(segments, &chars, smallest, policy);
+        merge(segments, smallest, into);
+    }
+    if segments.len() < before {
+        debug!(
+            "Consolidated the {} segments of {} into {}",
+            before,
+            path.display(),
+            segments.len()
+        );
+    }
+}
+
+/// The segment `from` is merged into
+fn target(segments: &[Segment], chars: &[usize], from: usize, policy: SegmentMerge) -> usize {
+    let category = &segments[from].category;
+    if policy == SegmentMerge::Category {
+        let closest = (0..segments.len())
+            .filter(|&i| i != from && segments[i].category == *category)
+            .min_by_key(|&i| i.abs_diff(from));
+        if let Some(closest) = closest {
+            return closest;
+        }
+    }
+
+    // A neighbour in the same category, else the shorter neighbour
+    let neighbours: Vec<usize> = [from.checked_sub(1), Some(from + 1)]
+        .into_iter()
+        .flatten()
+        .filter(|&i| i < segments.len())
+        .collect();
+    neighbours
+        .iter()
+        .copied()
+        .find(|&i| segments[i].category == *category)
+        .or_else(|| neighbours.iter().copied().min_by_key(|&i| chars[i]))
+        .expect("a segment has a neighbour when there are two or more")
+}
+
+/// Merge segment `from` into segment `into`, which keeps its category and paths
+fn merge(segments: &mut Vec<Segment>, from: usize, into: usize) {
+    let segment = segments.remove(from);
+    let into = if from < into { into - 1 } else { into };
+    let target = &mut segments[into];
+
+    target.content = if from <= into {
+        format!("{}\n\n{}", segment.content, target.content)
+    } else {
+        format!("{}\n\n{}", target.content, segment.content)
+    };
+    // The merged segment is only as certain as its least certain part
+    target.confidence = match (target.confidence, segment.confidence) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    };
+    target.skip_enhance &= segment.skip_enhance;
+    target.lines = match (target.lines, segment.lines) {
+        (Some(a), Some(b)) => Some(a.union(b)),
+        (a, b) => a.or(b),
+    };
+    for path in segment.cross_file_to {
+        if !target.cross_file_to.contains(&path) && !target.paths.contains(&path) {
+            target.cross_file_to.push(path);
+        }
+    }
+}
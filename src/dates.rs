@@ -0,0 +1,161 @@
+use chrono::{NaiveDate, NaiveTime};
+use std::path::Path;
+
+const MONTHS: [&str; 12] = [
+    "january",
+    "february",
+    "march",
+    "april",
+    "may",
+    "june",
+    "july",
+    "august",
+    "september",
+    "october",
+    "november",
+    "december",
+];
+
+/// Find the first calendar date in a string.
+///
+/// Recognizes `YYYY-MM-DD`, `YYYY_MM_DD`, `YYYY.MM.DD`, `YYYY/MM/DD`, compact
+/// `YYYYMMDD` and European `DD.MM.YYYY` forms, which covers most date-stamped
+/// note names, then written-out dates like `March 5, 2024` or `5 Mar 2024`.
+pub fn find_date(text: &str) -> Option<NaiveDate> {
+    let bytes = text.as_bytes();
+
+    for start in 0..bytes.len() {
+        // Dates must not be glued to a longer number
+        if start > 0 && bytes[start - 1].is_ascii_digit() {
+            continue;
+        }
+        if let Some(date) = date_at(&bytes[start..]) {
+            return Some(date);
+        }
+    }
+
+    written_date(text)
+}
+
+/// Date of a journal entry: from its source file name, else from its first line
+pub fn entry_date(original_path: &Path, content: &str) -> Option<NaiveDate> {
+    original_path
+        .file_name()
+        .and_then(|n| find_date(&n.to_string_lossy()))
+        .or_else(|| {
+            content
+                .lines()
+                .find(|l| !l.trim().is_empty())
+                .and_then(find_date)
+        })
+}
+
+/// First `HH:MM` time of day in a string
+pub fn find_time(text: &str) -> Option<NaiveTime> {
+    let bytes = text.as_bytes();
+    (0..bytes.len()).find_map(|start| {
+        if !bytes[start].is_ascii_digit() || (start > 0 && bytes[start - 1].is_ascii_digit()) {
+            return None;
+        }
+        let rest = &text[start..];
+        let colon = rest.find(':').filter(|&i| (1..=2).contains(&i))?;
+        let hour: u32 = rest[..colon].parse().ok()?;
+        let minute = rest.get(colon + 1..colon + 3)?;
+        if !minute.bytes().all(|b| b.is_ascii_digit())
+            || rest
+                .as_bytes()
+                .get(colon + 3)
+                .is_some_and(u8::is_ascii_digit)
+        {
+            return None;
+        }
+        NaiveTime::from_hms_opt(hour, minute.parse().ok()?, 0)
+    })
+}
+
+/// A date with a written-out month: `March 5, 2024`, `Mar 5th 2024` or `5 March 2024`
+fn written_date(text: &str) -> Option<NaiveDate> {
+    let words: Vec<&str> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect();
+    let day = |w: &&str| -> Option<u32> {
+        let w = w.to_lowercase();
+        let digits = ["st", "nd", "rd", "th"]
+            .iter()
+            .find_map(|suffix| w.strip_suffix(suffix))
+            .unwrap_or(&w);
+        digits.parse().ok().filter(|d| (1..=31).contains(d))
+    };
+    let year = |w: &&str| -> Option<i32> {
+        (w.len() == 4)
+            .then(|| w.parse().ok())
+            .flatten()
+            .filter(|y| (1900..=2099).contains(y))
+    };
+
+    words.iter().enumerate().find_map(|(i, word)| {
+        let month = month_number(word)?;
+        let next = words.get(i + 1);
+        // "March 5 2024", then "5 March 2024"
+        let (d, y) = match (next.and_then(day), words.get(i + 2).and_then(year)) {
+            (Some(d), Some(y)) => (d, y),
+            _ => (
+                i.checked_sub(1).and_then(|j| day(&words[j]))?,
+                next.and_then(year)?,
+            ),
+        };
+        NaiveDate::from_ymd_opt(y, month, d)
+    })
+}
+
+/// Month number for a month name or its abbreviation (at least three letters)
+fn month_number(word: &str) -> Option<u32> {
+    let word = word.to_lowercase();
+    if word.len() < 3 {
+        return None;
+    }
+    MONTHS
+        .iter()
+        .position(|name| name.starts_with(&word))
+        .map(|i| i as u32 + 1)
+}
+
+/// Parse a date starting at the beginning of `bytes`
+fn date_at(bytes: &[u8]) -> Option<NaiveDate> {
+    let digits = |range: std::ops::Range<usize>| -> Option<u32> {
+        let slice = bytes.get(range)?;
+        if !slice.iter().all(u8::is_ascii_digit) {
+            return None;
+        }
+        std::str::from_utf8(slice).ok()?.parse().ok()
+    };
+    let not_digit_at = |i: usize| bytes.get(i).is_none_or(|b| !b.is_ascii_digit());
+
+    if bytes.get(2) == Some(&b'.') && bytes.get(5) == Some(&b'.') {
+        let (day, month, year) = (digits(0..2)?, digits(3..5)?, digits(6..10)?);
+        if !not_digit_at(10) || !(1900..=2099).contains(&year) {
+            return None;
+        }
+        return NaiveDate::from_ymd_opt(year as i32, month, day);
+    }
+
+    let year = digits(0..4)?;
+    if !(1900..=2099).contains(&year) {
+        return None;
+    }
+
+    let (month, day, end) = match bytes.get(4)? {
+        b'-' | b'_' | b'.' | b'/' if bytes.get(7) == bytes.get(4) => {
+            (digits(5..7)?, digits(8..10)?, 10)
+        }
+        b if b.is_ascii_digit() => (digits(4..6)?, digits(6..8)?, 8),
+        _ => return None,
+    };
+
+    if !not_digit_at(end) {
+        return None;
+    }
+
+    NaiveDate::from_ymd_opt(year as i32, month, day)
+}
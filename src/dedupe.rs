@@ -0,0 +1,174 @@
+use crate::types::{EnhancedSegment, LineRange, OutputFormat, RawNote};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use tracing::{debug, info};
+
+/// Paragraphs shorter than this (headers, greetings, sign-offs) are too
+/// common to count as copies
+const MIN_PARAGRAPH_CHARS: usize = 80;
+
+/// Drop paragraphs found in more than one note from every note but the
+/// first, by path, that has them, and list the notes they were dropped from
+/// in that note's `duplicates`, with the lines they repeated. Dropped
+/// paragraphs are blanked rather than removed so line numbers still match
+/// the files.
+///
+/// Notes left with nothing are removed; returns each with the note it
+/// duplicated.
+pub fn dedupe(notes: &mut Vec<RawNote>) -> Vec<(PathBuf, PathBuf)> {
+    notes.sort_by(|a, b| a.path.cmp(&b.path));
+    // Hash of each paragraph seen, and the note and lines it was first seen at
+    let mut seen: HashMap<u64, (usize, LineRange)> = HashMap::new();
+    let mut dropped_paragraphs = 0;
+
+    for index in 0..notes.len() {
+        let lines: Vec<&str> = notes[index].content.lines().collect();
+        let mut keep = vec![true; lines.len()];
+        let mut originals = Vec::new();
+
+        for (start, end) in paragraphs(&lines) {
+            let text = normalize(&lines[start..end]);
+            if text.chars().count() < MIN_PARAGRAPH_CHARS {
+                continue;
+            }
+            let mut hasher = DefaultHasher::new();
+            text.hash(&mut hasher);
+            let lines = LineRange {
+                start: notes[index].first_line + start,
+                end: notes[index].first_line + end - 1,
+            };
+            let (first, first_lines) = *seen.entry(hasher.finish()).or_insert((index, lines));
+            if first != index {
+                keep[start..end].fill(false);
+                originals.push((first, first_lines));
+                dropped_paragraphs += 1;
+            }
+        }
+        if originals.is_empty() {
+            continue;
+        }
+
+        let content: Vec<&str> = lines
+            .iter()
+            .zip(&keep)
+            .map(|(line, keep)| if *keep { *line } else { "" })
+            .collect();
+        let content = content.join("\n");
+        let path = notes[index].path.clone();
+        for (first, lines) in originals {
+            let original = &mut notes[first];
+            debug!(
+                "{} repeats lines {} of {}",
+                path.display(),
+                lines,
+                original.path.display()
+            );
+            original.duplicates.push((lines, path.clone()));
+        }
+        notes[index].content = content;
+    }
+
+    let mut emptied = Vec::new();
+    let mut kept = Vec::new();
+    for note in notes.drain(..) {
+        if note.content.trim().is_empty() {
+            emptied.push(note.path);
+        } else {
+            kept.push(note);
+        }
+    }
+    // A note left empty only ever repeated others, so one of them lists it
+    let emptied: Vec<(PathBuf, PathBuf)> = emptied
+        .into_iter()
+        .filter_map(|path| {
+            let original = kept
+                .iter()
+                .find(|n| n.duplicates.iter().any(|(_, copy)| *copy == path))?;
+            Some((path, original.path.clone()))
+        })
+        .collect();
+    *notes = kept;
+
+    if dropped_paragraphs > 0 {
+        info!(
+            "Dropped {} paragraphs repeated across notes ({} notes were nothing but repeats)",
+            dropped_paragraphs,
+            emptied.len()
+        );
+    }
+    emptied
+}
+
+/// The notes that repeated a paragraph within a segment's lines of `note`, each once
+pub fn copies_within(note: &RawNote, lines: Option<LineRange>) -> Vec<PathBuf> {
+    let Some(lines) = lines else {
+        return Vec::new();
+    };
+    let mut copies: Vec<PathBuf> = Vec::new();
+    for (paragraph, path) in &note.duplicates {
+        if paragraph.overlaps(lines) && !copies.contains(path) {
+            copies.push(path.clone());
+        }
+    }
+    copies
+}
+
+/// Close each segment with a line listing the other notes its content was
+/// found in
+pub fn note_copies(segments: Vec<EnhancedSegment>, format: OutputFormat) -> Vec<EnhancedSegment> {
+    segments
+        .into_iter()
+        .map(|mut segment| {
+            if segment.duplicates.is_empty() {
+                return segment;
+            }
+            let paths: Vec<String> = segment
+                .duplicates
+                .iter()
+                .map(|p| p.display().to_string())
+                .collect();
+            let line = match format {
+                OutputFormat::Plain => format!("Also in: {}", paths.join(", ")),
+                OutputFormat::Markdown
+                | OutputFormat::Obsidian
+                | OutputFormat::Html
+                | OutputFormat::Logseq => format!("*Also in: {}*", paths.join(", ")),
+            };
+            segment.content = format!("{}\n\n{}", segment.content.trim_end(), line);
+            segment
+        })
+        .collect()
+}
+
+/// Line ranges of the paragraphs of a note, split at blank lines
+fn paragraphs(lines: &[&str]) -> Vec<(usize, usize)> {
+    let mut ranges = Vec::new();
+    let mut start = None;
+    for (i, line) in lines.iter().enumerate() {
+        match (line.trim().is_empty(), start) {
+            (false, None) => start = Some(i),
+            (true, Some(s)) => {
+                ranges.push((s, i));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        ranges.push((s, lines.len()));
+    }
+    ranges
+}
+
+/// A paragraph lowercased with its whitespace collapsed, so copies that were
+/// rewrapped or reindented still match
+fn normalize(lines: &[&str]) -> String {
+    lines
+        .iter()
+        .flat_map(|line| line.split_whitespace())
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
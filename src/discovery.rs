@@ -0,0 +1,420 @@
+use crate::config::Config;
+use crate::ocr;
+use crate::readers::{self, ReaderError};
+use crate::transcribe;
+use crate::types::ReaderKind;
+use glob::Pattern;
+use ignore::{DirEntry, WalkBuilder, WalkState};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
+
+/// A file found by the walk, not yet read
+#[derive(Debug)]
+pub enum Discovered {
+    /// Read as text, using the extension rule if there is one
+    Text {
+        path: PathBuf,
+        rule: Option<ReaderKind>,
+    },
+    /// Transcribed by the vision model
+    Image { path: PathBuf, mime: &'static str },
+    /// Transcribed by the speech-to-text model
+    Audio { path: PathBuf },
+}
+
+/// Why discovery passed over a file
+#[derive(Debug, Clone)]
+pub enum SkipReason {
+    Binary,
+    TooLarge(u64),
+    /// Excluded by a `<!-- notex:ignore -->` marker
+    Ignored,
+    /// Dropped by the `after_discovery` hook
+    Hook,
+    /// Nothing but paragraphs of another note (`--dedupe`)
+    Duplicate(PathBuf),
+}
+
+impl std::fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SkipReason::Binary => write!(f, "binary content"),
+            SkipReason::TooLarge(size) => write!(f, "{:.1} MB", *size as f64 / 1024.0 / 1024.0),
+            SkipReason::Ignored => write!(f, "notex:ignore marker"),
+            SkipReason::Hook => write!(f, "after_discovery hook"),
+            SkipReason::Duplicate(original) => write!(f, "copy of {}", original.display()),
+        }
+    }
+}
+
+/// Walks the input directory and decides how each file is ingested
+#[derive(Clone)]
+pub struct Discovery {
+    config: Config,
+    include_patterns: Vec<Pattern>,
+    exclude_patterns: Vec<Pattern>,
+    only: Option<Arc<HashSet<PathBuf>>>,
+    skipped: Arc<Mutex<Vec<(PathBuf, SkipReason)>>>,
+}
+
+impl Discovery {
+    pub fn new(config: &Config) -> Self {
+        // Parse include and exclude patterns
+        let include_patterns: Vec<Pattern> = config
+            .include
+            .iter()
+            .filter_map(|p| Pattern::new(p).ok())
+            .collect();
+        let exclude_patterns: Vec<Pattern> = config
+            .exclude
+            .iter()
+            .filter_map(|p| Pattern::new(p).ok())
+            .collect();
+
+        Self {
+            config: config.clone(),
+            include_patterns,
+            exclude_patterns,
+            only: None,
+            skipped: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Only admit these files (canonical paths), e.g. when retrying failures
+    /// or with `--since`
+    pub fn restrict_to(mut self, paths: HashSet<PathBuf>) -> Self {
+        self.only = Some(Arc::new(paths));
+        self
+    }
+
+    /// Files discovery passed over, with the reason for each
+    pub fn skipped_files(&self) -> Vec<(PathBuf, SkipReason)> {
+        self.skipped.lock().unwrap().clone()
+    }
+
+    /// Walk the input directory on blocking threads, sending files as they
+    /// are found. The handle gives the number of files sent.
+    ///
+    /// The channel holds at most `capacity` files, so the walk waits for the
+    /// pipeline instead of running ahead of it.
+    pub fn spawn(
+        self,
+        capacity: usize,
+    ) -> (
+        mpsc::Receiver<Discovered>,
+        JoinHandle<Result<usize, std::io::Error>>,
+    ) {
+        let (tx, rx) = mpsc::channel(capacity);
+        let handle = tokio::task::spawn_blocking(move || self.walk(tx));
+        (rx, handle)
+    }
+
+    /// Walk the input directory with one thread per core; the order files are
+    /// found in varies from run to run
+    fn walk(&self, tx: mpsc::Sender<Discovered>) -> Result<usize, std::io::Error> {
+        let root = self.config.input_dir().canonicalize()?;
+        let filter = self.clone();
+        let walker = WalkBuilder::new(self.config.input_dir())
+            // Hidden folders, .gitignore and the like are not skipped
+            .standard_filters(false)
+            .follow_links(self.config.follow_symlinks)
+            .filter_entry(move |e| filter.symlink_allowed(e, &root))
+            .build_parallel();
+
+        let sent = AtomicUsize::new(0);
+        walker.run(|| {
+            let (tx, sent) = (tx.clone(), &sent);
+            Box::new(move |entry| {
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(e) => {
+                        match symlink_loop(&e) {
+                            Some((ancestor, child)) => warn!(
+                                "Skipping symlink cycle at {} (loops back to {})",
+                                child.display(),
+                                ancestor.display()
+                            ),
+                            None => warn!("Could not walk input directory: {}", e),
+                        }
+                        return WalkState::Continue;
+                    }
+                };
+
+                if let Some(item) = self.classify(&entry) {
+                    // The pipeline has stopped listening
+                    if tx.blocking_send(item).is_err() {
+                        return WalkState::Quit;
+                    }
+                    sent.fetch_add(1, Ordering::Relaxed);
+                }
+                WalkState::Continue
+            })
+        });
+
+        Ok(sent.into_inner())
+    }
+
+    /// Decide how a walked entry is ingested, if at all
+    fn classify(&self, entry: &DirEntry) -> Option<Discovered> {
+        let path = entry.path();
+
+        // Skip directories
+        if path.is_dir() {
+            return None;
+        }
+
+        // Skip hidden files
+        if path
+            .file_name()
+            .map(|n| n.to_string_lossy().starts_with('.'))
+            .unwrap_or(false)
+        {
+            return None;
+        }
+
+        // When retrying, only the files that failed last time; with --since,
+        // only the ones git lists as changed
+        if let Some(only) = &self.only {
+            let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+            if !only.contains(&canonical) {
+                return None;
+            }
+        }
+
+        // Only admit files matching --include, if given
+        if !self.is_included(path) {
+            debug!("Not included: {}", path.display());
+            return None;
+        }
+
+        // Skip excluded patterns
+        if self.is_excluded(path) {
+            debug!("Excluded: {}", path.display());
+            return None;
+        }
+
+        let ext = readers::extension(path);
+        let size = entry.metadata().map(|m| m.len()).unwrap_or(0);
+        let rule = self.config.settings.reader_for(&ext);
+
+        match rule {
+            Some(ReaderKind::Skip) => {
+                debug!("Skipping by extension rule: {}", path.display());
+                return None;
+            }
+            Some(ReaderKind::Ocr) => {
+                return match ocr::image_mime(&ext) {
+                    Some(mime) => self.image(path, mime, size),
+                    None => {
+                        warn!(
+                            "No image type known for .{} files, skipping {}",
+                            ext,
+                            path.display()
+                        );
+                        None
+                    }
+                };
+            }
+            Some(_) => {}
+            None => {
+                // PDFs are only ingested when asked for
+                if ext == "pdf" && !self.config.include_pdf {
+                    debug!("Skipping PDF (use --include-pdf): {}", path.display());
+                    return None;
+                }
+
+                // Images and audio are transcribed later by a model
+                if let Some(mime) = ocr::image_mime(&ext) {
+                    if !self.config.include_images {
+                        debug!("Skipping image (use --include-images): {}", path.display());
+                        return None;
+                    }
+                    return self.image(path, mime, size);
+                }
+                if transcribe::is_audio(&ext) {
+                    if !self.config.include_audio {
+                        debug!("Skipping audio (use --include-audio): {}", path.display());
+                        return None;
+                    }
+                    if size > self.config.max_audio_size * 1024 * 1024 {
+                        warn!(
+                            "Skipping audio larger than {} MB: {}",
+                            self.config.max_audio_size,
+                            path.display()
+                        );
+                        return None;
+                    }
+                    return Some(Discovered::Audio {
+                        path: path.to_path_buf(),
+                    });
+                }
+            }
+        }
+
+        // Don't read huge logs or stray binaries as text
+        if size > self.config.max_file_size * 1024 * 1024 {
+            self.skip(path, SkipReason::TooLarge(size));
+            return None;
+        }
+        let sniff = match rule {
+            None => !readers::is_document(&ext),
+            Some(kind) => kind == ReaderKind::Text,
+        };
+        if sniff && readers::looks_binary(path).unwrap_or(false) {
+            self.skip(path, SkipReason::Binary);
+            return None;
+        }
+
+        debug!("Discovered: {}", path.display());
+        Some(Discovered::Text {
+            path: path.to_path_buf(),
+            rule,
+        })
+    }
+
+    /// An image to transcribe, unless it is over the size limit
+    fn image(&self, path: &Path, mime: &'static str, size: u64) -> Option<Discovered> {
+        if size > self.config.max_image_size * 1024 * 1024 {
+            warn!(
+                "Skipping image larger than {} MB: {}",
+                self.config.max_image_size,
+                path.display()
+            );
+            return None;
+        }
+        Some(Discovered::Image {
+            path: path.to_path_buf(),
+            mime,
+        })
+    }
+
+    /// Record a file that discovery passed over
+    pub fn skip(&self, path: &Path, reason: SkipReason) {
+        debug!("Skipping {} ({})", path.display(), reason);
+        self.skipped
+            .lock()
+            .unwrap()
+            .push((path.to_path_buf(), reason));
+    }
+
+    /// Check if a path should be excluded
+    fn is_excluded(&self, path: &Path) -> bool {
+        matches_any(&self.exclude_patterns, path)
+    }
+
+    /// Check if a path passes the include patterns (everything does when there are none)
+    fn is_included(&self, path: &Path) -> bool {
+        self.include_patterns.is_empty() || matches_any(&self.include_patterns, path)
+    }
+
+    /// Decide whether discovery may enter a symlinked file or directory
+    fn symlink_allowed(&self, entry: &DirEntry, root: &Path) -> bool {
+        if entry.depth() == 0 || !entry.path_is_symlink() {
+            return true;
+        }
+        if !self.config.follow_symlinks {
+            debug!(
+                "Skipping symlink (use --follow-symlinks): {}",
+                entry.path().display()
+            );
+            return false;
+        }
+        if self.config.allow_outside {
+            return true;
+        }
+
+        match entry.path().canonicalize() {
+            Ok(target) if target.starts_with(root) => true,
+            Ok(target) => {
+                warn!(
+                    "Skipping symlink outside the input directory (use --allow-outside): {} -> {}",
+                    entry.path().display(),
+                    target.display()
+                );
+                false
+            }
+            Err(e) => {
+                warn!("Skipping broken symlink {}: {}", entry.path().display(), e);
+                false
+            }
+        }
+    }
+}
+
+/// The directory a symlink cycle loops back to and the link that closes it,
+/// when that is what the walk error is
+fn symlink_loop(error: &ignore::Error) -> Option<(&Path, &Path)> {
+    match error {
+        ignore::Error::Loop { ancestor, child } => Some((ancestor, child)),
+        ignore::Error::WithPath { err, .. }
+        | ignore::Error::WithDepth { err, .. }
+        | ignore::Error::WithLineNumber { err, .. } => symlink_loop(err),
+        _ => None,
+    }
+}
+
+/// Read a discovered file as text, using its extension rule or the built-in defaults
+pub fn read_content(
+    path: &Path,
+    rule: Option<ReaderKind>,
+    include_pdf: bool,
+) -> Result<String, ReaderError> {
+    match rule {
+        Some(ReaderKind::Extract) => readers::read_document(path),
+        Some(ReaderKind::Table) => readers::read_table(path),
+        Some(ReaderKind::Html) => readers::read_html(path),
+        // Images and skipped files never get this far
+        Some(ReaderKind::Text | ReaderKind::Ocr | ReaderKind::Skip) => readers::read_text(path),
+        None => match readers::extension(path).as_str() {
+            "pdf" if include_pdf => readers::read_pdf(path),
+            "docx" => readers::read_docx(path),
+            "odt" => readers::read_odt(path),
+            _ => readers::read_text(path),
+        },
+    }
+}
+
+/// Marker line excluding a whole note from processing
+const IGNORE_NOTE: &str = "<!-- notex:ignore -->";
+/// Marker lines delimiting a region excluded from processing
+const IGNORE_START: &str = "<!-- notex:ignore-start -->";
+const IGNORE_END: &str = "<!-- notex:ignore-end -->";
+
+/// Drop the regions of a note marked as private, before anything sees them.
+///
+/// Returns `None` if a `<!-- notex:ignore -->` line excludes the whole note.
+/// Lines between `<!-- notex:ignore-start -->` and `<!-- notex:ignore-end -->`
+/// are removed with their markers; a start marker without an end hides the rest
+/// of the note.
+pub fn strip_ignored(content: String) -> Option<String> {
+    if !content.contains("notex:ignore") {
+        return Some(content);
+    }
+
+    let mut kept = String::with_capacity(content.len());
+    let mut ignoring = false;
+    for line in content.split_inclusive('\n') {
+        match line.trim() {
+            IGNORE_NOTE => return None,
+            IGNORE_START => ignoring = true,
+            IGNORE_END if ignoring => ignoring = false,
+            _ if !ignoring => kept.push_str(line),
+            _ => {}
+        }
+    }
+    Some(kept)
+}
+
+/// Check a path, or its file name, against a set of glob patterns
+pub fn matches_any(patterns: &[Pattern], path: &Path) -> bool {
+    let path_str = path.to_string_lossy();
+    patterns.iter().any(|p| {
+        p.matches(&path_str)
+            || p.matches(path.file_name().unwrap_or_default().to_str().unwrap_or(""))
+    })
+}
@@ -0,0 +1,135 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::path::PathBuf;
+
+/// Originals shorter than this many words are not judged by length
+const MIN_WORDS: usize = 40;
+
+/// Fewest numbers and names a text needs before their overlap counts
+const MIN_TERMS: usize = 3;
+
+/// Added to the enhancement prompt when a segment is enhanced again with
+/// `--drift-guard retry`
+pub const STRICT_INSTRUCTION: &str = "\n\nYour previous version of this note strayed too far from the original. This time stay close to it: fix errors and formatting, answer \"?\" markers briefly, and keep every name and number of the original. Do not add names, numbers, facts or resources that are not in the original.";
+
+/// How far an enhanced segment strays from its original, measured without
+/// the LLM
+#[derive(Debug, Clone, Copy)]
+pub struct Drift {
+    /// Enhanced words per original word
+    pub length_ratio: f32,
+    /// Share of the original's numbers and names missing from the enhanced text
+    pub lost: f32,
+    /// Share of the enhanced text's numbers and names not in the original
+    pub invented: f32,
+    /// Whether the original is long enough for the length ratio to count
+    judge_length: bool,
+}
+
+impl Drift {
+    pub fn measure(original: &str, enhanced: &str) -> Self {
+        let words = |text: &str| text.split_whitespace().count().max(1);
+        let (before, after) = (terms(original), terms(enhanced));
+        let share = |of: &HashSet<String>, missing_from: &HashSet<String>| {
+            if of.len() < MIN_TERMS {
+                return 0.0;
+            }
+            of.difference(missing_from).count() as f32 / of.len() as f32
+        };
+
+        Self {
+            length_ratio: words(enhanced) as f32 / words(original) as f32,
+            lost: share(&before, &after),
+            invented: share(&after, &before),
+            judge_length: original.split_whitespace().count() >= MIN_WORDS,
+        }
+    }
+
+    /// From 0 for a faithful edit towards 1: the worst of the length change
+    /// (0.5 at double or half the length), lost terms and invented terms
+    pub fn score(&self) -> f32 {
+        let length = if self.judge_length {
+            1.0 - self.length_ratio.min(1.0 / self.length_ratio)
+        } else {
+            0.0
+        };
+        length.max(self.lost).max(self.invented)
+    }
+}
+
+impl fmt::Display for Drift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "drift {:.2}: length x{:.2}, {:.0}% of names and numbers lost, {:.0}% new",
+            self.score(),
+            self.length_ratio,
+            self.lost * 100.0,
+            self.invented * 100.0
+        )
+    }
+}
+
+/// Numbers and capitalized names in a text. A capitalized word that starts
+/// a sentence, heading or list item only counts when it is an acronym.
+fn terms(text: &str) -> HashSet<String> {
+    let mut terms = HashSet::new();
+    for line in text.lines() {
+        let mut sentence_start = true;
+        for token in line.split_whitespace() {
+            let word = token.trim_matches(|c: char| !c.is_alphanumeric());
+            let ends_sentence = token.ends_with(['.', '!', '?', ':']);
+            if word.is_empty() {
+                // Heading and list markers
+                sentence_start = sentence_start || ends_sentence;
+                continue;
+            }
+
+            if word.chars().any(|c| c.is_ascii_digit()) {
+                terms.insert(word.replace(',', ""));
+            } else if word.chars().next().is_some_and(char::is_uppercase) {
+                let acronym = word.chars().count() > 1 && !word.chars().any(char::is_lowercase);
+                if !sentence_start || acronym {
+                    terms.insert(word.to_string());
+                }
+            }
+            sentence_start = ends_sentence;
+        }
+    }
+    terms
+}
+
+/// What `--drift-guard` wrote for a segment that drifted
+#[derive(Debug, Clone, Copy)]
+pub enum GuardAction {
+    /// The stricter enhancement, which stayed close enough
+    Reenhanced,
+    /// The original content
+    KeptOriginal,
+}
+
+/// A segment `--drift-guard` stepped in for, listed at the end of the run
+#[derive(Debug, Clone)]
+pub struct Guarded {
+    pub path: PathBuf,
+    pub position: usize,
+    pub drift: Drift,
+    pub action: GuardAction,
+}
+
+impl fmt::Display for Guarded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let action = match self.action {
+            GuardAction::Reenhanced => "enhanced again",
+            GuardAction::KeptOriginal => "kept the original",
+        };
+        write!(
+            f,
+            "{} (segment {}): {}; {}",
+            self.path.display(),
+            self.position + 1,
+            self.drift,
+            action
+        )
+    }
+}
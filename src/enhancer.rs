@@ -1,7 +1,11 @@
+use crate::chunking::chunk_text;
 use crate::client::{ClientError, LlmClient};
-use crate::types::{EnhancedSegment, OutputFormat, Segment};
-use std::path::PathBuf;
+use crate::types::{EnhanceLevel, EnhancedSegment, OutputFormat, Segment};
+use futures::future::join_all;
+use std::path::Path;
 use thiserror::Error;
+use tokio::sync::Semaphore;
+use tracing::{debug, warn};
 
 #[derive(Error, Debug)]
 pub enum EnhancementError {
@@ -9,31 +13,19 @@ pub enum EnhancementError {
     Client(#[from] ClientError),
 }
 
-fn get_enhancement_system_prompt(format: OutputFormat) -> String {
-    let format_instructions = match format {
-        OutputFormat::Markdown => {
-            r#"Format: Markdown
-- Use proper markdown headers (##, ###) for sections
-- Use LaTeX for equations: inline $equation$ or block $$equation$$
-- Use bullet points and numbered lists appropriately
-- Use code blocks with language hints when showing code
-- Use **bold** and *italic* for emphasis"#
-        }
-        OutputFormat::Plain => {
-            r#"Format: Plain text
-- Use simple text headers with underlines or caps
-- Use ASCII for equations (e.g., x^2 + y^2 = r^2)
-- Use simple - or * for bullet points
-- Keep formatting minimal but readable"#
-        }
-    };
-
-    format!(
-        r#"You are a note enhancement assistant. Your job is to improve and enrich notes while preserving their meaning.
+const LIGHT_INSTRUCTIONS: &str = r#"Enhancement tasks:
+1. Fix typos, spelling errors, and grammatical issues
+2. Fix broken or inconsistent formatting (headers, lists, code blocks, equations)
 
-{}
+Rules:
+- Do NOT add, remove or reorder content
+- Do NOT answer "?" markers, add equations or suggest resources; leave questions as they are
+- Do NOT use emojis
+- Keep the original wording wherever it is already correct
+- Preserve all links and references from the original
+- Output ONLY the corrected note content, no meta-commentary"#;
 
-Enhancement tasks:
+const STANDARD_INSTRUCTIONS: &str = r#"Enhancement tasks:
 1. Fix typos, spelling errors, and grammatical issues
 2. For any "?" markers (indicating questions the user had):
    - Provide helpful direction or answer
@@ -50,37 +42,376 @@ Rules:
 - Preserve all links and references from the original
 - Keep the same general structure/organization
 - Be concise but complete
-- Output ONLY the enhanced note content, no meta-commentary"#,
-        format_instructions
-    )
+- Output ONLY the enhanced note content, no meta-commentary"#;
+
+const AGGRESSIVE_INSTRUCTIONS: &str = r#"Enhancement tasks:
+1. Fix typos, spelling errors, and grammatical issues
+2. For any "?" markers (indicating questions the user had):
+   - Answer them fully, with derivations or examples where they help
+   - Preserve that it was originally a question using format: "[Q: original question] Your answer here"
+3. Add missing equations, definitions and short worked examples relevant to the topic
+4. Suggest 2-4 relevant resources (books, papers, links)
+5. Restructure freely: reorder, regroup and add headers so the note reads as a coherent reference
+6. Compress verbose sections while keeping essential details
+
+Rules:
+- Do NOT add unrelated information
+- Do NOT remove important details
+- Do NOT use emojis
+- Preserve all links and references from the original
+- Be thorough but concise
+- Output ONLY the enhanced note content, no meta-commentary"#;
+
+/// Tells the model what the placeholders for code blocks are
+const CODE_INSTRUCTION: &str = "Lines like ⟦code 1⟧ stand for the note's code blocks, which are put back unchanged afterwards. Keep each one exactly once, on a line of its own, where the code belongs; do not wrap it in a code block or write the code out.";
+
+/// System prompt for enhancing notes in an output format at an enhancement level,
+/// with the category's extra instructions at the end
+pub fn get_enhancement_system_prompt(
+    format: OutputFormat,
+    level: EnhanceLevel,
+    category_instructions: Option<&str>,
+    language_instruction: Option<&str>,
+    profile: Option<&str>,
+) -> String {
+    let format_instructions = match format {
+        OutputFormat::Markdown | OutputFormat::Html => {
+            r#"Format: Markdown
+- Use proper markdown headers (##, ###) for sections
+- Use LaTeX for equations: inline $equation$ or block $$equation$$
+- Use bullet points and numbered lists appropriately
+- Use code blocks with language hints when showing code
+- Use **bold** and *italic* for emphasis"#
+        }
+        OutputFormat::Obsidian => {
+            r#"Format: Obsidian Markdown
+- Use proper markdown headers (##, ###) for sections
+- Use LaTeX for equations: inline $equation$ or block $$equation$$
+- Use bullet points and numbered lists appropriately
+- Use code blocks with language hints when showing code
+- Use **bold** and *italic* for emphasis
+- Do NOT add YAML frontmatter or #tags, they are generated automatically"#
+        }
+        OutputFormat::Logseq => {
+            r#"Format: Markdown for a Logseq outliner
+- Use markdown headers (##, ###) for sections; they become parent blocks
+- Keep paragraphs short, each one becomes its own block
+- Use LaTeX for equations: inline $equation$ or block $$equation$$
+- Use bullet points for lists, nested with two spaces
+- Use code blocks with language hints when showing code
+- Do NOT add frontmatter or page properties, they are generated automatically"#
+        }
+        OutputFormat::Plain => {
+            r#"Format: Plain text
+- Use simple text headers with underlines or caps
+- Use ASCII for equations (e.g., x^2 + y^2 = r^2)
+- Use simple - or * for bullet points
+- Keep formatting minimal but readable"#
+        }
+    };
+
+    let instructions = match level {
+        // `Off` never reaches the LLM
+        EnhanceLevel::Off | EnhanceLevel::Light => LIGHT_INSTRUCTIONS,
+        EnhanceLevel::Standard => STANDARD_INSTRUCTIONS,
+        EnhanceLevel::Aggressive => AGGRESSIVE_INSTRUCTIONS,
+    };
+
+    // Stable parts first, so servers can reuse the cached prefix across notes
+    let mut prompt = format!(
+        "You are a note enhancement assistant. Your job is to improve and enrich notes while preserving their meaning.\n\n{}\n\n{}\n\n{}",
+        format_instructions, instructions, CODE_INSTRUCTION
+    );
+    if let Some(profile) = profile {
+        prompt.push_str(
+            "\n\nAbout the user whose notes these are (match their level, notation and vocabulary):\n",
+        );
+        prompt.push_str(profile);
+    }
+    if let Some(extra) = category_instructions {
+        prompt.push_str("\n\nInstructions for notes in this category:\n");
+        prompt.push_str(extra);
+    }
+    if let Some(language) = language_instruction {
+        prompt.push_str("\n\n");
+        prompt.push_str(language);
+    }
+    prompt
 }
 
 pub async fn enhance_segment(
     client: &LlmClient,
     segment: &Segment,
-    original_path: &PathBuf,
-    format: OutputFormat,
+    original_path: &Path,
+    system_prompt: &str,
 ) -> Result<EnhancedSegment, EnhancementError> {
-    let system_prompt = get_enhancement_system_prompt(format);
-
+    let (masked, blocks) = mask_code(&segment.content);
     let user_prompt = format!(
         "Category: {} ({})\n\nOriginal note segment:\n{}",
         segment.category,
         segment.subcategory.as_deref().unwrap_or("general"),
-        segment.content
+        masked
     );
 
-    let enhanced_content = client.chat(&system_prompt, &user_prompt).await?;
+    let enhanced_content = client.chat(system_prompt, &user_prompt).await?;
+    let enhanced_content = restore_code(&enhanced_content, &blocks, original_path);
+
+    Ok(build_enhanced(segment, original_path, enhanced_content))
+}
+
+const STITCH_SYSTEM_PROMPT: &str = r#"You are a note editor. You are given consecutive parts of ONE note that were enhanced separately. Combine them into a single coherent note.
+
+Rules:
+- Keep ALL content from every part, in order
+- Remove duplicated headers, repeated introductions and overlapping sentences at part boundaries
+- Smooth transitions between parts
+- Keep the existing formatting style
+- Keep lines like ⟦code 1⟧ exactly as written; they stand for code blocks
+- Do NOT add new information
+- Output ONLY the combined note content, no meta-commentary"#;
+
+/// Enhance a segment too long for one request: split it, enhance the parts in
+/// parallel, then stitch them back together with a final call.
+///
+/// Each LLM call takes its own permit from `semaphore`. If stitching fails the
+/// enhanced parts are joined as-is.
+pub async fn enhance_long_segment(
+    client: &LlmClient,
+    semaphore: &Semaphore,
+    segment: &Segment,
+    original_path: &Path,
+    system_prompt: &str,
+    max_tokens: usize,
+    overlap_tokens: usize,
+) -> Result<EnhancedSegment, EnhancementError> {
+    let (masked, blocks) = mask_code(&segment.content);
+    let chunks = chunk_text(&masked, max_tokens, overlap_tokens);
+    debug!(
+        "Enhancing long segment from {} in {} parts",
+        original_path.display(),
+        chunks.len()
+    );
+
+    let parts = join_all(chunks.iter().enumerate().map(|(i, chunk)| {
+        let context = if chunk.context.is_empty() {
+            String::new()
+        } else {
+            format!(
+                "Preceding text (for reference only, do NOT include it in your output):\n{}\n\n",
+                chunk.context
+            )
+        };
+        let user_prompt = format!(
+            "Category: {} ({})\n\n{}Original note segment (part {} of {}):\n{}",
+            segment.category,
+            segment.subcategory.as_deref().unwrap_or("general"),
+            context,
+            i + 1,
+            chunks.len(),
+            chunk.body
+        );
+
+        async move {
+            let _permit = semaphore.acquire().await.unwrap();
+            client.chat(system_prompt, &user_prompt).await
+        }
+    }))
+    .await
+    .into_iter()
+    .collect::<Result<Vec<_>, _>>()?;
+
+    let numbered: Vec<String> = parts
+        .iter()
+        .enumerate()
+        .map(|(i, part)| format!("=== Part {} ===\n{}", i + 1, part))
+        .collect();
+
+    let stitched = {
+        let _permit = semaphore.acquire().await.unwrap();
+        client
+            .chat(STITCH_SYSTEM_PROMPT, &numbered.join("\n\n"))
+            .await
+    };
+
+    let content = match stitched {
+        Ok(content) => content,
+        Err(e) => {
+            warn!(
+                "Stitching failed for {}, joining parts: {}",
+                original_path.display(),
+                e
+            );
+            parts.join("\n\n")
+        }
+    };
+    let content = restore_code(&content, &blocks, original_path);
+
+    Ok(build_enhanced(segment, original_path, content))
+}
+
+const SUMMARY_SYSTEM_PROMPT: &str = r#"You are a note summarizer. You are given a note assembled from several sections. Write an overview of it in 3 to 5 sentences so a reader can tell at a glance what it covers.
+
+Rules:
+- Cover the main topics of all sections, in the order they appear
+- Do NOT add information that is not in the note
+- Write in the language of the note
+- Do NOT use emojis, headers or lists
+- Output ONLY the summary text, no meta-commentary"#;
+
+/// A 3-5 sentence overview of a file assembled from several segments.
+///
+/// Only the first `max_tokens` of a very long file are sent.
+pub async fn summarize_file(
+    client: &LlmClient,
+    segments: &[EnhancedSegment],
+    max_tokens: usize,
+) -> Result<String, EnhancementError> {
+    let content = segments
+        .iter()
+        .map(|s| s.content.as_str())
+        .collect::<Vec<_>>()
+        .join("\n\n---\n\n");
+    let excerpt = chunk_text(&content, max_tokens, 0)
+        .into_iter()
+        .next()
+        .map(|chunk| chunk.body)
+        .unwrap_or(content);
+    let summary = client.chat(SUMMARY_SYSTEM_PROMPT, &excerpt).await?;
+    Ok(summary.trim().to_string())
+}
+
+/// Put a summary at the top of the first segment of a file
+pub fn prepend_summary(segment: &mut EnhancedSegment, summary: &str, format: OutputFormat) {
+    let heading = match format {
+        OutputFormat::Plain => "Summary\n=======",
+        _ => "## Summary",
+    };
+    segment.content = format!("{}\n\n{}\n\n{}", heading, summary, segment.content);
+}
+
+/// Replace every fenced code block with a `⟦code N⟧` line, so the model
+/// cannot change code. Returns the masked text and the blocks, in order.
+fn mask_code(content: &str) -> (String, Vec<String>) {
+    let mut blocks = Vec::new();
+    let mut out = String::with_capacity(content.len());
+    let mut lines = content.split_inclusive('\n');
+
+    while let Some(line) = lines.next() {
+        let trimmed = line.trim_start();
+        let Some(fence) = ["```", "~~~"].into_iter().find(|f| trimmed.starts_with(f)) else {
+            out.push_str(line);
+            continue;
+        };
+        let mut block = line.to_string();
+        for next in lines.by_ref() {
+            block.push_str(next);
+            if next.trim_start().starts_with(fence) {
+                break;
+            }
+        }
+        blocks.push(block.trim_end_matches('\n').to_string());
+        let indent = &line[..line.len() - trimmed.len()];
+        out.push_str(&format!("{}⟦code {}⟧", indent, blocks.len()));
+        if block.ends_with('\n') {
+            out.push('\n');
+        }
+    }
+    (out, blocks)
+}
+
+/// Put masked code blocks back verbatim. A fence the model wrapped around a
+/// placeholder is dropped, and blocks whose placeholder it lost are added at
+/// the end, so no code is ever changed or dropped.
+fn restore_code(content: &str, blocks: &[String], original_path: &Path) -> String {
+    if blocks.is_empty() {
+        return content.to_string();
+    }
+    let placeholder = |line: &str| {
+        line.trim()
+            .strip_prefix("⟦code ")?
+            .strip_suffix('⟧')?
+            .parse::<usize>()
+            .ok()
+            .filter(|n| (1..=blocks.len()).contains(n))
+    };
+    let is_fence = |line: &str| line.trim_start().starts_with("```");
+
+    let lines: Vec<&str> = content.lines().collect();
+    let mut restored = vec![false; blocks.len()];
+    let mut out: Vec<String> = Vec::with_capacity(lines.len());
+    let mut skip_fence = false;
+    // Whether the last line in `out` is the model's previous line
+    let mut copied = false;
+    for (i, line) in lines.iter().enumerate() {
+        if std::mem::take(&mut skip_fence) {
+            copied = false;
+            continue;
+        }
+        let Some(n) = placeholder(line) else {
+            out.push(line.to_string());
+            copied = true;
+            continue;
+        };
+        let wrapped =
+            copied && is_fence(lines[i - 1]) && lines.get(i + 1).is_some_and(|l| l.trim() == "```");
+        if wrapped {
+            out.pop();
+            skip_fence = true;
+        }
+        copied = false;
+        if !restored[n - 1] {
+            restored[n - 1] = true;
+            out.push(blocks[n - 1].clone());
+        }
+    }
+
+    let mut content = out.join("\n");
+    let mut dropped = 0;
+    for (i, block) in blocks.iter().enumerate().filter(|(i, _)| !restored[*i]) {
+        let placeholder = format!("⟦code {}⟧", i + 1);
+        content = match content.find(&placeholder) {
+            // Written inside a line: give the block lines of its own
+            Some(_) => content.replacen(&placeholder, &format!("\n{}\n", block), 1),
+            None => {
+                dropped += 1;
+                format!("{}\n\n{}", content.trim_end(), block)
+            }
+        };
+    }
+    if dropped > 0 {
+        warn!(
+            "Enhancement of a segment from {} dropped {} code blocks; added them back at the end",
+            original_path.display(),
+            dropped
+        );
+    }
+    content
+}
+
+/// A segment written as-is, without enhancement
+pub fn keep_original(segment: &Segment, original_path: &Path) -> EnhancedSegment {
+    build_enhanced(segment, original_path, segment.content.clone())
+}
 
+/// Wrap enhanced content with the segment's metadata
+fn build_enhanced(segment: &Segment, original_path: &Path, content: String) -> EnhancedSegment {
     // Combine primary paths with cross-file paths
     let mut all_paths = segment.paths.clone();
     all_paths.extend(segment.cross_file_to.clone());
 
-    Ok(EnhancedSegment {
-        original_path: original_path.clone(),
-        content: enhanced_content,
+    EnhancedSegment {
+        original_path: original_path.to_path_buf(),
+        content,
         category: segment.category.clone(),
         subcategory: segment.subcategory.clone(),
         output_paths: all_paths,
-    })
+        position: 0,
+        lines: segment.lines,
+        duplicates: segment.duplicates.clone(),
+        tags: segment.source.tags.clone(),
+        source: segment.source.clone(),
+        cards: Vec::new(),
+        missing_facts: Vec::new(),
+        changes: None,
+    }
 }
@@ -0,0 +1,105 @@
+use crate::failures::Stage;
+use crate::types::{ProgressMode, ReorgResponse};
+use serde::Serialize;
+use std::io::Write;
+use std::path::Path;
+
+/// One line of `--progress json` output
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    PhaseStarted {
+        phase: u8,
+        name: &'a str,
+    },
+    NoteCategorized {
+        path: &'a Path,
+        segments: usize,
+    },
+    SegmentEnhanced {
+        path: &'a Path,
+        category: String,
+    },
+    /// Where a segment would go, in a `--dry-run`
+    SegmentPlanned {
+        path: &'a Path,
+        category: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        subcategory: Option<&'a str>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        confidence: Option<f32>,
+        paths: &'a [String],
+    },
+    FileWritten {
+        path: &'a Path,
+    },
+    FileSkipped {
+        path: &'a Path,
+        reason: String,
+    },
+    /// The reorganization pass's plan, and where it was saved in a dry run
+    ReorgPlanned {
+        root: &'a Path,
+        plan: &'a ReorgResponse,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        saved: Option<&'a Path>,
+    },
+    /// A cross-reference found; `applied` is false in a dry run
+    CrossReference {
+        root: &'a Path,
+        from: &'a str,
+        to: &'a str,
+        context: &'a str,
+        applied: bool,
+    },
+    Error {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        stage: Option<Stage>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        path: Option<&'a Path>,
+        message: String,
+    },
+    Finished {
+        files: usize,
+        failures: usize,
+    },
+}
+
+/// Writes progress events to stdout as newline-delimited JSON, when enabled
+#[derive(Debug, Clone, Copy)]
+pub struct Events {
+    enabled: bool,
+}
+
+impl Events {
+    pub fn new(mode: ProgressMode) -> Self {
+        Self {
+            enabled: matches!(mode, ProgressMode::Json),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Print text meant for people: to stdout, or to stderr when stdout
+    /// carries the events
+    pub fn print(&self, text: &str) {
+        if self.enabled {
+            eprintln!("{}", text);
+        } else {
+            println!("{}", text);
+        }
+    }
+
+    pub fn emit(&self, event: Event) {
+        if !self.enabled {
+            return;
+        }
+        if let Ok(line) = serde_json::to_string(&event) {
+            let mut out = std::io::stdout().lock();
+            let _ = writeln!(out, "{}", line);
+            let _ = out.flush();
+        }
+    }
+}
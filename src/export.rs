@@ -0,0 +1,100 @@
+use crate::types::{
+    Category, CrossReference, EnhancedSegment, LineRange, OutputFormat, ReviewCard,
+};
+use crate::writer::segment_output_path;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// One line of the JSONL export
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportRecord {
+    pub source_path: PathBuf,
+    pub content: String,
+    pub category: Category,
+    #[serde(default)]
+    pub subcategory: Option<String>,
+    /// Lines of the source note the segment came from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lines: Option<LineRange>,
+    /// Other notes the content was also found in
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub duplicates: Vec<PathBuf>,
+    pub output_paths: Vec<String>,
+    #[serde(default)]
+    pub cross_refs: Vec<CrossReference>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cards: Vec<ReviewCard>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub missing_facts: Vec<String>,
+}
+
+/// Export every enhanced segment as one JSON object per line
+pub fn export_jsonl(
+    path: &Path,
+    segments: &[EnhancedSegment],
+    cross_refs: &[CrossReference],
+    format: OutputFormat,
+) -> Result<usize, std::io::Error> {
+    if let Some(parent) = path.parent() {
+        if !parent.as_os_str().is_empty() {
+            std::fs::create_dir_all(parent)?;
+        }
+    }
+
+    let mut out = BufWriter::new(File::create(path)?);
+
+    for segment in segments {
+        let output_paths: Vec<String> = segment
+            .output_paths
+            .iter()
+            .map(|p| segment_output_path(p, segment, format))
+            .collect();
+
+        // A segment carries the references of every file it was written to
+        let refs = cross_refs
+            .iter()
+            .filter(|r| output_paths.contains(&r.from_file))
+            .cloned()
+            .collect();
+
+        let record = ExportRecord {
+            source_path: segment.original_path.clone(),
+            content: segment.content.clone(),
+            category: segment.category.clone(),
+            subcategory: segment.subcategory.clone(),
+            lines: segment.lines,
+            duplicates: segment.duplicates.clone(),
+            output_paths,
+            cross_refs: refs,
+            tags: segment.tags.clone(),
+            cards: segment.cards.clone(),
+            missing_facts: segment.missing_facts.clone(),
+        };
+
+        serde_json::to_writer(&mut out, &record)?;
+        out.write_all(b"\n")?;
+    }
+
+    out.flush()?;
+    Ok(segments.len())
+}
+
+/// Read records back from a JSONL export
+pub fn read_jsonl(path: &Path) -> Result<Vec<ExportRecord>, std::io::Error> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut records = Vec::new();
+
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        records.push(serde_json::from_str(&line)?);
+    }
+
+    Ok(records)
+}
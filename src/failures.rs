@@ -0,0 +1,161 @@
+use crate::types::Segment;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use thiserror::Error;
+
+/// File name of the report written to the output directory
+pub const REPORT_FILE: &str = "failures.json";
+
+#[derive(Error, Debug)]
+pub enum ReportError {
+    #[error("could not read failure report {0}: {1}")]
+    Io(PathBuf, std::io::Error),
+    #[error("invalid failure report {0}: {1}")]
+    Parse(PathBuf, serde_json::Error),
+}
+
+/// Pipeline step an item failed in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    Read,
+    Transcribe,
+    Categorize,
+    Enhance,
+}
+
+impl Stage {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Stage::Read => "read",
+            Stage::Transcribe => "transcribe",
+            Stage::Categorize => "categorize",
+            Stage::Enhance => "enhance",
+        }
+    }
+}
+
+/// One note or segment that could not be processed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Failure {
+    pub stage: Stage,
+    pub path: PathBuf,
+    /// The segment itself, for enhancement failures
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub segment: Option<Segment>,
+    pub error: String,
+}
+
+/// Failures collected over a run
+#[derive(Debug, Default)]
+pub struct FailureLog {
+    failures: Mutex<Vec<Failure>>,
+}
+
+impl FailureLog {
+    /// Record a failed note
+    pub fn note(&self, stage: Stage, path: &Path, error: impl ToString) {
+        self.push(Failure {
+            stage,
+            path: path.to_path_buf(),
+            segment: None,
+            error: error.to_string(),
+        });
+    }
+
+    /// Record a segment that failed enhancement
+    pub fn segment(&self, path: &Path, segment: &Segment, error: impl ToString) {
+        self.push(Failure {
+            stage: Stage::Enhance,
+            path: path.to_path_buf(),
+            segment: Some(segment.clone()),
+            error: error.to_string(),
+        });
+    }
+
+    pub fn len(&self) -> usize {
+        self.failures.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.failures.lock().unwrap().is_empty()
+    }
+
+    /// The earliest recorded failure
+    pub fn first(&self) -> Option<Failure> {
+        self.failures.lock().unwrap().first().cloned()
+    }
+
+    /// Every failure recorded so far
+    pub fn all(&self) -> Vec<Failure> {
+        self.failures.lock().unwrap().clone()
+    }
+
+    /// Number of failures per stage
+    pub fn counts(&self) -> BTreeMap<Stage, usize> {
+        let mut counts = BTreeMap::new();
+        for failure in self.failures.lock().unwrap().iter() {
+            *counts.entry(failure.stage).or_default() += 1;
+        }
+        counts
+    }
+
+    fn push(&self, failure: Failure) {
+        self.failures.lock().unwrap().push(failure);
+    }
+
+    /// Write the report to `path`, or remove a stale one if nothing failed.
+    ///
+    /// Returns the number of failures written.
+    pub fn save(&self, path: &Path) -> Result<usize, std::io::Error> {
+        let mut failures = self.failures.lock().unwrap().clone();
+        if failures.is_empty() {
+            if path.exists() {
+                std::fs::remove_file(path)?;
+            }
+            return Ok(0);
+        }
+
+        failures.sort_by(|a, b| a.path.cmp(&b.path));
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_string_pretty(&failures)?)?;
+        Ok(failures.len())
+    }
+}
+
+/// A report from an earlier run, loaded for `--retry-failed`
+#[derive(Debug, Clone)]
+pub struct RetryPlan {
+    /// Notes to read and categorize again
+    pub notes: HashSet<PathBuf>,
+    /// Segments to enhance again, without re-categorizing their note
+    pub segments: Vec<(PathBuf, Segment)>,
+}
+
+impl RetryPlan {
+    pub fn load(path: &Path) -> Result<Self, ReportError> {
+        let text =
+            std::fs::read_to_string(path).map_err(|e| ReportError::Io(path.to_path_buf(), e))?;
+        let failures: Vec<Failure> =
+            serde_json::from_str(&text).map_err(|e| ReportError::Parse(path.to_path_buf(), e))?;
+
+        let mut notes = HashSet::new();
+        let mut segments = Vec::new();
+        for failure in failures {
+            match failure.segment {
+                Some(segment) => segments.push((failure.path, segment)),
+                None => {
+                    // Canonical so it matches however the walk spells the path
+                    let path = failure.path.canonicalize().unwrap_or(failure.path);
+                    notes.insert(path);
+                }
+            }
+        }
+
+        Ok(Self { notes, segments })
+    }
+}
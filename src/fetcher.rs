@@ -0,0 +1,136 @@
+use crate::client::{ClientError, LlmClient};
+use crate::http::HttpSettings;
+use crate::readers::{html_to_markdown, ReaderError};
+use std::time::Duration;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum FetchError {
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("Domain not in allowlist: {0}")]
+    NotAllowed(String),
+    #[error("Unsupported content type: {0}")]
+    ContentType(String),
+    #[error("Could not extract content: {0}")]
+    Extract(#[from] ReaderError),
+    #[error("LLM client error: {0}")]
+    Client(#[from] ClientError),
+}
+
+const SUMMARIZE_SYSTEM_PROMPT: &str = r#"You are a research assistant. Given the main content of a web page, write a concise summary for a personal knowledge base.
+
+Rules:
+- 3-6 sentences, or a short bullet list for reference material
+- Capture what the page is, its key points, and why it might be useful
+- Do NOT invent information that is not in the page
+- Do NOT use emojis
+- Output ONLY the summary, no meta-commentary"#;
+
+/// Maximum characters of page content sent to the LLM
+const MAX_PAGE_CHARS: usize = 12_000;
+
+/// Find http(s) URLs in a block of text, in order and without duplicates
+pub fn extract_urls(text: &str) -> Vec<String> {
+    let mut urls: Vec<String> = Vec::new();
+    let mut rest = text;
+
+    while let Some(pos) = ["http://", "https://"]
+        .iter()
+        .filter_map(|scheme| rest.find(scheme))
+        .min()
+    {
+        let candidate = &rest[pos..];
+        let end = candidate
+            .find(|c: char| c.is_whitespace() || matches!(c, ')' | '>' | ']' | '"' | '\'' | '<'))
+            .unwrap_or(candidate.len());
+        let url = candidate[..end].trim_end_matches(['.', ',', ';', ':', '!', '?']);
+        if !urls.iter().any(|u| u == url) {
+            urls.push(url.to_string());
+        }
+        rest = &candidate[end..];
+    }
+
+    urls
+}
+
+/// Host part of a URL, lower-cased
+fn host(url: &str) -> Option<String> {
+    let after_scheme = url.split_once("://")?.1;
+    let authority = after_scheme.split(['/', '?', '#']).next()?;
+    let host = authority.rsplit('@').next()?.split(':').next()?;
+    Some(host.to_lowercase())
+}
+
+/// Fetches linked pages and summarizes them
+#[derive(Clone)]
+pub struct LinkExpander {
+    http: reqwest::Client,
+    allowed_domains: Vec<String>,
+}
+
+impl LinkExpander {
+    pub fn new(
+        timeout: Duration,
+        allowed_domains: &[String],
+        settings: &HttpSettings,
+    ) -> Result<Self, FetchError> {
+        let http = settings
+            .builder()
+            .timeout(timeout)
+            .user_agent(concat!("notex/", env!("CARGO_PKG_VERSION")))
+            .build()?;
+
+        Ok(Self {
+            http,
+            allowed_domains: allowed_domains.iter().map(|d| d.to_lowercase()).collect(),
+        })
+    }
+
+    /// Whether a URL's domain (or a parent domain) is allowlisted; an empty list allows all
+    pub fn is_allowed(&self, url: &str) -> bool {
+        if self.allowed_domains.is_empty() {
+            return true;
+        }
+        let Some(host) = host(url) else {
+            return false;
+        };
+        self.allowed_domains
+            .iter()
+            .any(|d| host == *d || host.ends_with(&format!(".{}", d)))
+    }
+
+    /// Fetch a page and return its main content as markdown
+    pub async fn fetch(&self, url: &str) -> Result<String, FetchError> {
+        if !self.is_allowed(url) {
+            return Err(FetchError::NotAllowed(url.to_string()));
+        }
+
+        let response = self.http.get(url).send().await?.error_for_status()?;
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("text/html")
+            .to_string();
+        let body = response.text().await?;
+
+        if content_type.contains("html") {
+            Ok(html_to_markdown(&body)?)
+        } else if content_type.starts_with("text/") {
+            Ok(body)
+        } else {
+            Err(FetchError::ContentType(content_type))
+        }
+    }
+
+    /// Fetch a page and have the LLM summarize it
+    pub async fn summarize(&self, client: &LlmClient, url: &str) -> Result<String, FetchError> {
+        let page = self.fetch(url).await?;
+        let page: String = page.chars().take(MAX_PAGE_CHARS).collect();
+
+        let user_prompt = format!("URL: {}\n\nPage content:\n{}", url, page);
+        let summary = client.chat(SUMMARIZE_SYSTEM_PROMPT, &user_prompt).await?;
+        Ok(summary.trim().to_string())
+    }
+}
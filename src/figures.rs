@@ -0,0 +1,233 @@
+use crate::client::LlmClient;
+use crate::links::{
+    extract_links, is_external, relative_path, replace_target, resolve_relative, LinkKind,
+};
+use crate::ocr::{image_mime, OcrError};
+use crate::types::EnhancedSegment;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::{debug, warn};
+use walkdir::DirEntry;
+
+/// Output folder holding the images, PDFs and other files notes link to
+pub const ATTACHMENTS_DIR: &str = "assets";
+
+/// Whether a walk of the output directory has reached the attachments folder
+pub fn is_attachments(entry: &DirEntry) -> bool {
+    entry.depth() == 1 && entry.file_name() == ATTACHMENTS_DIR
+}
+
+const DESCRIBE_SYSTEM_PROMPT: &str = r#"You are describing a figure embedded in a note for someone who cannot see it. Say what it shows: the kind of figure, its labels, axes, values, equations or code, and what it illustrates.
+
+Rules:
+- 1-4 sentences, in the language of any text in the image
+- Transcribe short text and equations exactly; write equations in LaTeX
+- Do not guess at things you cannot read
+- Output ONLY the description, no meta-commentary"#;
+
+/// Files notes link to, copied to `assets/` when the run is written
+#[derive(Debug, Default)]
+pub struct Attachments {
+    /// Source image to its path under the output directory
+    files: Mutex<BTreeMap<PathBuf, String>>,
+}
+
+impl Attachments {
+    /// Output path for a source image, numbering names that are already taken
+    fn add(&self, source: &Path) -> String {
+        let mut files = self.files.lock().unwrap();
+        if let Some(dest) = files.get(source) {
+            return dest.clone();
+        }
+        let stem = source.file_stem().unwrap_or_default().to_string_lossy();
+        let ext = source.extension().unwrap_or_default().to_string_lossy();
+        let dest = (1..)
+            .map(|n| match n {
+                1 => format!("{}/{}.{}", ATTACHMENTS_DIR, stem, ext),
+                n => format!("{}/{}-{}.{}", ATTACHMENTS_DIR, stem, n, ext),
+            })
+            .find(|dest| !files.values().any(|taken| taken == dest))
+            .unwrap_or_default();
+        files.insert(source.to_path_buf(), dest.clone());
+        dest
+    }
+
+    /// Copy every attachment into the output directory, returning how many were copied
+    pub fn copy(&self, output_dir: &Path) -> io::Result<usize> {
+        let files = self.files.lock().unwrap();
+        for (source, dest) in files.iter() {
+            let dest = output_dir.join(dest);
+            if let Some(parent) = dest.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::copy(source, dest)?;
+        }
+        Ok(files.len())
+    }
+}
+
+/// A local file a note links to or embeds, other than another note
+struct LocalFile {
+    target: String,
+    kind: LinkKind,
+    path: PathBuf,
+}
+
+/// Files a note links to that exist on disk, each once
+fn local_files(input_dir: &Path, note: &Path, content: &str) -> Vec<LocalFile> {
+    let note_rel = note
+        .strip_prefix(input_dir)
+        .unwrap_or(note)
+        .to_string_lossy()
+        .replace('\\', "/");
+
+    let mut files: Vec<LocalFile> = Vec::new();
+    for link in extract_links(content) {
+        if is_external(&link.target) || files.iter().any(|f| f.target == link.target) {
+            continue;
+        }
+        let target = link
+            .target
+            .split('#')
+            .next()
+            .unwrap_or("")
+            .replace("%20", " ");
+        let ext = target.rsplit_once('.').map(|(_, ext)| ext.to_lowercase());
+        // Links to other notes are not attachments
+        if matches!(ext.as_deref(), None | Some("md" | "markdown")) {
+            continue;
+        }
+
+        // Obsidian embeds are found by name: next to the note, then at the root
+        let mut candidates: Vec<String> =
+            resolve_relative(&note_rel, &target).into_iter().collect();
+        // Only names inside the input directory; `resolve_relative` refuses
+        // targets that climb out of it
+        if link.kind == LinkKind::Wiki
+            && !target.starts_with('/')
+            && !Path::new(&target).is_absolute()
+        {
+            candidates.extend(resolve_relative("", &target));
+        }
+        let Some(path) = candidates
+            .iter()
+            .map(|c| input_dir.join(c))
+            .find(|p| p.is_file())
+        else {
+            debug!("Attachment {} in {} not found", link.target, note.display());
+            continue;
+        };
+        files.push(LocalFile {
+            target: link.target,
+            kind: link.kind,
+            path,
+        });
+    }
+    files
+}
+
+/// Point a note's links to local files at their copies in `assets/`, which
+/// are made when the run is written
+pub fn attach(attachments: &Attachments, input_dir: &Path, note: &Path, content: String) -> String {
+    let mut content = content;
+    for file in local_files(input_dir, note, &content) {
+        let dest = attachments.add(&file.path);
+        // Keep fragments such as `#page=3`
+        let fragment = file.target.find('#').map_or("", |i| &file.target[i..]);
+        let new_target = match file.kind {
+            LinkKind::Wiki => dest.rsplit('/').next().unwrap_or(&dest),
+            LinkKind::Markdown | LinkKind::Html => &dest,
+        };
+        content = replace_target(
+            &content,
+            file.kind,
+            &file.target,
+            &format!("{}{}", new_target, fragment),
+        );
+    }
+    content
+}
+
+/// Describe the images a note embeds with the vision model and add each
+/// description as a `[Figure: ...]` line under the image
+pub async fn describe_figures(
+    client: &LlmClient,
+    input_dir: &Path,
+    note: &Path,
+    content: String,
+    max_bytes: u64,
+) -> String {
+    let mut content = content;
+    for file in local_files(input_dir, note, &content) {
+        let ext = file.path.extension().unwrap_or_default().to_string_lossy();
+        let Some(mime) = image_mime(&ext.to_lowercase()) else {
+            continue;
+        };
+        let size = fs::metadata(&file.path).map(|m| m.len()).unwrap_or(0);
+        if size > max_bytes {
+            debug!("Not describing {}: too large", file.path.display());
+            continue;
+        }
+        match describe(client, &file.path, mime).await {
+            Ok(description) if !description.is_empty() => {
+                content = insert_description(&content, &file.target, &description);
+            }
+            Ok(_) => {}
+            Err(e) => warn!("Could not describe {}: {}", file.path.display(), e),
+        }
+    }
+    content
+}
+
+async fn describe(client: &LlmClient, path: &Path, mime: &str) -> Result<String, OcrError> {
+    let image = tokio::fs::read(path).await?;
+    let user_prompt = format!(
+        "Describe this figure (file: {}).",
+        path.file_name().unwrap_or_default().to_string_lossy()
+    );
+    let description = client
+        .chat_with_image(DESCRIBE_SYSTEM_PROMPT, &user_prompt, &image, mime)
+        .await?;
+    Ok(description.split_whitespace().collect::<Vec<_>>().join(" "))
+}
+
+/// Add a `[Figure: ...]` line after the first line referencing `target`,
+/// indented like it so lists stay intact
+fn insert_description(content: &str, target: &str, description: &str) -> String {
+    let mut out = String::with_capacity(content.len() + description.len() + 16);
+    let mut inserted = false;
+    for line in content.split_inclusive('\n') {
+        out.push_str(line);
+        if !inserted && line.contains(target) {
+            if !line.ends_with('\n') {
+                out.push('\n');
+            }
+            let indent = &line[..line.len() - line.trim_start().len()];
+            out.push_str(&format!("{}[Figure: {}]\n", indent, description));
+            inserted = true;
+        }
+    }
+    out
+}
+
+/// Point `assets/` links at the right place from the file they
+/// end up in
+pub fn relink(segments: Vec<EnhancedSegment>, file: &str) -> Vec<EnhancedSegment> {
+    let prefix = format!("{}/", ATTACHMENTS_DIR);
+    segments
+        .into_iter()
+        .map(|mut segment| {
+            for link in extract_links(&segment.content) {
+                if link.kind != LinkKind::Wiki && link.target.starts_with(&prefix) {
+                    let relative = relative_path(file, &link.target);
+                    segment.content =
+                        replace_target(&segment.content, link.kind, &link.target, &relative);
+                }
+            }
+            segment
+        })
+        .collect()
+}
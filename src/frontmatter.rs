@@ -0,0 +1,201 @@
+use serde::{Deserialize, Serialize};
+use serde_yaml::{Mapping, Value};
+use tracing::warn;
+
+/// Split a leading YAML frontmatter block from a note, returning the YAML and the body
+pub fn split(content: &str) -> Option<(&str, &str)> {
+    let rest = content
+        .strip_prefix("---\n")
+        .or_else(|| content.strip_prefix("---\r\n"))?;
+    if let Some(body) = rest.strip_prefix("---\n") {
+        return Some(("", body));
+    }
+    let end = rest.find("\n---\n").or_else(|| rest.find("\n---\r\n"))?;
+    let body = rest[end + 4..].trim_start_matches(['\r', '\n']);
+    Some((&rest[..end], body))
+}
+
+/// Frontmatter keys with dates that are kept in the output, in output order
+const DATE_KEYS: [&str; 4] = ["date", "created", "updated", "modified"];
+
+/// Parse a note's frontmatter, if it has a valid YAML mapping
+pub fn parse(content: &str) -> Option<Mapping> {
+    let (yaml, _) = split(content)?;
+    match serde_yaml::from_str(yaml) {
+        Ok(Value::Mapping(mapping)) => Some(mapping),
+        _ => None,
+    }
+}
+
+/// Take a note's frontmatter off its content, returning the parsed mapping
+/// (empty without one) and the body. Frontmatter that is not a valid YAML
+/// mapping is left in the content.
+pub fn take(content: String) -> (Mapping, String) {
+    match (parse(&content), split(&content)) {
+        (Some(frontmatter), Some((_, body))) => (frontmatter, body.to_string()),
+        _ => (Mapping::new(), content),
+    }
+}
+
+/// Tags listed in frontmatter: a YAML list, or a comma- or space-separated string
+pub fn tags(frontmatter: &Mapping) -> Vec<String> {
+    list(frontmatter, &["tags", "tag"], &[',', ' '])
+        .into_iter()
+        .map(|t| t.trim_start_matches('#').to_string())
+        .filter(|t| !t.is_empty())
+        .collect()
+}
+
+/// Aliases listed in frontmatter: a YAML list or a comma-separated string
+pub fn aliases(frontmatter: &Mapping) -> Vec<String> {
+    list(frontmatter, &["aliases", "alias"], &[','])
+}
+
+/// The first of `keys` holding a YAML list or a string split on `separators`
+fn list(frontmatter: &Mapping, keys: &[&str], separators: &[char]) -> Vec<String> {
+    let items: Vec<String> = match keys.iter().find_map(|key| frontmatter.get(key)) {
+        Some(Value::Sequence(items)) => items
+            .iter()
+            .filter_map(|v| v.as_str())
+            .map(str::to_string)
+            .collect(),
+        Some(Value::String(s)) => s.split(separators).map(str::to_string).collect(),
+        _ => Vec::new(),
+    };
+    items
+        .into_iter()
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+/// A string value from frontmatter
+pub fn string(frontmatter: &Mapping, key: &str) -> Option<String> {
+    frontmatter
+        .get(key)
+        .and_then(Value::as_str)
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+}
+
+/// What a source note's frontmatter says about it that is carried into the
+/// output frontmatter as-is, instead of passing through the LLM
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SourceMeta {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+    /// `date`, `created`, `updated` and `modified` values, by key
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub dates: Vec<(String, String)>,
+}
+
+impl SourceMeta {
+    pub fn new(frontmatter: &Mapping) -> Self {
+        let dates = DATE_KEYS
+            .iter()
+            .filter_map(|key| scalar(frontmatter, key).map(|value| (key.to_string(), value)))
+            .collect();
+        Self {
+            tags: tags(frontmatter),
+            aliases: aliases(frontmatter),
+            dates,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// The metadata of several sources written to one file: aliases of all
+    /// of them, and each date from the first source that has it
+    pub fn merge<'a>(sources: impl IntoIterator<Item = &'a SourceMeta>) -> SourceMeta {
+        let mut merged = SourceMeta::default();
+        for source in sources {
+            for alias in &source.aliases {
+                if !merged.aliases.contains(alias) {
+                    merged.aliases.push(alias.clone());
+                }
+            }
+            for (key, value) in &source.dates {
+                if !merged.dates.iter().any(|(k, _)| k == key) {
+                    merged.dates.push((key.clone(), value.clone()));
+                }
+            }
+        }
+        merged
+            .dates
+            .sort_by_key(|(key, _)| DATE_KEYS.iter().position(|k| k == key));
+        merged
+    }
+
+    /// The `aliases:` list and date fields of a YAML frontmatter block
+    pub fn yaml_fields(&self) -> String {
+        let mut fields = String::new();
+        if !self.aliases.is_empty() {
+            fields.push_str("aliases:\n");
+            for alias in &self.aliases {
+                fields.push_str(&format!("  - {}\n", quoted(alias)));
+            }
+        }
+        for (key, value) in &self.dates {
+            fields.push_str(&format!("{}: {}\n", key, yaml_scalar(value)));
+        }
+        fields
+    }
+}
+
+/// A string, number or date value from frontmatter, as written
+fn scalar(frontmatter: &Mapping, key: &str) -> Option<String> {
+    match frontmatter.get(key)? {
+        Value::Number(n) => Some(n.to_string()),
+        _ => string(frontmatter, key),
+    }
+}
+
+/// A value as a YAML scalar: dates and times unquoted, anything else quoted
+fn yaml_scalar(value: &str) -> String {
+    if value
+        .chars()
+        .all(|c| c.is_ascii_digit() || "-:.TZ+ ".contains(c))
+    {
+        value.to_string()
+    } else {
+        quoted(value)
+    }
+}
+
+/// A double-quoted YAML string, with backslashes, quotes and line breaks escaped
+fn quoted(value: &str) -> String {
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+    format!("\"{}\"", escaped)
+}
+
+/// Routing directives from a note's `notex:` frontmatter key, e.g.
+/// `notex: {category: finance, path: finance/taxes.md, skip_enhance: true}`
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Routing {
+    pub category: Option<String>,
+    pub subcategory: Option<String>,
+    pub path: Option<String>,
+    pub skip_enhance: bool,
+}
+
+/// Routing directives of a note, if its frontmatter has a `notex:` key
+pub fn routing(frontmatter: &Mapping) -> Option<Routing> {
+    let value = frontmatter.get("notex")?.clone();
+    match serde_yaml::from_value(value) {
+        Ok(routing) => Some(routing),
+        Err(e) => {
+            warn!("Ignoring invalid notex frontmatter: {}", e);
+            None
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum GitError {
+    #[error("could not run git: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("git {0} failed: {1}")]
+    Failed(String, String),
+    #[error("{0} is not in a git repository")]
+    NotARepo(String),
+}
+
+/// Run git in `dir` and return its standard output
+fn git(dir: &Path, args: &[&str]) -> Result<String, GitError> {
+    let output = Command::new("git").arg("-C").arg(dir).args(args).output()?;
+    if !output.status.success() {
+        return Err(GitError::Failed(
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Whether `dir` is inside a git work tree
+pub fn is_repo(dir: &Path) -> bool {
+    git(dir, &["rev-parse", "--is-inside-work-tree"]).is_ok_and(|out| out.trim() == "true")
+}
+
+/// Files under `dir` that changed since `reference` (a commit, tag or
+/// branch): committed since, modified in the work tree or new and not
+/// ignored. Deleted files are left out. Paths are canonical.
+pub fn changed_since(dir: &Path, reference: &str) -> Result<HashSet<PathBuf>, GitError> {
+    let changed = git(
+        dir,
+        &[
+            "diff",
+            "--name-only",
+            "--relative",
+            "--diff-filter=d",
+            reference,
+            "--",
+            ".",
+        ],
+    )?;
+    let untracked = git(
+        dir,
+        &["ls-files", "--others", "--exclude-standard", "--", "."],
+    )?;
+
+    Ok(changed
+        .lines()
+        .chain(untracked.lines())
+        .filter(|line| !line.is_empty())
+        .filter_map(|rel| dir.join(rel).canonicalize().ok())
+        .collect())
+}
+
+/// What a run did, for the message of its `--git-commit` commit
+#[derive(Debug)]
+pub struct RunSummary<'a> {
+    pub notes: usize,
+    pub segments: usize,
+    pub written: usize,
+    pub moved: usize,
+    pub model: &'a str,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+impl RunSummary<'_> {
+    fn message(&self) -> String {
+        let mut subject = format!("notex: wrote {} files", self.written);
+        if self.moved > 0 {
+            subject.push_str(&format!(", moved {}", self.moved));
+        }
+        format!(
+            "{}\n\nNotes: {}\nSegments: {}\nModel: {}\nTokens: {} prompt, {} completion\n",
+            subject,
+            self.notes,
+            self.segments,
+            self.model,
+            self.prompt_tokens,
+            self.completion_tokens
+        )
+    }
+}
+
+/// Stage everything under `dir` and commit it with a message summarizing the
+/// run. Returns the short hash of the commit, or None when nothing changed.
+pub fn commit_run(dir: &Path, summary: &RunSummary) -> Result<Option<String>, GitError> {
+    if !is_repo(dir) {
+        return Err(GitError::NotARepo(dir.display().to_string()));
+    }
+    git(dir, &["add", "--all", "--", "."])?;
+    // `diff --quiet` exits with 1 when there are staged changes
+    if git(dir, &["diff", "--cached", "--quiet", "--", "."]).is_ok() {
+        return Ok(None);
+    }
+    git(
+        dir,
+        &["commit", "--quiet", "-m", &summary.message(), "--", "."],
+    )?;
+    let hash = git(dir, &["rev-parse", "--short", "HEAD"])?;
+    Ok(Some(hash.trim().to_string()))
+}
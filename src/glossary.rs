@@ -0,0 +1,165 @@
+use crate::categorizer::extract_json;
+use crate::client::{ClientError, LlmClient};
+use crate::indexer::write_index;
+use crate::tokens::count_tokens;
+use crate::types::{EnhancedSegment, OutputFormat};
+use crate::writer::output_rel_path;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// File name of a category's glossary (before format mapping)
+pub const GLOSSARY_FILE: &str = "glossary.md";
+
+const GLOSSARY_SYSTEM_PROMPT: &str = r#"You are a glossary writer. Given notes from one category, extract the key terms a reader must know and define each one.
+
+Respond with JSON in this exact format:
+{
+  "terms": [
+    {"term": "Eigenvalue", "definition": "A scalar λ for which Av = λv has a non-zero solution v."}
+  ]
+}
+
+Rules:
+- Only terms that are defined, used or explained in the notes
+- One or two sentences per definition, consistent with how the notes use the term
+- Skip common words and names that need no definition
+- Write definitions in the language of the notes
+- Do NOT use emojis"#;
+
+#[derive(Error, Debug)]
+pub enum GlossaryError {
+    #[error("LLM client error: {0}")]
+    Client(#[from] ClientError),
+    #[error("Failed to parse LLM response: {0}")]
+    Parse(#[from] serde_json::Error),
+}
+
+/// A glossary entry
+#[derive(Debug, Clone, Deserialize)]
+pub struct Term {
+    pub term: String,
+    pub definition: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct GlossaryResponse {
+    terms: Vec<Term>,
+}
+
+/// Join a category's segments into texts of at most `max_tokens` each, one request per text
+pub fn batches(segments: &[&EnhancedSegment], max_tokens: usize) -> Vec<String> {
+    let mut batches = Vec::new();
+    let mut current = String::new();
+    let mut tokens = 0;
+    for segment in segments {
+        let size = count_tokens(&segment.content);
+        if tokens > 0 && tokens + size > max_tokens {
+            batches.push(std::mem::take(&mut current));
+            tokens = 0;
+        }
+        current.push_str(&segment.content);
+        current.push_str("\n\n---\n\n");
+        tokens += size;
+    }
+    if !current.is_empty() {
+        batches.push(current);
+    }
+    batches
+}
+
+/// Ask the LLM for the key terms in a batch of notes from a category
+pub async fn extract_terms(
+    client: &LlmClient,
+    category: &str,
+    notes: &str,
+) -> Result<Vec<Term>, GlossaryError> {
+    let user_prompt = format!("Category: {}\n\nNotes:\n{}", category, notes);
+    let response = client
+        .chat_json(GLOSSARY_SYSTEM_PROMPT, &user_prompt)
+        .await?;
+    let response: GlossaryResponse = serde_json::from_str(extract_json(&response))?;
+    Ok(response.terms)
+}
+
+/// Merge new terms into `category/glossary.md`, keeping the definitions already
+/// there. Returns the file and the number of terms added.
+pub fn write_glossary(
+    output_dir: &Path,
+    category: &str,
+    terms: Vec<Term>,
+    format: OutputFormat,
+) -> Result<(PathBuf, usize), std::io::Error> {
+    let rel_path = output_rel_path(&format!("{}/{}", category, GLOSSARY_FILE), format);
+    let path = output_dir.join(&rel_path);
+
+    let mut glossary: BTreeMap<String, Term> = BTreeMap::new();
+    if let Ok(existing) = fs::read_to_string(&path) {
+        for term in read_terms(&existing, format) {
+            glossary.entry(term.term.to_lowercase()).or_insert(term);
+        }
+    }
+    let before = glossary.len();
+    for term in terms {
+        let term = Term {
+            term: term.term.trim().to_string(),
+            definition: term
+                .definition
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" "),
+        };
+        if !term.term.is_empty() && !term.definition.is_empty() {
+            glossary.entry(term.term.to_lowercase()).or_insert(term);
+        }
+    }
+
+    let title = format!("Glossary: {}", category.replace('_', " "));
+    let mut md = format!("# {}\n\n", title);
+    for term in glossary.values() {
+        md.push_str(&format!("- **{}**: {}\n", term.term, term.definition));
+    }
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    write_index(&path, &title, &md, format)?;
+    Ok((path, glossary.len() - before))
+}
+
+/// Entries of a glossary written by an earlier run
+fn read_terms(content: &str, format: OutputFormat) -> Vec<Term> {
+    let (open, close, item) = match format {
+        OutputFormat::Html => ("<strong>", "</strong>: ", "<li>"),
+        _ => ("**", "**: ", "- "),
+    };
+    content
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim().strip_prefix(item)?.strip_prefix(open)?;
+            let (term, definition) = line.split_once(close)?;
+            let definition = definition.strip_suffix("</li>").unwrap_or(definition);
+            Some(match format {
+                OutputFormat::Html => Term {
+                    term: unescape(term),
+                    definition: unescape(definition),
+                },
+                _ => Term {
+                    term: term.to_string(),
+                    definition: definition.to_string(),
+                },
+            })
+        })
+        .collect()
+}
+
+/// Undo the entity escaping of rendered HTML text
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
@@ -0,0 +1,202 @@
+use crate::export::ExportRecord;
+use crate::indexer::collect_indexable;
+use crate::links::{extract_links, resolve_relative, resolve_wikilink, LinkKind};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+/// A note in the graph
+#[derive(Debug, Clone, Serialize)]
+pub struct Node {
+    pub id: String,
+    pub label: String,
+    pub category: String,
+}
+
+/// Why two notes are connected
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum EdgeKind {
+    CrossReference,
+    SharedSource,
+}
+
+/// A connection between two notes
+#[derive(Debug, Clone, Serialize)]
+pub struct Edge {
+    pub source: String,
+    pub target: String,
+    pub kind: EdgeKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+}
+
+/// Notes and their connections
+#[derive(Debug, Clone, Serialize)]
+pub struct Graph {
+    pub nodes: Vec<Node>,
+    pub edges: Vec<Edge>,
+}
+
+/// Build the note graph for an output directory.
+///
+/// Cross-reference edges come from links inside the files (and the export's
+/// cross-refs when given); shared-source edges need the JSONL export, since
+/// provenance is not recorded in the files themselves.
+pub fn build_graph(output_dir: &Path, export: Option<&[ExportRecord]>) -> Graph {
+    let files = collect_indexable(output_dir);
+    let known: BTreeSet<&str> = files.iter().map(|f| f.as_str()).collect();
+
+    let nodes = files
+        .iter()
+        .map(|f| {
+            let name = f.rsplit('/').next().unwrap_or(f);
+            Node {
+                id: f.clone(),
+                label: name
+                    .rsplit_once('.')
+                    .map(|(s, _)| s)
+                    .unwrap_or(name)
+                    .to_string(),
+                category: f.split_once('/').map(|(c, _)| c).unwrap_or("").to_string(),
+            }
+        })
+        .collect();
+
+    // Keyed by (source, target, kind) to drop duplicates
+    let mut edges: BTreeMap<(String, String, EdgeKind), Option<String>> = BTreeMap::new();
+
+    for file in &files {
+        let Ok(content) = std::fs::read_to_string(output_dir.join(file)) else {
+            continue;
+        };
+        for link in extract_links(&content) {
+            let target = match link.kind {
+                LinkKind::Wiki => resolve_wikilink(&link.target, &files).map(str::to_string),
+                LinkKind::Markdown | LinkKind::Html => resolve_relative(file, &link.target),
+            };
+            if let Some(target) = target {
+                if target != *file && known.contains(target.as_str()) {
+                    edges
+                        .entry((file.clone(), target, EdgeKind::CrossReference))
+                        .or_insert(None);
+                }
+            }
+        }
+    }
+
+    if let Some(records) = export {
+        let mut by_source: BTreeMap<&Path, BTreeSet<&str>> = BTreeMap::new();
+
+        for record in records {
+            for path in &record.output_paths {
+                if known.contains(path.as_str()) {
+                    by_source
+                        .entry(record.source_path.as_path())
+                        .or_default()
+                        .insert(path);
+                }
+            }
+            for xref in &record.cross_refs {
+                if known.contains(xref.from_file.as_str()) && known.contains(xref.to_file.as_str())
+                {
+                    edges.insert(
+                        (
+                            xref.from_file.clone(),
+                            xref.to_file.clone(),
+                            EdgeKind::CrossReference,
+                        ),
+                        Some(xref.context.clone()),
+                    );
+                }
+            }
+        }
+
+        for (source, outputs) in by_source {
+            let outputs: Vec<&str> = outputs.into_iter().collect();
+            for (i, a) in outputs.iter().enumerate() {
+                for b in &outputs[i + 1..] {
+                    edges
+                        .entry((a.to_string(), b.to_string(), EdgeKind::SharedSource))
+                        .or_insert_with(|| Some(source.display().to_string()));
+                }
+            }
+        }
+    }
+
+    let edges = edges
+        .into_iter()
+        .map(|((source, target, kind), label)| Edge {
+            source,
+            target,
+            kind,
+            label,
+        })
+        .collect();
+
+    Graph { nodes, edges }
+}
+
+/// Quote a string for DOT
+fn dot_quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Render the graph in Graphviz DOT format, clustering nodes by category
+pub fn render_dot(graph: &Graph) -> String {
+    let mut out = String::from("digraph notes {\n    rankdir=LR;\n    node [shape=box];\n");
+
+    let mut by_category: BTreeMap<&str, Vec<&Node>> = BTreeMap::new();
+    for node in &graph.nodes {
+        by_category.entry(&node.category).or_default().push(node);
+    }
+
+    for (category, nodes) in by_category {
+        let indent = if category.is_empty() {
+            "    "
+        } else {
+            out.push_str(&format!(
+                "    subgraph {} {{\n        label={};\n",
+                dot_quote(&format!("cluster_{}", category)),
+                dot_quote(category)
+            ));
+            "        "
+        };
+        for node in nodes {
+            out.push_str(&format!(
+                "{}{} [label={}];\n",
+                indent,
+                dot_quote(&node.id),
+                dot_quote(&node.label)
+            ));
+        }
+        if !category.is_empty() {
+            out.push_str("    }\n");
+        }
+    }
+
+    for edge in &graph.edges {
+        let mut attrs = Vec::new();
+        if edge.kind == EdgeKind::SharedSource {
+            attrs.push("style=dashed".to_string());
+            attrs.push("dir=none".to_string());
+        }
+        if let Some(label) = &edge.label {
+            attrs.push(format!("tooltip={}", dot_quote(label)));
+        }
+        let attrs = if attrs.is_empty() {
+            String::new()
+        } else {
+            format!(" [{}]", attrs.join(", "))
+        };
+        out.push_str(&format!(
+            "    {} -> {}{};\n",
+            dot_quote(&edge.source),
+            dot_quote(&edge.target),
+            attrs
+        ));
+    }
+
+    out.push_str("}\n");
+    out
+}
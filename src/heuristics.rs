@@ -0,0 +1,208 @@
+use crate::config::Config;
+use crate::discovery::matches_any;
+use crate::frontmatter::{self, Routing, SourceMeta};
+use crate::types::{Category, RawNote, Segment};
+use glob::Pattern;
+use serde_yaml::Mapping;
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+/// Categorizes notes with local rules instead of the LLM (`--no-llm-categorize`).
+///
+/// Rules are tried in order: frontmatter `category`/tags, file name patterns,
+/// folders named after a built-in category, keyword rules, and finally the
+/// note's top-level folder as a custom category.
+pub struct Heuristics {
+    input_dir: PathBuf,
+    filenames: Vec<(Category, Vec<Pattern>)>,
+    keywords: Vec<(Category, Vec<String>)>,
+}
+
+impl Heuristics {
+    pub fn new(config: &Config) -> Self {
+        let rules = &config.settings.categorize;
+        let filenames = rules
+            .filenames
+            .iter()
+            .map(|(category, patterns)| {
+                let patterns = patterns
+                    .iter()
+                    .filter_map(|p| Pattern::new(p).ok())
+                    .collect();
+                (Category::from_name(category), patterns)
+            })
+            .collect();
+        let keywords = rules
+            .keywords
+            .iter()
+            .map(|(category, words)| {
+                let words = words.iter().map(|w| w.to_lowercase()).collect();
+                (Category::from_name(category), words)
+            })
+            .collect();
+
+        Self {
+            input_dir: config.input_dir().to_path_buf(),
+            filenames,
+            keywords,
+        }
+    }
+
+    /// Categorize a note as a single segment filed under `category/[subcategory/]name.md`
+    pub fn categorize(&self, note: &RawNote) -> Vec<Segment> {
+        let rel_path = note
+            .path
+            .strip_prefix(&self.input_dir)
+            .unwrap_or(&note.path);
+        let folders: Vec<String> = rel_path
+            .parent()
+            .into_iter()
+            .flat_map(Path::components)
+            .map(|c| c.as_os_str().to_string_lossy().to_string())
+            .collect();
+
+        let (category, subcategory, rule) = by_frontmatter(&note.frontmatter)
+            .map(|(c, s)| (c, s, "frontmatter"))
+            .or_else(|| self.by_filename(rel_path).map(|c| (c, None, "file name")))
+            .or_else(|| by_folders(&folders).map(|(c, s)| (c, s, "folder")))
+            .or_else(|| {
+                self.by_keywords(&note.content)
+                    .map(|c| (c, None, "keywords"))
+            })
+            .or_else(|| {
+                let top = folders.first()?;
+                Some((
+                    Category::from_name(top),
+                    folders.get(1).map(|s| slug(s)),
+                    "folder",
+                ))
+            })
+            .unwrap_or((Category::Uncategorized, None, "default"));
+        debug!(
+            "{} → {} (by {})",
+            note.path.display(),
+            category.dir_name(),
+            rule
+        );
+
+        let path = default_path(&category, subcategory.as_deref(), &note.path);
+        vec![Segment {
+            content: note.content.clone(),
+            category,
+            subcategory,
+            paths: vec![path],
+            cross_file_to: Vec::new(),
+            confidence: None,
+            skip_enhance: false,
+            source: SourceMeta::default(),
+            lines: None,
+            duplicates: Vec::new(),
+        }]
+    }
+
+    fn by_filename(&self, rel_path: &Path) -> Option<Category> {
+        self.filenames
+            .iter()
+            .find(|(_, patterns)| matches_any(patterns, rel_path))
+            .map(|(category, _)| category.clone())
+    }
+
+    /// The category whose keywords occur most often in the note
+    fn by_keywords(&self, content: &str) -> Option<Category> {
+        let content = content.to_lowercase();
+        self.keywords
+            .iter()
+            .map(|(category, words)| {
+                let hits: usize = words.iter().map(|w| count_word(&content, w)).sum();
+                (category, hits)
+            })
+            .filter(|(_, hits)| *hits > 0)
+            // First rule wins a tie
+            .max_by(|a, b| a.1.cmp(&b.1).then(std::cmp::Ordering::Greater))
+            .map(|(category, _)| category.clone())
+    }
+}
+
+/// The segment a note's `notex:` frontmatter routes it to, bypassing categorization.
+///
+/// Needs a category or a path; a missing one is derived from the other.
+pub fn routed(note: &RawNote, routing: &Routing) -> Option<Segment> {
+    let path = routing
+        .path
+        .as_deref()
+        .map(|p| p.trim_start_matches('/').to_string());
+    let category = match (&routing.category, &path) {
+        (Some(category), _) => Category::from_name(category),
+        (None, Some(path)) => Category::from_name(path.split('/').next().unwrap_or(path)),
+        (None, None) => return None,
+    };
+    let subcategory = routing.subcategory.as_deref().map(slug);
+    let path = path.unwrap_or_else(|| default_path(&category, subcategory.as_deref(), &note.path));
+    Some(Segment {
+        content: note.content.clone(),
+        category,
+        subcategory,
+        paths: vec![path],
+        cross_file_to: Vec::new(),
+        confidence: None,
+        skip_enhance: routing.skip_enhance,
+        source: SourceMeta::default(),
+        lines: None,
+        duplicates: Vec::new(),
+    })
+}
+
+/// `category/[subcategory/]name.md` for a note
+pub fn default_path(category: &Category, subcategory: Option<&str>, note_path: &Path) -> String {
+    let name = note_path
+        .file_stem()
+        .map(|s| slug(&s.to_string_lossy()))
+        .unwrap_or_else(|| "note".to_string());
+    match subcategory {
+        Some(sub) => format!("{}/{}/{}.md", category.dir_name(), sub, name),
+        None => format!("{}/{}.md", category.dir_name(), name),
+    }
+}
+
+/// `category:` (and `subcategory:`) from frontmatter, or the first tag naming a built-in category
+fn by_frontmatter(frontmatter: &Mapping) -> Option<(Category, Option<String>)> {
+    let subcategory = frontmatter::string(frontmatter, "subcategory").map(|s| slug(&s));
+    if let Some(category) = frontmatter::string(frontmatter, "category") {
+        return Some((Category::from_name(&category), subcategory));
+    }
+    frontmatter::tags(frontmatter)
+        .iter()
+        .map(|tag| Category::from_name(tag))
+        .find(Category::is_builtin)
+        .map(|category| (category, subcategory))
+}
+
+/// The deepest folder named after a built-in category, with the folder below it
+/// as the subcategory
+fn by_folders(folders: &[String]) -> Option<(Category, Option<String>)> {
+    folders.iter().enumerate().rev().find_map(|(i, folder)| {
+        let category = Category::from_name(folder);
+        category
+            .is_builtin()
+            .then(|| (category, folders.get(i + 1).map(|s| slug(s))))
+    })
+}
+
+/// Occurrences of a word or phrase in lower-cased text, on word boundaries
+fn count_word(text: &str, word: &str) -> usize {
+    if word.is_empty() {
+        return 0;
+    }
+    text.match_indices(word)
+        .filter(|(pos, _)| {
+            let before = text[..*pos].chars().next_back();
+            let after = text[pos + word.len()..].chars().next();
+            !before.is_some_and(char::is_alphanumeric) && !after.is_some_and(char::is_alphanumeric)
+        })
+        .count()
+}
+
+/// Lower-case a name for use in an output path
+fn slug(name: &str) -> String {
+    name.trim().to_lowercase().replace(' ', "_")
+}
@@ -0,0 +1,241 @@
+use crate::types::{Category, EnhancedSegment, Segment};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use thiserror::Error;
+use tokio::io::AsyncWriteExt;
+use tracing::{debug, warn};
+
+#[derive(Error, Debug)]
+pub enum HookError {
+    #[error("could not run: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("exited with {0}")]
+    Failed(std::process::ExitStatus),
+    #[error("printed invalid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// The `[hooks]` table: shell commands run at the pipeline's boundaries.
+///
+/// Each gets JSON on stdin. The filter hooks may print the same JSON back,
+/// changed, to replace what the pipeline continues with; printing nothing
+/// keeps it as it was.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Hooks {
+    /// Each note once it is read, as `{"path", "content"}`; print `null` to drop it
+    pub after_discovery: Option<String>,
+    /// The segments of every note, as `{"notes": [{"path", "segments"}]}`
+    pub after_categorize: Option<String>,
+    /// The files about to be written, as `{"files": [{"path", "segments"}]}`;
+    /// files and segments left out are not written
+    pub before_write: Option<String>,
+    /// A summary of the finished run; what it prints is ignored
+    pub after_run: Option<String>,
+}
+
+/// A note as the `after_discovery` hook sees it
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NoteData {
+    pub path: PathBuf,
+    pub content: String,
+}
+
+/// A note's segments as the `after_categorize` hook sees them
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CategorizedNote {
+    pub path: PathBuf,
+    pub segments: Vec<Segment>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Categorized {
+    notes: Vec<CategorizedNote>,
+}
+
+/// An output file as the `before_write` hook sees it
+#[derive(Debug, Serialize, Deserialize)]
+struct OutputFile {
+    path: String,
+    segments: Vec<OutputSegment>,
+}
+
+/// A segment of an output file; `source` and `position` identify it
+#[derive(Debug, Serialize, Deserialize)]
+struct OutputSegment {
+    source: PathBuf,
+    position: usize,
+    #[serde(default, skip_deserializing)]
+    category: Option<Category>,
+    content: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct OutputFiles {
+    files: Vec<OutputFile>,
+}
+
+/// What the `after_run` hook gets
+#[derive(Debug, Serialize)]
+pub struct RunData<'a> {
+    pub output: &'a Path,
+    pub files: &'a [PathBuf],
+    pub notes: usize,
+    pub segments: usize,
+    pub failures: usize,
+}
+
+/// Run a hook with `input` as JSON on stdin. Returns what it printed, parsed,
+/// or None when it printed nothing.
+async fn run<T: Serialize, R: DeserializeOwned>(
+    name: &str,
+    command: &str,
+    input: &T,
+) -> Result<Option<R>, HookError> {
+    let (shell, flag) = if cfg!(windows) {
+        ("cmd", "/C")
+    } else {
+        ("sh", "-c")
+    };
+    let mut child = tokio::process::Command::new(shell)
+        .args([flag, command])
+        .env("NOTEX_HOOK", name)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+
+    let json = serde_json::to_vec(input)?;
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    // Written while the output is read, so a hook that prints as it reads
+    // can't fill its stdout pipe and stall
+    let writer = tokio::spawn(async move { stdin.write_all(&json).await });
+
+    let output = child.wait_with_output().await?;
+    match writer.await.map_err(std::io::Error::other)? {
+        // A hook that doesn't read its input closes the pipe early; that's fine
+        Err(e) if e.kind() != std::io::ErrorKind::BrokenPipe => return Err(e.into()),
+        _ => {}
+    }
+    if !output.status.success() {
+        return Err(HookError::Failed(output.status));
+    }
+    if output.stdout.iter().all(u8::is_ascii_whitespace) {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::from_slice(&output.stdout)?))
+}
+
+/// Run the `after_discovery` hook on a note. Returns the note's content, or
+/// None when the hook dropped it.
+pub async fn after_discovery(command: &str, path: &Path, content: String) -> Option<String> {
+    let note = NoteData {
+        path: path.to_path_buf(),
+        content,
+    };
+    match run::<_, Option<NoteData>>("after_discovery", command, &note).await {
+        Ok(None) => Some(note.content),
+        Ok(Some(Some(changed))) => Some(changed.content),
+        Ok(Some(None)) => {
+            debug!("after_discovery hook dropped {}", path.display());
+            None
+        }
+        Err(e) => {
+            warn!("after_discovery hook for {}: {}", path.display(), e);
+            Some(note.content)
+        }
+    }
+}
+
+/// Run the `after_categorize` hook on every note's segments
+pub async fn after_categorize(
+    command: &str,
+    categorized: Vec<(PathBuf, Vec<Segment>)>,
+) -> Vec<(PathBuf, Vec<Segment>)> {
+    let input = Categorized {
+        notes: categorized
+            .into_iter()
+            .map(|(path, segments)| CategorizedNote { path, segments })
+            .collect(),
+    };
+    let notes = match run::<_, Categorized>("after_categorize", command, &input).await {
+        Ok(Some(changed)) => changed.notes,
+        Ok(None) => input.notes,
+        Err(e) => {
+            warn!("after_categorize hook: {}", e);
+            input.notes
+        }
+    };
+    notes
+        .into_iter()
+        .filter(|note| !note.segments.is_empty())
+        .map(|note| (note.path, note.segments))
+        .collect()
+}
+
+/// Run the `before_write` hook on the files about to be written, keeping
+/// only the files and segments it returns, with its content
+pub async fn before_write(command: &str, grouped: &mut HashMap<String, Vec<EnhancedSegment>>) {
+    let mut paths: Vec<&String> = grouped.keys().collect();
+    paths.sort();
+    let input = OutputFiles {
+        files: paths
+            .into_iter()
+            .map(|path| OutputFile {
+                path: path.clone(),
+                segments: grouped[path]
+                    .iter()
+                    .map(|s| OutputSegment {
+                        source: s.original_path.clone(),
+                        position: s.position,
+                        category: Some(s.category.clone()),
+                        content: s.content.clone(),
+                    })
+                    .collect(),
+            })
+            .collect(),
+    };
+
+    let changed = match run::<_, OutputFiles>("before_write", command, &input).await {
+        Ok(Some(changed)) => changed,
+        Ok(None) => return,
+        Err(e) => {
+            warn!("before_write hook: {}", e);
+            return;
+        }
+    };
+    let mut kept: HashMap<String, Vec<EnhancedSegment>> = HashMap::new();
+    for file in changed.files {
+        let Some(segments) = grouped.get(&file.path) else {
+            warn!("before_write hook returned unknown file {}", file.path);
+            continue;
+        };
+        let segments: Vec<EnhancedSegment> = file
+            .segments
+            .into_iter()
+            .filter_map(|changed| {
+                let segment = segments.iter().find(|s| {
+                    s.original_path == changed.source && s.position == changed.position
+                })?;
+                let mut segment = segment.clone();
+                segment.content = changed.content;
+                Some(segment)
+            })
+            .collect();
+        if !segments.is_empty() {
+            kept.insert(file.path, segments);
+        }
+    }
+    *grouped = kept;
+}
+
+/// Run the `after_run` hook with a summary of the run
+pub async fn after_run(command: &str, data: &RunData<'_>) {
+    match run::<_, serde_json::Value>("after_run", command, data).await {
+        // Its output is not used, JSON or not
+        Ok(_) | Err(HookError::Json(_)) => {}
+        Err(e) => warn!("after_run hook: {}", e),
+    }
+}
@@ -0,0 +1,144 @@
+use pulldown_cmark::{html, CowStr, Event, Options, Parser, Tag};
+use std::sync::OnceLock;
+
+/// Marker closing the page body; later passes insert content before it
+const BODY_END: &str = "</main>";
+
+const STYLESHEET: &str = r#"body { max-width: 48rem; margin: 2rem auto; padding: 0 1rem; font-family: -apple-system, "Segoe UI", Helvetica, Arial, sans-serif; line-height: 1.6; color: #222; }
+h1, h2, h3 { line-height: 1.25; }
+pre { background: #f5f5f5; padding: 0.75rem; overflow-x: auto; border-radius: 4px; }
+code { font-family: "SFMono-Regular", Consolas, monospace; font-size: 0.9em; }
+blockquote { border-left: 4px solid #ddd; margin: 0; padding-left: 1rem; color: #555; }
+table { border-collapse: collapse; }
+th, td { border: 1px solid #ddd; padding: 0.3rem 0.6rem; }
+hr { border: none; border-top: 1px solid #ddd; margin: 2rem 0; }
+.math-display { display: block; overflow-x: auto; text-align: center; margin: 1rem 0; }"#;
+
+/// Whether pages may load KaTeX from its CDN; not with `--local-only`
+static REMOTE_MATH: OnceLock<bool> = OnceLock::new();
+
+/// Renders `.math` spans with KaTeX when it is available, leaving the raw TeX otherwise
+const MATH_HOOK: &str = r#"<link rel="stylesheet" href="https://cdn.jsdelivr.net/npm/katex@0.16/dist/katex.min.css">
+<script defer src="https://cdn.jsdelivr.net/npm/katex@0.16/dist/katex.min.js"></script>
+<script>
+window.addEventListener("load", function () {
+  if (!window.katex) return;
+  document.querySelectorAll(".math").forEach(function (el) {
+    katex.render(el.textContent, el, {
+      displayMode: el.classList.contains("math-display"),
+      throwOnError: false
+    });
+  });
+});
+</script>"#;
+
+/// Leave the KaTeX hook out of rendered pages when `local_only`, so opening
+/// them contacts no outside host; math then shows as raw TeX
+pub fn init(local_only: bool) {
+    REMOTE_MATH.get_or_init(|| !local_only);
+}
+
+/// Map a markdown output path to its HTML counterpart
+pub fn html_path(path: &str) -> String {
+    match path.strip_suffix(".md") {
+        Some(stem) => format!("{}.html", stem),
+        None if path.ends_with(".html") => path.to_string(),
+        None => format!("{}.html", path),
+    }
+}
+
+/// Check whether a link target points at another local note
+fn is_local_link(url: &str) -> bool {
+    !url.contains("://") && !url.starts_with('#') && !url.starts_with("mailto:")
+}
+
+/// Convert markdown to an HTML fragment, pointing local `.md` links at the `.html` pages
+pub fn markdown_to_html(markdown: &str) -> String {
+    let options = Options::ENABLE_TABLES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS
+        | Options::ENABLE_MATH;
+
+    let parser = Parser::new_ext(markdown, options).map(|event| match event {
+        Event::Start(Tag::Link {
+            link_type,
+            dest_url,
+            title,
+            id,
+        }) if is_local_link(&dest_url) => {
+            let (target, anchor) = match dest_url.split_once('#') {
+                Some((target, anchor)) => (target, Some(anchor)),
+                None => (dest_url.as_ref(), None),
+            };
+            let dest_url = match (target.strip_suffix(".md"), anchor) {
+                (Some(stem), Some(anchor)) => CowStr::from(format!("{}.html#{}", stem, anchor)),
+                (Some(stem), None) => CowStr::from(format!("{}.html", stem)),
+                _ => dest_url,
+            };
+            Event::Start(Tag::Link {
+                link_type,
+                dest_url,
+                title,
+                id,
+            })
+        }
+        other => other,
+    });
+
+    let mut out = String::new();
+    html::push_html(&mut out, parser);
+    out
+}
+
+/// Escape text for use inside HTML
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Render a standalone HTML page from markdown content
+pub fn render_page(title: &str, markdown: &str) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{}</title>
+<style>
+{}
+</style>
+{}
+</head>
+<body>
+<main>
+{}{}
+</body>
+</html>
+"#,
+        escape(title),
+        STYLESHEET,
+        if *REMOTE_MATH.get().unwrap_or(&true) {
+            MATH_HOOK
+        } else {
+            ""
+        },
+        markdown_to_html(markdown),
+        BODY_END
+    )
+}
+
+/// Append a markdown section to the body of a rendered page
+pub fn append_to_page(page: &str, markdown: &str) -> String {
+    insert_into_page(page, &markdown_to_html(markdown))
+}
+
+/// Insert an HTML fragment at the end of the body of a rendered page
+pub fn insert_into_page(page: &str, fragment: &str) -> String {
+    match page.rfind(BODY_END) {
+        Some(pos) => format!("{}{}{}", &page[..pos], fragment, &page[pos..]),
+        None => format!("{}{}", page, fragment),
+    }
+}
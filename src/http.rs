@@ -0,0 +1,81 @@
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum HttpError {
+    #[error("Invalid proxy {0}: {1}")]
+    Proxy(String, reqwest::Error),
+    #[error("Could not read CA certificate {0}: {1}")]
+    Io(PathBuf, std::io::Error),
+    #[error("Invalid CA certificate {0}: {1}")]
+    Certificate(PathBuf, reqwest::Error),
+    #[error("No certificates in {0}")]
+    NoCertificates(PathBuf),
+    #[error("Could not set up the HTTP client: {0}")]
+    Client(reqwest::Error),
+}
+
+/// Proxy and TLS settings for every HTTP client notex builds: the LLM
+/// endpoints and `--expand-links` fetching.
+///
+/// Without `--proxy`, the `HTTPS_PROXY`, `HTTP_PROXY`, `ALL_PROXY` and
+/// `NO_PROXY` environment variables are honored.
+#[derive(Debug, Clone, Default)]
+pub struct HttpSettings {
+    proxy: Option<reqwest::Proxy>,
+    /// Ignore the proxy environment variables, for `--local-only`
+    no_env_proxy: bool,
+    /// Trusted in addition to the system roots
+    ca_certs: Vec<reqwest::Certificate>,
+}
+
+impl HttpSettings {
+    /// Parse the proxy URL and read the PEM certificates, checking that a
+    /// client can be built with them
+    pub fn load(proxy: Option<&str>, ca_cert: Option<&Path>) -> Result<Self, HttpError> {
+        let proxy = proxy
+            .map(|url| reqwest::Proxy::all(url).map_err(|e| HttpError::Proxy(url.to_string(), e)))
+            .transpose()?;
+
+        let mut ca_certs = Vec::new();
+        if let Some(path) = ca_cert {
+            let pem = std::fs::read(path).map_err(|e| HttpError::Io(path.to_path_buf(), e))?;
+            ca_certs = reqwest::Certificate::from_pem_bundle(&pem)
+                .map_err(|e| HttpError::Certificate(path.to_path_buf(), e))?;
+            if ca_certs.is_empty() {
+                return Err(HttpError::NoCertificates(path.to_path_buf()));
+            }
+        }
+
+        let settings = Self {
+            proxy,
+            no_env_proxy: false,
+            ca_certs,
+        };
+        settings.builder().build().map_err(HttpError::Client)?;
+        Ok(settings)
+    }
+
+    /// The same settings without the proxy environment variables, so only
+    /// an explicit `--proxy` is used
+    pub fn without_env_proxy(self) -> Self {
+        Self {
+            no_env_proxy: true,
+            ..self
+        }
+    }
+
+    /// A client builder with the proxy and certificates applied
+    pub fn builder(&self) -> reqwest::ClientBuilder {
+        let mut builder = reqwest::Client::builder();
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(proxy.clone());
+        } else if self.no_env_proxy {
+            builder = builder.no_proxy();
+        }
+        for cert in &self.ca_certs {
+            builder = builder.add_root_certificate(cert.clone());
+        }
+        builder
+    }
+}
@@ -0,0 +1,314 @@
+use crate::atomic;
+use crate::changes;
+use crate::client::{ClientError, LlmClient};
+use crate::failures::REPORT_FILE;
+use crate::figures;
+use crate::html;
+use crate::links::relative_path;
+use crate::manifest::MANIFEST_FILE;
+use crate::originals;
+use crate::reorg::PLAN_FILE;
+use crate::types::OutputFormat;
+use crate::verify::VERIFY_REPORT_FILE;
+use crate::writer::output_rel_path;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+const DESCRIBE_SYSTEM_PROMPT: &str = r#"You are a librarian writing a table of contents. Given a note, describe what it covers in ONE short line (max 15 words).
+
+Rules:
+- Output ONLY the description, no quotes, no prefix, no trailing period
+- Do NOT use emojis"#;
+
+const FOLDER_SYSTEM_PROMPT: &str = r#"You are a librarian. Given the contents of a folder of notes (files with one-line descriptions, and subfolders), summarize what the folder contains in 1-2 sentences.
+
+Rules:
+- Describe the topics covered, not the file names
+- Output ONLY the summary, no prefix
+- Do NOT use emojis"#;
+
+/// File name of the per-directory overview written by `--readmes`
+pub const README_FILE: &str = "README.md";
+
+/// Most entries of a folder listed when asking for its summary
+const FOLDER_PROMPT_ENTRIES: usize = 60;
+
+/// File names of generated index files, glossaries and reports, skipped when indexing
+const INDEX_NAMES: &[&str] = &[
+    README_FILE,
+    "index.md",
+    "index.html",
+    "glossary.md",
+    "glossary.html",
+    "INDEX.md",
+    "INDEX.html",
+    "BACKLINKS.md",
+    "BACKLINKS.html",
+    VERIFY_REPORT_FILE,
+    MANIFEST_FILE,
+    REPORT_FILE,
+    PLAN_FILE,
+];
+
+/// Collect relative paths of all note files in the output directory, excluding
+/// indexes, reports, archived originals, attachments, `--show-changes` diffs
+/// and `--backup bak` copies, so every consumer agrees on what a note is
+pub fn collect_indexable(output_dir: &Path) -> Vec<String> {
+    let mut files: Vec<String> = WalkDir::new(output_dir)
+        .into_iter()
+        // Depth 0 is the output directory itself, which may be `.`
+        .filter_entry(|e| {
+            e.depth() == 0
+                || !e.file_name().to_string_lossy().starts_with('.')
+                    && !originals::is_archive(e)
+                    && !figures::is_attachments(e)
+                    && !changes::is_changes(e)
+        })
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file() && !atomic::is_backup(e.path()))
+        .filter(|e| !INDEX_NAMES.contains(&e.file_name().to_string_lossy().as_ref()))
+        .filter_map(|e| {
+            e.path()
+                .strip_prefix(output_dir)
+                .ok()
+                .map(|p| p.to_string_lossy().replace('\\', "/"))
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+/// Ask the LLM for a one-line description of a file
+pub async fn describe_file(
+    client: &LlmClient,
+    rel_path: &str,
+    content: &str,
+) -> Result<String, ClientError> {
+    let excerpt: String = content.chars().take(1500).collect();
+    let user_prompt = format!("File: {}\n\nContent:\n{}", rel_path, excerpt);
+
+    let response = client.chat(DESCRIBE_SYSTEM_PROMPT, &user_prompt).await?;
+    let line = response
+        .lines()
+        .find(|l| !l.trim().is_empty())
+        .unwrap_or("");
+    Ok(line
+        .trim()
+        .trim_matches('"')
+        .trim_end_matches('.')
+        .to_string())
+}
+
+/// A directory of the output and what it holds
+#[derive(Debug, Default)]
+pub struct Folder {
+    /// Files directly in the folder, with their descriptions
+    pub files: Vec<(String, String)>,
+    /// Folders directly below it
+    pub subfolders: BTreeSet<String>,
+}
+
+/// Every folder with described files in it or below it, by path (`""` is the output root)
+pub fn folders(descriptions: &BTreeMap<String, String>) -> BTreeMap<String, Folder> {
+    let mut folders: BTreeMap<String, Folder> = BTreeMap::new();
+    for (rel_path, description) in descriptions {
+        let (dir, name) = rel_path.rsplit_once('/').unwrap_or(("", rel_path));
+        folders
+            .entry(dir.to_string())
+            .or_default()
+            .files
+            .push((name.to_string(), description.clone()));
+
+        // List the folder in each of its ancestors
+        let mut child = dir;
+        while !child.is_empty() {
+            let (parent, name) = child.rsplit_once('/').unwrap_or(("", child));
+            folders
+                .entry(parent.to_string())
+                .or_default()
+                .subfolders
+                .insert(name.to_string());
+            child = parent;
+        }
+    }
+    folders
+}
+
+/// Ask the LLM for a 1-2 sentence summary of a folder from its listing
+pub async fn describe_folder(
+    client: &LlmClient,
+    dir: &str,
+    folder: &Folder,
+) -> Result<String, ClientError> {
+    let listing: String = folder
+        .subfolders
+        .iter()
+        .map(|name| format!("- {}/ (folder)\n", name))
+        .chain(
+            folder
+                .files
+                .iter()
+                .map(|(name, description)| format!("- {}: {}\n", name, description)),
+        )
+        .take(FOLDER_PROMPT_ENTRIES)
+        .collect();
+    let name = if dir.is_empty() { "(top level)" } else { dir };
+    let user_prompt = format!("Folder: {}\n\nContents:\n{}", name, listing);
+
+    let response = client.chat(FOLDER_SYSTEM_PROMPT, &user_prompt).await?;
+    Ok(response.trim().to_string())
+}
+
+/// Write `README.md` in a folder: its summary, subfolders, and files with descriptions
+pub fn write_readme(
+    output_dir: &Path,
+    dir: &str,
+    folder: &Folder,
+    summary: &str,
+) -> Result<PathBuf, std::io::Error> {
+    let title = match dir.rsplit('/').next() {
+        Some(name) if !name.is_empty() => entry_title(name),
+        _ => "Notes".to_string(),
+    };
+    let mut md = format!("# {}\n", title);
+    if !summary.is_empty() {
+        md.push_str(&format!("\n{}\n", summary));
+    }
+    if !folder.subfolders.is_empty() {
+        md.push_str("\n## Folders\n\n");
+        for name in &folder.subfolders {
+            md.push_str(&format!("- [{}](<{}/>)\n", entry_title(name), name));
+        }
+    }
+    if !folder.files.is_empty() {
+        md.push_str("\n## Files\n\n");
+        for (name, description) in &folder.files {
+            md.push_str(&format!("- [{}](<{}>)", entry_title(name), name));
+            if !description.is_empty() {
+                md.push_str(&format!(" - {}", description));
+            }
+            md.push('\n');
+        }
+    }
+
+    let path = output_dir.join(dir).join(README_FILE);
+    atomic::write(&path, md)?;
+    Ok(path)
+}
+
+/// Title for a file or directory entry in an index
+fn entry_title(name: &str) -> String {
+    let stem = name.rsplit_once('.').map(|(s, _)| s).unwrap_or(name);
+    stem.replace('_', " ")
+}
+
+/// Write a markdown index page in the output's format
+pub fn write_index(
+    path: &Path,
+    title: &str,
+    markdown: &str,
+    format: OutputFormat,
+) -> Result<(), std::io::Error> {
+    let content = match format {
+        OutputFormat::Html => html::render_page(title, markdown),
+        _ => markdown.to_string(),
+    };
+    atomic::write(path, content)
+}
+
+/// Regenerate `<category>/index.md` for every category and a top-level `INDEX.md`
+pub fn write_indexes(
+    output_dir: &Path,
+    descriptions: &BTreeMap<String, String>,
+    format: OutputFormat,
+) -> Result<Vec<PathBuf>, std::io::Error> {
+    let mut by_category: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    let mut top_level: Vec<&str> = Vec::new();
+
+    for rel_path in descriptions.keys() {
+        match rel_path.split_once('/') {
+            Some((category, _)) => by_category.entry(category).or_default().push(rel_path),
+            None => top_level.push(rel_path),
+        }
+    }
+
+    let category_index = output_rel_path("index.md", format);
+    let mut written = Vec::new();
+
+    for (category, files) in &by_category {
+        let mut md = format!("# {}\n\n", entry_title(category));
+        for rel_path in files {
+            let inner = &rel_path[category.len() + 1..];
+            md.push_str(&format!(
+                "- [{}](<{}>) - {}\n",
+                entry_title(inner.rsplit('/').next().unwrap_or(inner)),
+                inner,
+                descriptions[*rel_path]
+            ));
+        }
+
+        let path = output_dir.join(category).join(&category_index);
+        write_index(&path, &entry_title(category), &md, format)?;
+        written.push(path);
+    }
+
+    let mut md = String::from("# Index\n\n");
+    if !by_category.is_empty() {
+        md.push_str("## Categories\n\n");
+        for (category, files) in &by_category {
+            md.push_str(&format!(
+                "- [{}](<{}/{}>) - {} files\n",
+                entry_title(category),
+                category,
+                category_index,
+                files.len()
+            ));
+        }
+    }
+    if !top_level.is_empty() {
+        md.push_str("\n## Other notes\n\n");
+        for rel_path in &top_level {
+            md.push_str(&format!(
+                "- [{}](<{}>) - {}\n",
+                entry_title(rel_path),
+                rel_path,
+                descriptions[*rel_path]
+            ));
+        }
+    }
+
+    let path = output_dir.join(output_rel_path("INDEX.md", format));
+    write_index(&path, "Index", &md, format)?;
+    written.push(path);
+
+    Ok(written)
+}
+
+/// Write a top-level `BACKLINKS.md` listing, for every note, the notes that link to it
+pub fn write_backlinks(
+    output_dir: &Path,
+    backlinks: &BTreeMap<String, BTreeSet<String>>,
+    format: OutputFormat,
+) -> Result<PathBuf, std::io::Error> {
+    let rel_path = output_rel_path("BACKLINKS.md", format);
+    let mut md = String::from("# Backlinks\n");
+    for (target, sources) in backlinks {
+        md.push_str(&format!(
+            "\n## [{}](<{}>)\n\n",
+            target,
+            relative_path(&rel_path, target)
+        ));
+        for source in sources {
+            md.push_str(&format!(
+                "- [{}](<{}>)\n",
+                source,
+                relative_path(&rel_path, source)
+            ));
+        }
+    }
+
+    let path = output_dir.join(rel_path);
+    write_index(&path, "Backlinks", &md, format)?;
+    Ok(path)
+}
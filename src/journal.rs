@@ -0,0 +1,64 @@
+use crate::dates::{entry_date, find_date, find_time};
+use crate::types::{Category, EnhancedSegment, Segment};
+use chrono::NaiveDate;
+use std::collections::HashMap;
+use std::path::Path;
+use tracing::debug;
+
+/// File for a day's entries, `journal/YYYY/MM/YYYY-MM-DD.md`
+pub fn day_path(date: NaiveDate) -> String {
+    format!(
+        "{}/{}.md",
+        Category::Journal.dir_name(),
+        date.format("%Y/%m/%Y-%m-%d")
+    )
+}
+
+/// Route a note's journal segments to the file for their day (`--journal`).
+///
+/// A note split into several entries dates each one by its first line before
+/// falling back to the file name; a single entry goes by the file name first.
+/// Entries without a recognizable date keep their suggested path.
+pub fn route(note_path: &Path, segments: &mut [Segment]) {
+    let entries = segments
+        .iter()
+        .filter(|s| s.category == Category::Journal)
+        .count();
+    for segment in segments
+        .iter_mut()
+        .filter(|s| s.category == Category::Journal)
+    {
+        let date = if entries > 1 {
+            first_line(&segment.content)
+                .and_then(find_date)
+                .or_else(|| entry_date(note_path, ""))
+        } else {
+            entry_date(note_path, &segment.content)
+        };
+        match date {
+            Some(date) => segment.paths = vec![day_path(date)],
+            None => debug!("No date for a journal entry in {}", note_path.display()),
+        }
+    }
+}
+
+/// Order the entries of each day file by the time of day in their first line
+/// (entries without one first), then by source note
+pub fn sort_entries(grouped: &mut HashMap<String, Vec<EnhancedSegment>>) {
+    let prefix = format!("{}/", Category::Journal.dir_name());
+    for (_, entries) in grouped
+        .iter_mut()
+        .filter(|(path, _)| path.starts_with(&prefix))
+    {
+        entries.sort_by(|a, b| {
+            let time = |s: &EnhancedSegment| first_line(&s.content).and_then(find_time);
+            time(a)
+                .cmp(&time(b))
+                .then_with(|| a.original_path.cmp(&b.original_path))
+        });
+    }
+}
+
+fn first_line(content: &str) -> Option<&str> {
+    content.lines().find(|l| !l.trim().is_empty())
+}
@@ -0,0 +1,23 @@
+use whatlang::detect;
+
+/// Longest excerpt of a note used to detect its language
+const DETECT_CHARS: usize = 2000;
+
+/// English name of the language a text is written in, when detection is confident
+pub fn detect_language(text: &str) -> Option<&'static str> {
+    let excerpt: String = text.chars().take(DETECT_CHARS).collect();
+    detect(&excerpt)
+        .filter(|info| info.is_reliable())
+        .map(|info| info.lang().eng_name())
+}
+
+/// Enhancement prompt rule keeping a note in its original language
+pub fn preserve_instruction(text: &str) -> String {
+    match detect_language(text) {
+        Some(language) => format!(
+            "Language: the note is written in {0}. Write the enhanced note in {0} and do NOT translate it; headers, explanations and anything you add are in {0} too.",
+            language
+        ),
+        None => "Language: write the enhanced note in the same language as the original and do NOT translate it.".to_string(),
+    }
+}
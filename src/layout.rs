@@ -0,0 +1,68 @@
+use crate::dates::{entry_date, find_date};
+use crate::types::{Layout, Segment};
+use chrono::{DateTime, Local, NaiveDate};
+use std::fs;
+use std::path::Path;
+use tracing::debug;
+
+/// Date a note is filed under: a date in its file name or first line, else
+/// the time the file was last modified
+pub fn note_date(note_path: &Path, content: &str) -> Option<NaiveDate> {
+    entry_date(note_path, content).or_else(|| {
+        let modified = fs::metadata(note_path).ok()?.modified().ok()?;
+        Some(DateTime::<Local>::from(modified).date_naive())
+    })
+}
+
+/// Date of a segment: the first date in its note's frontmatter, else the
+/// note's `note_date`
+pub fn segment_date(note_path: &Path, segment: &Segment) -> Option<NaiveDate> {
+    segment
+        .source
+        .dates
+        .iter()
+        .find_map(|(_, value)| find_date(value))
+        .or_else(|| note_date(note_path, &segment.content))
+}
+
+/// Move a note's segments into `YYYY/MM/` folders for the date-based layouts (`--layout`),
+/// by their `segment_date`.
+///
+/// `by-date` keeps only the file name under the month; `hybrid` puts the month
+/// inside the category folder.
+pub fn apply(layout: Layout, note_path: &Path, segments: &mut [Segment]) {
+    if layout == Layout::ByCategory {
+        return;
+    }
+    for segment in segments.iter_mut() {
+        let Some(date) = segment_date(note_path, segment) else {
+            debug!("No date to file a segment of {} by", note_path.display());
+            continue;
+        };
+        segment.paths = segment
+            .paths
+            .iter()
+            .map(|p| dated_path(layout, p, date))
+            .collect();
+        let cross_file_to: Vec<String> = segment
+            .cross_file_to
+            .iter()
+            .map(|p| dated_path(layout, p, date))
+            .filter(|p| !segment.paths.contains(p))
+            .collect();
+        segment.cross_file_to = cross_file_to;
+    }
+}
+
+fn dated_path(layout: Layout, path: &str, date: NaiveDate) -> String {
+    let month = date.format("%Y/%m");
+    match (layout, path.split_once('/')) {
+        (Layout::ByCategory, _) => path.to_string(),
+        (Layout::ByDate, _) => {
+            let name = path.rsplit('/').next().unwrap_or(path);
+            format!("{}/{}", month, name)
+        }
+        (Layout::Hybrid, Some((category, rest))) => format!("{}/{}/{}", category, month, rest),
+        (Layout::Hybrid, None) => format!("{}/{}", month, path),
+    }
+}
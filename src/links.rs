@@ -0,0 +1,198 @@
+/// How a link was written in the source file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LinkKind {
+    /// `[label](target)` or `![alt](target)`
+    Markdown,
+    /// `[[target]]` or `[[target|label]]`
+    Wiki,
+    /// `href="target"` or `src="target"`
+    Html,
+}
+
+/// A link found in a note
+#[derive(Debug, Clone)]
+pub struct Link {
+    pub target: String,
+    pub kind: LinkKind,
+}
+
+/// Extract all links from a note, skipping fenced code blocks
+pub fn extract_links(content: &str) -> Vec<Link> {
+    let mut links = Vec::new();
+    let mut in_fence = false;
+
+    for line in content.lines() {
+        if line.trim_start().starts_with("```") {
+            in_fence = !in_fence;
+            continue;
+        }
+        if in_fence {
+            continue;
+        }
+
+        let mut push = |target: &str, kind| {
+            let target = target.trim();
+            if !target.is_empty() {
+                links.push(Link {
+                    target: target.to_string(),
+                    kind,
+                });
+            }
+        };
+
+        // Markdown links: find every "](" and read the destination
+        let mut rest = line;
+        while let Some(pos) = rest.find("](") {
+            let after = &rest[pos + 2..];
+            let (target, consumed) = if let Some(inner) = after.strip_prefix('<') {
+                match inner.find('>') {
+                    Some(end) => (&inner[..end], end + 2),
+                    None => break,
+                }
+            } else {
+                match after.find(')') {
+                    // Drop an optional link title: [a](path "title")
+                    Some(end) => (after[..end].split(' ').next().unwrap_or(""), end + 1),
+                    None => break,
+                }
+            };
+            push(target, LinkKind::Markdown);
+            rest = &after[consumed..];
+        }
+
+        // Wikilinks
+        let mut rest = line;
+        while let Some(pos) = rest.find("[[") {
+            let after = &rest[pos + 2..];
+            match after.find("]]") {
+                Some(end) => {
+                    let inner = &after[..end];
+                    let target = inner.split('|').next().unwrap_or(inner);
+                    push(target, LinkKind::Wiki);
+                    rest = &after[end + 2..];
+                }
+                None => break,
+            }
+        }
+
+        // HTML attributes
+        for attr in ["href=\"", "src=\""] {
+            let mut rest = line;
+            while let Some(pos) = rest.find(attr) {
+                let after = &rest[pos + attr.len()..];
+                match after.find('"') {
+                    Some(end) => {
+                        push(&after[..end], LinkKind::Html);
+                        rest = &after[end + 1..];
+                    }
+                    None => break,
+                }
+            }
+        }
+    }
+
+    links
+}
+
+/// Whether a link target points outside the note collection
+pub fn is_external(target: &str) -> bool {
+    target.contains("://")
+        || target.starts_with("mailto:")
+        || target.starts_with("data:")
+        || target.starts_with('#')
+}
+
+/// Resolve a relative link target against the file containing it.
+///
+/// Both paths are relative to the output root. Returns `None` if the
+/// target is external or would escape the root.
+pub fn resolve_relative(from_file: &str, target: &str) -> Option<String> {
+    if is_external(target) {
+        return None;
+    }
+    let target = target.split('#').next().unwrap_or(target);
+    if target.is_empty() {
+        return None;
+    }
+
+    let mut parts: Vec<&str> = if target.starts_with('/') {
+        Vec::new()
+    } else {
+        let mut dir: Vec<&str> = from_file.split('/').collect();
+        dir.pop();
+        dir
+    };
+
+    for part in target.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop()?;
+            }
+            other => parts.push(other),
+        }
+    }
+
+    Some(parts.join("/"))
+}
+
+/// Resolve a wikilink target to one of the known files.
+///
+/// Tries a root-relative path first, then a unique file name match, the
+/// way Obsidian resolves links.
+pub fn resolve_wikilink<'a>(target: &str, files: &'a [String]) -> Option<&'a str> {
+    let target = target.split('#').next().unwrap_or(target).trim();
+    let candidates = [target.to_string(), format!("{}.md", target)];
+
+    if let Some(found) = files.iter().find(|f| candidates.contains(f)) {
+        return Some(found);
+    }
+
+    let mut by_name = files.iter().filter(|f| {
+        let name = f.rsplit('/').next().unwrap_or(f);
+        candidates.iter().any(|c| c == name)
+    });
+    match (by_name.next(), by_name.next()) {
+        (Some(found), None) => Some(found),
+        _ => None,
+    }
+}
+
+/// Relative link from one file to another, both relative to the output root
+pub fn relative_path(from: &str, to: &str) -> String {
+    let from_parts: Vec<&str> = from.split('/').collect();
+    let to_parts: Vec<&str> = to.split('/').collect();
+
+    // Find common prefix length, of folders only so a link to the file itself works
+    let common = from_parts
+        .iter()
+        .zip(to_parts.iter())
+        .take(from_parts.len() - 1)
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    // Build relative path
+    let up_count = from_parts.len() - common - 1;
+    let ups = std::iter::repeat_n("..", up_count);
+    let downs = to_parts.iter().skip(common);
+
+    ups.chain(downs.copied()).collect::<Vec<_>>().join("/")
+}
+
+/// Replace every link of the given kind whose target is exactly `old`
+pub fn replace_target(content: &str, kind: LinkKind, old: &str, new: &str) -> String {
+    let patterns: &[(&str, &str)] = match kind {
+        LinkKind::Markdown => &[("](", ")"), ("](", " "), ("](<", ">")],
+        LinkKind::Wiki => &[("[[", "]]"), ("[[", "|")],
+        LinkKind::Html => &[("href=\"", "\""), ("src=\"", "\"")],
+    };
+
+    let mut content = content.to_string();
+    for (open, close) in patterns {
+        content = content.replace(
+            &format!("{}{}{}", open, old, close),
+            &format!("{}{}{}", open, new, close),
+        );
+    }
+    content
+}
@@ -0,0 +1,253 @@
+use crate::links::{extract_links, is_external, resolve_relative, resolve_wikilink, LinkKind};
+use crate::logseq;
+use crate::reorg::collect_linked;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// Characters a complete note does not end on
+const DANGLING_ENDINGS: &[char] = &[',', ';', '(', '[', '{', '='];
+
+/// What is wrong at a location
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    BrokenLink(String),
+    UnclosedInlineMath,
+    UnclosedMathBlock,
+    UnclosedCodeBlock,
+    Empty,
+    Truncated,
+    DuplicateHeading { heading: String, first_line: usize },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::BrokenLink(target) => write!(f, "broken link to {}", target),
+            Problem::UnclosedInlineMath => write!(f, "unbalanced $ math delimiter"),
+            Problem::UnclosedMathBlock => write!(f, "$$ math block is never closed"),
+            Problem::UnclosedCodeBlock => write!(f, "code block is never closed"),
+            Problem::Empty => write!(f, "file has no content"),
+            Problem::Truncated => write!(f, "file ends mid-sentence, output may be truncated"),
+            Problem::DuplicateHeading {
+                heading,
+                first_line,
+            } => write!(
+                f,
+                "duplicate heading \"{}\" (first on line {})",
+                heading, first_line
+            ),
+        }
+    }
+}
+
+/// A problem found in an output file
+#[derive(Debug, Clone)]
+pub struct Issue {
+    /// Path relative to the output directory
+    pub path: String,
+    /// 1-based line number
+    pub line: usize,
+    pub problem: Problem,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.path, self.line, self.problem)
+    }
+}
+
+/// Check every written file in an output directory (`notex lint`)
+pub fn lint(output_dir: &Path) -> Vec<Issue> {
+    let mut files = collect_linked(output_dir);
+    files.sort();
+    // Logseq links pages by name, not by file
+    let page_names: Vec<String> = files
+        .iter()
+        .map(|f| logseq::page_name(f).to_lowercase())
+        .collect();
+
+    let mut issues = Vec::new();
+    for file in &files {
+        let Ok(content) = fs::read_to_string(output_dir.join(file)) else {
+            continue;
+        };
+        let mut found = Vec::new();
+        check_links(output_dir, file, &content, &files, &page_names, &mut found);
+        check_structure(&content, &mut found);
+        issues.extend(found.into_iter().map(|(line, problem)| Issue {
+            path: file.clone(),
+            line,
+            problem,
+        }));
+    }
+    issues.sort_by(|a, b| a.path.cmp(&b.path).then(a.line.cmp(&b.line)));
+    issues
+}
+
+/// Relative and wiki links that point at nothing
+fn check_links(
+    output_dir: &Path,
+    file: &str,
+    content: &str,
+    files: &[String],
+    page_names: &[String],
+    found: &mut Vec<(usize, Problem)>,
+) {
+    let mut in_fence = false;
+    for (i, line) in content.lines().enumerate() {
+        if line.trim_start().starts_with("```") {
+            in_fence = !in_fence;
+            continue;
+        }
+        if in_fence {
+            continue;
+        }
+        for link in extract_links(line) {
+            if is_external(&link.target) {
+                continue;
+            }
+            let ok = match link.kind {
+                LinkKind::Wiki => {
+                    let target = link.target.split('#').next().unwrap_or("").trim();
+                    target.is_empty()
+                        || resolve_wikilink(target, files).is_some()
+                        || page_names.contains(&target.to_lowercase())
+                        || resolve_relative(file, target)
+                            .is_some_and(|p| output_dir.join(p).exists())
+                }
+                LinkKind::Markdown | LinkKind::Html => {
+                    let target = link.target.replace("%20", " ");
+                    resolve_relative(file, &target).is_some_and(|p| output_dir.join(p).exists())
+                }
+            };
+            if !ok {
+                found.push((i + 1, Problem::BrokenLink(link.target)));
+            }
+        }
+    }
+}
+
+/// Math delimiters, code fences, empty or truncated content and repeated headings
+fn check_structure(content: &str, found: &mut Vec<(usize, Problem)>) {
+    let mut code_start: Option<usize> = None;
+    let mut math_start: Option<usize> = None;
+    let mut headings: HashMap<(usize, String), usize> = HashMap::new();
+    let mut has_body = false;
+    let mut last_text: Option<&str> = None;
+    let mut in_frontmatter = content.starts_with("---\n");
+
+    for (i, line) in content.lines().enumerate() {
+        let n = i + 1;
+        let trimmed = line.trim();
+        if in_frontmatter {
+            if n > 1 && trimmed == "---" {
+                in_frontmatter = false;
+            }
+            continue;
+        }
+
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            code_start = match code_start {
+                Some(_) => None,
+                None => Some(n),
+            };
+            has_body = true;
+            last_text = None;
+            continue;
+        }
+        if code_start.is_some() {
+            continue;
+        }
+
+        if trimmed.starts_with("$$") {
+            let one_line = trimmed.len() > 4 && trimmed.ends_with("$$");
+            if !one_line {
+                math_start = match math_start {
+                    Some(_) => None,
+                    None => Some(n),
+                };
+            }
+            has_body = true;
+            last_text = None;
+            continue;
+        }
+        if math_start.is_some() {
+            continue;
+        }
+
+        if inline_math_unbalanced(trimmed) {
+            found.push((n, Problem::UnclosedInlineMath));
+        }
+
+        // Logseq writes headings as blocks: `- ## Heading`
+        let heading_line = trimmed.strip_prefix("- ").unwrap_or(trimmed);
+        let level = heading_line.chars().take_while(|c| *c == '#').count();
+        if level > 0 && heading_line[level..].starts_with(' ') {
+            let heading = heading_line[level..].trim().to_string();
+            if let Some(first_line) = headings.get(&(level, heading.to_lowercase())) {
+                found.push((
+                    n,
+                    Problem::DuplicateHeading {
+                        heading,
+                        first_line: *first_line,
+                    },
+                ));
+            } else {
+                headings.insert((level, heading.to_lowercase()), n);
+            }
+            last_text = None;
+        } else if !trimmed.is_empty() && !trimmed.starts_with("<!--") {
+            has_body = true;
+            last_text = Some(trimmed);
+        }
+    }
+
+    if let Some(start) = code_start {
+        found.push((start, Problem::UnclosedCodeBlock));
+    }
+    if let Some(start) = math_start {
+        found.push((start, Problem::UnclosedMathBlock));
+    }
+    let lines = content.lines().count().max(1);
+    if !has_body {
+        found.push((1, Problem::Empty));
+    } else if last_text.is_some_and(|text| text.ends_with(DANGLING_ENDINGS)) {
+        found.push((lines, Problem::Truncated));
+    }
+}
+
+/// Whether a line has a `$` that opens inline math and is never closed.
+///
+/// Inline code is skipped, and an unclosed `$` before a digit is taken to be
+/// a price rather than math.
+fn inline_math_unbalanced(line: &str) -> bool {
+    let mut rest = line;
+    while let Some(c) = rest.chars().next() {
+        rest = &rest[c.len_utf8()..];
+        match c {
+            '\\' => {
+                if let Some(next) = rest.chars().next() {
+                    rest = &rest[next.len_utf8()..];
+                }
+            }
+            '`' => match rest.find('`') {
+                Some(end) => rest = &rest[end + 1..],
+                None => return false,
+            },
+            '$' if rest.starts_with('$') => match rest[1..].find("$$") {
+                Some(end) => rest = &rest[end + 3..],
+                None => return true,
+            },
+            '$' if !rest.starts_with(char::is_whitespace) && !rest.is_empty() => {
+                match rest.find('$') {
+                    Some(end) => rest = &rest[end + 1..],
+                    None => return !rest.starts_with(|c: char| c.is_ascii_digit()),
+                }
+            }
+            _ => {}
+        }
+    }
+    false
+}
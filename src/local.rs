@@ -0,0 +1,78 @@
+use crate::config::Config;
+use ipnet::IpNet;
+use std::net::{IpAddr, ToSocketAddrs};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum LocalError {
+    #[error("Invalid {0} {1}")]
+    InvalidUrl(&'static str, String),
+    #[error("Could not resolve {0} {1}: {2}")]
+    Unresolved(&'static str, String, std::io::Error),
+    #[error("{0} {1} resolves to {2}, which is not local (allow its range with --local-network)")]
+    Remote(&'static str, String, IpAddr),
+}
+
+/// Ranges that never leave the box or the private network: loopback,
+/// RFC 1918, link-local and IPv6 unique local addresses
+const PRIVATE_RANGES: &[&str] = &[
+    "127.0.0.0/8",
+    "10.0.0.0/8",
+    "172.16.0.0/12",
+    "192.168.0.0/16",
+    "169.254.0.0/16",
+    "::1/128",
+    "fc00::/7",
+    "fe80::/10",
+];
+
+/// Check that every endpoint notex would talk to with `--local-only` is
+/// local: the LLM, vision and transcription URLs, the proxy and the OTLP
+/// collector. Host names must resolve to local addresses only.
+pub fn check(config: &Config) -> Result<(), LocalError> {
+    let mut allowed: Vec<IpNet> = PRIVATE_RANGES
+        .iter()
+        .filter_map(|range| range.parse().ok())
+        .collect();
+    allowed.extend(config.local_networks.iter().copied());
+
+    let endpoints = [
+        ("--url", Some(&config.url)),
+        ("--vision-url", config.vision_url.as_ref()),
+        ("--transcribe-url", config.transcribe_url.as_ref()),
+        ("--proxy", config.proxy.as_ref()),
+        ("--otlp-endpoint", config.otlp_endpoint.as_ref()),
+    ];
+    for (flag, url) in endpoints {
+        if let Some(url) = url {
+            check_url(flag, url, &allowed)?;
+        }
+    }
+    Ok(())
+}
+
+fn check_url(flag: &'static str, url: &str, allowed: &[IpNet]) -> Result<(), LocalError> {
+    let invalid = || LocalError::InvalidUrl(flag, url.to_string());
+    let parsed = reqwest::Url::parse(url).map_err(|_| invalid())?;
+    let host = parsed.host_str().ok_or_else(invalid)?;
+    let port = parsed.port_or_known_default().unwrap_or(80);
+    // IPv6 hosts come bracketed
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+
+    let unresolved = |e| LocalError::Unresolved(flag, url.to_string(), e);
+    let addrs: Vec<_> = (host, port)
+        .to_socket_addrs()
+        .map_err(unresolved)?
+        .collect();
+    if addrs.is_empty() {
+        return Err(unresolved(std::io::Error::other("no addresses")));
+    }
+    for addr in addrs {
+        // IPv4-mapped IPv6 addresses count as their IPv4 address
+        let ip = addr.ip().to_canonical();
+        if !allowed.iter().any(|range| range.contains(&ip)) {
+            return Err(LocalError::Remote(flag, url.to_string(), ip));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,142 @@
+use crate::dates::entry_date;
+use crate::frontmatter::SourceMeta;
+use crate::types::{Category, EnhancedSegment};
+use std::collections::BTreeSet;
+
+/// Logseq page file path for an output path: `math/topology.md` -> `pages/math___topology.md`
+pub fn page_path(path: &str) -> String {
+    let name = path.strip_suffix(".md").unwrap_or(path);
+    let name = name.trim_matches('/').replace('/', "___");
+    format!("pages/{}.md", name)
+}
+
+/// Journal file path for a journal segment with a recognizable date
+pub fn journal_path(segment: &EnhancedSegment) -> Option<String> {
+    if segment.category != Category::Journal {
+        return None;
+    }
+
+    entry_date(&segment.original_path, &segment.content)
+        .map(|date| format!("journals/{}.md", date.format("%Y_%m_%d")))
+}
+
+/// Page name Logseq uses to link to a page or journal file
+pub fn page_name(path: &str) -> String {
+    let name = path.strip_suffix(".md").unwrap_or(path);
+    if let Some(journal) = name.strip_prefix("journals/") {
+        // Journal pages are linked by their date
+        return journal.replace('_', "-");
+    }
+    let name = name.strip_prefix("pages/").unwrap_or(name);
+    name.replace("___", "/")
+}
+
+/// Build the page-properties block that opens a Logseq page
+pub fn page_properties(segments: &[EnhancedSegment]) -> String {
+    let categories: BTreeSet<String> = segments.iter().map(|s| s.category.to_string()).collect();
+    let mut tags: BTreeSet<String> = categories.clone();
+    tags.extend(segments.iter().filter_map(|s| s.subcategory.clone()));
+    tags.extend(segments.iter().flat_map(|s| s.tags.iter().cloned()));
+    let sources: BTreeSet<String> = segments
+        .iter()
+        .map(|s| s.original_path.to_string_lossy().to_string())
+        .collect();
+
+    let join = |set: &BTreeSet<String>| set.iter().cloned().collect::<Vec<_>>().join(", ");
+    let mut out = format!("category:: {}\ntags:: {}\n", join(&categories), join(&tags));
+    let source = SourceMeta::merge(segments.iter().map(|s| &s.source));
+    if !source.aliases.is_empty() {
+        out.push_str(&format!("alias:: {}\n", source.aliases.join(", ")));
+    }
+    for (key, value) in &source.dates {
+        out.push_str(&format!("{}:: {}\n", key, value));
+    }
+    if !sources.is_empty() {
+        out.push_str(&format!("source:: {}\n", join(&sources)));
+    }
+    out.push('\n');
+    out
+}
+
+/// Number of leading `#` in a markdown heading line
+fn heading_level(line: &str) -> Option<usize> {
+    let level = line.chars().take_while(|c| *c == '#').count();
+    (level > 0 && line[level..].starts_with(' ')).then_some(level)
+}
+
+/// Split a list item into (indent, text) when the line is a bullet
+fn bullet_item(line: &str) -> Option<(usize, &str)> {
+    let trimmed = line.trim_start();
+    let indent = line.len() - trimmed.len();
+    ["- ", "* ", "+ "]
+        .iter()
+        .find_map(|marker| trimmed.strip_prefix(marker))
+        .map(|text| (indent, text))
+}
+
+/// Convert markdown into Logseq outliner blocks.
+///
+/// Headings open a block and nest the content that follows them, bullet
+/// lists keep their nesting, paragraphs and code fences become single blocks.
+pub fn to_outline(markdown: &str) -> String {
+    let mut out = String::new();
+    let mut base_depth = 0;
+    let mut min_heading: Option<usize> = None;
+    let mut lines = markdown.lines().peekable();
+
+    let push_block = |out: &mut String, depth: usize, block: &[&str]| {
+        let indent = "\t".repeat(depth);
+        for (i, line) in block.iter().enumerate() {
+            let prefix = if i == 0 { "- " } else { "  " };
+            out.push_str(&format!("{}{}{}\n", indent, prefix, line));
+        }
+    };
+
+    while let Some(line) = lines.next() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        if let Some(level) = heading_level(line) {
+            let min = *min_heading.get_or_insert(level);
+            let depth = level.saturating_sub(min);
+            push_block(&mut out, depth, &[line]);
+            base_depth = depth + 1;
+            continue;
+        }
+
+        if line.trim_start().starts_with("```") {
+            let mut block = vec![line.trim_start()];
+            for inner in lines.by_ref() {
+                block.push(inner);
+                if inner.trim_start().starts_with("```") {
+                    break;
+                }
+            }
+            push_block(&mut out, base_depth, &block);
+            continue;
+        }
+
+        if let Some((indent, text)) = bullet_item(line) {
+            push_block(&mut out, base_depth + indent / 2, &[text]);
+            continue;
+        }
+
+        // Paragraph: gather until a blank line or another block starts
+        let mut block = vec![line.trim()];
+        while let Some(next) = lines.peek() {
+            if next.trim().is_empty()
+                || heading_level(next).is_some()
+                || bullet_item(next).is_some()
+                || next.trim_start().starts_with("```")
+            {
+                break;
+            }
+            block.push(next.trim());
+            lines.next();
+        }
+        push_block(&mut out, base_depth, &block);
+    }
+
+    out
+}
@@ -1,57 +1,253 @@
+mod align;
+mod archive;
+mod atomic;
+mod breaker;
 mod categorizer;
+mod changes;
+mod chunking;
+mod clarify;
 mod client;
+mod cluster;
 mod config;
+mod consolidate;
+mod dates;
+mod dedupe;
+mod discovery;
+mod drift;
 mod enhancer;
+mod events;
+mod export;
+mod failures;
+mod fetcher;
+mod figures;
+mod frontmatter;
+mod git;
+mod glossary;
+mod graph;
+mod heuristics;
+mod hooks;
+mod html;
+mod http;
+mod indexer;
+mod journal;
+mod language;
+mod layout;
+mod links;
+mod lint;
+mod local;
+mod logseq;
+mod manifest;
+mod mcp;
+mod metrics;
+mod obsidian;
+mod ocr;
+mod originals;
+mod path_rules;
+mod preflight;
 mod processor;
+mod publish;
+mod questions;
+mod readers;
+mod related;
+mod reorg;
+mod roots;
+mod serve;
+mod srs;
+mod stats;
+mod tags;
+mod telemetry;
+mod template;
+mod toc;
+mod todos;
+mod tokens;
+mod transcribe;
+mod translate;
 mod types;
+mod verify;
+mod wikilinks;
 mod writer;
 
-use config::Config;
+use config::{Command, Config};
+use events::{Event, Events};
 use processor::Processor;
-use tracing::{error, info, Level};
-use tracing_subscriber::FmtSubscriber;
+use tracing::{error, info};
+use types::{EnhanceLevel, GraphFormat};
 
 #[tokio::main]
 async fn main() {
     let config = Config::parse_args();
 
-    // Setup logging
-    let log_level = if config.verbose {
-        Level::DEBUG
-    } else {
-        Level::INFO
-    };
+    // Setup logging and tracing
+    let telemetry = telemetry::init(&config);
+    // Published and indexed pages render through the same template
+    html::init(config.local_only);
 
-    FmtSubscriber::builder()
-        .with_max_level(log_level)
-        .with_target(false)
-        .with_thread_ids(false)
-        .compact()
-        .init();
+    if let Some(command) = &config.command {
+        let code = run_command(command).await;
+        // Exiting skips destructors, so spans are flushed first
+        telemetry.shutdown();
+        if code != 0 {
+            std::process::exit(code);
+        }
+        return;
+    }
+
+    if let Some(plan) = &config.apply_reorg {
+        info!(
+            "Applying reorganization plan {:?} to {:?}",
+            plan, config.output
+        );
+        match reorg::apply_plan(plan, &config.output) {
+            Ok(moves) => println!("\nApplied {} moves", moves.len()),
+            Err(e) => {
+                error!("Could not apply plan: {}", e);
+                telemetry.shutdown();
+                std::process::exit(1);
+            }
+        }
+        telemetry.shutdown();
+        return;
+    }
 
     info!("notex - AI-powered note compressor");
-    info!("Input: {:?}", config.input);
+    info!("Input: {:?}", config.input_dir());
     info!("Output: {:?}", config.output);
     info!("Model: {} @ {}", config.model, config.url);
-    info!("Parallel: {} | Retries: {}", config.parallel, config.retries);
+    info!(
+        "Parallel: {} (categorize {}, enhance {}) | Retries: {}",
+        config.parallel,
+        config.categorize_parallelism(),
+        config.enhance_parallelism(),
+        config.retries
+    );
     info!("Format: {:?}", config.format);
+    if config.local_only {
+        info!("Local only: every endpoint is on this machine or a private network");
+    }
 
     if config.dry_run {
         info!("Mode: DRY RUN (no files will be written)");
     }
+    if !config.only_categories.is_empty() {
+        info!("Only categories: {}", config.only_categories.join(", "));
+    }
+    if !config.never_categories.is_empty() {
+        info!("Never categories: {}", config.never_categories.join(", "));
+    }
+    if config.no_llm_categorize {
+        info!("Categorization: local rules (no LLM)");
+    }
+    if let Some(threshold) = config.confidence_threshold {
+        info!("Review threshold: {} confidence", threshold);
+    }
+    if config.no_enhance {
+        info!("Enhancement: DISABLED (original text kept verbatim)");
+    } else if config.enhance_level != EnhanceLevel::Standard {
+        info!("Enhancement level: {:?}", config.enhance_level);
+    }
+    if let Some(report) = &config.retry_failed {
+        info!("Retrying failures from {:?}", report);
+    }
+    if let Some(reference) = &config.since {
+        info!("Only notes changed since {}", reference);
+    }
+    if !config.include.is_empty() {
+        info!("Including: {:?}", config.include);
+    }
     if !config.exclude.is_empty() {
         info!("Excluding: {:?}", config.exclude);
     }
-    if config.reorganize {
+    if config.include_pdf {
+        info!("PDF ingestion: ENABLED");
+    }
+    if config.include_images {
+        info!(
+            "Image transcription: ENABLED ({})",
+            config.vision_model.as_deref().unwrap_or(&config.model)
+        );
+    }
+    if config.include_audio {
+        info!("Audio transcription: ENABLED ({})", config.transcribe_model);
+    }
+    if config.expand_links {
+        if config.expand_domains.is_empty() {
+            info!("Link expansion: ENABLED (all domains)");
+        } else {
+            info!(
+                "Link expansion: ENABLED ({})",
+                config.expand_domains.join(", ")
+            );
+        }
+    }
+    if config.reorganize_dry_run {
+        info!("Reorganization pass: DRY RUN (plan saved, no files moved)");
+    } else if config.reorganize {
         info!("Reorganization pass: ENABLED");
     }
     if config.cross_ref {
         info!("Cross-referencing: ENABLED");
     }
+    if config.summaries {
+        info!(
+            "Summaries: ENABLED (files with {}+ segments)",
+            config.summary_min_segments
+        );
+    }
+    if config.toc {
+        info!(
+            "Tables of contents: files with {}+ headings or {}+ lines",
+            config.toc_min_headers, config.toc_min_lines
+        );
+    }
+    if config.tags {
+        info!("Tagging: ENABLED (tags/index.md)");
+    }
+    if config.journal {
+        info!("Journal mode: entries filed by date under journal/YYYY/MM/");
+    }
+    if config.glossary {
+        info!("Glossaries: ENABLED");
+    }
+    if config.questions {
+        info!("Question lists: ENABLED");
+    }
+    if config.todos {
+        info!("TODO aggregation: ENABLED");
+    }
+    if config.index {
+        info!("Index generation: ENABLED");
+    }
+    if let Some(path) = &config.export_json {
+        info!("JSONL export: {:?}", path);
+    }
+    if config.git_commit {
+        info!("Git: committing the output when the run ends");
+    }
 
     let processor = Processor::new(config.clone());
 
-    match processor.run().await {
+    let events = Events::new(config.progress);
+    let result = processor.run().await;
+
+    if let Some(path) = &config.metrics_file {
+        if let Err(e) = metrics::global().write(path, result.is_ok()) {
+            error!("Could not write metrics to {}: {}", path.display(), e);
+        }
+    }
+
+    match result {
+        Ok(files) if events.enabled() => {
+            for (path, reason) in &processor.skipped_files() {
+                events.emit(Event::FileSkipped {
+                    path,
+                    reason: reason.to_string(),
+                });
+            }
+            events.emit(Event::Finished {
+                files: files.len(),
+                failures: processor.failure_count(),
+            });
+        }
         Ok(files) => {
             if !config.dry_run {
                 info!("Successfully processed notes!");
@@ -60,10 +256,146 @@ async fn main() {
                 }
                 println!("\nWrote {} files", files.len());
             }
+
+            let skipped = processor.skipped_files();
+            if !skipped.is_empty() {
+                println!("\nSkipped {} files:", skipped.len());
+                for (path, reason) in &skipped {
+                    println!("  {} ({})", path.display(), reason);
+                }
+            }
+
+            let guarded = processor.guarded_segments();
+            if !guarded.is_empty() {
+                println!("\nDrift guard stepped in for {} segments:", guarded.len());
+                for segment in &guarded {
+                    println!("  {}", segment);
+                }
+            }
         }
         Err(e) => {
             error!("Processing failed: {}", e);
-            std::process::exit(1);
+            events.emit(Event::Error {
+                stage: None,
+                path: None,
+                message: e.to_string(),
+            });
+            telemetry.shutdown();
+            std::process::exit(e.exit_code());
+        }
+    }
+
+    telemetry.shutdown();
+}
+
+/// Run a standalone subcommand, returning the process exit code
+async fn run_command(command: &Command) -> i32 {
+    match command {
+        Command::Publish {
+            source,
+            dest,
+            target,
+            title,
+        } => {
+            info!("Publishing {:?} as {:?} to {:?}", source, target, dest);
+            match publish::publish(source, dest, *target, title) {
+                Ok(files) => println!("\nPublished {} files to {}", files.len(), dest.display()),
+                Err(e) => {
+                    error!("Publishing failed: {}", e);
+                    return 1;
+                }
+            }
+        }
+        Command::Graph {
+            source,
+            format,
+            export,
+            output,
+        } => {
+            let records = match export.as_deref().map(export::read_jsonl).transpose() {
+                Ok(records) => records,
+                Err(e) => {
+                    error!("Failed to read export: {}", e);
+                    return 1;
+                }
+            };
+
+            let graph = graph::build_graph(source, records.as_deref());
+            let rendered = match format {
+                GraphFormat::Dot => graph::render_dot(&graph),
+                GraphFormat::Json => {
+                    serde_json::to_string_pretty(&graph).expect("graph serializes to JSON")
+                }
+            };
+
+            match output {
+                Some(path) => {
+                    if let Err(e) = std::fs::write(path, rendered) {
+                        error!("Failed to write graph: {}", e);
+                        return 1;
+                    }
+                    info!(
+                        "Wrote graph with {} nodes and {} edges to {:?}",
+                        graph.nodes.len(),
+                        graph.edges.len(),
+                        path
+                    );
+                }
+                None => print!("{}", rendered),
+            }
+        }
+        Command::Lint { source } => {
+            if !source.is_dir() {
+                error!("Not a directory: {}", source.display());
+                return 1;
+            }
+            let issues = lint::lint(source);
+            for issue in &issues {
+                println!("{}", issue);
+            }
+            if !issues.is_empty() {
+                println!("\nFound {} problems", issues.len());
+                return 1;
+            }
+            info!("No problems found in {}", source.display());
+        }
+        Command::Stats { source, json, top } => {
+            if !source.is_dir() {
+                error!("Not a directory: {}", source.display());
+                return 1;
+            }
+            let stats = stats::collect(source, *top);
+            if *json {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&stats).expect("stats serialize to JSON")
+                );
+            } else {
+                print!("{}", stats::render_table(&stats));
+            }
+        }
+        Command::Serve {
+            listen,
+            token,
+            inbox,
+            options,
+        } => {
+            let token = token.clone();
+            if let Err(e) = serve::serve(*listen, token, inbox.clone(), options.clone()).await {
+                error!("Server failed: {}", e);
+                return 1;
+            }
+        }
+        Command::Mcp {
+            source,
+            inbox,
+            options,
+        } => {
+            if let Err(e) = mcp::serve(source.clone(), inbox.clone(), options.clone()).await {
+                error!("MCP server failed: {}", e);
+                return 1;
+            }
         }
     }
+    0
 }
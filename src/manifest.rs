@@ -0,0 +1,200 @@
+use crate::failures::{Failure, Stage};
+use crate::metrics;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// File name of the manifest of the last run, in the output directory
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// Everything one processing run read and produced, replaced by each run.
+///
+/// Paths are relative to the input or output directory; files written under
+/// another `[roots]` directory keep their full path.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    /// When the run finished, local time
+    pub finished: String,
+    pub model: String,
+    pub inputs: Vec<InputFile>,
+    pub outputs: Vec<OutputFile>,
+    pub moves: Vec<FileMove>,
+    pub cross_refs: Vec<AddedLink>,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub errors: Vec<RunError>,
+}
+
+/// A note read by the run and the number of segments it was split into
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InputFile {
+    pub path: String,
+    pub segments: usize,
+}
+
+/// A file as the run left it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutputFile {
+    pub path: String,
+    /// Hex SHA-256 of the file's content
+    pub sha256: String,
+}
+
+/// A file moved by the reorganization pass
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileMove {
+    pub from: String,
+    pub to: String,
+}
+
+/// A cross-reference added from one output file to another
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AddedLink {
+    pub from: String,
+    pub to: String,
+}
+
+/// A note or segment that failed, without the segment itself
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunError {
+    pub stage: Stage,
+    pub path: String,
+    pub error: String,
+}
+
+impl Manifest {
+    /// An empty manifest for a run with `model` that finishes now, with the
+    /// tokens it used
+    pub fn new(model: &str) -> Self {
+        let (prompt_tokens, completion_tokens) = metrics::global().token_totals();
+        Self {
+            finished: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+            model: model.to_string(),
+            inputs: vec![],
+            outputs: vec![],
+            moves: vec![],
+            cross_refs: vec![],
+            prompt_tokens,
+            completion_tokens,
+            errors: vec![],
+        }
+    }
+
+    /// Record the notes read and the segments each gave
+    pub fn with_inputs(mut self, input_dir: &Path, inputs: &[(PathBuf, usize)]) -> Self {
+        let mut segments: BTreeMap<String, usize> = BTreeMap::new();
+        for (path, count) in inputs {
+            *segments.entry(relative(input_dir, path)).or_default() += count;
+        }
+        self.inputs = segments
+            .into_iter()
+            .map(|(path, segments)| InputFile { path, segments })
+            .collect();
+        self
+    }
+
+    /// Record the files written, hashed as they are now
+    pub fn with_outputs(mut self, output_dir: &Path, outputs: &[PathBuf]) -> Self {
+        self.outputs = outputs
+            .iter()
+            .filter_map(|path| match hash_file(path) {
+                Ok(sha256) => Some(OutputFile {
+                    path: relative(output_dir, path),
+                    sha256,
+                }),
+                Err(e) => {
+                    warn!("Could not hash {}: {}", path.display(), e);
+                    None
+                }
+            })
+            .collect();
+        self.outputs.sort_by(|a, b| a.path.cmp(&b.path));
+        self.outputs.dedup_by(|a, b| a.path == b.path);
+        self
+    }
+
+    /// Record the files moved by reorganization and the cross-references added
+    pub fn with_changes(
+        mut self,
+        output_dir: &Path,
+        moves: &[(PathBuf, PathBuf)],
+        links: &[(PathBuf, PathBuf)],
+    ) -> Self {
+        self.moves = moves
+            .iter()
+            .map(|(from, to)| FileMove {
+                from: relative(output_dir, from),
+                to: relative(output_dir, to),
+            })
+            .collect();
+        self.cross_refs = links
+            .iter()
+            .map(|(from, to)| AddedLink {
+                from: relative(output_dir, from),
+                to: relative(output_dir, to),
+            })
+            .collect();
+        self
+    }
+
+    /// Record the run's failures, sorted by path
+    pub fn with_errors(mut self, input_dir: &Path, failures: Vec<Failure>) -> Self {
+        self.errors = failures
+            .into_iter()
+            .map(|f| RunError {
+                stage: f.stage,
+                path: relative(input_dir, &f.path),
+                error: f.error,
+            })
+            .collect();
+        self.errors.sort_by(|a, b| a.path.cmp(&b.path));
+        self
+    }
+
+    /// Output files changed or removed since the run wrote them
+    pub fn edited(&self, output_dir: &Path) -> Vec<String> {
+        self.outputs
+            .iter()
+            .filter(|file| {
+                let path = output_dir.join(&file.path);
+                hash_file(&path).map_or(true, |sha256| sha256 != file.sha256)
+            })
+            .map(|file| file.path.clone())
+            .collect()
+    }
+}
+
+/// Write the manifest to the output directory, replacing the last run's
+pub fn write(output_dir: &Path, manifest: &Manifest) -> Result<(), std::io::Error> {
+    let json = serde_json::to_string_pretty(manifest).map_err(std::io::Error::other)?;
+    fs::create_dir_all(output_dir)?;
+    fs::write(output_dir.join(MANIFEST_FILE), json)
+}
+
+/// The manifest of the last run in the output directory, if there is one
+pub fn read(output_dir: &Path) -> Option<Manifest> {
+    let path = output_dir.join(MANIFEST_FILE);
+    let content = fs::read_to_string(&path).ok()?;
+    match serde_json::from_str(&content) {
+        Ok(manifest) => Some(manifest),
+        Err(e) => {
+            warn!("Ignoring {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+fn hash_file(path: &Path) -> Result<String, std::io::Error> {
+    Ok(format!("{:x}", Sha256::digest(fs::read(path)?)))
+}
+
+/// A path relative to `dir` with `/` separators, or as it is when outside it
+fn relative(dir: &Path, path: &Path) -> String {
+    path.strip_prefix(dir)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .replace('\\', "/")
+}
@@ -0,0 +1,287 @@
+use crate::indexer::collect_indexable;
+use crate::questions::unanswered_lines;
+use crate::serve::{run_pipeline, save_note, Run};
+use serde_json::{json, Value};
+use std::fs;
+use std::path::PathBuf;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tracing::{debug, info};
+
+/// Protocol version answered when the client does not name one
+const PROTOCOL_VERSION: &str = "2025-03-26";
+
+/// Most notes `search_notes` returns unless asked for another number
+const DEFAULT_RESULTS: usize = 5;
+
+/// Most open questions `open_questions` lists
+const MAX_QUESTIONS: usize = 50;
+
+/// A JSON-RPC error: code and message
+type RpcError = (i64, String);
+
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+/// The notes a Model Context Protocol client can file and search
+struct Tools {
+    /// Processed notes directory, searched and written to
+    output: PathBuf,
+    /// Filed notes are saved under here, one folder per note
+    inbox: PathBuf,
+    /// Options for the runs that file notes
+    options: Vec<String>,
+    filed: u64,
+}
+
+/// Serve the notex tools over the Model Context Protocol on stdin and
+/// stdout, one JSON-RPC message per line, until stdin closes
+pub async fn serve(output: PathBuf, inbox: PathBuf, options: Vec<String>) -> std::io::Result<()> {
+    let mut tools = Tools {
+        output,
+        inbox,
+        options,
+        filed: 0,
+    };
+    info!("Serving MCP tools for {}", tools.output.display());
+
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    let mut stdout = tokio::io::stdout();
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let reply = match serde_json::from_str::<Value>(&line) {
+            Ok(message) => tools.handle(&message).await,
+            Err(e) => Some(json!({
+                "jsonrpc": "2.0",
+                "id": null,
+                "error": { "code": -32700, "message": e.to_string() },
+            })),
+        };
+        if let Some(reply) = reply {
+            stdout.write_all(format!("{}\n", reply).as_bytes()).await?;
+            stdout.flush().await?;
+        }
+    }
+    Ok(())
+}
+
+impl Tools {
+    /// Answer a request; notifications get no answer
+    async fn handle(&mut self, message: &Value) -> Option<Value> {
+        let method = message["method"].as_str().unwrap_or_default();
+        let id = message.get("id")?.clone();
+        debug!("MCP request {}", method);
+
+        let params = &message["params"];
+        let result = match method {
+            "initialize" => Ok(json!({
+                "protocolVersion": params["protocolVersion"].as_str().unwrap_or(PROTOCOL_VERSION),
+                "capabilities": { "tools": {} },
+                "serverInfo": { "name": "notex", "version": env!("CARGO_PKG_VERSION") },
+            })),
+            "ping" => Ok(json!({})),
+            "tools/list" => Ok(json!({ "tools": tool_list() })),
+            "tools/call" => self.call(params).await,
+            _ => Err((METHOD_NOT_FOUND, format!("Unknown method {}", method))),
+        };
+
+        Some(match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err((code, message)) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": code, "message": message },
+            }),
+        })
+    }
+
+    /// Run a tool; its failures are reported in the result, for the model to see
+    async fn call(&mut self, params: &Value) -> Result<Value, RpcError> {
+        let args = &params["arguments"];
+        let text = |key: &str| args[key].as_str().map(str::to_string);
+        let outcome = match params["name"].as_str().unwrap_or_default() {
+            "file_note" => {
+                let content = text("content")
+                    .ok_or((INVALID_PARAMS, "file_note needs content".to_string()))?;
+                self.file_note(&content, text("name").as_deref()).await
+            }
+            "search_notes" => {
+                let query = text("query")
+                    .ok_or((INVALID_PARAMS, "search_notes needs a query".to_string()))?;
+                let limit = args["limit"]
+                    .as_u64()
+                    .map_or(DEFAULT_RESULTS, |n| n as usize);
+                Ok(self.search_notes(&query, limit))
+            }
+            "open_questions" => Ok(self.open_questions(text("topic").as_deref())),
+            name => return Err((INVALID_PARAMS, format!("Unknown tool {}", name))),
+        };
+
+        let (text, is_error) = match outcome {
+            Ok(text) => (text, false),
+            Err(text) => (text, true),
+        };
+        Ok(json!({
+            "content": [{ "type": "text", "text": text }],
+            "isError": is_error,
+        }))
+    }
+
+    /// Save the note to the inbox and run the pipeline on it, returning the
+    /// files written with their content
+    async fn file_note(&mut self, content: &str, name: Option<&str>) -> Result<String, String> {
+        self.filed += 1;
+        let stamp = chrono::Local::now().format("%Y%m%d-%H%M%S");
+        let dir = self.inbox.join(format!("{}-{}", stamp, self.filed));
+        save_note(&dir, name, content)
+            .await
+            .map_err(|e| format!("Could not save the note: {}", e))?;
+
+        let exe = std::env::current_exe().map_err(|e| e.to_string())?;
+        let mut options = vec!["-o".to_string(), self.output.display().to_string()];
+        options.extend(self.options.iter().cloned());
+        let mut run = Run::new(self.filed, dir.clone());
+        let status = run_pipeline(&exe, &dir, &options, |event| run.follow(event))
+            .await
+            .map_err(|e| format!("Could not run notex: {}", e))?;
+
+        if !status.success() || run.files.is_empty() {
+            let mut message = format!("Filing the note failed ({})", status);
+            for error in &run.errors {
+                message.push_str(&format!("\n- {}", error));
+            }
+            return Err(message);
+        }
+        let mut out = format!("Filed the note in {} files:", run.files.len());
+        for file in &run.files {
+            let content = fs::read_to_string(file).unwrap_or_default();
+            out.push_str(&format!(
+                "\n\n=== {}\n\n{}",
+                file.display(),
+                content.trim_end()
+            ));
+        }
+        Ok(out)
+    }
+
+    /// Processed notes that mention the most of the query's words, each with
+    /// the first line that does
+    fn search_notes(&self, query: &str, limit: usize) -> String {
+        let words: Vec<String> = query
+            .split_whitespace()
+            .map(str::to_lowercase)
+            .filter(|w| w.chars().count() > 1)
+            .collect();
+
+        let mut hits: Vec<(usize, String, String)> = Vec::new();
+        for file in collect_indexable(&self.output) {
+            let Ok(content) = fs::read_to_string(self.output.join(&file)) else {
+                continue;
+            };
+            let lower = content.to_lowercase();
+            let score: usize = words
+                .iter()
+                .map(|w| lower.matches(w.as_str()).count())
+                .sum();
+            if score == 0 {
+                continue;
+            }
+            let line = content
+                .lines()
+                .find(|line| {
+                    let line = line.to_lowercase();
+                    words.iter().any(|w| line.contains(w.as_str()))
+                })
+                .unwrap_or_default()
+                .trim()
+                .to_string();
+            hits.push((score, file, line));
+        }
+        hits.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+        hits.truncate(limit);
+
+        if hits.is_empty() {
+            return format!("No notes mention {}", query);
+        }
+        hits.iter()
+            .map(|(_, file, line)| format!("{}\n  {}", file, line))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Questions left unanswered in the processed notes, optionally only
+    /// those in files or lines mentioning `topic`
+    fn open_questions(&self, topic: Option<&str>) -> String {
+        let topic = topic.map(str::to_lowercase);
+        let mut found = Vec::new();
+        for file in collect_indexable(&self.output) {
+            let Ok(content) = fs::read_to_string(self.output.join(&file)) else {
+                continue;
+            };
+            let in_file = topic
+                .as_deref()
+                .is_none_or(|t| file.to_lowercase().contains(t));
+            for line in unanswered_lines(&content) {
+                let matches = in_file
+                    || topic
+                        .as_deref()
+                        .is_some_and(|t| line.to_lowercase().contains(t));
+                if matches {
+                    found.push(format!("{}: {}", file, line));
+                }
+            }
+        }
+
+        if found.is_empty() {
+            return "No open questions".to_string();
+        }
+        let total = found.len();
+        found.truncate(MAX_QUESTIONS);
+        let mut out = found.join("\n");
+        if total > MAX_QUESTIONS {
+            out.push_str(&format!("\n({} more)", total - MAX_QUESTIONS));
+        }
+        out
+    }
+}
+
+/// The tools and their argument schemas, for `tools/list`
+fn tool_list() -> Value {
+    json!([
+        {
+            "name": "file_note",
+            "description": "File a note: categorize it, enhance it and write it into the processed notes. Returns the files written.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "content": { "type": "string", "description": "The note, in markdown" },
+                    "name": { "type": "string", "description": "File name for the note, e.g. idea.md" }
+                },
+                "required": ["content"]
+            }
+        },
+        {
+            "name": "search_notes",
+            "description": "Find processed notes that mention the words of a query, best matches first.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "query": { "type": "string" },
+                    "limit": { "type": "integer", "description": "Most notes to return (default 5)" }
+                },
+                "required": ["query"]
+            }
+        },
+        {
+            "name": "open_questions",
+            "description": "List the [Q: ...] questions left without an answer in the processed notes, optionally only those about a topic.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "topic": { "type": "string", "description": "Only questions in notes or lines mentioning this" }
+                }
+            }
+        }
+    ])
+}
@@ -0,0 +1,275 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Upper bounds (seconds) of the latency histogram buckets
+const LATENCY_BUCKETS: &[f64] = &[0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0];
+
+static METRICS: OnceLock<Metrics> = OnceLock::new();
+
+/// Metrics for the current run
+pub fn global() -> &'static Metrics {
+    METRICS.get_or_init(Metrics::default)
+}
+
+#[derive(Debug, Default, Clone)]
+struct Histogram {
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        if self.buckets.is_empty() {
+            self.buckets = vec![0; LATENCY_BUCKETS.len()];
+        }
+        for (bucket, bound) in self.buckets.iter_mut().zip(LATENCY_BUCKETS) {
+            if value <= *bound {
+                *bucket += 1;
+            }
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    requests: BTreeMap<&'static str, u64>,
+    request_errors: BTreeMap<&'static str, u64>,
+    retries: BTreeMap<&'static str, u64>,
+    json_repairs: BTreeMap<&'static str, u64>,
+    latency: BTreeMap<&'static str, Histogram>,
+    prompt_tokens: BTreeMap<&'static str, u64>,
+    completion_tokens: BTreeMap<&'static str, u64>,
+    cached_tokens: BTreeMap<&'static str, u64>,
+    phase_seconds: BTreeMap<&'static str, f64>,
+    failures: BTreeMap<&'static str, u64>,
+}
+
+/// Counters and histograms collected over a run, rendered in Prometheus text format
+#[derive(Debug, Default)]
+pub struct Metrics {
+    inner: Mutex<Inner>,
+}
+
+impl Metrics {
+    /// Record one LLM request attempt and how long it took
+    pub fn record_request(&self, phase: &'static str, elapsed: Duration, ok: bool) {
+        let mut inner = self.inner.lock().unwrap();
+        *inner.requests.entry(phase).or_default() += 1;
+        if !ok {
+            *inner.request_errors.entry(phase).or_default() += 1;
+        }
+        inner
+            .latency
+            .entry(phase)
+            .or_default()
+            .observe(elapsed.as_secs_f64());
+    }
+
+    /// Record a retry after a failed attempt
+    pub fn record_retry(&self, phase: &'static str) {
+        *self.inner.lock().unwrap().retries.entry(phase).or_default() += 1;
+    }
+
+    /// Record a follow-up request asking the model to fix invalid JSON
+    pub fn record_json_repair(&self, phase: &'static str) {
+        *self
+            .inner
+            .lock()
+            .unwrap()
+            .json_repairs
+            .entry(phase)
+            .or_default() += 1;
+    }
+
+    /// Record the token usage reported by the server
+    pub fn record_tokens(&self, phase: &'static str, prompt: u32, completion: u32) {
+        let mut inner = self.inner.lock().unwrap();
+        *inner.prompt_tokens.entry(phase).or_default() += u64::from(prompt);
+        *inner.completion_tokens.entry(phase).or_default() += u64::from(completion);
+    }
+
+    /// Record prompt tokens the server answered from its prompt cache
+    pub fn record_cached_tokens(&self, phase: &'static str, cached: u32) {
+        *self
+            .inner
+            .lock()
+            .unwrap()
+            .cached_tokens
+            .entry(phase)
+            .or_default() += u64::from(cached);
+    }
+
+    /// Prompt and completion tokens reported so far, over all phases
+    pub fn token_totals(&self) -> (u64, u64) {
+        let inner = self.inner.lock().unwrap();
+        (
+            inner.prompt_tokens.values().sum(),
+            inner.completion_tokens.values().sum(),
+        )
+    }
+
+    /// Record the wall-clock time of a pipeline phase
+    pub fn record_phase(&self, phase: &'static str, elapsed: Duration) {
+        *self
+            .inner
+            .lock()
+            .unwrap()
+            .phase_seconds
+            .entry(phase)
+            .or_default() += elapsed.as_secs_f64();
+    }
+
+    /// Record a note or segment that failed
+    pub fn record_failure(&self, stage: &'static str) {
+        *self
+            .inner
+            .lock()
+            .unwrap()
+            .failures
+            .entry(stage)
+            .or_default() += 1;
+    }
+
+    /// Render every metric in the Prometheus text exposition format
+    pub fn render(&self, success: bool) -> String {
+        let inner = self.inner.lock().unwrap();
+        let mut out = String::new();
+
+        counter(
+            &mut out,
+            "notex_llm_requests_total",
+            "LLM request attempts",
+            "phase",
+            &inner.requests,
+        );
+        counter(
+            &mut out,
+            "notex_llm_request_errors_total",
+            "LLM request attempts that failed",
+            "phase",
+            &inner.request_errors,
+        );
+        counter(
+            &mut out,
+            "notex_llm_retries_total",
+            "LLM requests retried after a failure",
+            "phase",
+            &inner.retries,
+        );
+        counter(
+            &mut out,
+            "notex_llm_json_repairs_total",
+            "Follow-up requests asking the model to fix invalid JSON",
+            "phase",
+            &inner.json_repairs,
+        );
+        counter(
+            &mut out,
+            "notex_prompt_tokens_total",
+            "Prompt tokens reported by the server",
+            "phase",
+            &inner.prompt_tokens,
+        );
+        counter(
+            &mut out,
+            "notex_completion_tokens_total",
+            "Completion tokens reported by the server",
+            "phase",
+            &inner.completion_tokens,
+        );
+        counter(
+            &mut out,
+            "notex_cached_prompt_tokens_total",
+            "Prompt tokens the server reported as read from its prompt cache",
+            "phase",
+            &inner.cached_tokens,
+        );
+        counter(
+            &mut out,
+            "notex_failures_total",
+            "Notes and segments that failed",
+            "stage",
+            &inner.failures,
+        );
+
+        let _ = writeln!(
+            out,
+            "# HELP notex_llm_request_duration_seconds LLM request latency\n# TYPE notex_llm_request_duration_seconds histogram"
+        );
+        for (phase, histogram) in &inner.latency {
+            for (count, bound) in histogram.buckets.iter().zip(LATENCY_BUCKETS) {
+                let _ = writeln!(
+                    out,
+                    "notex_llm_request_duration_seconds_bucket{{phase=\"{}\",le=\"{}\"}} {}",
+                    phase, bound, count
+                );
+            }
+            let _ = writeln!(
+                out,
+                "notex_llm_request_duration_seconds_bucket{{phase=\"{}\",le=\"+Inf\"}} {}\nnotex_llm_request_duration_seconds_sum{{phase=\"{}\"}} {}\nnotex_llm_request_duration_seconds_count{{phase=\"{}\"}} {}",
+                phase, histogram.count, phase, histogram.sum, phase, histogram.count
+            );
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP notex_phase_duration_seconds Wall-clock time of each pipeline phase\n# TYPE notex_phase_duration_seconds gauge"
+        );
+        for (phase, seconds) in &inner.phase_seconds {
+            let _ = writeln!(
+                out,
+                "notex_phase_duration_seconds{{phase=\"{}\"}} {}",
+                phase, seconds
+            );
+        }
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let _ = writeln!(
+            out,
+            "# HELP notex_last_run_success Whether the last run finished without a fatal error\n# TYPE notex_last_run_success gauge\nnotex_last_run_success {}",
+            u8::from(success)
+        );
+        let _ = writeln!(
+            out,
+            "# HELP notex_last_run_timestamp_seconds When the last run finished\n# TYPE notex_last_run_timestamp_seconds gauge\nnotex_last_run_timestamp_seconds {}",
+            now
+        );
+
+        out
+    }
+
+    /// Write the metrics file, replacing it atomically so collectors never see half a file
+    pub fn write(&self, path: &Path, success: bool) -> Result<(), std::io::Error> {
+        if let Some(parent) = path.parent() {
+            if !parent.as_os_str().is_empty() {
+                std::fs::create_dir_all(parent)?;
+            }
+        }
+        let tmp = path.with_extension("prom.tmp");
+        std::fs::write(&tmp, self.render(success))?;
+        std::fs::rename(tmp, path)
+    }
+}
+
+/// Render a labelled counter family
+fn counter(
+    out: &mut String,
+    name: &str,
+    help: &str,
+    label: &str,
+    values: &BTreeMap<&'static str, u64>,
+) {
+    let _ = writeln!(out, "# HELP {} {}\n# TYPE {} counter", name, help, name);
+    for (value, count) in values {
+        let _ = writeln!(out, "{}{{{}=\"{}\"}} {}", name, label, value, count);
+    }
+}
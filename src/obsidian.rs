@@ -0,0 +1,107 @@
+use crate::frontmatter::SourceMeta;
+use crate::types::EnhancedSegment;
+use std::collections::BTreeSet;
+
+/// Characters Obsidian refuses in file names (or that break wikilinks)
+const FORBIDDEN_CHARS: &[char] = &['*', '"', '\\', '<', '>', ':', '|', '?', '#', '^', '[', ']'];
+
+/// Make a relative output path safe to open as part of an Obsidian vault
+pub fn vault_safe_path(path: &str) -> String {
+    path.split('/')
+        .filter(|part| !part.is_empty())
+        .map(vault_safe_name)
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Make a single path component safe for Obsidian
+fn vault_safe_name(name: &str) -> String {
+    let cleaned: String = name
+        .chars()
+        .map(|c| if FORBIDDEN_CHARS.contains(&c) { '-' } else { c })
+        .collect();
+
+    // Leading dots would hide the file from the vault
+    let cleaned = cleaned.trim_start_matches('.').trim();
+    if cleaned.is_empty() {
+        "untitled".to_string()
+    } else {
+        cleaned.to_string()
+    }
+}
+
+/// Convert a category or subcategory into a valid Obsidian tag
+fn tag_name(name: &str) -> String {
+    name.trim()
+        .to_lowercase()
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '_' || c == '-' || c == '/' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Collect the tags for a file from the categories of its segments
+pub fn tags_for(segments: &[EnhancedSegment]) -> Vec<String> {
+    let mut tags = BTreeSet::new();
+
+    for segment in segments {
+        let category = tag_name(&segment.category.to_string());
+        if category.is_empty() {
+            continue;
+        }
+        if let Some(sub) = segment.subcategory.as_deref().map(tag_name) {
+            if !sub.is_empty() {
+                tags.insert(format!("{}/{}", category, sub));
+            }
+        }
+        tags.insert(category);
+        tags.extend(
+            segment
+                .tags
+                .iter()
+                .map(|t| tag_name(t))
+                .filter(|t| !t.is_empty()),
+        );
+    }
+
+    tags.into_iter().collect()
+}
+
+/// Build the YAML frontmatter block for an Obsidian note
+pub fn frontmatter(segments: &[EnhancedSegment]) -> String {
+    let mut out = String::from("---\ntags:\n");
+    for tag in tags_for(segments) {
+        out.push_str(&format!("  - {}\n", tag));
+    }
+    out.push_str(&SourceMeta::merge(segments.iter().map(|s| &s.source)).yaml_fields());
+
+    let sources: BTreeSet<String> = segments
+        .iter()
+        .map(|s| s.original_path.to_string_lossy().to_string())
+        .collect();
+    if !sources.is_empty() {
+        out.push_str("sources:\n");
+        for source in sources {
+            out.push_str(&format!("  - \"{}\"\n", source.replace('"', "\\\"")));
+        }
+    }
+
+    out.push_str("---\n\n");
+    out
+}
+
+/// Build a wikilink to another note in the vault
+pub fn wikilink(path: &str) -> String {
+    let target = path.strip_suffix(".md").unwrap_or(path);
+    let label = target.rsplit('/').next().unwrap_or(target);
+    if label == target {
+        format!("[[{}]]", target)
+    } else {
+        format!("[[{}|{}]]", target, label)
+    }
+}
@@ -0,0 +1,56 @@
+use crate::client::{ClientError, LlmClient};
+use std::path::Path;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum OcrError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("LLM client error: {0}")]
+    Client(#[from] ClientError),
+}
+
+const OCR_SYSTEM_PROMPT: &str = r#"You are a transcription assistant. Given an image of notes (whiteboard photo, screenshot, handwritten page, slide), transcribe its content as text.
+
+Rules:
+- Transcribe all readable text faithfully, keeping the original language
+- Reproduce structure with markdown: headers, bullet points, tables
+- Write equations in LaTeX: inline $equation$ or block $$equation$$
+- Briefly describe diagrams or figures in [Figure: ...] brackets
+- Mark unreadable parts as [illegible]
+- Output ONLY the transcription, no meta-commentary"#;
+
+/// Image extensions sent to the vision model, with their MIME types
+const IMAGE_TYPES: &[(&str, &str)] = &[
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("webp", "image/webp"),
+];
+
+/// MIME type for an image extension, if it is one we can transcribe
+pub fn image_mime(ext: &str) -> Option<&'static str> {
+    IMAGE_TYPES
+        .iter()
+        .find(|(e, _)| *e == ext)
+        .map(|(_, mime)| *mime)
+}
+
+/// Transcribe an image file into note text using a vision-capable model
+pub async fn transcribe_image(
+    client: &LlmClient,
+    path: &Path,
+    mime: &str,
+) -> Result<String, OcrError> {
+    let image = tokio::fs::read(path).await?;
+    let user_prompt = format!(
+        "Transcribe this image of notes (file: {}).",
+        path.file_name().unwrap_or_default().to_string_lossy()
+    );
+
+    let text = client
+        .chat_with_image(OCR_SYSTEM_PROMPT, &user_prompt, &image, mime)
+        .await?;
+    Ok(text.trim().to_string())
+}
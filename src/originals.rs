@@ -0,0 +1,81 @@
+use crate::discovery::strip_ignored;
+use crate::links::relative_path;
+use crate::types::{EnhancedSegment, OutputFormat};
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::Path;
+use walkdir::DirEntry;
+
+/// Output folder holding copies of the source notes (`--keep-originals`)
+pub const ORIGINALS_DIR: &str = "_originals";
+
+/// Whether a walk of the output directory has reached the archive folder
+pub fn is_archive(entry: &DirEntry) -> bool {
+    entry.depth() == 1 && entry.file_name() == ORIGINALS_DIR
+}
+
+/// Where a source note is archived, relative to the output directory
+pub fn archive_path(input_dir: &Path, original: &Path) -> String {
+    let rel = original
+        .strip_prefix(input_dir)
+        .ok()
+        .or_else(|| original.file_name().map(Path::new))
+        .unwrap_or(original);
+    format!(
+        "{}/{}",
+        ORIGINALS_DIR,
+        rel.to_string_lossy().replace('\\', "/")
+    )
+}
+
+/// Copy source notes into `_originals/<relative path>`, returning how many were archived.
+///
+/// Text sources lose their `notex:ignore` regions on the way, like everything
+/// else written to the output directory.
+pub fn archive<'a>(
+    input_dir: &Path,
+    output_dir: &Path,
+    sources: impl IntoIterator<Item = &'a Path>,
+) -> io::Result<usize> {
+    let sources: BTreeSet<&Path> = sources.into_iter().collect();
+    for source in &sources {
+        let dest = output_dir.join(archive_path(input_dir, source));
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        match String::from_utf8(fs::read(source)?) {
+            Ok(text) => fs::write(&dest, strip_ignored(text).unwrap_or_default())?,
+            // Images, audio and documents are copied as they are
+            Err(e) => fs::write(&dest, e.into_bytes())?,
+        }
+    }
+    Ok(sources.len())
+}
+
+/// Add a link to its archived original after each segment written to `file`
+pub fn link_sources(
+    segments: Vec<EnhancedSegment>,
+    file: &str,
+    input_dir: &Path,
+    format: OutputFormat,
+) -> Vec<EnhancedSegment> {
+    segments
+        .into_iter()
+        .map(|mut segment| {
+            let archived = archive_path(input_dir, &segment.original_path);
+            let name = archived.rsplit('/').next().unwrap_or(&archived).to_string();
+            let link = relative_path(file, &archived);
+            let line = match format {
+                OutputFormat::Plain => format!("Original: {}", link),
+                // HTML pages and Logseq blocks are built from markdown
+                OutputFormat::Markdown
+                | OutputFormat::Obsidian
+                | OutputFormat::Html
+                | OutputFormat::Logseq => format!("*Original: [{}](<{}>)*", name, link),
+            };
+            segment.content = format!("{}\n\n{}", segment.content.trim_end(), line);
+            segment
+        })
+        .collect()
+}
@@ -0,0 +1,139 @@
+use crate::config::{Config, ConfigError};
+use crate::layout::segment_date;
+use crate::types::{Category, Segment};
+use glob::Pattern;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+/// A `[[paths]]` rule from the config file: segments matching every condition
+/// given are written to `path` instead of where the categorizer put them
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PathRule {
+    pub category: Option<String>,
+    pub subcategory: Option<String>,
+    /// Glob on the source note's path under the input directory, e.g. `work/**`
+    pub source: Option<String>,
+    /// Output path, with `{category}`, `{subcategory}`, `{year}`, `{month}`,
+    /// `{day}`, `{name}` (the source note's name) and `{title}` (the file
+    /// name the categorizer suggested) filled in
+    pub path: String,
+}
+
+/// A rule with its category normalized and its glob compiled
+struct Rule {
+    category: Option<Category>,
+    subcategory: Option<String>,
+    source: Option<Pattern>,
+    path: String,
+}
+
+/// The `[[paths]]` rules, ready to match
+pub struct PathRules {
+    input_dir: PathBuf,
+    rules: Vec<Rule>,
+}
+
+impl PathRules {
+    /// None when the config file has no `[[paths]]` rules. A source glob
+    /// that doesn't compile is an error rather than a rule matching every note.
+    pub fn new(config: &Config) -> Result<Option<Self>, ConfigError> {
+        let rules = config
+            .settings
+            .paths
+            .iter()
+            .map(|rule| {
+                let source = rule
+                    .source
+                    .as_deref()
+                    .map(|p| Pattern::new(p).map_err(|e| ConfigError::Glob(p.to_string(), e)))
+                    .transpose()?;
+                Ok(Rule {
+                    category: rule.category.as_deref().map(Category::from_name),
+                    subcategory: rule.subcategory.as_deref().map(normalize),
+                    source,
+                    path: rule.path.clone(),
+                })
+            })
+            .collect::<Result<Vec<_>, ConfigError>>()?;
+        Ok((!rules.is_empty()).then(|| Self {
+            input_dir: config.input_dir().to_path_buf(),
+            rules,
+        }))
+    }
+
+    /// Point each of a note's segments at the path of the first rule it
+    /// matches. Segments no rule matches keep their paths.
+    pub fn apply(&self, note_path: &Path, segments: &mut [Segment]) {
+        let rel_path = note_path.strip_prefix(&self.input_dir).unwrap_or(note_path);
+        for segment in segments.iter_mut() {
+            let subcategory = segment.subcategory.as_deref().map(normalize);
+            let rule = self.rules.iter().find(|rule| {
+                rule.category
+                    .as_ref()
+                    .is_none_or(|c| *c == segment.category)
+                    && rule
+                        .subcategory
+                        .as_ref()
+                        .is_none_or(|s| Some(s) == subcategory.as_ref())
+                    && rule
+                        .source
+                        .as_ref()
+                        .is_none_or(|p| p.matches_path(rel_path))
+            });
+            let Some(rule) = rule else {
+                continue;
+            };
+
+            let path = render(&rule.path, note_path, segment);
+            debug!(
+                "Path rule files a segment of {} under {}",
+                note_path.display(),
+                path
+            );
+            segment.paths = vec![path];
+            segment.cross_file_to.clear();
+        }
+    }
+}
+
+fn normalize(name: &str) -> String {
+    name.trim().to_lowercase().replace([' ', '-'], "_")
+}
+
+/// Fill in a rule's path for a segment
+fn render(template: &str, note_path: &Path, segment: &Segment) -> String {
+    let stem = |path: &Path| {
+        path.file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string()
+    };
+    let title = segment
+        .paths
+        .first()
+        .map(|p| stem(Path::new(p)))
+        .unwrap_or_else(|| stem(note_path));
+
+    let mut path = template
+        .replace("{category}", &segment.category.dir_name())
+        .replace(
+            "{subcategory}",
+            segment.subcategory.as_deref().unwrap_or("general"),
+        )
+        .replace("{name}", &stem(note_path))
+        .replace("{title}", &title);
+    if path.contains("{year}") || path.contains("{month}") || path.contains("{day}") {
+        let date = segment_date(note_path, segment);
+        let part = |format: &str| {
+            date.map(|d| d.format(format).to_string())
+                .unwrap_or_else(|| "undated".to_string())
+        };
+        path = path
+            .replace("{year}", &part("%Y"))
+            .replace("{month}", &part("%m"))
+            .replace("{day}", &part("%d"));
+    }
+    path
+}
@@ -0,0 +1,158 @@
+use crate::config::Config;
+use serde::Deserialize;
+use serde_json::Value;
+use std::time::Duration;
+use thiserror::Error;
+use tracing::{debug, info, warn};
+
+/// How long the server gets to answer each preflight request
+const PREFLIGHT_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Error, Debug)]
+pub enum PreflightError {
+    #[error("Cannot reach the LLM server at {0}: {1}. Is it running, and is --url right?")]
+    Unreachable(String, reqwest::Error),
+    #[error("The LLM server at {0} rejected the API key (HTTP {1}); check --api-key")]
+    Unauthorized(String, u16),
+    #[error("The LLM server at {0} answered HTTP {1}: {2}")]
+    Status(String, u16, String),
+}
+
+#[derive(Debug, Deserialize)]
+struct ModelList {
+    data: Vec<Value>,
+}
+
+/// Check the main LLM endpoint before any work starts: that it answers and
+/// that the API key is accepted, warning when it doesn't list `--model`.
+/// Servers without a `/models` endpoint pass. Logs the context window the
+/// server reports.
+pub async fn check(config: &Config) -> Result<(), PreflightError> {
+    let base = config.url.trim_end_matches('/');
+    let url = format!("{}/models", base);
+    let http = config
+        .http
+        .builder()
+        .timeout(PREFLIGHT_TIMEOUT)
+        .build()
+        .map_err(|e| PreflightError::Unreachable(base.to_string(), e))?;
+
+    let response = http
+        .get(&url)
+        .bearer_auth(&config.api_key)
+        .send()
+        .await
+        .map_err(|e| PreflightError::Unreachable(base.to_string(), e))?;
+    let status = response.status().as_u16();
+    match status {
+        401 | 403 => return Err(PreflightError::Unauthorized(base.to_string(), status)),
+        404 | 405 | 501 => {
+            debug!(
+                "{} does not list models (HTTP {}), skipping the model check",
+                url, status
+            );
+            return Ok(());
+        }
+        _ if !response.status().is_success() => {
+            let body = response.text().await.unwrap_or_default();
+            return Err(PreflightError::Status(
+                base.to_string(),
+                status,
+                body.chars().take(200).collect(),
+            ));
+        }
+        _ => {}
+    }
+
+    let Ok(list) = response.json::<ModelList>().await else {
+        debug!(
+            "{} returned an unexpected model list, skipping the model check",
+            url
+        );
+        return Ok(());
+    };
+    let ids: Vec<String> = list
+        .data
+        .iter()
+        .filter_map(|m| m["id"].as_str().map(str::to_string))
+        .collect();
+
+    let entry = list.data.iter().find(|m| {
+        m["id"]
+            .as_str()
+            .is_some_and(|id| same_model(id, &config.model))
+    });
+    let entry = match (entry, list.data.as_slice()) {
+        (Some(entry), _) => {
+            info!("LLM server at {} serves {}", base, config.model);
+            Some(entry)
+        }
+        // Single-model servers such as llama-server answer for any model name
+        (None, [only]) => {
+            warn!(
+                "Model {} is not listed by {}; it serves only {}, which will likely answer instead",
+                config.model, base, ids[0]
+            );
+            Some(only)
+        }
+        (None, []) => None,
+        // Some servers load models on demand without listing them
+        (None, _) => {
+            warn!(
+                "Model {} is not listed by {}, so requests may fail; available: {}",
+                config.model,
+                base,
+                ids.join(", ")
+            );
+            None
+        }
+    };
+
+    let context = match entry.and_then(model_context) {
+        Some(context) => Some(context),
+        None => server_context(&http, base).await,
+    };
+    match (context, config.context_size) {
+        (Some(server), Some(configured)) if configured > server => warn!(
+            "--context-size is {} tokens but the server reports {}; prompts may be truncated",
+            configured, server
+        ),
+        (Some(server), _) => info!("Context window: {} tokens", server),
+        (None, _) => debug!("The server does not report its context window"),
+    }
+    Ok(())
+}
+
+/// Whether two model names are the same, treating Ollama's default `:latest`
+/// tag as implied
+fn same_model(a: &str, b: &str) -> bool {
+    let untagged = |name: &str| name.strip_suffix(":latest").unwrap_or(name).to_string();
+    untagged(a) == untagged(b)
+}
+
+/// Context window from a `/models` entry (vLLM, OpenRouter and LM Studio style)
+fn model_context(model: &Value) -> Option<usize> {
+    ["max_model_len", "context_length", "max_context_length"]
+        .iter()
+        .find_map(|key| model[key].as_u64())
+        .map(|n| n as usize)
+}
+
+/// Per-request context window of a llama.cpp server, from `/props` next to
+/// the `/v1` API
+async fn server_context(http: &reqwest::Client, base: &str) -> Option<usize> {
+    let root = base.strip_suffix("/v1").unwrap_or(base);
+    let props: Value = http
+        .get(format!("{}/props", root))
+        .send()
+        .await
+        .ok()?
+        .error_for_status()
+        .ok()?
+        .json()
+        .await
+        .ok()?;
+    props["default_generation_settings"]["n_ctx"]
+        .as_u64()
+        .map(|n| n as usize)
+}
@@ -1,85 +1,304 @@
-use crate::categorizer::{categorize_note, CategorizationError};
-use crate::client::LlmClient;
+use crate::align;
+use crate::archive::{self, ArchiveError};
+use crate::atomic;
+use crate::categorizer::{categorize_note, CategorizationError, CategoryFilter, PromptContext};
+use crate::changes::{word_diff, write_changes, CHANGES_DIR};
+use crate::client::{ClientError, LlmClient};
+use crate::cluster::cluster;
 use crate::config::Config;
-use crate::enhancer::{enhance_segment, EnhancementError};
-use crate::types::{CrossRefResponse, EnhancedSegment, RawNote, ReorgResponse, Segment};
-use crate::writer::{group_by_output_path, write_outputs, WriterError};
-use futures::stream::{self, StreamExt};
-use glob::Pattern;
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
-use std::collections::HashMap;
-use std::path::PathBuf;
-use std::sync::Arc;
+use crate::consolidate;
+use crate::dedupe;
+use crate::discovery::{read_content, strip_ignored, Discovered, Discovery, SkipReason};
+use crate::drift::{Drift, GuardAction, Guarded, STRICT_INSTRUCTION};
+use crate::enhancer::{
+    enhance_long_segment, enhance_segment, get_enhancement_system_prompt, keep_original,
+    prepend_summary, summarize_file, EnhancementError,
+};
+use crate::events::{Event, Events};
+use crate::export::export_jsonl;
+use crate::failures::{FailureLog, ReportError, RetryPlan, Stage, REPORT_FILE};
+use crate::fetcher::{extract_urls, FetchError, LinkExpander};
+use crate::figures::{attach, describe_figures, Attachments, ATTACHMENTS_DIR};
+use crate::frontmatter::{self, SourceMeta};
+use crate::git::{self, GitError, RunSummary};
+use crate::glossary::{self, write_glossary, Term};
+use crate::heuristics::{routed, Heuristics};
+use crate::hooks::{self, RunData};
+use crate::indexer::{
+    collect_indexable, describe_file, describe_folder, folders, write_backlinks, write_indexes,
+    write_readme,
+};
+use crate::journal;
+use crate::language::preserve_instruction;
+use crate::layout;
+use crate::manifest::{self, Manifest, MANIFEST_FILE};
+use crate::metrics;
+use crate::ocr::transcribe_image;
+use crate::originals::{self, ORIGINALS_DIR};
+use crate::path_rules::PathRules;
+use crate::preflight::{self, PreflightError};
+use crate::questions::{write_questions, QUESTIONS_DIR};
+use crate::related::{merge_section, section_links, Section};
+use crate::reorg::{self, Move, ReorgError};
+use crate::roots::OutputRoots;
+use crate::srs::review_cards;
+use crate::stats::{record_run, RunRecord, RUNS_FILE};
+use crate::tags::{suggest_tags, write_tag_index};
+use crate::template::{Template, TemplateError};
+use crate::toc::{self, TocRule};
+use crate::todos::{self, write_todo_index, SCHEDULE_BATCH};
+use crate::tokens::{self, count_tokens};
+use crate::transcribe::transcribe_audio;
+use crate::translate::{language_dir, translate};
+use crate::types::{
+    Category, ClarifyMode, CrossRefResponse, CrossReference, DriftGuard, EnhanceLevel,
+    EnhancedSegment, Layout, OutputFormat, RawNote, ReorgResponse, Segment,
+};
+use crate::verify::{self, missing_facts, retention_instruction, VERIFY_REPORT_FILE};
+use crate::wikilinks::NoteLinks;
+use crate::writer::{
+    group_by_output_path, is_safe_path, normalize_output_paths, write_outputs, WriterError,
+};
+use futures::future;
+use futures::stream::{self, Stream, StreamExt};
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use thiserror::Error;
 use tokio::sync::Semaphore;
 use tracing::{debug, error, info, warn};
-use walkdir::WalkDir;
 
 #[derive(Error, Debug)]
 pub enum ProcessorError {
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
+    #[error("LLM client error: {0}")]
+    Client(#[from] ClientError),
+    #[error("Failed to parse LLM response: {0}")]
+    Parse(#[from] serde_json::Error),
     #[error("Categorization error: {0}")]
     Categorization(#[from] CategorizationError),
     #[error("Enhancement error: {0}")]
     Enhancement(#[from] EnhancementError),
     #[error("Writer error: {0}")]
     Writer(#[from] WriterError),
+    #[error("Link expansion error: {0}")]
+    Fetch(#[from] FetchError),
+    #[error("{0}")]
+    Report(#[from] ReportError),
+    #[error("{0}")]
+    Reorg(#[from] ReorgError),
+    #[error("{0}")]
+    Template(#[from] TemplateError),
+    #[error("{0}")]
+    Preflight(#[from] PreflightError),
+    #[error("{0}")]
+    Git(#[from] GitError),
+    #[error("{0}")]
+    Archive(#[from] ArchiveError),
+    #[error("stopped by --fail-fast: {0}")]
+    FailFast(String),
+    #[error("failures over the {threshold}% threshold ({breakdown})")]
+    FailThreshold { threshold: f64, breakdown: String },
 }
 
+impl ProcessorError {
+    /// Process exit code: 2 when the failure policy stopped the run, 1 for other errors
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ProcessorError::FailFast(_) | ProcessorError::FailThreshold { .. } => 2,
+            _ => 1,
+        }
+    }
+}
+
+/// Files the walk may queue ahead of the pipeline
+const DISCOVERY_BUFFER: usize = 256;
+
+/// Output folder for segments below `--confidence-threshold`
+const REVIEW_DIR: &str = "review";
+
+/// Characters of a file embedded, after its path, to cluster it for reorganization
+const EMBED_CHARS: usize = 2000;
+
+/// Files embedded per request
+const EMBED_BATCH: usize = 64;
+
 /// Main processor that orchestrates the entire pipeline
 pub struct Processor {
     client: LlmClient,
+    vision_client: LlmClient,
+    attachments: Attachments,
+    audio_client: LlmClient,
+    /// Clusters files for the reorganization pass (`--embedding-model`)
+    embed_client: Option<LlmClient>,
     config: Config,
     semaphore: Arc<Semaphore>,
-    exclude_patterns: Vec<Pattern>,
+    categorize_semaphore: Arc<Semaphore>,
+    enhance_semaphore: Arc<Semaphore>,
+    discovery: Discovery,
+    /// Local rules used instead of the LLM with `--no-llm-categorize`
+    heuristics: Option<Heuristics>,
+    path_rules: Option<PathRules>,
+    roots: OutputRoots,
+    category_filter: CategoryFilter,
+    categorize_context: PromptContext,
+    failures: FailureLog,
+    events: Events,
+    current_phase: Mutex<Option<(&'static str, Instant)>>,
+    /// Segments `--drift-guard` stepped in for
+    guarded: Mutex<Vec<Guarded>>,
 }
 
 impl Processor {
     pub fn new(config: Config) -> Self {
-        let client = LlmClient::new(&config.url, &config.api_key, &config.model, config.retries);
+        tokens::init(&config.model);
+        let client = LlmClient::new(&config.url, &config.api_key, &config.model, config.retries)
+            .with_http(&config.http)
+            .with_backoff(config.backoff())
+            .with_breaker(config.circuit_breaker())
+            .with_prompt_cache(config.prompt_cache)
+            .with_context_size(config.context_size)
+            .with_sampling(config.sampling());
+        let vision_client = LlmClient::new(
+            config.vision_url.as_deref().unwrap_or(&config.url),
+            config.vision_api_key.as_deref().unwrap_or(&config.api_key),
+            config.vision_model.as_deref().unwrap_or(&config.model),
+            config.retries,
+        )
+        .with_http(&config.http)
+        .with_backoff(config.backoff())
+        .with_breaker(config.circuit_breaker())
+        .with_prompt_cache(config.prompt_cache)
+        .with_sampling(config.sampling())
+        .for_phase("transcribe");
+        let audio_client = LlmClient::new(
+            config.transcribe_url.as_deref().unwrap_or(&config.url),
+            config
+                .transcribe_api_key
+                .as_deref()
+                .unwrap_or(&config.api_key),
+            &config.transcribe_model,
+            config.retries,
+        )
+        .with_http(&config.http)
+        .with_backoff(config.backoff())
+        .with_breaker(config.circuit_breaker())
+        .for_phase("transcribe");
+        let embed_client = config.embedding_model.as_deref().map(|model| {
+            LlmClient::new(&config.url, &config.api_key, model, config.retries)
+                .with_http(&config.http)
+                .with_backoff(config.backoff())
+                .with_breaker(config.circuit_breaker())
+                .for_phase("reorganize")
+        });
         let semaphore = Arc::new(Semaphore::new(config.parallel));
+        let categorize_semaphore = Arc::new(Semaphore::new(config.categorize_parallelism()));
+        let enhance_semaphore = Arc::new(Semaphore::new(config.enhance_parallelism()));
 
-        // Parse exclude patterns
-        let exclude_patterns: Vec<Pattern> = config
-            .exclude
-            .iter()
-            .filter_map(|p| Pattern::new(p).ok())
-            .collect();
+        let discovery = Discovery::new(&config);
+        let heuristics = config.no_llm_categorize.then(|| Heuristics::new(&config));
+        let path_rules =
+            PathRules::new(&config).expect("[[paths]] globs are checked with the arguments");
+        let roots = OutputRoots::new(&config);
+        atomic::init_backups(config.backup, &roots.all(), config.backup_keep);
+        let category_filter =
+            CategoryFilter::new(&config.only_categories, &config.never_categories)
+                .with_custom(&config.configured_categories(), config.new_categories);
+        let categorize_context = PromptContext {
+            profile: config.profile().map(str::to_string),
+            examples: config.settings.categorize.examples.clone(),
+            clarify: config.clarify,
+        };
+        let events = Events::new(config.progress);
 
         Self {
             client,
+            vision_client,
+            attachments: Attachments::default(),
+            audio_client,
+            embed_client,
             config,
             semaphore,
-            exclude_patterns,
+            categorize_semaphore,
+            enhance_semaphore,
+            discovery,
+            heuristics,
+            path_rules,
+            roots,
+            category_filter,
+            categorize_context,
+            failures: FailureLog::default(),
+            events,
+            current_phase: Mutex::new(None),
+            guarded: Mutex::default(),
         }
     }
 
-    /// Check if a path should be excluded
-    fn is_excluded(&self, path: &std::path::Path) -> bool {
-        let path_str = path.to_string_lossy();
-        self.exclude_patterns
-            .iter()
-            .any(|p| p.matches(&path_str) || p.matches(path.file_name().unwrap_or_default().to_str().unwrap_or("")))
+    /// Mark the start of a phase: time it and report it to `--progress json` consumers
+    fn phase(&self, phase: u8, name: &'static str) {
+        self.end_phase();
+        *self.current_phase.lock().unwrap() = Some((name, Instant::now()));
+        self.events.emit(Event::PhaseStarted { phase, name });
+    }
+
+    /// Record the duration of the running phase, if any
+    fn end_phase(&self) {
+        if let Some((name, started)) = self.current_phase.lock().unwrap().take() {
+            metrics::global().record_phase(name, started.elapsed());
+        }
+    }
+
+    /// Files discovery passed over, with the reason for each
+    pub fn skipped_files(&self) -> Vec<(PathBuf, SkipReason)> {
+        self.discovery.skipped_files()
+    }
+
+    /// Segments `--drift-guard` replaced or enhanced again
+    pub fn guarded_segments(&self) -> Vec<Guarded> {
+        self.guarded.lock().unwrap().clone()
     }
 
     /// Run the full processing pipeline
     pub async fn run(&self) -> Result<Vec<PathBuf>, ProcessorError> {
-        let mp = MultiProgress::new();
-
-        // Phase 1: Discovery & Ingestion
-        info!("Phase 1: Discovering notes in {:?}", self.config.input);
-        let notes = self.discover_notes()?;
-        info!("Found {} notes", notes.len());
+        let result = self.run_phases().await;
+        self.end_phase();
+        result
+    }
 
-        if notes.is_empty() {
-            warn!("No notes found to process");
-            return Ok(vec![]);
+    async fn run_phases(&self) -> Result<Vec<PathBuf>, ProcessorError> {
+        // Checked up front so a bad template fails before any LLM calls
+        let template = self
+            .config
+            .template
+            .as_deref()
+            .map(Template::load)
+            .transpose()?;
+        if !self.config.no_preflight && self.config.uses_llm() {
+            preflight::check(&self.config).await?;
+        }
+        let mp = MultiProgress::new();
+        // Bars would draw over `--clarify ask` questions
+        if self.events.enabled() || self.config.clarify == ClarifyMode::Ask {
+            mp.set_draw_target(ProgressDrawTarget::hidden());
         }
 
-        // Phase 2: Categorization (parallel)
-        info!("Phase 2: Categorizing notes...");
-        let cat_pb = mp.add(ProgressBar::new(notes.len() as u64));
+        // Phases 1 & 2: Discovery streams straight into categorization, so the
+        // first notes are categorized while the walk is still running
+        info!(
+            "Phase 1: Discovering notes in {:?}",
+            self.config.input_dir()
+        );
+        info!("Phase 2: Categorizing notes as they are found...");
+        // Discovery overlaps categorization, so it isn't timed on its own
+        self.events.emit(Event::PhaseStarted {
+            phase: 1,
+            name: "discover",
+        });
+        self.phase(2, "categorize");
+        let cat_pb = mp.add(ProgressBar::new(0));
         cat_pb.set_style(
             ProgressStyle::default_bar()
                 .template("{spinner:.green} [{bar:40.cyan/blue}] {pos}/{len} Categorizing")
@@ -87,33 +306,154 @@ impl Processor {
                 .progress_chars("#>-"),
         );
 
-        let categorized = self.categorize_all(notes, cat_pb.clone()).await;
+        // When retrying, only failed notes are rediscovered; with --since,
+        // only notes changed since the given commit
+        let retry = match &self.config.retry_failed {
+            Some(report) => {
+                let plan = RetryPlan::load(report)?;
+                info!(
+                    "Retrying {} notes and {} segments from {:?}",
+                    plan.notes.len(),
+                    plan.segments.len(),
+                    report
+                );
+                Some(plan)
+            }
+            None => None,
+        };
+        let discovery = match (&retry, &self.config.since) {
+            (Some(plan), _) => self.discovery.clone().restrict_to(plan.notes.clone()),
+            (None, Some(reference)) => {
+                let changed = git::changed_since(self.config.input_dir(), reference)?;
+                info!("{} files changed since {}", changed.len(), reference);
+                self.discovery.clone().restrict_to(changed)
+            }
+            (None, None) => self.discovery.clone(),
+        };
+
+        let (rx, walk) = discovery.spawn(DISCOVERY_BUFFER);
+        let notes = stream::unfold(rx, |mut rx| async move {
+            rx.recv().await.map(|item| (item, rx))
+        })
+        .take_while(|_| future::ready(!self.aborted()))
+        .map(|item| {
+            cat_pb.inc_length(1);
+            self.load(item, cat_pb.clone())
+        })
+        .buffer_unordered(self.config.parallel)
+        .filter_map(future::ready);
+
+        let mut categorized = if self.config.dedupe {
+            // Copies can only be told apart once every note has been read
+            let mut notes: Vec<RawNote> = notes.collect().await;
+            for (path, original) in dedupe::dedupe(&mut notes) {
+                self.discovery.skip(&path, SkipReason::Duplicate(original));
+                cat_pb.inc(1);
+            }
+            self.categorize_all(stream::iter(notes), cat_pb.clone())
+                .await
+        } else {
+            self.categorize_all(notes, cat_pb.clone()).await
+        };
+        let found = walk.await.map_err(std::io::Error::other)??;
         cat_pb.finish_with_message("Categorization complete");
+        self.check_fail_fast()?;
+
+        // Segments that only failed enhancement skip straight to Phase 3
+        if let Some(plan) = retry.as_ref() {
+            for (path, segment) in &plan.segments {
+                categorized.push((path.clone(), vec![segment.clone()]));
+            }
+        }
+
+        if let Some(command) = &self.config.settings.hooks.after_categorize {
+            categorized = hooks::after_categorize(command, categorized).await;
+        }
+
+        info!("Found {} notes", found);
+        let skipped = self.discovery.skipped_files().len();
+        if skipped > 0 {
+            info!("Skipped {} binary, oversized or ignored files", skipped);
+        }
+
+        if found == 0 && categorized.is_empty() {
+            warn!("No notes found to process");
+            return Ok(vec![]);
+        }
+        if categorized.is_empty() {
+            warn!("No notes could be categorized");
+            self.save_failures()?;
+            self.check_fail_threshold(found, 0)?;
+            return Ok(vec![]);
+        }
 
         let total_segments: usize = categorized.iter().map(|(_, s)| s.len()).sum();
         info!("Categorized into {} segments", total_segments);
+        let for_review = categorized
+            .iter()
+            .flat_map(|(_, segments)| segments)
+            .filter(|s| self.needs_review(s))
+            .count();
+        if for_review > 0 {
+            warn!(
+                "{} low-confidence segments filed under {}/ for review",
+                for_review, REVIEW_DIR
+            );
+        }
 
         // Dry run: just show the plan
+        if self.config.dry_run && self.events.enabled() {
+            for (path, segments) in &categorized {
+                for seg in segments {
+                    self.events.emit(Event::SegmentPlanned {
+                        path,
+                        category: seg.category.to_string(),
+                        subcategory: seg.subcategory.as_deref(),
+                        confidence: seg.confidence,
+                        paths: &seg.paths,
+                    });
+                }
+            }
+            return Ok(vec![]);
+        }
         if self.config.dry_run {
             println!("\n=== DRY RUN: Categorization Plan ===\n");
             for (path, segments) in &categorized {
                 println!("  {}", path.display());
                 for seg in segments {
+                    let confidence = seg
+                        .confidence
+                        .map(|c| format!(" ({:.2})", c))
+                        .unwrap_or_default();
                     println!(
-                        "   → {}/{} → {:?}",
+                        "   → {}/{}{} → {:?}",
                         seg.category,
                         seg.subcategory.as_deref().unwrap_or("general"),
+                        confidence,
                         seg.paths
                     );
                 }
             }
-            println!("\nTotal: {} notes → {} segments", categorized.len(), total_segments);
+            println!(
+                "\nTotal: {} notes → {} segments",
+                categorized.len(),
+                total_segments
+            );
             println!("Run without --dry-run to process and write files.");
             return Ok(vec![]);
         }
 
+        // Link expansion (optional): summarize pages behind bare URLs
+        let categorized = if self.config.expand_links {
+            info!("Expanding links in link notes...");
+            self.expand_links(categorized).await?
+        } else {
+            categorized
+        };
+
         // Phase 3: Enhancement (parallel)
         info!("Phase 3: Enhancing segments...");
+        self.phase(3, "enhance");
         let enh_pb = mp.add(ProgressBar::new(total_segments as u64));
         enh_pb.set_style(
             ProgressStyle::default_bar()
@@ -125,89 +465,563 @@ impl Processor {
         let enhanced = self.enhance_all(categorized.clone(), enh_pb.clone()).await;
         enh_pb.finish_with_message("Enhancement complete");
         info!("Enhanced {} segments", enhanced.len());
+        self.check_fail_fast()?;
 
         // Phase 4: Output
         info!("Phase 4: Writing output files...");
-        let grouped = group_by_output_path(enhanced.clone());
-        let written = write_outputs(&self.config.output, grouped, self.config.format)?;
-        info!("Wrote {} files to {:?}", written.len(), self.config.output);
+        self.phase(4, "write");
+        let mut enhanced = enhanced;
+        normalize_output_paths(
+            &mut enhanced,
+            self.config.on_collision,
+            self.config.max_depth(),
+        )?;
+        let mut grouped = group_by_output_path(enhanced.clone());
+        // Retries and --since runs only see part of the notes, so they add to
+        // existing files instead of replacing them, and leave links and the tag
+        // index alone
+        let partial = retry.is_some() || self.config.since.is_some();
+        let note_links = (!partial).then(|| {
+            NoteLinks::new(
+                self.config.input_dir(),
+                &grouped,
+                self.config.format,
+                &self.roots,
+            )
+        });
+        if self.config.journal {
+            journal::sort_entries(&mut grouped);
+        }
+        if self.config.summaries && !partial {
+            self.add_summaries(&mut grouped).await;
+        }
+        if let Some(language) = &self.config.translate_to {
+            if self.config.translate_subtree {
+                let mut translated = grouped.clone();
+                self.translate_all(&mut translated, language).await;
+                let dir = language_dir(language);
+                grouped.extend(
+                    translated
+                        .into_iter()
+                        .map(|(path, segments)| (format!("{}/{}", dir, path), segments)),
+                );
+            } else {
+                self.translate_all(&mut grouped, language).await;
+            }
+        }
+        if self.config.tags && partial {
+            info!("Left the tag index as it is, since this run only saw some of the notes");
+        } else if self.config.tags {
+            let index = write_tag_index(&self.config.output, &grouped, self.config.format)?;
+            info!("Wrote tag index {}", index.display());
+        }
+        if let Some(note_links) = &note_links {
+            let relinked = note_links.relink(&mut grouped);
+            if relinked.resolved + relinked.unresolved > 0 {
+                info!(
+                    "Pointed {} links between notes at their new files ({} not found)",
+                    relinked.resolved, relinked.unresolved
+                );
+            }
+        }
+        if let Some(command) = &self.config.settings.hooks.before_write {
+            hooks::before_write(command, &mut grouped).await;
+        }
+        let mut written = Vec::new();
+        for (root, files) in self.roots.split(grouped) {
+            // Links to originals and attachments are relative, so each root gets its own copies
+            let sources: BTreeSet<PathBuf> = files
+                .values()
+                .flatten()
+                .map(|s| s.original_path.clone())
+                .collect();
+            let files = write_outputs(
+                &root,
+                files,
+                self.config.format,
+                partial,
+                self.config.keep_originals.then(|| self.config.input_dir()),
+                self.config.layout == Layout::ByDate || self.config.max_depth() == Some(0),
+                template.as_ref(),
+            )?;
+            for file in &files {
+                self.events.emit(Event::FileWritten { path: file });
+            }
+            info!("Wrote {} files to {:?}", files.len(), root);
+            written.extend(files);
+
+            if self.config.keep_originals {
+                let archived = originals::archive(
+                    self.config.input_dir(),
+                    &root,
+                    sources.iter().map(PathBuf::as_path),
+                )?;
+                info!(
+                    "Archived {} originals to {}",
+                    archived,
+                    root.join(ORIGINALS_DIR).display()
+                );
+            }
+            let copied = self.attachments.copy(&root)?;
+            if copied > 0 {
+                info!(
+                    "Copied {} attachments to {}",
+                    copied,
+                    root.join(ATTACHMENTS_DIR).display()
+                );
+            }
+        }
+        if self.config.toc
+            && matches!(
+                self.config.format,
+                OutputFormat::Markdown | OutputFormat::Obsidian
+            )
+        {
+            let rule = TocRule {
+                min_headers: self.config.toc_min_headers,
+                min_lines: self.config.toc_min_lines,
+            };
+            let updated = toc::update_files(&written, self.config.format, rule);
+            info!("Updated tables of contents in {} files", updated);
+        }
+        if self.config.show_changes {
+            let files = write_changes(&self.config.output, self.config.input_dir(), &enhanced)?;
+            info!("Wrote the changes to {} notes to {}/", files, CHANGES_DIR);
+        }
+        self.save_failures()?;
+        if self.config.verify {
+            let flagged = verify::write_report(&self.config.output, &enhanced)?;
+            if flagged > 0 {
+                warn!(
+                    "{} segments lost facts during enhancement; see {}",
+                    flagged,
+                    self.config.output.join(VERIFY_REPORT_FILE).display()
+                );
+            }
+        }
+
+        // Outputs and the failure report are written first so a failed run can be retried
+        self.check_fail_threshold(found, total_segments)?;
 
         // Phase 5: Reorganization pass (optional)
-        if self.config.reorganize {
+        let mut moves = Vec::new();
+        if self.config.reorganize || self.config.reorganize_dry_run {
             info!("Phase 5: Running reorganization pass...");
-            self.run_reorganization(&written).await?;
+            self.phase(5, "reorganize");
+            for (root, files) in self.roots.group(&written.clone()) {
+                for mv in self.run_reorganization(root, &files).await? {
+                    let (from, to) = (root.join(&mv.from), root.join(&mv.to));
+                    for file in written.iter_mut().filter(|f| **f == from) {
+                        *file = to.clone();
+                    }
+                    moves.push((from, to));
+                }
+            }
         }
 
         // Phase 6: Cross-referencing (optional)
-        if self.config.cross_ref {
+        let mut cross_refs = Vec::new();
+        // The same links with the full paths of both files, for the manifest
+        let mut links = Vec::new();
+        if self.config.cross_ref || self.config.cross_ref_dry_run {
             info!("Phase 6: Adding cross-references...");
-            self.run_cross_referencing(&written).await?;
+            self.phase(6, "cross_reference");
+            for (root, files) in self.roots.group(&written) {
+                let added = self.run_cross_referencing(root, &files).await?;
+                links.extend(
+                    added
+                        .iter()
+                        .map(|x| (root.join(&x.from_file), root.join(&x.to_file))),
+                );
+                cross_refs.extend(added);
+            }
+        }
+
+        // Phase 7: Glossaries (optional)
+        if self.config.glossary {
+            info!("Phase 7: Building category glossaries...");
+            self.phase(7, "glossary");
+            self.run_glossaries(&enhanced).await?;
+        }
+
+        // Phase 8: Question lists (optional)
+        if self.config.questions {
+            info!("Phase 8: Collecting open questions...");
+            self.phase(8, "questions");
+            let lists = write_questions(&self.config.output, self.config.format)?;
+            info!("Wrote {} question lists to {}/", lists.len(), QUESTIONS_DIR);
+        }
+
+        // Phase 9: Index and README generation (optional)
+        if self.config.index || self.config.readmes {
+            info!("Phase 9: Generating indexes...");
+            self.phase(9, "index");
+            let descriptions = self.describe_outputs().await;
+            if self.config.index {
+                let indexes =
+                    write_indexes(&self.config.output, &descriptions, self.config.format)?;
+                info!("Wrote {} index files", indexes.len());
+            }
+            if self.config.readmes {
+                let readmes = self.write_readmes(&descriptions).await?;
+                info!("Wrote {} folder READMEs", readmes);
+            }
+        }
+
+        // Phase 10: Task aggregation (optional); replaces the todo category's index
+        if self.config.todos {
+            info!("Phase 10: Aggregating tasks...");
+            self.phase(10, "todos");
+            self.run_todos().await?;
         }
 
+        // Phase 11: Structured export (optional)
+        if let Some(path) = &self.config.export_json {
+            info!("Phase 11: Exporting segments to {:?}...", path);
+            self.phase(11, "export");
+            let count = export_jsonl(path, &enhanced, &cross_refs, self.config.format)?;
+            info!("Exported {} segments", count);
+        }
+
+        self.record_run(&categorized, &enhanced);
+        self.write_manifest(&categorized, &written, &moves, &links);
+        if self.config.git_commit {
+            self.commit_output(
+                categorized.len(),
+                enhanced.len(),
+                written.len(),
+                moves.len(),
+            );
+        }
+        if let Some(path) = &self.config.archive {
+            let format = archive::Format::from_path(path).expect("checked by --archive's parser");
+            let packed = archive::write(&self.config.output, path, format)?;
+            info!("Archived {} files to {}", packed, path.display());
+        }
+        if let Some(command) = &self.config.settings.hooks.after_run {
+            let data = RunData {
+                output: &self.config.output,
+                files: &written,
+                notes: categorized.len(),
+                segments: enhanced.len(),
+                failures: self.failures.len(),
+            };
+            hooks::after_run(command, &data).await;
+        }
+        if self.config.archive_only {
+            std::fs::remove_dir_all(&self.config.output)?;
+        }
         Ok(written)
     }
 
-    /// Discover all notes in the input directory
-    fn discover_notes(&self) -> Result<Vec<RawNote>, std::io::Error> {
-        let mut notes = Vec::new();
+    /// Commit everything the run changed in the output directory (`--git-commit`)
+    fn commit_output(&self, notes: usize, segments: usize, written: usize, moved: usize) {
+        let (prompt_tokens, completion_tokens) = metrics::global().token_totals();
+        let summary = RunSummary {
+            notes,
+            segments,
+            written,
+            moved,
+            model: &self.config.model,
+            prompt_tokens,
+            completion_tokens,
+        };
+        match git::commit_run(&self.config.output, &summary) {
+            Ok(Some(hash)) => info!("Committed the output as {}", hash),
+            Ok(None) => info!("Nothing to commit in {:?}", self.config.output),
+            Err(e) => warn!("Could not commit the output: {}", e),
+        }
+    }
 
-        for entry in WalkDir::new(&self.config.input)
-            .follow_links(true)
-            .into_iter()
-            .filter_map(|e| e.ok())
-        {
-            let path = entry.path();
+    /// Append this run to the output directory's history for `notex stats`
+    fn record_run(&self, categorized: &[(PathBuf, Vec<Segment>)], enhanced: &[EnhancedSegment]) {
+        let mut segments_per_category: BTreeMap<String, usize> = BTreeMap::new();
+        for segment in enhanced {
+            *segments_per_category
+                .entry(segment.category.dir_name())
+                .or_default() += 1;
+        }
+        let (prompt_tokens, completion_tokens) = metrics::global().token_totals();
+        let record = RunRecord {
+            finished: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+            notes: categorized.len(),
+            segments: enhanced.len(),
+            segments_per_category,
+            input_chars: categorized
+                .iter()
+                .flat_map(|(_, segments)| segments)
+                .map(|s| s.content.chars().count())
+                .sum(),
+            output_chars: enhanced.iter().map(|s| s.content.chars().count()).sum(),
+            prompt_tokens,
+            completion_tokens,
+        };
+        if let Err(e) = record_run(&self.config.output, &record) {
+            warn!("Could not record run in {}: {}", RUNS_FILE, e);
+        }
+    }
 
-            // Skip directories
-            if path.is_dir() {
-                continue;
-            }
+    /// Describe the run in `manifest.json`: notes read, files written and
+    /// their hashes, moves, cross-references, tokens and failures
+    fn write_manifest(
+        &self,
+        categorized: &[(PathBuf, Vec<Segment>)],
+        written: &[PathBuf],
+        moves: &[(PathBuf, PathBuf)],
+        links: &[(PathBuf, PathBuf)],
+    ) {
+        let inputs: Vec<(PathBuf, usize)> = categorized
+            .iter()
+            .map(|(path, segments)| (path.clone(), segments.len()))
+            .collect();
+        let output = &self.config.output;
+        let manifest = Manifest::new(&self.config.model)
+            .with_inputs(self.config.input_dir(), &inputs)
+            .with_outputs(output, written)
+            .with_changes(output, moves, links)
+            .with_errors(self.config.input_dir(), self.failures.all());
+        if let Err(e) = manifest::write(output, &manifest) {
+            warn!("Could not write {}: {}", MANIFEST_FILE, e);
+        }
+    }
 
-            // Skip hidden files
-            if path
-                .file_name()
-                .map(|n| n.to_string_lossy().starts_with('.'))
-                .unwrap_or(false)
-            {
-                continue;
+    /// Number of notes and segments that failed so far
+    pub fn failure_count(&self) -> usize {
+        self.failures.len()
+    }
+
+    /// Record a failed note in the report and the event stream
+    fn note_failed(&self, stage: Stage, path: &Path, error: impl ToString) {
+        let message = error.to_string();
+        self.events.emit(Event::Error {
+            stage: Some(stage),
+            path: Some(path),
+            message: message.clone(),
+        });
+        metrics::global().record_failure(stage.as_str());
+        self.failures.note(stage, path, message);
+    }
+
+    /// Record a segment that failed enhancement in the report and the event stream
+    fn segment_failed(&self, path: &Path, segment: &Segment, error: impl ToString) {
+        let message = error.to_string();
+        self.events.emit(Event::Error {
+            stage: Some(Stage::Enhance),
+            path: Some(path),
+            message: message.clone(),
+        });
+        metrics::global().record_failure(Stage::Enhance.as_str());
+        self.failures.segment(path, segment, message);
+    }
+
+    /// With `--fail-fast`, whether a failure means the run should stop
+    fn aborted(&self) -> bool {
+        self.config.fail_fast && !self.failures.is_empty()
+    }
+
+    /// Stop the run after a phase if `--fail-fast` tripped during it
+    fn check_fail_fast(&self) -> Result<(), ProcessorError> {
+        match self.failures.first() {
+            Some(failure) if self.config.fail_fast => {
+                self.save_failures()?;
+                Err(ProcessorError::FailFast(format!(
+                    "{} failed for {}: {}",
+                    failure.stage.as_str(),
+                    failure.path.display(),
+                    failure.error
+                )))
             }
+            _ => Ok(()),
+        }
+    }
 
-            // Skip excluded patterns
-            if self.is_excluded(path) {
-                debug!("Excluded: {}", path.display());
-                continue;
+    /// Fail the run if the share of failed notes or segments is over `--fail-threshold`
+    fn check_fail_threshold(&self, notes: usize, segments: usize) -> Result<(), ProcessorError> {
+        let Some(threshold) = self.config.fail_threshold else {
+            return Ok(());
+        };
+
+        let counts = self.failures.counts();
+        let failed_notes: usize = counts
+            .iter()
+            .filter(|(stage, _)| **stage != Stage::Enhance)
+            .map(|(_, n)| n)
+            .sum();
+        let failed_segments = counts.get(&Stage::Enhance).copied().unwrap_or(0);
+
+        let percent = |failed: usize, total: usize| {
+            if total == 0 {
+                0.0
+            } else {
+                failed as f64 * 100.0 / total as f64
             }
+        };
+        if percent(failed_notes, notes) <= threshold
+            && percent(failed_segments, segments) <= threshold
+        {
+            return Ok(());
+        }
 
-            // Read file content
-            match std::fs::read_to_string(path) {
-                Ok(content) => {
-                    if !content.trim().is_empty() {
-                        debug!("Discovered: {}", path.display());
-                        notes.push(RawNote {
-                            path: path.to_path_buf(),
-                            content,
-                        });
-                    }
-                }
-                Err(e) => {
+        let breakdown = counts
+            .iter()
+            .map(|(stage, failed)| {
+                let (total, unit) = match stage {
+                    Stage::Enhance => (segments, "segments"),
+                    _ => (notes, "notes"),
+                };
+                format!(
+                    "{}: {} of {} {} ({:.1}%)",
+                    stage.as_str(),
+                    failed,
+                    total,
+                    unit,
+                    percent(*failed, total)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        Err(ProcessorError::FailThreshold {
+            threshold,
+            breakdown,
+        })
+    }
+
+    /// Write the failure report next to the outputs, if anything failed
+    fn save_failures(&self) -> Result<(), ProcessorError> {
+        if self.config.dry_run {
+            return Ok(());
+        }
+
+        let path = self.config.output.join(REPORT_FILE);
+        let count = self.failures.save(&path)?;
+        if count > 0 {
+            warn!(
+                "{} items failed; rerun them with --retry-failed {}",
+                count,
+                path.display()
+            );
+        }
+        Ok(())
+    }
+
+    /// Read or transcribe a discovered file into a note.
+    ///
+    /// Files that yield no note still count as done on the progress bar.
+    async fn load(&self, item: Discovered, pb: ProgressBar) -> Option<RawNote> {
+        let is_text = matches!(item, Discovered::Text { .. });
+        let (path, result) = match item {
+            Discovered::Text { path, rule } => {
+                let include_pdf = self.config.include_pdf;
+                let read_path = path.clone();
+                let result = tokio::task::spawn_blocking(move || {
+                    read_content(&read_path, rule, include_pdf)
+                })
+                .await
+                .map_err(|e| e.to_string())
+                .and_then(|r| r.map_err(|e| e.to_string()));
+                if let Err(e) = &result {
                     warn!("Could not read {}: {}", path.display(), e);
+                    self.note_failed(Stage::Read, &path, e);
+                    pb.inc(1);
+                    return None;
                 }
+                (path, result)
             }
-        }
+            Discovered::Image { path, mime } => {
+                let _permit = self.semaphore.acquire().await.unwrap();
+                debug!("Transcribing image: {}", path.display());
+                let result = transcribe_image(&self.vision_client, &path, mime)
+                    .await
+                    .map_err(|e| e.to_string());
+                (path, result)
+            }
+            Discovered::Audio { path } => {
+                let _permit = self.semaphore.acquire().await.unwrap();
+                debug!("Transcribing audio: {}", path.display());
+                let result = transcribe_audio(&self.audio_client, &path)
+                    .await
+                    .map_err(|e| e.to_string());
+                (path, result)
+            }
+        };
 
-        Ok(notes)
+        let mut note = match result.map(strip_ignored) {
+            Ok(None) => {
+                self.discovery.skip(&path, SkipReason::Ignored);
+                None
+            }
+            Ok(Some(content)) if !content.trim().is_empty() => {
+                let lines = content.lines().count();
+                let (frontmatter, content) = frontmatter::take(content);
+                Some(RawNote {
+                    routing: frontmatter::routing(&frontmatter),
+                    path,
+                    first_line: lines - content.lines().count() + 1,
+                    duplicates: Vec::new(),
+                    content,
+                    frontmatter,
+                })
+            }
+            Ok(_) => {
+                debug!("Nothing to process in {}", path.display());
+                None
+            }
+            Err(e) => {
+                error!("Failed to transcribe {}: {}", path.display(), e);
+                self.note_failed(Stage::Transcribe, &path, e);
+                None
+            }
+        };
+        if let Some(note) = note.as_mut().filter(|_| is_text) {
+            let input_dir = self.config.input_dir();
+            if self.config.describe_images {
+                let _permit = self.semaphore.acquire().await.unwrap();
+                note.content = describe_figures(
+                    &self.vision_client.for_phase("describe"),
+                    input_dir,
+                    &note.path,
+                    std::mem::take(&mut note.content),
+                    self.config.max_image_size * 1024 * 1024,
+                )
+                .await;
+            }
+            note.content = attach(
+                &self.attachments,
+                input_dir,
+                &note.path,
+                std::mem::take(&mut note.content),
+            );
+        }
+        if let (Some(command), Some(raw)) = (&self.config.settings.hooks.after_discovery, &mut note)
+        {
+            let content = std::mem::take(&mut raw.content);
+            match hooks::after_discovery(command, &raw.path, content).await {
+                Some(content) => raw.content = content,
+                None => {
+                    self.discovery.skip(&raw.path, SkipReason::Hook);
+                    note = None;
+                }
+            }
+        }
+        if note.is_none() {
+            pb.inc(1);
+        }
+        note
     }
 
-    /// Categorize all notes in parallel
+    /// Categorize notes in parallel as they arrive
     async fn categorize_all(
         &self,
-        notes: Vec<RawNote>,
+        notes: impl Stream<Item = RawNote>,
         pb: ProgressBar,
     ) -> Vec<(PathBuf, Vec<Segment>)> {
-        let client = self.client.clone();
-        let semaphore = self.semaphore.clone();
+        let client = self.client.for_phase("categorize");
+        let semaphore = self.categorize_semaphore.clone();
 
-        let results: Vec<_> = stream::iter(notes)
+        let max_note_tokens = self.config.max_note_tokens;
+        let chunk_overlap = self.config.chunk_overlap;
+
+        let results: Vec<_> = notes
             .map(|note| {
                 let client = client.clone();
                 let semaphore = semaphore.clone();
@@ -217,17 +1031,88 @@ impl Processor {
                     let _permit = semaphore.acquire().await.unwrap();
                     debug!("Categorizing: {}", note.path.display());
 
-                    let result = match categorize_note(&client, &note).await {
-                        Ok(segments) => {
+                    let routed = note
+                        .routing
+                        .as_ref()
+                        .and_then(|routing| routed(&note, routing));
+                    let categorized = match (routed, &self.heuristics) {
+                        (Some(segment), _) => {
+                            debug!("{} routed by its frontmatter", note.path.display());
+                            let mut segments = vec![segment];
+                            self.category_filter
+                                .enforce(&mut segments)
+                                .map(|()| segments)
+                        }
+                        (None, Some(heuristics)) => {
+                            let mut segments = heuristics.categorize(&note);
+                            self.category_filter
+                                .enforce(&mut segments)
+                                .map(|()| segments)
+                        }
+                        (None, None) => {
+                            categorize_note(
+                                &client,
+                                &note,
+                                max_note_tokens,
+                                chunk_overlap,
+                                &self.config.split_on,
+                                &self.category_filter,
+                                &self.categorize_context,
+                            )
+                            .await
+                        }
+                    };
+                    let result = match categorized {
+                        Ok(mut segments) => {
+                            for segment in &mut segments {
+                                segment.lines = align::line_range(
+                                    &note.content,
+                                    note.first_line,
+                                    &segment.content,
+                                );
+                            }
+                            consolidate::consolidate(
+                                &note.path,
+                                &mut segments,
+                                self.config.min_segment_chars,
+                                self.config.max_segments_per_note,
+                                self.config.segment_merge,
+                            );
+                            let source = SourceMeta::new(&note.frontmatter);
+                            for segment in &mut segments {
+                                segment.source = source.clone();
+                                segment.duplicates = dedupe::copies_within(&note, segment.lines);
+                            }
+                            if note.routing.as_ref().is_some_and(|r| r.skip_enhance) {
+                                for segment in &mut segments {
+                                    segment.skip_enhance = true;
+                                }
+                            }
+                            let pinned = note.routing.as_ref().is_some_and(|r| r.path.is_some());
+                            if !pinned {
+                                layout::apply(self.config.layout, &note.path, &mut segments);
+                            }
+                            if self.config.journal && !pinned {
+                                journal::route(&note.path, &mut segments);
+                            }
+                            if let Some(rules) = self.path_rules.as_ref().filter(|_| !pinned) {
+                                rules.apply(&note.path, &mut segments);
+                            }
+                            self.route_for_review(&note.path, &mut segments);
                             debug!(
                                 "Categorized {} into {} segments",
                                 note.path.display(),
                                 segments.len()
                             );
+                            self.events.emit(Event::NoteCategorized {
+                                path: &note.path,
+                                segments: segments.len(),
+                            });
                             Some((note.path, segments))
                         }
                         Err(e) => {
                             error!("Failed to categorize {}: {}", note.path.display(), e);
+                            self.note_failed(Stage::Categorize, &note.path, e);
                             None
                         }
                     };
@@ -235,47 +1120,263 @@ impl Processor {
                     result
                 }
             })
-            .buffer_unordered(self.config.parallel)
+            .buffer_unordered(self.config.categorize_parallelism())
             .collect()
             .await;
 
         results.into_iter().flatten().collect()
     }
 
+    /// Whether a segment's confidence is below `--confidence-threshold`
+    fn needs_review(&self, segment: &Segment) -> bool {
+        match (self.config.confidence_threshold, segment.confidence) {
+            (Some(threshold), Some(confidence)) => confidence < threshold,
+            _ => false,
+        }
+    }
+
+    /// File low-confidence segments under `review/`, keeping the suggested
+    /// path underneath so they are easy to move once checked
+    fn route_for_review(&self, path: &Path, segments: &mut [Segment]) {
+        for segment in segments.iter_mut().filter(|s| self.needs_review(s)) {
+            debug!(
+                "Low confidence ({:.2}) for a {} segment of {}",
+                segment.confidence.unwrap_or_default(),
+                segment.category,
+                path.display()
+            );
+            segment.paths = segment
+                .paths
+                .iter()
+                .map(|p| format!("{}/{}", REVIEW_DIR, p))
+                .collect();
+            segment.cross_file_to.clear();
+        }
+    }
+
+    /// Fetch and summarize the URLs in `links` segments, appending the summaries
+    async fn expand_links(
+        &self,
+        mut categorized: Vec<(PathBuf, Vec<Segment>)>,
+    ) -> Result<Vec<(PathBuf, Vec<Segment>)>, ProcessorError> {
+        let expander = LinkExpander::new(
+            Duration::from_secs(self.config.fetch_timeout),
+            &self.config.expand_domains,
+            &self.config.http,
+        )?;
+
+        let mut tasks = Vec::new();
+        for (i, (_, segments)) in categorized.iter().enumerate() {
+            for (j, segment) in segments.iter().enumerate() {
+                if segment.category != Category::Links {
+                    continue;
+                }
+                for url in extract_urls(&segment.content) {
+                    if expander.is_allowed(&url) {
+                        tasks.push((i, j, url));
+                    } else {
+                        debug!("Not expanding {} (domain not allowed)", url);
+                    }
+                }
+            }
+        }
+        info!("Fetching {} links...", tasks.len());
+
+        let client = self.client.for_phase("expand_links");
+        let semaphore = self.semaphore.clone();
+
+        let mut results: Vec<_> = stream::iter(tasks)
+            .map(|(i, j, url)| {
+                let client = client.clone();
+                let semaphore = semaphore.clone();
+                let expander = expander.clone();
+
+                async move {
+                    let _permit = semaphore.acquire().await.unwrap();
+                    match expander.summarize(&client, &url).await {
+                        Ok(summary) => Some((i, j, url, summary)),
+                        Err(e) => {
+                            warn!("Could not expand {}: {}", url, e);
+                            None
+                        }
+                    }
+                }
+            })
+            .buffer_unordered(self.config.parallel)
+            .filter_map(|r| async { r })
+            .collect()
+            .await;
+
+        // Keep summaries in the order the links appear in each segment
+        results.sort_by_key(|(i, j, url, _)| {
+            let pos = categorized[*i].1[*j].content.find(url.as_str());
+            (*i, *j, pos)
+        });
+
+        let mut expanded = 0;
+        let mut last: Option<(usize, usize)> = None;
+        for (i, j, url, summary) in results {
+            let segment = &mut categorized[i].1[j];
+            if last != Some((i, j)) {
+                segment.content.push_str("\n\n## Link summaries\n");
+                last = Some((i, j));
+            }
+            segment
+                .content
+                .push_str(&format!("\n### <{}>\n\n{}\n", url, summary));
+            expanded += 1;
+        }
+        info!("Expanded {} links", expanded);
+
+        Ok(categorized)
+    }
+
     /// Enhance all segments in parallel
     async fn enhance_all(
         &self,
         categorized: Vec<(PathBuf, Vec<Segment>)>,
         pb: ProgressBar,
     ) -> Vec<EnhancedSegment> {
-        // Flatten into (path, segment) pairs
+        // Flatten into (path, position, segment) triples
         let tasks: Vec<_> = categorized
             .into_iter()
             .flat_map(|(path, segments)| {
                 segments
                     .into_iter()
-                    .map(move |segment| (path.clone(), segment))
+                    .enumerate()
+                    .map(move |(position, segment)| (path.clone(), position, segment))
             })
             .collect();
 
-        let client = self.client.clone();
-        let semaphore = self.semaphore.clone();
+        let client = self.client.for_phase("enhance");
+        let tag_client = self.client.for_phase("tag");
+        let srs_client = self.client.for_phase("srs");
+        let verify_client = self.client.for_phase("verify");
+        let semaphore = self.enhance_semaphore.clone();
         let format = self.config.format;
 
         let results: Vec<_> = stream::iter(tasks)
-            .map(|(path, segment)| {
+            .take_while(|_| future::ready(!self.aborted()))
+            .map(|(path, position, segment)| {
                 let client = client.clone();
+                let tag_client = tag_client.clone();
+                let srs_client = srs_client.clone();
+                let verify_client = verify_client.clone();
                 let semaphore = semaphore.clone();
                 let pb = pb.clone();
 
                 async move {
-                    let _permit = semaphore.acquire().await.unwrap();
                     debug!("Enhancing segment from: {}", path.display());
 
-                    let result = match enhance_segment(&client, &segment, &path, format).await {
-                        Ok(enhanced) => Some(enhanced),
+                    let level = self.config.enhance_level(&segment.category);
+                    // Translation happens after enhancement, so the enhancer keeps the note's language
+                    let language = self
+                        .config
+                        .translate_to
+                        .is_none()
+                        .then(|| preserve_instruction(&segment.content));
+                    let system_prompt = get_enhancement_system_prompt(
+                        format,
+                        level,
+                        self.config.enhance_instructions(&segment.category),
+                        language.as_deref(),
+                        self.config.profile(),
+                    );
+                    let enhanced = if segment.skip_enhance || level == EnhanceLevel::Off {
+                        Ok(keep_original(&segment, &path))
+                    } else {
+                        let enhanced = self
+                            .enhance_with(&client, &semaphore, &segment, &path, &system_prompt)
+                            .await;
+                        let enhanced = match enhanced {
+                            Ok(enhanced) if self.config.verify => Ok(self
+                                .verify_enhanced(
+                                    &verify_client,
+                                    &client,
+                                    &segment,
+                                    &path,
+                                    &system_prompt,
+                                    enhanced,
+                                )
+                                .await),
+                            other => other,
+                        };
+                        match enhanced {
+                            Ok(enhanced) if self.config.drift_guard != DriftGuard::Off => Ok(self
+                                .guard_drift(
+                                    &client,
+                                    &segment,
+                                    &path,
+                                    position,
+                                    &system_prompt,
+                                    enhanced,
+                                )
+                                .await),
+                            other => other,
+                        }
+                    };
+
+                    let enhanced = enhanced.map(|mut enhanced| {
+                        if self.config.show_changes && !segment.skip_enhance && level != EnhanceLevel::Off {
+                            let diff = word_diff(&segment.content, &enhanced.content);
+                            if self.config.verbose {
+                                pb.suspend(|| {
+                                    eprintln!(
+                                        "--- {} (segment {})\n{}\n",
+                                        path.display(),
+                                        position + 1,
+                                        diff
+                                    )
+                                });
+                            }
+                            enhanced.changes = Some(diff);
+                        }
+                        enhanced
+                    });
+                    let result = match enhanced {
+                        Ok(mut enhanced) => {
+                            enhanced.position = position;
+                            if self.config.tags {
+                                let _permit = semaphore.acquire().await.unwrap();
+                                match suggest_tags(&tag_client, &enhanced).await {
+                                    Ok(tags) => {
+                                        for tag in tags {
+                                            if !enhanced.tags.contains(&tag) {
+                                                enhanced.tags.push(tag);
+                                            }
+                                        }
+                                    }
+                                    Err(e) => {
+                                        warn!(
+                                            "Could not tag a segment from {}: {}",
+                                            path.display(),
+                                            e
+                                        )
+                                    }
+                                }
+                            }
+                            if self.config.srs {
+                                let _permit = semaphore.acquire().await.unwrap();
+                                match review_cards(&srs_client, &enhanced).await {
+                                    Ok(cards) => enhanced.cards = cards,
+                                    Err(e) => {
+                                        warn!(
+                                            "Could not write review questions for a segment from {}: {}",
+                                            path.display(),
+                                            e
+                                        )
+                                    }
+                                }
+                            }
+                            self.events.emit(Event::SegmentEnhanced {
+                                path: &path,
+                                category: enhanced.category.to_string(),
+                            });
+                            Some(enhanced)
+                        }
                         Err(e) => {
                             error!("Failed to enhance segment from {}: {}", path.display(), e);
+                            self.segment_failed(&path, &segment, e);
                             None
                         }
                     };
@@ -283,21 +1384,257 @@ impl Processor {
                     result
                 }
             })
-            .buffer_unordered(self.config.parallel)
+            .buffer_unordered(self.config.enhance_parallelism())
             .collect()
             .await;
 
         results.into_iter().flatten().collect()
     }
 
-    /// Run reorganization pass to suggest better structure
-    async fn run_reorganization(&self, files: &[PathBuf]) -> Result<(), ProcessorError> {
+    /// Enhance a segment in one request, or in parts when it is too long
+    async fn enhance_with(
+        &self,
+        client: &LlmClient,
+        semaphore: &Semaphore,
+        segment: &Segment,
+        path: &Path,
+        system_prompt: &str,
+    ) -> Result<EnhancedSegment, EnhancementError> {
+        if count_tokens(&segment.content) > self.config.max_segment_tokens {
+            enhance_long_segment(
+                client,
+                semaphore,
+                segment,
+                path,
+                system_prompt,
+                self.config.max_segment_tokens,
+                self.config.chunk_overlap,
+            )
+            .await
+        } else {
+            let _permit = semaphore.acquire().await.unwrap();
+            enhance_segment(client, segment, path, system_prompt).await
+        }
+    }
+
+    /// Check an enhanced segment for facts dropped from the original (`--verify`).
+    ///
+    /// With `--verify-retry`, a segment over the threshold is enhanced again with
+    /// the missing facts spelled out, and the version that lost fewer is kept.
+    /// Segments still over the threshold carry their missing facts for the report.
+    async fn verify_enhanced(
+        &self,
+        verify_client: &LlmClient,
+        client: &LlmClient,
+        segment: &Segment,
+        path: &Path,
+        system_prompt: &str,
+        mut enhanced: EnhancedSegment,
+    ) -> EnhancedSegment {
+        let semaphore = self.enhance_semaphore.as_ref();
+        let check = |content: String| async move {
+            let _permit = semaphore.acquire().await.unwrap();
+            missing_facts(verify_client, &segment.content, &content).await
+        };
+        let threshold = self.config.verify_threshold;
+
+        let mut missing = match check(enhanced.content.clone()).await {
+            Ok(missing) => missing,
+            Err(e) => {
+                warn!("Could not verify a segment from {}: {}", path.display(), e);
+                return enhanced;
+            }
+        };
+
+        if missing.len() > threshold && self.config.verify_retry {
+            info!(
+                "Re-enhancing a segment from {} that dropped {} facts",
+                path.display(),
+                missing.len()
+            );
+            let prompt = format!("{}{}", system_prompt, retention_instruction(&missing));
+            let retried = match self
+                .enhance_with(client, semaphore, segment, path, &prompt)
+                .await
+            {
+                Ok(retried) => check(retried.content.clone())
+                    .await
+                    .map(|still| (retried, still))
+                    .map_err(|e| e.to_string()),
+                Err(e) => Err(e.to_string()),
+            };
+            match retried {
+                Ok((retried, still)) if still.len() < missing.len() => {
+                    enhanced = retried;
+                    missing = still;
+                }
+                Ok(_) => {}
+                Err(e) => warn!(
+                    "Could not re-enhance a segment from {}: {}",
+                    path.display(),
+                    e
+                ),
+            }
+        }
+
+        if missing.len() > threshold {
+            warn!(
+                "A segment from {} is missing {} facts of the original",
+                path.display(),
+                missing.len()
+            );
+            enhanced.missing_facts = missing;
+        }
+        enhanced
+    }
+
+    /// Measure how far an enhanced segment drifted from its original
+    /// (`--drift-guard`). Over `--drift-threshold`, the segment is enhanced
+    /// again with a stricter prompt (`retry`) or replaced by the original; a
+    /// retry that still drifts too far falls back to the original as well.
+    async fn guard_drift(
+        &self,
+        client: &LlmClient,
+        segment: &Segment,
+        path: &Path,
+        position: usize,
+        system_prompt: &str,
+        enhanced: EnhancedSegment,
+    ) -> EnhancedSegment {
+        let threshold = self.config.drift_threshold;
+        let drift = Drift::measure(&segment.content, &enhanced.content);
+        debug!("Segment from {}: {}", path.display(), drift);
+        if drift.score() <= threshold {
+            return enhanced;
+        }
+
+        let mut guarded = (keep_original(segment, path), GuardAction::KeptOriginal);
+        if self.config.drift_guard == DriftGuard::Retry {
+            let prompt = format!("{}{}", system_prompt, STRICT_INSTRUCTION);
+            let semaphore = self.enhance_semaphore.as_ref();
+            match self
+                .enhance_with(client, semaphore, segment, path, &prompt)
+                .await
+            {
+                Ok(retried) => {
+                    let again = Drift::measure(&segment.content, &retried.content);
+                    if again.score() <= threshold {
+                        guarded = (retried, GuardAction::Reenhanced);
+                    } else {
+                        debug!(
+                            "Stricter enhancement of a segment from {} still drifted ({})",
+                            path.display(),
+                            again
+                        );
+                    }
+                }
+                Err(e) => warn!(
+                    "Could not re-enhance a segment from {}: {}",
+                    path.display(),
+                    e
+                ),
+            }
+        }
+
+        let (result, action) = guarded;
+        let record = Guarded {
+            path: path.to_path_buf(),
+            position,
+            drift,
+            action,
+        };
+        warn!("Drift guard: {}", record);
+        self.guarded.lock().unwrap().push(record);
+        result
+    }
+
+    /// Run reorganization pass to suggest better structure for the files
+    /// written under an output root
+    ///
+    /// Returns the moves that were applied (none in a dry run).
+    async fn run_reorganization(
+        &self,
+        root: &Path,
+        files: &[PathBuf],
+    ) -> Result<Vec<Move>, ProcessorError> {
         let file_list: Vec<String> = files
             .iter()
-            .map(|p| p.strip_prefix(&self.config.output).unwrap_or(p))
+            .map(|p| p.strip_prefix(root).unwrap_or(p))
             .map(|p| p.to_string_lossy().to_string())
             .collect();
 
+        let plan = match self.cluster_files(files, &file_list).await {
+            Some(clusters) => {
+                info!(
+                    "Reorganizing {} files in {} clusters",
+                    file_list.len(),
+                    clusters.len()
+                );
+                let folders: BTreeSet<&str> = file_list
+                    .iter()
+                    .filter_map(|f| f.split_once('/').map(|(dir, _)| dir))
+                    .collect();
+                let folders: Vec<&str> = folders.into_iter().collect();
+                let plans: Vec<_> = stream::iter(clusters)
+                    .map(|cluster| {
+                        let folders = &folders;
+                        async move {
+                            let _permit = self.semaphore.acquire().await.unwrap();
+                            let plan = self.propose_reorg(&cluster, Some(folders)).await?;
+                            Some((cluster, plan))
+                        }
+                    })
+                    .buffered(self.config.parallel)
+                    .filter_map(future::ready)
+                    .collect()
+                    .await;
+                (!plans.is_empty()).then(|| reorg::merge_plans(plans))
+            }
+            None => self.propose_reorg(&file_list, None).await,
+        };
+        let Some(reorg) = plan else {
+            return Ok(vec![]);
+        };
+
+        if reorg.file_moves.is_empty() && reorg.new_categories.is_empty() {
+            info!("No reorganization needed - structure looks good!");
+            return Ok(vec![]);
+        }
+        self.events.print(reorg::format_plan(&reorg).trim_end());
+
+        if self.config.reorganize_dry_run {
+            let plan_path = root.join(reorg::PLAN_FILE);
+            reorg::save_plan(&plan_path, &reorg)?;
+            self.events.emit(Event::ReorgPlanned {
+                root,
+                plan: &reorg,
+                saved: Some(&plan_path),
+            });
+            self.events.print(&format!(
+                "\nPlan saved to {}. Review it, then apply with: notex --apply-reorg {} -o {}",
+                plan_path.display(),
+                plan_path.display(),
+                root.display()
+            ));
+            return Ok(vec![]);
+        }
+        self.events.emit(Event::ReorgPlanned {
+            root,
+            plan: &reorg,
+            saved: None,
+        });
+        let moves = reorg::apply_moves(root, &reorg.file_moves)?;
+        reorg::fix_links(root, &moves)?;
+        Ok(moves)
+    }
+
+    /// Ask for a reorganization of `files`. With `folders`, the files are one
+    /// cluster of the output and these are the folders of the rest.
+    async fn propose_reorg(
+        &self,
+        files: &[String],
+        folders: Option<&[&str]>,
+    ) -> Option<ReorgResponse> {
         let system_prompt = r#"You are a file organization expert. Given a list of note files, analyze the structure and suggest improvements.
 
 Consider:
@@ -316,79 +1653,313 @@ Return JSON:
   ]
 }"#;
 
-        let user_prompt = format!("Current file structure:\n{}", file_list.join("\n"));
-
-        match self.client.chat_json(system_prompt, &user_prompt).await {
-            Ok(response) => {
-                let json_str = extract_json(&response);
-                match serde_json::from_str::<ReorgResponse>(json_str) {
-                    Ok(reorg) => {
-                        if !reorg.file_moves.is_empty() || !reorg.new_categories.is_empty() {
-                            println!("\n=== Reorganization Suggestions ===\n");
-
-                            if !reorg.file_moves.is_empty() {
-                                println!("File moves:");
-                                for mv in &reorg.file_moves {
-                                    println!(
-                                        "   {} → {}\n      Reason: {}",
-                                        mv.current_path, mv.suggested_path, mv.reason
-                                    );
-                                }
-                            }
+        let mut user_prompt = format!("Current file structure:\n{}", files.join("\n"));
+        if let Some(folders) = folders {
+            user_prompt.push_str(&format!(
+                "\n\nThese are one group of related files from a larger collection. Only suggest moves for them. Top-level folders of the whole collection, which they may also move into:\n{}",
+                folders.join("\n")
+            ));
+        }
 
-                            if !reorg.new_categories.is_empty() {
-                                println!("\nNew categories:");
-                                for cat in &reorg.new_categories {
-                                    println!(
-                                        "   {}{}\n      Files: {:?}\n      Reason: {}",
-                                        cat.category,
-                                        cat.subcategory
-                                            .as_ref()
-                                            .map(|s| format!("/{}", s))
-                                            .unwrap_or_default(),
-                                        cat.affected_files,
-                                        cat.reason
-                                    );
-                                }
-                            }
+        match self
+            .client
+            .for_phase("reorganize")
+            .chat_json_parsed::<ReorgResponse>(system_prompt, &user_prompt)
+            .await
+        {
+            Ok(reorg) => Some(reorg),
+            Err(e) => {
+                warn!("Reorganization pass failed: {}", e);
+                None
+            }
+        }
+    }
 
-                            // Apply the moves
-                            for mv in &reorg.file_moves {
-                                let src = self.config.output.join(&mv.current_path);
-                                let dst = self.config.output.join(&mv.suggested_path);
-                                if src.exists() {
-                                    if let Some(parent) = dst.parent() {
-                                        std::fs::create_dir_all(parent)?;
-                                    }
-                                    std::fs::rename(&src, &dst)?;
-                                    info!("Moved {} → {}", mv.current_path, mv.suggested_path);
-                                }
-                            }
-                        } else {
-                            info!("No reorganization needed - structure looks good!");
-                        }
+    /// Group files by the similarity of their embeddings, when there is an
+    /// `--embedding-model` and too many files for one prompt. None means one
+    /// prompt for all of them.
+    async fn cluster_files(
+        &self,
+        files: &[PathBuf],
+        file_list: &[String],
+    ) -> Option<Vec<Vec<String>>> {
+        let client = self.embed_client.as_ref()?;
+        if file_list.len() <= self.config.reorg_cluster_size {
+            return None;
+        }
+
+        let texts: Vec<String> = files
+            .iter()
+            .zip(file_list)
+            .map(|(path, rel)| {
+                let content = std::fs::read_to_string(path).unwrap_or_default();
+                let head: String = content.chars().take(EMBED_CHARS).collect();
+                format!("{}\n\n{}", rel, head)
+            })
+            .collect();
+        let mut vectors = Vec::with_capacity(texts.len());
+        for batch in texts.chunks(EMBED_BATCH) {
+            match client.embed(batch).await {
+                Ok(batch) => vectors.extend(batch),
+                Err(e) => {
+                    warn!(
+                        "Could not embed files for clustering, reorganizing them in one prompt: {}",
+                        e
+                    );
+                    return None;
+                }
+            }
+        }
+        if vectors.len() != texts.len() {
+            warn!(
+                "Got {} embeddings for {} files, reorganizing them in one prompt",
+                vectors.len(),
+                texts.len()
+            );
+            return None;
+        }
+
+        let clusters = cluster(&vectors, self.config.reorg_cluster_size);
+        Some(
+            clusters
+                .into_iter()
+                .map(|c| c.into_iter().map(|i| file_list[i].clone()).collect())
+                .collect(),
+        )
+    }
+
+    /// Prepend a summary to every file with at least `--summary-min-segments` segments
+    async fn add_summaries(&self, grouped: &mut HashMap<String, Vec<EnhancedSegment>>) {
+        let client = self.client.for_phase("summarize");
+        let format = self.config.format;
+        let max_tokens = self.config.max_note_tokens;
+        let min_segments = self.config.summary_min_segments.max(1);
+
+        let count = stream::iter(
+            grouped
+                .iter_mut()
+                .filter(|(_, segments)| segments.len() >= min_segments),
+        )
+        .map(|(path, segments)| {
+            let client = client.clone();
+            async move {
+                let _permit = self.semaphore.acquire().await.unwrap();
+                match summarize_file(&client, segments, max_tokens).await {
+                    Ok(summary) if !summary.is_empty() => {
+                        prepend_summary(&mut segments[0], &summary, format);
+                        true
                     }
+                    Ok(_) => false,
                     Err(e) => {
-                        warn!("Failed to parse reorganization response: {}", e);
+                        warn!("Failed to summarize {}: {}", path, e);
+                        false
                     }
                 }
             }
-            Err(e) => {
-                warn!("Reorganization pass failed: {}", e);
-            }
+        })
+        .buffer_unordered(self.config.parallel)
+        .filter(|added| future::ready(*added))
+        .count()
+        .await;
+        info!("Added summaries to {} files", count);
+    }
+
+    /// Translate every segment in place; segments that fail keep their text
+    async fn translate_all(
+        &self,
+        grouped: &mut HashMap<String, Vec<EnhancedSegment>>,
+        language: &str,
+    ) {
+        let client = self.client.for_phase("translate");
+        let max_tokens = self.config.max_segment_tokens;
+
+        let count =
+            stream::iter(grouped.iter_mut().flat_map(|(path, segments)| {
+                segments.iter_mut().map(move |segment| (path, segment))
+            }))
+            .map(|(path, segment)| {
+                let client = client.clone();
+                async move {
+                    let _permit = self.semaphore.acquire().await.unwrap();
+                    match translate(&client, &segment.content, language, max_tokens).await {
+                        Ok(translated) => {
+                            segment.content = translated;
+                            true
+                        }
+                        Err(e) => {
+                            warn!("Failed to translate a segment of {}: {}", path, e);
+                            false
+                        }
+                    }
+                }
+            })
+            .buffer_unordered(self.config.parallel)
+            .filter(|translated| future::ready(*translated))
+            .count()
+            .await;
+        info!("Translated {} segments into {}", count, language);
+    }
+
+    /// Extract key terms per category and merge them into each `category/glossary.md`
+    async fn run_glossaries(&self, enhanced: &[EnhancedSegment]) -> Result<(), ProcessorError> {
+        let mut by_category: BTreeMap<String, Vec<&EnhancedSegment>> = BTreeMap::new();
+        for segment in enhanced {
+            by_category
+                .entry(segment.category.dir_name())
+                .or_default()
+                .push(segment);
         }
 
+        let client = self.client.for_phase("glossary");
+        let requests: Vec<(String, String)> = by_category
+            .iter()
+            .flat_map(|(category, segments)| {
+                glossary::batches(segments, self.config.max_note_tokens)
+                    .into_iter()
+                    .map(move |notes| (category.clone(), notes))
+            })
+            .collect();
+
+        let results: Vec<(String, Vec<Term>)> = stream::iter(requests)
+            .map(|(category, notes)| {
+                let client = client.clone();
+                async move {
+                    let _permit = self.semaphore.acquire().await.unwrap();
+                    match glossary::extract_terms(&client, &category, &notes).await {
+                        Ok(terms) => (category, terms),
+                        Err(e) => {
+                            warn!("Failed to extract glossary terms for {}: {}", category, e);
+                            (category, Vec::new())
+                        }
+                    }
+                }
+            })
+            .buffer_unordered(self.config.parallel)
+            .collect()
+            .await;
+
+        let mut terms: BTreeMap<String, Vec<Term>> = BTreeMap::new();
+        for (category, found) in results {
+            terms.entry(category).or_default().extend(found);
+        }
+        for (category, terms) in terms {
+            let (path, added) =
+                write_glossary(&self.config.output, &category, terms, self.config.format)?;
+            info!("Added {} terms to {}", added, path.display());
+        }
         Ok(())
     }
 
-    /// Run cross-referencing to link related notes
-    async fn run_cross_referencing(&self, files: &[PathBuf]) -> Result<(), ProcessorError> {
-        // Build a map of file path -> content summary
-        let mut file_summaries: HashMap<String, String> = HashMap::new();
+    /// Collect open tasks from the output, schedule them with the LLM and write `todo/index.md`
+    async fn run_todos(&self) -> Result<PathBuf, ProcessorError> {
+        let mut tasks = todos::collect(&self.config.output, self.config.format);
+        let client = self.client.for_phase("todos");
+        let today = chrono::Local::now().format("%Y-%m-%d").to_string();
+
+        stream::iter(tasks.chunks_mut(SCHEDULE_BATCH))
+            .for_each_concurrent(self.config.parallel, |batch| {
+                let client = client.clone();
+                let today = &today;
+                async move {
+                    let _permit = self.semaphore.acquire().await.unwrap();
+                    if let Err(e) = todos::schedule(&client, batch, today).await {
+                        warn!("Could not infer due dates and priorities: {}", e);
+                    }
+                }
+            })
+            .await;
+
+        let path = write_todo_index(&self.config.output, &mut tasks, self.config.format)?;
+        info!("Collected {} tasks in {}", tasks.len(), path.display());
+        Ok(path)
+    }
+
+    /// One-line LLM description of every output file, empty where it failed
+    async fn describe_outputs(&self) -> BTreeMap<String, String> {
+        let files = collect_indexable(&self.config.output);
+        let client = self.client.for_phase("index");
+        let semaphore = self.semaphore.clone();
+        let output = self.config.output.clone();
+
+        stream::iter(files)
+            .map(|rel_path| {
+                let client = client.clone();
+                let semaphore = semaphore.clone();
+                let path = output.join(&rel_path);
+
+                async move {
+                    let content = match std::fs::read_to_string(&path) {
+                        Ok(content) => content,
+                        Err(e) => {
+                            warn!("Could not read {}: {}", path.display(), e);
+                            return (rel_path, String::new());
+                        }
+                    };
+
+                    let _permit = semaphore.acquire().await.unwrap();
+                    let description = match describe_file(&client, &rel_path, &content).await {
+                        Ok(description) => description,
+                        Err(e) => {
+                            warn!("Failed to describe {}: {}", rel_path, e);
+                            String::new()
+                        }
+                    };
+                    (rel_path, description)
+                }
+            })
+            .buffer_unordered(self.config.parallel)
+            .collect()
+            .await
+    }
+
+    /// Write a summarized README.md in every output folder
+    async fn write_readmes(
+        &self,
+        descriptions: &BTreeMap<String, String>,
+    ) -> Result<usize, ProcessorError> {
+        if matches!(self.config.format, OutputFormat::Logseq) {
+            warn!("Skipping folder READMEs: a Logseq graph has no topic folders");
+            return Ok(0);
+        }
+        let client = self.client.for_phase("readme");
+        let folders = folders(descriptions);
+
+        let summaries: Vec<(&String, String)> = stream::iter(&folders)
+            .map(|(dir, folder)| {
+                let client = client.clone();
+                async move {
+                    let _permit = self.semaphore.acquire().await.unwrap();
+                    let summary = describe_folder(&client, dir, folder)
+                        .await
+                        .unwrap_or_else(|e| {
+                            warn!("Failed to summarize folder {:?}: {}", dir, e);
+                            String::new()
+                        });
+                    (dir, summary)
+                }
+            })
+            .buffer_unordered(self.config.parallel)
+            .collect()
+            .await;
+
+        for (dir, summary) in &summaries {
+            write_readme(&self.config.output, dir, &folders[*dir], summary)?;
+        }
+        Ok(summaries.len())
+    }
+
+    /// Run cross-referencing to link related notes, returning the references added
+    async fn run_cross_referencing(
+        &self,
+        root: &Path,
+        files: &[PathBuf],
+    ) -> Result<Vec<CrossReference>, ProcessorError> {
+        // Sorted by path, so a batch mostly holds one category and its neighbours
+        let mut file_summaries: BTreeMap<String, String> = BTreeMap::new();
         for file in files {
             if let Ok(content) = std::fs::read_to_string(file) {
                 let rel_path = file
-                    .strip_prefix(&self.config.output)
+                    .strip_prefix(root)
                     .unwrap_or(file)
                     .to_string_lossy()
                     .to_string();
@@ -398,7 +1969,153 @@ Return JSON:
             }
         }
 
-        let system_prompt = r#"You are a knowledge linking expert. Given a set of notes with their content summaries, identify meaningful connections between them.
+        let summaries: Vec<(&String, &String)> = file_summaries.iter().collect();
+        let batches = overlapping_batches(&summaries, self.config.cross_ref_batch_size);
+        if batches.len() > 1 {
+            info!(
+                "Cross-referencing {} files in {} overlapping batches",
+                summaries.len(),
+                batches.len()
+            );
+        }
+
+        let client = self.client.for_phase("cross_reference");
+        let semaphore = self.semaphore.clone();
+        let found: Vec<Vec<CrossReference>> = stream::iter(batches.into_iter().enumerate())
+            .map(|(i, batch)| {
+                let client = client.clone();
+                let semaphore = semaphore.clone();
+                async move {
+                    let _permit = semaphore.acquire().await.unwrap();
+                    find_cross_refs(&client, &batch)
+                        .await
+                        .inspect_err(|e| warn!("Cross-referencing batch {} failed: {}", i + 1, e))
+                        .unwrap_or_default()
+                }
+            })
+            .buffered(self.config.parallel)
+            .collect()
+            .await;
+
+        // Batches overlap, so the same pair can be suggested more than once
+        let mut seen = HashSet::new();
+        let references: Vec<CrossReference> = found
+            .into_iter()
+            .flatten()
+            // Only files of this root, named by paths that stay inside it
+            .filter(|xref| {
+                is_safe_path(&xref.from_file)
+                    && is_safe_path(&xref.to_file)
+                    && file_summaries.contains_key(&xref.from_file)
+                    && file_summaries.contains_key(&xref.to_file)
+            })
+            .filter(|xref| seen.insert((xref.from_file.clone(), xref.to_file.clone())))
+            .collect();
+
+        let mut added = Vec::new();
+        if references.is_empty() {
+            info!("No cross-references found");
+            return Ok(added);
+        }
+
+        if self.config.cross_ref_dry_run {
+            self.events
+                .print("\n=== Cross-References (dry run, nothing written) ===\n");
+        } else {
+            self.events.print("\n=== Cross-References Added ===\n");
+        }
+
+        let refs: Vec<&CrossReference> = references.iter().collect();
+        for xref in self.merge_cross_refs(root, Section::Related, &refs)? {
+            self.events.print(&format!(
+                "   {} → {} ({})",
+                xref.from_file, xref.to_file, xref.context
+            ));
+            self.events.emit(Event::CrossReference {
+                root,
+                from: &xref.from_file,
+                to: &xref.to_file,
+                context: &xref.context,
+                applied: !self.config.cross_ref_dry_run,
+            });
+            if !self.config.cross_ref_dry_run {
+                added.push(xref.clone());
+            }
+        }
+
+        if !self.config.cross_ref_dry_run {
+            let refs: Vec<&CrossReference> = added.iter().collect();
+            let backlinks = self.merge_cross_refs(root, Section::ReferencedBy, &refs)?;
+            info!("Added {} backlinks", backlinks.len());
+            let path = self.write_backlinks_index(root)?;
+            info!("Wrote backlinks index {:?}", path);
+        }
+
+        Ok(added)
+    }
+
+    /// Merge references into a section of the files that hold it, one update per
+    /// file. Returns the references that were new; nothing is written in a dry run.
+    fn merge_cross_refs<'a>(
+        &self,
+        root: &Path,
+        section: Section,
+        refs: &[&'a CrossReference],
+    ) -> Result<Vec<&'a CrossReference>, std::io::Error> {
+        // Grouped by file, keeping the order the LLM gave
+        let mut by_file: Vec<(&str, Vec<&CrossReference>)> = Vec::new();
+        for xref in refs {
+            let file = match section {
+                Section::Related => &xref.from_file,
+                Section::ReferencedBy => &xref.to_file,
+            };
+            match by_file.iter_mut().find(|(f, _)| f == file) {
+                Some((_, refs)) => refs.push(xref),
+                None => by_file.push((file, vec![xref])),
+            }
+        }
+
+        let mut added = Vec::new();
+        for (file, refs) in by_file {
+            let path = root.join(file);
+            let Ok(content) = std::fs::read_to_string(&path) else {
+                continue;
+            };
+            let Some((content, new_refs)) =
+                merge_section(&content, file, section, &refs, self.config.format)
+            else {
+                continue;
+            };
+            if !self.config.cross_ref_dry_run {
+                atomic::write(&path, content)?;
+            }
+            added.extend(new_refs);
+        }
+
+        Ok(added)
+    }
+
+    /// Rebuild the backlinks index from the Related sections of every file
+    /// under an output root
+    fn write_backlinks_index(&self, root: &Path) -> Result<PathBuf, std::io::Error> {
+        let files = collect_indexable(root);
+        let mut backlinks: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+        for file in &files {
+            let Ok(content) = std::fs::read_to_string(root.join(file)) else {
+                continue;
+            };
+            for target in
+                section_links(&content, file, Section::Related, self.config.format, &files)
+            {
+                backlinks.entry(target).or_default().insert(file.clone());
+            }
+        }
+
+        write_backlinks(root, &backlinks, self.config.format)
+    }
+}
+
+const CROSS_REF_SYSTEM_PROMPT: &str = r#"You are a knowledge linking expert. Given a set of notes with their content summaries, identify meaningful connections between them.
 
 Look for:
 1. Notes that reference concepts explained in other notes
@@ -412,93 +2129,46 @@ Return JSON:
   ]
 }"#;
 
-        let summaries_str: String = file_summaries
-            .iter()
-            .map(|(path, summary)| format!("=== {} ===\n{}\n", path, summary))
-            .collect::<Vec<_>>()
-            .join("\n");
-
-        let user_prompt = format!("Notes to analyze:\n\n{}", summaries_str);
-
-        match self.client.chat_json(system_prompt, &user_prompt).await {
-            Ok(response) => {
-                let json_str = extract_json(&response);
-                match serde_json::from_str::<CrossRefResponse>(json_str) {
-                    Ok(refs) => {
-                        if !refs.references.is_empty() {
-                            println!("\n=== Cross-References Added ===\n");
-
-                            for xref in &refs.references {
-                                // Add reference to the source file
-                                let src_path = self.config.output.join(&xref.from_file);
-                                if src_path.exists() {
-                                    if let Ok(mut content) = std::fs::read_to_string(&src_path) {
-                                        let ref_section = format!(
-                                            "\n\n---\n\n**See also:** [{}](./{}) - {}\n",
-                                            xref.to_file,
-                                            relative_path(&xref.from_file, &xref.to_file),
-                                            xref.context
-                                        );
-                                        content.push_str(&ref_section);
-                                        std::fs::write(&src_path, content)?;
-                                        println!(
-                                            "   {} → {} ({})",
-                                            xref.from_file, xref.to_file, xref.context
-                                        );
-                                    }
-                                }
-                            }
-                        } else {
-                            info!("No cross-references found");
-                        }
-                    }
-                    Err(e) => {
-                        warn!("Failed to parse cross-reference response: {}", e);
-                    }
-                }
-            }
-            Err(e) => {
-                warn!("Cross-referencing pass failed: {}", e);
-            }
-        }
-
-        Ok(())
-    }
-}
-
-/// Calculate relative path from one file to another
-fn relative_path(from: &str, to: &str) -> String {
-    let from_parts: Vec<&str> = from.split('/').collect();
-    let to_parts: Vec<&str> = to.split('/').collect();
-
-    // Find common prefix length
-    let common = from_parts
+/// Ask the LLM for connections between one batch of notes
+async fn find_cross_refs(
+    client: &LlmClient,
+    batch: &[(&String, &String)],
+) -> Result<Vec<CrossReference>, ProcessorError> {
+    let summaries_str: String = batch
         .iter()
-        .zip(to_parts.iter())
-        .take_while(|(a, b)| a == b)
-        .count();
-
-    // Build relative path
-    let up_count = from_parts.len() - common - 1;
-    let ups = std::iter::repeat("..").take(up_count);
-    let downs = to_parts.iter().skip(common);
+        .map(|(path, summary)| format!("=== {} ===\n{}\n", path, summary))
+        .collect::<Vec<_>>()
+        .join("\n");
 
-    ups.chain(downs.map(|s| *s)).collect::<Vec<_>>().join("/")
+    let user_prompt = format!("Notes to analyze:\n\n{}", summaries_str);
+    let refs: CrossRefResponse = client
+        .chat_json_parsed(CROSS_REF_SYSTEM_PROMPT, &user_prompt)
+        .await?;
+    Ok(refs.references)
 }
 
-/// Extract JSON from response, handling potential markdown code blocks
-fn extract_json(response: &str) -> &str {
-    let trimmed = response.trim();
+/// Split items into batches of at most `size`, each sharing a quarter of its
+/// items with the next so links across a batch boundary are not missed
+fn overlapping_batches<T: Clone>(items: &[T], size: usize) -> Vec<Vec<T>> {
+    let size = size.max(2);
+    if items.len() <= size {
+        return if items.is_empty() {
+            vec![]
+        } else {
+            vec![items.to_vec()]
+        };
+    }
 
-    // Check for markdown code blocks
-    if trimmed.starts_with("```") {
-        if let Some(start) = trimmed.find('\n') {
-            let rest = &trimmed[start + 1..];
-            if let Some(end) = rest.rfind("```") {
-                return rest[..end].trim();
-            }
+    let step = size - (size / 4).max(1);
+    let mut batches = Vec::new();
+    let mut start = 0;
+    loop {
+        let end = (start + size).min(items.len());
+        batches.push(items[start..end].to_vec());
+        if end == items.len() {
+            break;
         }
+        start += step;
     }
-
-    trimmed
+    batches
 }
@@ -0,0 +1,221 @@
+use crate::atomic;
+use crate::frontmatter;
+use crate::html;
+use crate::types::PublishTarget;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use walkdir::WalkDir;
+
+#[derive(Error, Debug)]
+pub enum PublishError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Source directory not found: {0}")]
+    MissingSource(PathBuf),
+}
+
+/// A directory in the published tree, keyed by name so output is sorted
+#[derive(Default)]
+struct DirNode {
+    dirs: BTreeMap<String, DirNode>,
+    files: Vec<String>,
+}
+
+impl DirNode {
+    fn insert(&mut self, rel_path: &str) {
+        let mut node = self;
+        let mut parts: Vec<&str> = rel_path.split('/').collect();
+        let file = parts.pop().unwrap_or_default();
+        for part in parts {
+            node = node.dirs.entry(part.to_string()).or_default();
+        }
+        node.files.push(file.to_string());
+        node.files.sort();
+    }
+}
+
+/// Publish a processed output directory as an mdBook or static site
+pub fn publish(
+    source: &Path,
+    dest: &Path,
+    target: PublishTarget,
+    title: &str,
+) -> Result<Vec<PathBuf>, PublishError> {
+    if !source.is_dir() {
+        return Err(PublishError::MissingSource(source.to_path_buf()));
+    }
+
+    let files = collect_files(source, dest);
+    match target {
+        PublishTarget::Mdbook => publish_mdbook(source, dest, &files, title),
+        PublishTarget::Site => publish_site(source, dest, &files, title),
+    }
+}
+
+/// Collect relative paths of all visible files under the source directory
+fn collect_files(source: &Path, dest: &Path) -> Vec<String> {
+    let mut files: Vec<String> = WalkDir::new(source)
+        .into_iter()
+        .filter_entry(|e| {
+            // Skip hidden entries and the destination if it lives inside the source
+            !e.file_name().to_string_lossy().starts_with('.') && e.path() != dest
+        })
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file() && !atomic::is_backup(e.path()))
+        .filter_map(|e| {
+            e.path()
+                .strip_prefix(source)
+                .ok()
+                .map(|p| p.to_string_lossy().replace('\\', "/"))
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+/// Human-readable title from a file or directory name
+fn display_title(name: &str) -> String {
+    let stem = name
+        .strip_suffix(".md")
+        .or_else(|| name.strip_suffix(".html"))
+        .unwrap_or(name);
+    stem.replace(['_', '-'], " ")
+}
+
+/// Remove a leading YAML frontmatter block, which neither target renders
+fn strip_frontmatter(content: &str) -> &str {
+    frontmatter::split(content).map_or(content, |(_, body)| body.trim_start())
+}
+
+fn publish_mdbook(
+    source: &Path,
+    dest: &Path,
+    files: &[String],
+    title: &str,
+) -> Result<Vec<PathBuf>, PublishError> {
+    let src_dir = dest.join("src");
+    fs::create_dir_all(&src_dir)?;
+    let mut written = Vec::new();
+
+    let mut tree = DirNode::default();
+    for rel in files {
+        let from = source.join(rel);
+        let to = src_dir.join(rel);
+        if let Some(parent) = to.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        if rel.ends_with(".md") {
+            let content = fs::read_to_string(&from)?;
+            fs::write(&to, strip_frontmatter(&content))?;
+            tree.insert(rel);
+        } else {
+            fs::copy(&from, &to)?;
+        }
+        written.push(to);
+    }
+
+    // Top-level categories become part titles, deeper directories draft chapters
+    let mut summary = String::from("# Summary\n\n");
+    for file in &tree.files {
+        summary.push_str(&format!("[{}](<{}>)\n", display_title(file), file));
+    }
+    for (name, node) in &tree.dirs {
+        summary.push_str(&format!("\n# {}\n\n", display_title(name)));
+        write_summary_entries(&mut summary, node, name, 0);
+    }
+    let summary_path = src_dir.join("SUMMARY.md");
+    fs::write(&summary_path, summary)?;
+    written.push(summary_path);
+
+    let book_toml = format!(
+        "[book]\ntitle = \"{}\"\nsrc = \"src\"\n\n[output.html]\nmathjax-support = true\n",
+        title.replace('"', "\\\"")
+    );
+    let book_path = dest.join("book.toml");
+    fs::write(&book_path, book_toml)?;
+    written.push(book_path);
+
+    Ok(written)
+}
+
+fn write_summary_entries(out: &mut String, node: &DirNode, prefix: &str, depth: usize) {
+    let indent = "  ".repeat(depth);
+    for file in &node.files {
+        out.push_str(&format!(
+            "{}- [{}](<{}/{}>)\n",
+            indent,
+            display_title(file),
+            prefix,
+            file
+        ));
+    }
+    for (name, child) in &node.dirs {
+        out.push_str(&format!("{}- [{}]()\n", indent, display_title(name)));
+        write_summary_entries(out, child, &format!("{}/{}", prefix, name), depth + 1);
+    }
+}
+
+fn publish_site(
+    source: &Path,
+    dest: &Path,
+    files: &[String],
+    title: &str,
+) -> Result<Vec<PathBuf>, PublishError> {
+    fs::create_dir_all(dest)?;
+    let mut written = Vec::new();
+
+    let mut tree = DirNode::default();
+    for rel in files {
+        let from = source.join(rel);
+        if rel.ends_with(".md") {
+            let rel_html = html::html_path(rel);
+            let to = dest.join(&rel_html);
+            if let Some(parent) = to.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let content = fs::read_to_string(&from)?;
+            let name = rel.rsplit('/').next().unwrap_or(rel);
+            let page = html::render_page(&display_title(name), strip_frontmatter(&content));
+            fs::write(&to, page)?;
+            tree.insert(&rel_html);
+            written.push(to);
+        } else {
+            let to = dest.join(rel);
+            if let Some(parent) = to.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::copy(&from, &to)?;
+            if rel.ends_with(".html") {
+                tree.insert(rel);
+            }
+            written.push(to);
+        }
+    }
+
+    let mut index = format!("# {}\n\n", title);
+    write_index_entries(&mut index, &tree, "", 0);
+    let index_path = dest.join("index.html");
+    fs::write(&index_path, html::render_page(title, &index))?;
+    written.push(index_path);
+
+    Ok(written)
+}
+
+fn write_index_entries(out: &mut String, node: &DirNode, prefix: &str, depth: usize) {
+    let indent = "  ".repeat(depth);
+    for file in &node.files {
+        out.push_str(&format!(
+            "{}- [{}](<{}{}>)\n",
+            indent,
+            display_title(file),
+            prefix,
+            file
+        ));
+    }
+    for (name, child) in &node.dirs {
+        out.push_str(&format!("{}- **{}**\n", indent, display_title(name)));
+        write_index_entries(out, child, &format!("{}{}/", prefix, name), depth + 1);
+    }
+}
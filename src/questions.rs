@@ -0,0 +1,150 @@
+use crate::indexer::{collect_indexable, write_index};
+use crate::links::relative_path;
+use crate::logseq;
+use crate::types::OutputFormat;
+use crate::writer::output_rel_path;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// Folder holding the consolidated question lists (`--questions`)
+pub const QUESTIONS_DIR: &str = "questions";
+
+/// Marker enhancement puts in front of an answered `?` question
+const QUESTION_MARKER: &str = "[Q:";
+
+/// The questions of every `[Q: question] answer` item in a note, in order
+pub fn extract(content: &str) -> Vec<String> {
+    let mut questions: Vec<String> = Vec::new();
+    let mut rest = content;
+    while let Some(start) = rest.find(QUESTION_MARKER) {
+        rest = &rest[start + QUESTION_MARKER.len()..];
+        // The question itself may contain brackets, e.g. a link or an index
+        let mut depth = 0;
+        let Some(end) = rest.find(|c| match c {
+            '[' => {
+                depth += 1;
+                false
+            }
+            ']' if depth == 0 => true,
+            ']' => {
+                depth -= 1;
+                false
+            }
+            _ => false,
+        }) else {
+            break;
+        };
+        let question = rest[..end].split_whitespace().collect::<Vec<_>>().join(" ");
+        if !question.is_empty() && !questions.contains(&question) {
+            questions.push(question);
+        }
+        rest = &rest[end + 1..];
+    }
+    questions
+}
+
+/// Number of questions in a note that were left without an answer: `[Q: …]`
+/// items with nothing after them (outside code). Other lines ending in `?`,
+/// such as headings or quotes, are not counted.
+pub fn unanswered(content: &str) -> usize {
+    unanswered_lines(content).len()
+}
+
+/// The lines of a note holding questions left without an answer, trimmed
+pub fn unanswered_lines(content: &str) -> Vec<&str> {
+    let mut in_code = false;
+    let mut lines = Vec::new();
+    for line in content.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_code = !in_code;
+            continue;
+        }
+        if in_code {
+            continue;
+        }
+        let open = trimmed.find(QUESTION_MARKER).is_some_and(|start| {
+            let item = &trimmed[start..];
+            item.rfind(']')
+                .is_none_or(|end| item[end + 1..].trim().is_empty())
+        });
+        if open {
+            lines.push(trimmed);
+        }
+    }
+    lines
+}
+
+/// Category an output file belongs to: its top-level folder (or Logseq namespace)
+fn category_of(file: &str, format: OutputFormat) -> String {
+    let name = match format {
+        OutputFormat::Logseq if file.starts_with("journals/") => return "journal".to_string(),
+        OutputFormat::Logseq => logseq::page_name(file),
+        _ => file.to_string(),
+    };
+    match name.split_once('/') {
+        Some((category, _)) => category.to_string(),
+        None => "uncategorized".to_string(),
+    }
+}
+
+/// Whether an output file is one of the generated question lists
+pub fn is_question_list(file: &str, format: OutputFormat) -> bool {
+    category_of(file, format) == QUESTIONS_DIR
+}
+
+/// Collect the `[Q: …]` items of every note in the output directory into
+/// `questions/<category>.md`, grouped by the file that answers them
+pub fn write_questions(
+    output_dir: &Path,
+    format: OutputFormat,
+) -> Result<Vec<PathBuf>, std::io::Error> {
+    let mut by_category: BTreeMap<String, BTreeMap<String, Vec<String>>> = BTreeMap::new();
+    for file in collect_indexable(output_dir) {
+        if is_question_list(&file, format) {
+            continue;
+        }
+        let category = category_of(&file, format);
+        let content = match fs::read_to_string(output_dir.join(&file)) {
+            Ok(content) => content,
+            Err(e) => {
+                warn!("Could not read {}: {}", file, e);
+                continue;
+            }
+        };
+        let questions = extract(&content);
+        if !questions.is_empty() {
+            by_category
+                .entry(category)
+                .or_default()
+                .insert(file, questions);
+        }
+    }
+
+    let mut written = Vec::new();
+    for (category, files) in &by_category {
+        let rel_path = output_rel_path(&format!("{}/{}.md", QUESTIONS_DIR, category), format);
+        let title = format!("Open questions: {}", category);
+        let mut md = format!("# {}\n", title);
+        for (file, questions) in files {
+            md.push_str(&format!(
+                "\n## [{}](<{}>)\n\n",
+                file,
+                relative_path(&rel_path, file)
+            ));
+            for question in questions {
+                md.push_str(&format!("- {}\n", question));
+            }
+        }
+
+        let path = output_dir.join(&rel_path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        write_index(&path, &title, &md, format)?;
+        written.push(path);
+    }
+    Ok(written)
+}
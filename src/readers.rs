@@ -0,0 +1,478 @@
+use chardetng::{EncodingDetector, Iso2022JpDetection, Utf8Detection};
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use scraper::{ElementRef, Html, Selector};
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::Path;
+use thiserror::Error;
+use tracing::debug;
+
+#[derive(Error, Debug)]
+pub enum ReaderError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("PDF extraction failed: {0}")]
+    Pdf(String),
+    #[error("Document extraction failed: {0}")]
+    Document(String),
+}
+
+/// Lower-cased file extension of a path
+pub fn extension(path: &Path) -> String {
+    path.extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+/// Formats that are binary on disk but have a text extractor
+pub fn is_document(ext: &str) -> bool {
+    matches!(ext, "pdf" | "docx" | "odt")
+}
+
+/// Sniff the start of a file for content that is not text.
+///
+/// A NUL byte, or more than one control character in ten, marks the file as binary.
+/// UTF-16 text is not binary.
+pub fn looks_binary(path: &Path) -> std::io::Result<bool> {
+    let mut head = Vec::with_capacity(8192);
+    std::fs::File::open(path)?
+        .take(8192)
+        .read_to_end(&mut head)?;
+
+    if utf16(&head).is_some() {
+        return Ok(false);
+    }
+    if head.contains(&0) {
+        return Ok(true);
+    }
+    let control = head
+        .iter()
+        .filter(|&&b| b < 0x20 && !matches!(b, b'\t' | b'\n' | b'\r' | 0x0c | 0x1b))
+        .count();
+    Ok(control * 10 > head.len())
+}
+
+/// UTF-16 from a byte order mark, or from mostly ASCII text where every
+/// other byte is NUL (Windows tools often leave the mark out)
+fn utf16(head: &[u8]) -> Option<&'static Encoding> {
+    match Encoding::for_bom(head) {
+        Some((encoding, _)) if encoding == UTF_16LE || encoding == UTF_16BE => {
+            return Some(encoding)
+        }
+        Some(_) => return None,
+        None => {}
+    }
+    let pairs = head.len() / 2;
+    if pairs < 2 {
+        return None;
+    }
+    let nuls = |offset: usize| {
+        head.iter()
+            .skip(offset)
+            .step_by(2)
+            .filter(|&&b| b == 0)
+            .count()
+    };
+    let (even, odd) = (nuls(0), nuls(1));
+    if odd * 10 >= pairs * 9 && even == 0 {
+        Some(UTF_16LE)
+    } else if even * 10 >= pairs * 9 && odd == 0 {
+        Some(UTF_16BE)
+    } else {
+        None
+    }
+}
+
+/// Read a text file in whatever encoding it was saved in.
+///
+/// UTF-8 is read as is. A byte order mark or the UTF-16 pattern picks the
+/// encoding, and anything else (latin-1, Windows-1252, Shift_JIS...) is
+/// guessed from the bytes and transcoded to UTF-8.
+pub fn read_text(path: &Path) -> Result<String, ReaderError> {
+    let bytes = std::fs::read(path)?;
+    let marked = Encoding::for_bom(&bytes)
+        .map(|(encoding, _)| encoding)
+        .or_else(|| utf16(&bytes[..bytes.len().min(8192)]));
+    if let Some(encoding) = marked {
+        return Ok(transcode(path, encoding, &bytes));
+    }
+
+    match String::from_utf8(bytes) {
+        Ok(text) => Ok(text),
+        Err(e) => {
+            let bytes = e.into_bytes();
+            let mut detector = EncodingDetector::new(Iso2022JpDetection::Deny);
+            detector.feed(&bytes, true);
+            let encoding = detector.guess(None, Utf8Detection::Deny);
+            Ok(transcode(path, encoding, &bytes))
+        }
+    }
+}
+
+fn transcode(path: &Path, encoding: &'static Encoding, bytes: &[u8]) -> String {
+    debug!("Reading {} as {}", path.display(), encoding.name());
+    let (text, _, malformed) = encoding.decode(bytes);
+    if malformed {
+        debug!(
+            "{} has bytes that are not valid {}; replaced them",
+            path.display(),
+            encoding.name()
+        );
+    }
+    text.into_owned()
+}
+
+/// Extract the text of a PDF, keeping a marker at the start of every page
+pub fn read_pdf(path: &Path) -> Result<String, ReaderError> {
+    let bytes = std::fs::read(path)?;
+
+    // pdf-extract panics on some malformed documents
+    let pages = std::panic::catch_unwind(|| pdf_extract::extract_text_from_mem_by_pages(&bytes))
+        .map_err(|_| ReaderError::Pdf("extractor panicked".to_string()))?
+        .map_err(|e| ReaderError::Pdf(e.to_string()))?;
+
+    let mut content = String::new();
+    for (i, page) in pages.iter().enumerate() {
+        let text = page.trim();
+        if text.is_empty() {
+            continue;
+        }
+        if !content.is_empty() {
+            content.push_str("\n\n");
+        }
+        content.push_str(&format!("[Page {}]\n\n{}", i + 1, text));
+    }
+
+    Ok(content)
+}
+
+/// Read an XML member out of a zip-based office document
+fn read_zip_member(path: &Path, member: &str) -> Result<String, ReaderError> {
+    let file = std::fs::File::open(path)?;
+    let mut archive =
+        zip::ZipArchive::new(file).map_err(|e| ReaderError::Document(e.to_string()))?;
+    let mut entry = archive
+        .by_name(member)
+        .map_err(|e| ReaderError::Document(format!("{}: {}", member, e)))?;
+
+    let mut xml = String::new();
+    entry.read_to_string(&mut xml)?;
+    Ok(xml)
+}
+
+/// Value of an attribute by local name, ignoring its namespace prefix
+fn attr_value(element: &BytesStart, name: &[u8]) -> Option<String> {
+    element
+        .attributes()
+        .flatten()
+        .find(|a| a.key.local_name().as_ref() == name)
+        .and_then(|a| a.unescape_value().ok())
+        .map(|v| v.to_string())
+}
+
+fn xml_error(e: quick_xml::Error) -> ReaderError {
+    ReaderError::Document(e.to_string())
+}
+
+/// Append a finished paragraph to the markdown output
+fn push_paragraph(out: &mut String, prefix: &str, text: &str, list_item: bool) {
+    let text = text.trim();
+    if text.is_empty() {
+        return;
+    }
+    // List items stay tight, everything else is separated by a blank line
+    if !list_item && !out.is_empty() && !out.ends_with("\n\n") {
+        out.push('\n');
+    }
+    out.push_str(prefix);
+    out.push_str(text);
+    out.push_str(if list_item { "\n" } else { "\n\n" });
+}
+
+/// Heading level for a Word paragraph style such as `Heading2` or `Title`
+fn docx_heading_level(style: &str) -> Option<usize> {
+    let style = style.to_lowercase();
+    if style == "title" {
+        return Some(1);
+    }
+    let level: usize = style.strip_prefix("heading")?.trim().parse().ok()?;
+    (1..=6).contains(&level).then_some(level)
+}
+
+/// Extract a .docx file as markdown, turning heading styles into headers
+pub fn read_docx(path: &Path) -> Result<String, ReaderError> {
+    let xml = read_zip_member(path, "word/document.xml")?;
+    let mut reader = Reader::from_str(&xml);
+
+    let mut out = String::new();
+    let mut para = String::new();
+    let mut prefix = String::new();
+    let mut list_item = false;
+    let mut in_text = false;
+
+    loop {
+        match reader.read_event().map_err(xml_error)? {
+            Event::Start(e) => match e.local_name().as_ref() {
+                b"p" => {
+                    para.clear();
+                    prefix.clear();
+                    list_item = false;
+                }
+                b"t" => in_text = true,
+                b"numPr" if prefix.is_empty() => {
+                    prefix = "- ".to_string();
+                    list_item = true;
+                }
+                _ => {}
+            },
+            Event::Empty(e) => match e.local_name().as_ref() {
+                b"pStyle" => {
+                    if let Some(level) = attr_value(&e, b"val")
+                        .as_deref()
+                        .and_then(docx_heading_level)
+                    {
+                        prefix = format!("{} ", "#".repeat(level));
+                        list_item = false;
+                    }
+                }
+                b"tab" => para.push('\t'),
+                b"br" | b"cr" => para.push('\n'),
+                _ => {}
+            },
+            Event::Text(t) if in_text => para.push_str(&t.unescape().map_err(xml_error)?),
+            Event::End(e) => match e.local_name().as_ref() {
+                b"t" => in_text = false,
+                b"p" => push_paragraph(&mut out, &prefix, &para, list_item),
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(out.trim_end().to_string())
+}
+
+/// Extract an .odt file as markdown, turning outline headings into headers
+pub fn read_odt(path: &Path) -> Result<String, ReaderError> {
+    let xml = read_zip_member(path, "content.xml")?;
+    let mut reader = Reader::from_str(&xml);
+
+    let mut out = String::new();
+    let mut para = String::new();
+    let mut prefix = String::new();
+    let mut list_depth = 0usize;
+    let mut in_para = false;
+
+    loop {
+        match reader.read_event().map_err(xml_error)? {
+            Event::Start(e) => match e.local_name().as_ref() {
+                b"h" => {
+                    let level: usize = attr_value(&e, b"outline-level")
+                        .and_then(|l| l.parse().ok())
+                        .unwrap_or(1);
+                    prefix = format!("{} ", "#".repeat(level.clamp(1, 6)));
+                    para.clear();
+                    in_para = true;
+                }
+                b"p" => {
+                    prefix = if list_depth > 0 {
+                        format!("{}- ", "  ".repeat(list_depth - 1))
+                    } else {
+                        String::new()
+                    };
+                    para.clear();
+                    in_para = true;
+                }
+                b"list" => list_depth += 1,
+                _ => {}
+            },
+            Event::Empty(e) if in_para => match e.local_name().as_ref() {
+                b"s" => {
+                    let count: usize = attr_value(&e, b"c")
+                        .and_then(|c| c.parse().ok())
+                        .unwrap_or(1);
+                    para.push_str(&" ".repeat(count));
+                }
+                b"tab" => para.push('\t'),
+                b"line-break" => para.push('\n'),
+                _ => {}
+            },
+            Event::Text(t) if in_para => para.push_str(&t.unescape().map_err(xml_error)?),
+            Event::End(e) => match e.local_name().as_ref() {
+                b"h" | b"p" => {
+                    push_paragraph(
+                        &mut out,
+                        &prefix,
+                        &para,
+                        list_depth > 0 && !prefix.starts_with('#'),
+                    );
+                    in_para = false;
+                }
+                b"list" => list_depth = list_depth.saturating_sub(1),
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(out.trim_end().to_string())
+}
+
+/// Elements that never hold the main content of a page
+const BOILERPLATE_TAGS: &[&str] = &[
+    "script", "style", "noscript", "nav", "header", "footer", "aside", "form", "iframe", "svg",
+    "button",
+];
+
+/// Selectors that usually wrap the main content, tried in order
+const MAIN_SELECTORS: &[&str] = &[
+    "article",
+    "main",
+    "[role=main]",
+    "#content",
+    ".post-content",
+    ".entry-content",
+    ".content",
+];
+
+/// Minimum text length for a candidate to count as the main content
+const MIN_MAIN_CHARS: usize = 200;
+
+fn text_len(element: &ElementRef) -> usize {
+    element.text().map(|t| t.trim().len()).sum()
+}
+
+/// Pick the element holding the main content, readability-style.
+///
+/// Prefers semantic containers; otherwise scores every paragraph's parent
+/// by the amount of paragraph text it holds and takes the best one.
+fn main_content<'a>(doc: &'a Html) -> Option<ElementRef<'a>> {
+    for selector in MAIN_SELECTORS {
+        let selector = Selector::parse(selector).ok()?;
+        if let Some(found) = doc
+            .select(&selector)
+            .filter(|e| text_len(e) >= MIN_MAIN_CHARS)
+            .max_by_key(text_len)
+        {
+            return Some(found);
+        }
+    }
+
+    let paragraphs = Selector::parse("p").ok()?;
+    let mut scores: HashMap<_, (usize, ElementRef)> = HashMap::new();
+    for p in doc.select(&paragraphs) {
+        if let Some(parent) = p.parent().and_then(ElementRef::wrap) {
+            scores.entry(parent.id()).or_insert((0, parent)).0 += text_len(&p);
+        }
+    }
+    if let Some((_, best)) = scores.into_values().max_by_key(|(score, _)| *score) {
+        return Some(best);
+    }
+
+    let body = Selector::parse("body").ok()?;
+    doc.select(&body).next()
+}
+
+/// Strip page boilerplate from an HTML document and convert the main content to markdown
+pub fn html_to_markdown(raw: &str) -> Result<String, ReaderError> {
+    let doc = Html::parse_document(raw);
+
+    let title = Selector::parse("title")
+        .ok()
+        .and_then(|s| doc.select(&s).next())
+        .map(|t| t.text().collect::<String>().trim().to_string())
+        .filter(|t| !t.is_empty());
+
+    let main_html = main_content(&doc)
+        .map(|e| e.html())
+        .unwrap_or_else(|| raw.to_string());
+
+    let converter = htmd::HtmlToMarkdown::builder()
+        .skip_tags(BOILERPLATE_TAGS.to_vec())
+        .build();
+    let markdown = converter.convert(&main_html)?;
+    let markdown = markdown.trim();
+
+    match title {
+        Some(title) if !markdown.starts_with('#') => Ok(format!("# {}\n\n{}", title, markdown)),
+        _ => Ok(markdown.to_string()),
+    }
+}
+
+/// Extract text from a document, picking the extractor by extension
+pub fn read_document(path: &Path) -> Result<String, ReaderError> {
+    match extension(path).as_str() {
+        "pdf" => read_pdf(path),
+        "docx" => read_docx(path),
+        "odt" => read_odt(path),
+        ext => Err(ReaderError::Document(format!(
+            "no text extractor for .{} files",
+            ext
+        ))),
+    }
+}
+
+/// Split one line of a delimited file into fields, honouring double quotes
+fn split_record(line: &str, delimiter: char) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            c if c == delimiter && !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+/// Read a CSV or TSV file as a markdown table, treating the first row as the header
+pub fn read_table(path: &Path) -> Result<String, ReaderError> {
+    let text = read_text(path)?;
+    let delimiter = if extension(path) == "tsv" { '\t' } else { ',' };
+
+    let rows: Vec<Vec<String>> = text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| split_record(line, delimiter))
+        .collect();
+    let width = rows.iter().map(|r| r.len()).max().unwrap_or(0);
+    if width == 0 {
+        return Ok(String::new());
+    }
+
+    let render = |row: &[String]| {
+        let cells: Vec<String> = (0..width)
+            .map(|i| {
+                row.get(i)
+                    .map(|cell| cell.trim().replace('|', "\\|"))
+                    .unwrap_or_default()
+            })
+            .collect();
+        format!("| {} |\n", cells.join(" | "))
+    };
+
+    let mut out = render(&rows[0]);
+    out.push_str(&format!("|{}\n", " --- |".repeat(width)));
+    for row in &rows[1..] {
+        out.push_str(&render(row));
+    }
+    Ok(out)
+}
+
+/// Read a saved HTML page as markdown
+pub fn read_html(path: &Path) -> Result<String, ReaderError> {
+    html_to_markdown(&read_text(path)?)
+}
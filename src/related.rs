@@ -0,0 +1,283 @@
+use crate::html;
+use crate::links::{extract_links, relative_path, resolve_relative, resolve_wikilink, LinkKind};
+use crate::logseq;
+use crate::obsidian;
+use crate::types::{CrossReference, OutputFormat};
+use std::collections::HashSet;
+
+/// Marker closing a section in HTML pages
+const HTML_END: &str = "</section>";
+
+/// A link list maintained by the cross-referencing pass
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Section {
+    /// Notes a file points to ("See also")
+    Related,
+    /// Notes pointing to a file
+    ReferencedBy,
+}
+
+impl Section {
+    fn title(self) -> &'static str {
+        match self {
+            Section::Related => "Related",
+            Section::ReferencedBy => "Referenced by",
+        }
+    }
+
+    fn html_class(self) -> &'static str {
+        match self {
+            Section::Related => "related",
+            Section::ReferencedBy => "backlinks",
+        }
+    }
+
+    /// The file holding this section for a reference
+    fn owner(self, xref: &CrossReference) -> &str {
+        match self {
+            Section::Related => &xref.from_file,
+            Section::ReferencedBy => &xref.to_file,
+        }
+    }
+
+    /// The file this section links to for a reference
+    fn target(self, xref: &CrossReference) -> &str {
+        match self {
+            Section::Related => &xref.to_file,
+            Section::ReferencedBy => &xref.from_file,
+        }
+    }
+
+    fn heading(self, format: OutputFormat) -> String {
+        match format {
+            OutputFormat::Html => format!("<section class=\"{}\">", self.html_class()),
+            OutputFormat::Logseq => format!("- ## {}", self.title()),
+            OutputFormat::Markdown | OutputFormat::Plain | OutputFormat::Obsidian => {
+                format!("## {}", self.title())
+            }
+        }
+    }
+}
+
+/// A file split around one of its sections
+struct Split {
+    before: String,
+    items: Vec<String>,
+    after: String,
+    found: bool,
+}
+
+/// Merge cross-references into a section of `file`, keeping a single section
+/// per file.
+///
+/// Entries already listed are kept and never repeated, so re-running the pass
+/// does not grow the file. Returns the new content and the references that
+/// were added, or `None` if every reference was already there.
+pub fn merge_section<'a>(
+    content: &str,
+    file: &str,
+    section: Section,
+    refs: &[&'a CrossReference],
+    format: OutputFormat,
+) -> Option<(String, Vec<&'a CrossReference>)> {
+    let mut split = split_section(content, section, format);
+    let mut listed: HashSet<String> = split
+        .items
+        .iter()
+        .filter_map(|item| entry_target(item, file))
+        .collect();
+
+    let mut added = Vec::new();
+    for xref in refs {
+        let target = section.target(xref);
+        if section.owner(xref) != file || target == file {
+            continue;
+        }
+        let item = entry(file, target, &xref.context, format);
+        let Some(key) = entry_target(&item, file) else {
+            continue;
+        };
+        if listed.insert(key) {
+            split.items.push(item);
+            added.push(*xref);
+        }
+    }
+
+    if added.is_empty() {
+        return None;
+    }
+    Some((render_section(&split, section, format), added))
+}
+
+/// Files listed in a section of `file`, resolved against the known output files
+pub fn section_links(
+    content: &str,
+    file: &str,
+    section: Section,
+    format: OutputFormat,
+    files: &[String],
+) -> Vec<String> {
+    split_section(content, section, format)
+        .items
+        .iter()
+        .filter_map(|item| extract_links(item).into_iter().next())
+        .filter_map(|link| match link.kind {
+            LinkKind::Markdown | LinkKind::Html => resolve_relative(file, &link.target),
+            LinkKind::Wiki if matches!(format, OutputFormat::Logseq) => {
+                let page = logseq::page_path(&link.target);
+                let journal = format!("journals/{}.md", link.target.replace('-', "_"));
+                files
+                    .iter()
+                    .find(|f| **f == page || **f == journal)
+                    .cloned()
+            }
+            LinkKind::Wiki => resolve_wikilink(&link.target, files).map(str::to_string),
+        })
+        .filter(|target| files.contains(target))
+        .collect()
+}
+
+/// One list entry in `file` linking to `target`
+fn entry(file: &str, target: &str, context: &str, format: OutputFormat) -> String {
+    let context = context.replace('\n', " ");
+    match format {
+        OutputFormat::Obsidian => format!("- {} - {}", obsidian::wikilink(target), context),
+        OutputFormat::Logseq => format!("- [[{}]] - {}", logseq::page_name(target), context),
+        OutputFormat::Html => format!(
+            "<li><a href=\"{}\">{}</a> - {}</li>",
+            html::escape(&relative_path(file, target)),
+            html::escape(target),
+            html::escape(&context)
+        ),
+        OutputFormat::Markdown | OutputFormat::Plain => format!(
+            "- [{}]({}) - {}",
+            target,
+            relative_path(file, target),
+            context
+        ),
+    }
+}
+
+/// The note an entry links to, used to recognize entries already listed
+fn entry_target(item: &str, file: &str) -> Option<String> {
+    let link = extract_links(item).into_iter().next()?;
+    match link.kind {
+        LinkKind::Wiki => Some(link.target),
+        LinkKind::Markdown | LinkKind::Html => resolve_relative(file, &link.target),
+    }
+}
+
+/// Split a file around a section, collecting the section's entries
+fn split_section(content: &str, section: Section, format: OutputFormat) -> Split {
+    let heading = section.heading(format);
+    let not_found = || Split {
+        before: content.to_string(),
+        items: Vec::new(),
+        after: String::new(),
+        found: false,
+    };
+
+    if matches!(format, OutputFormat::Html) {
+        let Some(start) = content.rfind(&heading) else {
+            return not_found();
+        };
+        let Some(len) = content[start..].find(HTML_END) else {
+            return not_found();
+        };
+        let end = start + len + HTML_END.len();
+        let items = content[start..end]
+            .lines()
+            .map(str::trim)
+            .filter(|l| l.starts_with("<li>"))
+            .map(str::to_string)
+            .collect();
+        let after = content[end..].strip_prefix('\n').unwrap_or(&content[end..]);
+        return Split {
+            before: content[..start].to_string(),
+            items,
+            after: after.to_string(),
+            found: true,
+        };
+    }
+
+    let lines: Vec<&str> = content.lines().collect();
+    let Some(start) = lines.iter().rposition(|l| l.trim_end() == heading) else {
+        return not_found();
+    };
+    let end = lines[start + 1..]
+        .iter()
+        .position(|l| match format {
+            // Child blocks are indented; the section ends at the next top-level block
+            OutputFormat::Logseq => !l.is_empty() && !l.starts_with([' ', '\t']),
+            // The section is the list under the heading; anything else ends it
+            _ => !l.trim().is_empty() && !l.starts_with("- "),
+        })
+        .map_or(lines.len(), |i| start + 1 + i);
+    let items = lines[start + 1..end]
+        .iter()
+        .map(|l| l.trim_start())
+        .filter(|l| l.starts_with("- "))
+        .map(str::to_string)
+        .collect();
+
+    Split {
+        before: lines[..start].join("\n"),
+        items,
+        after: lines[end..].join("\n"),
+        found: true,
+    }
+}
+
+/// Put the section back where it was, or at the end of the file if it is new
+fn render_section(split: &Split, section: Section, format: OutputFormat) -> String {
+    let heading = section.heading(format);
+    match format {
+        OutputFormat::Html => {
+            let mut block = format!("{}\n<h2>{}</h2>\n<ul>\n", heading, section.title());
+            for item in &split.items {
+                block.push_str(item);
+                block.push('\n');
+            }
+            block.push_str("</ul>\n");
+            block.push_str(HTML_END);
+            block.push('\n');
+            if split.found {
+                format!("{}{}{}", split.before, block, split.after)
+            } else {
+                html::insert_into_page(&split.before, &block)
+            }
+        }
+        OutputFormat::Logseq => {
+            let mut out = split.before.clone();
+            if !out.is_empty() && !out.ends_with('\n') {
+                out.push('\n');
+            }
+            out.push_str(&heading);
+            out.push('\n');
+            for item in &split.items {
+                out.push('\t');
+                out.push_str(item);
+                out.push('\n');
+            }
+            if !split.after.is_empty() {
+                out.push_str(&split.after);
+                out.push('\n');
+            }
+            out
+        }
+        OutputFormat::Markdown | OutputFormat::Plain | OutputFormat::Obsidian => {
+            let mut out = format!(
+                "{}\n\n{}\n\n{}\n",
+                split.before.trim_end(),
+                heading,
+                split.items.join("\n")
+            );
+            if !split.after.trim().is_empty() {
+                out.push('\n');
+                out.push_str(split.after.trim_start());
+                out.push('\n');
+            }
+            out
+        }
+    }
+}
@@ -0,0 +1,304 @@
+use crate::atomic;
+use crate::links::{
+    extract_links, relative_path, replace_target, resolve_relative, resolve_wikilink, LinkKind,
+};
+use crate::originals;
+use crate::types::{ReorgResponse, ReorgSuggestion};
+use crate::writer::{is_safe_path, normalize_path};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use tracing::{debug, info, warn};
+use walkdir::WalkDir;
+
+/// Extensions of output files whose links are checked after a move
+const LINKED_EXTENSIONS: &[&str] = &["md", "html", "txt"];
+
+/// File name of the plan saved by `--reorganize-dry-run`
+pub const PLAN_FILE: &str = "reorg-plan.json";
+
+#[derive(Error, Debug)]
+pub enum ReorgError {
+    #[error("could not read reorganization plan {0}: {1}")]
+    Io(PathBuf, std::io::Error),
+    #[error("invalid reorganization plan {0}: {1}")]
+    Parse(PathBuf, serde_json::Error),
+    #[error("IO error: {0}")]
+    Apply(#[from] std::io::Error),
+}
+
+/// A file moved by the reorganization pass, as paths relative to the output directory
+#[derive(Debug, Clone)]
+pub struct Move {
+    pub from: String,
+    pub to: String,
+}
+
+/// The suggested moves and new categories, for printing
+pub fn format_plan(plan: &ReorgResponse) -> String {
+    let mut out = String::from("\n=== Reorganization Suggestions ===\n");
+
+    if !plan.file_moves.is_empty() {
+        out.push_str("\nFile moves:\n");
+        for mv in &plan.file_moves {
+            out.push_str(&format!(
+                "   {} → {}\n      Reason: {}\n",
+                mv.current_path, mv.suggested_path, mv.reason
+            ));
+        }
+    }
+
+    if !plan.new_categories.is_empty() {
+        out.push_str("\nNew categories:\n");
+        for cat in &plan.new_categories {
+            out.push_str(&format!(
+                "   {}{}\n      Files: {:?}\n      Reason: {}\n",
+                cat.category,
+                cat.subcategory
+                    .as_ref()
+                    .map(|s| format!("/{}", s))
+                    .unwrap_or_default(),
+                cat.affected_files,
+                cat.reason
+            ));
+        }
+    }
+    out
+}
+
+/// Combine the plans proposed for clusters of files into one. Each plan may
+/// only move and regroup the files of its own cluster; a file's first move wins.
+pub fn merge_plans(plans: Vec<(Vec<String>, ReorgResponse)>) -> ReorgResponse {
+    let mut merged = ReorgResponse {
+        file_moves: Vec::new(),
+        new_categories: Vec::new(),
+    };
+    let mut moved = HashSet::new();
+
+    for (files, plan) in plans {
+        let files: HashSet<&String> = files.iter().collect();
+        for mv in plan.file_moves {
+            if !files.contains(&mv.current_path) {
+                debug!(
+                    "Dropping suggested move of {}: not in its cluster",
+                    mv.current_path
+                );
+                continue;
+            }
+            if moved.insert(mv.current_path.clone()) {
+                merged.file_moves.push(mv);
+            }
+        }
+        for mut suggestion in plan.new_categories {
+            suggestion.affected_files.retain(|f| files.contains(f));
+            if suggestion.affected_files.is_empty() {
+                continue;
+            }
+            let same = merged.new_categories.iter_mut().find(|c| {
+                c.category == suggestion.category && c.subcategory == suggestion.subcategory
+            });
+            match same {
+                Some(existing) => existing.affected_files.extend(suggestion.affected_files),
+                None => merged.new_categories.push(suggestion),
+            }
+        }
+    }
+    merged
+}
+
+/// Save a plan for review and a later `--apply-reorg`
+pub fn save_plan(path: &Path, plan: &ReorgResponse) -> Result<(), std::io::Error> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, serde_json::to_string_pretty(plan)?)
+}
+
+/// Load a reviewed plan
+pub fn load_plan(path: &Path) -> Result<ReorgResponse, ReorgError> {
+    let text = std::fs::read_to_string(path).map_err(|e| ReorgError::Io(path.to_path_buf(), e))?;
+    serde_json::from_str(&text).map_err(|e| ReorgError::Parse(path.to_path_buf(), e))
+}
+
+/// Move files within the output directory, skipping any whose source is gone
+/// or whose destination is already taken
+pub fn apply_moves(
+    output_dir: &Path,
+    moves: &[ReorgSuggestion],
+) -> Result<Vec<Move>, std::io::Error> {
+    let mut applied = Vec::new();
+
+    for mv in moves {
+        if !is_safe_path(&mv.current_path) || !is_safe_path(&mv.suggested_path) {
+            warn!(
+                "Not moving {:?} → {:?}: paths must stay inside the output directory",
+                mv.current_path, mv.suggested_path
+            );
+            continue;
+        }
+        let to = normalize_path(&mv.suggested_path);
+        let src = output_dir.join(&mv.current_path);
+        let dst = output_dir.join(&to);
+        if !src.exists() {
+            warn!("Not moving {}: file does not exist", mv.current_path);
+            continue;
+        }
+        if dst.exists() {
+            warn!("Not moving {}: {} already exists", mv.current_path, to);
+            continue;
+        }
+
+        if let Some(parent) = dst.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::rename(&src, &dst)?;
+        info!("Moved {} → {}", mv.current_path, to);
+        applied.push(Move {
+            from: mv.current_path.clone(),
+            to,
+        });
+    }
+
+    Ok(applied)
+}
+
+/// Apply a reviewed plan to an output directory
+pub fn apply_plan(plan_path: &Path, output_dir: &Path) -> Result<Vec<Move>, ReorgError> {
+    let plan = load_plan(plan_path)?;
+    let moves = apply_moves(output_dir, &plan.file_moves)?;
+    fix_links(output_dir, &moves)?;
+    Ok(moves)
+}
+
+/// Rewrite relative links broken by moves: links pointing at a moved file, and
+/// links inside a moved file that were relative to its old location.
+///
+/// Returns the number of files updated.
+pub fn fix_links(output_dir: &Path, moves: &[Move]) -> Result<usize, std::io::Error> {
+    if moves.is_empty() {
+        return Ok(0);
+    }
+
+    let moved: HashMap<&str, &str> = moves
+        .iter()
+        .map(|m| (m.from.as_str(), m.to.as_str()))
+        .collect();
+    let previous: HashMap<&str, &str> = moves
+        .iter()
+        .map(|m| (m.to.as_str(), m.from.as_str()))
+        .collect();
+
+    let files = collect_linked(output_dir);
+    // Wikilinks are resolved the way they were written, against the old layout
+    let old_files: Vec<String> = files
+        .iter()
+        .map(|f| previous.get(f.as_str()).copied().unwrap_or(f).to_string())
+        .collect();
+
+    let mut updated = 0;
+    for file in &files {
+        let path = output_dir.join(file);
+        let Ok(content) = std::fs::read_to_string(&path) else {
+            continue;
+        };
+        let old_path = previous.get(file.as_str()).copied().unwrap_or(file);
+
+        let mut rewritten = content.clone();
+        let mut seen = HashSet::new();
+        for link in extract_links(&content) {
+            if !seen.insert((link.kind, link.target.clone())) {
+                continue;
+            }
+            let new_target = match link.kind {
+                LinkKind::Wiki => fix_wikilink(&link.target, &old_files, &moved),
+                LinkKind::Markdown | LinkKind::Html => {
+                    fix_relative(file, old_path, &link.target, &moved)
+                }
+            };
+            if let Some(new_target) = new_target.filter(|t| *t != link.target) {
+                debug!("{}: {} → {}", file, link.target, new_target);
+                rewritten = replace_target(&rewritten, link.kind, &link.target, &new_target);
+            }
+        }
+
+        if rewritten != content {
+            atomic::write(&path, rewritten)?;
+            updated += 1;
+        }
+    }
+
+    if updated > 0 {
+        info!("Updated links in {} files", updated);
+    }
+    Ok(updated)
+}
+
+/// New target for a relative link in `file` (formerly at `old_path`)
+fn fix_relative(
+    file: &str,
+    old_path: &str,
+    target: &str,
+    moved: &HashMap<&str, &str>,
+) -> Option<String> {
+    let resolved = resolve_relative(old_path, target)?;
+    let new_resolved = moved.get(resolved.as_str()).copied();
+    if new_resolved.is_none() && file == old_path {
+        return None;
+    }
+    let new_resolved = new_resolved.unwrap_or(&resolved);
+
+    let fragment = target.find('#').map(|i| &target[i..]).unwrap_or("");
+    let path = if target.starts_with('/') {
+        format!("/{}", new_resolved)
+    } else {
+        relative_path(file, new_resolved)
+    };
+    Some(format!("{}{}", path, fragment))
+}
+
+/// New target for a wikilink to a moved file, keeping the link's style
+/// (bare name or root-relative path, with or without the extension)
+fn fix_wikilink(target: &str, old_files: &[String], moved: &HashMap<&str, &str>) -> Option<String> {
+    let resolved = resolve_wikilink(target, old_files)?;
+    let new_path = *moved.get(resolved)?;
+
+    let (name, fragment) = match target.find('#') {
+        Some(i) => (&target[..i], &target[i..]),
+        None => (target, ""),
+    };
+    let mut new_name = if name.contains('/') {
+        new_path
+    } else {
+        new_path.rsplit('/').next().unwrap_or(new_path)
+    };
+    if !name.ends_with(".md") {
+        new_name = new_name.strip_suffix(".md").unwrap_or(new_name);
+    }
+    Some(format!("{}{}", new_name, fragment))
+}
+
+/// Relative paths of every output file that can contain links, indexes included
+/// (archived originals and `--backup bak` copies are left as they were)
+pub fn collect_linked(output_dir: &Path) -> Vec<String> {
+    WalkDir::new(output_dir)
+        .into_iter()
+        // Depth 0 is the output directory itself, which may be `.`
+        .filter_entry(|e| {
+            e.depth() == 0
+                || !e.file_name().to_string_lossy().starts_with('.') && !originals::is_archive(e)
+        })
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file() && !atomic::is_backup(e.path()))
+        .filter(|e| {
+            e.path()
+                .extension()
+                .is_some_and(|ext| LINKED_EXTENSIONS.contains(&ext.to_string_lossy().as_ref()))
+        })
+        .filter_map(|e| {
+            e.path()
+                .strip_prefix(output_dir)
+                .ok()
+                .map(|p| p.to_string_lossy().replace('\\', "/"))
+        })
+        .collect()
+}
@@ -0,0 +1,124 @@
+use crate::config::{normalized, Config};
+use crate::links::relative_path;
+use crate::types::{Category, EnhancedSegment};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+/// The output directories files are written under: `-o` and the `[roots]`
+/// entries of the config file, which give categories directories of their own
+pub struct OutputRoots {
+    main: PathBuf,
+    categories: Vec<(Category, PathBuf)>,
+}
+
+impl OutputRoots {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            main: config.output.clone(),
+            categories: config
+                .settings
+                .roots
+                .iter()
+                .map(|(category, dir)| (Category::from_name(category), expand_home(dir)))
+                .collect(),
+        }
+    }
+
+    /// Every root, `-o` first
+    pub fn all(&self) -> Vec<&Path> {
+        let mut roots = vec![self.main.as_path()];
+        for (_, dir) in &self.categories {
+            if !roots.contains(&dir.as_path()) {
+                roots.push(dir);
+            }
+        }
+        roots
+    }
+
+    /// The root a file goes to and its path under it: the root of its first
+    /// segment's category, without the category's folder at the start of its
+    /// path; files of other categories stay under `-o`.
+    pub fn locate(&self, path: &str, segments: &[EnhancedSegment]) -> (&Path, String) {
+        let root = segments.first().and_then(|s| {
+            self.categories
+                .iter()
+                .find(|(category, _)| *category == s.category)
+        });
+        match root {
+            Some((category, dir)) => {
+                let prefix = format!("{}/", category.dir_name());
+                let rel = path.strip_prefix(&prefix).unwrap_or(path).to_string();
+                (dir.as_path(), rel)
+            }
+            None => (self.main.as_path(), path.to_string()),
+        }
+    }
+
+    /// Split files to write by root, as `locate` places them
+    pub fn split(
+        &self,
+        grouped: HashMap<String, Vec<EnhancedSegment>>,
+    ) -> Vec<(PathBuf, HashMap<String, Vec<EnhancedSegment>>)> {
+        let mut split: Vec<(PathBuf, HashMap<String, Vec<EnhancedSegment>>)> = self
+            .all()
+            .into_iter()
+            .map(|root| (root.to_path_buf(), HashMap::new()))
+            .collect();
+        for (path, segments) in grouped {
+            let (dir, rel) = self.locate(&path, &segments);
+            if dir != self.main {
+                debug!("{} goes to {}", path, dir.join(&rel).display());
+            }
+            let files = split
+                .iter_mut()
+                .find(|(root, _)| root == dir)
+                .map(|(_, files)| files)
+                .expect("every root is listed");
+            files.insert(rel, segments);
+        }
+        split.retain(|(_, files)| !files.is_empty());
+        split
+    }
+
+    /// A relative link from one file to another, each given as its root and
+    /// its path under it
+    pub fn link(&self, from: (&Path, &str), to: (&Path, &str)) -> String {
+        if from.0 == to.0 {
+            return relative_path(from.1, to.1);
+        }
+        let absolute = |(root, path): (&Path, &str)| {
+            normalized(&root.join(path))
+                .to_string_lossy()
+                .replace('\\', "/")
+        };
+        relative_path(&absolute(from), &absolute(to))
+    }
+
+    /// The files written under each root, for the passes that work within one.
+    /// A file under none of them counts as under `-o`.
+    pub fn group<'a>(&'a self, files: &[PathBuf]) -> Vec<(&'a Path, Vec<PathBuf>)> {
+        let roots = self.all();
+        let mut grouped: Vec<(&Path, Vec<PathBuf>)> =
+            roots.iter().map(|root| (*root, Vec::new())).collect();
+        for file in files {
+            // The deepest root holding the file, in case one is inside another
+            let index = (0..roots.len())
+                .filter(|&i| file.starts_with(roots[i]))
+                .max_by_key(|&i| roots[i].components().count())
+                .unwrap_or(0);
+            grouped[index].1.push(file.clone());
+        }
+        grouped.retain(|(_, files)| !files.is_empty());
+        grouped
+    }
+}
+
+/// A directory with a leading `~` taken as the home directory
+fn expand_home(dir: &Path) -> PathBuf {
+    let home = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"));
+    match (dir.strip_prefix("~"), home) {
+        (Ok(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => dir.to_path_buf(),
+    }
+}
@@ -0,0 +1,324 @@
+use axum::extract::{Path as UrlPath, Request, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use subtle::ConstantTimeEq;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tracing::{info, warn};
+
+/// Finished and failed runs kept for `GET /runs`; older ones are forgotten
+const KEPT_RUNS: usize = 100;
+
+/// Where a run is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunState {
+    Queued,
+    Running,
+    Finished,
+    Failed,
+}
+
+/// A run of the pipeline started over the API, as `GET /runs/{id}` shows it
+#[derive(Debug, Clone, Serialize)]
+pub struct Run {
+    pub id: u64,
+    pub input: PathBuf,
+    pub state: RunState,
+    /// Pipeline phase being worked on
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub phase: Option<String>,
+    /// Output files written so far
+    pub files: Vec<PathBuf>,
+    pub failures: usize,
+    pub errors: Vec<String>,
+}
+
+struct Server {
+    /// notex itself, run once per submission
+    exe: PathBuf,
+    /// Bearer token every request must carry (`--token`)
+    token: Option<String>,
+    /// Options given after `--`, passed to every run
+    options: Vec<String>,
+    /// Submitted notes are saved under here, one folder per run
+    inbox: PathBuf,
+    runs: Mutex<BTreeMap<u64, Run>>,
+    last_id: AtomicU64,
+    /// Runs share the output directory, so they go one at a time
+    queue: tokio::sync::Mutex<()>,
+}
+
+type Shared = Arc<Server>;
+
+/// Body of `POST /notes`
+#[derive(Debug, Deserialize)]
+struct NewNote {
+    content: String,
+    /// File name to save the note under (default `note.md`)
+    name: Option<String>,
+}
+
+/// Body of `POST /runs`
+#[derive(Debug, Deserialize)]
+struct NewRun {
+    input: PathBuf,
+}
+
+/// An output file and its content, from `GET /runs/{id}/results`
+#[derive(Debug, Serialize)]
+struct OutputFile {
+    path: PathBuf,
+    content: String,
+}
+
+/// Answer with a status code and a JSON `{"error": ...}` body
+fn error(status: StatusCode, message: impl ToString) -> Response {
+    let body = serde_json::json!({ "error": message.to_string() });
+    (status, Json(body)).into_response()
+}
+
+/// Serve the HTTP API until the process is stopped. Every run starts
+/// `notex <input> <options> --progress json` and follows its events.
+pub async fn serve(
+    listen: SocketAddr,
+    token: Option<String>,
+    inbox: PathBuf,
+    options: Vec<String>,
+) -> std::io::Result<()> {
+    let server = Arc::new(Server {
+        exe: std::env::current_exe()?,
+        token,
+        options,
+        inbox,
+        runs: Mutex::new(BTreeMap::new()),
+        last_id: AtomicU64::new(0),
+        queue: tokio::sync::Mutex::new(()),
+    });
+
+    let app = Router::new()
+        .route("/notes", post(submit_note))
+        .route("/runs", get(list_runs).post(submit_run))
+        .route("/runs/{id}", get(run_status))
+        .route("/runs/{id}/results", get(run_results))
+        .route_layer(middleware::from_fn_with_state(
+            Arc::clone(&server),
+            authorize,
+        ))
+        .with_state(server);
+
+    let listener = tokio::net::TcpListener::bind(listen).await?;
+    info!("Listening on http://{}", listener.local_addr()?);
+    axum::serve(listener, app).await
+}
+
+/// Turn away requests without the `--token` bearer token, when one is set
+async fn authorize(State(server): State<Shared>, request: Request, next: Next) -> Response {
+    if let Some(token) = &server.token {
+        let given = request
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        // Constant time, so response timing doesn't reveal how much of a guess matched
+        let matches =
+            given.is_some_and(|given| bool::from(given.as_bytes().ct_eq(token.as_bytes())));
+        if !matches {
+            return error(StatusCode::UNAUTHORIZED, "Missing or wrong bearer token");
+        }
+    }
+    next.run(request).await
+}
+
+async fn submit_note(State(server): State<Shared>, Json(note): Json<NewNote>) -> Response {
+    let id = server.next_id();
+    let dir = server.inbox.join(id.to_string());
+    if let Err(e) = save_note(&dir, note.name.as_deref(), &note.content).await {
+        return error(StatusCode::INTERNAL_SERVER_ERROR, e);
+    }
+    server.start(id, dir)
+}
+
+async fn submit_run(State(server): State<Shared>, Json(run): Json<NewRun>) -> Response {
+    if !run.input.is_dir() {
+        let message = format!("Not a directory: {}", run.input.display());
+        return error(StatusCode::BAD_REQUEST, message);
+    }
+    let id = server.next_id();
+    server.start(id, run.input)
+}
+
+async fn list_runs(State(server): State<Shared>) -> Json<Vec<Run>> {
+    Json(server.runs.lock().unwrap().values().cloned().collect())
+}
+
+async fn run_status(State(server): State<Shared>, UrlPath(id): UrlPath<u64>) -> Response {
+    match server.runs.lock().unwrap().get(&id) {
+        Some(run) => Json(run.clone()).into_response(),
+        None => error(StatusCode::NOT_FOUND, format!("No run {}", id)),
+    }
+}
+
+async fn run_results(State(server): State<Shared>, UrlPath(id): UrlPath<u64>) -> Response {
+    let run = server.runs.lock().unwrap().get(&id).cloned();
+    let Some(run) = run else {
+        return error(StatusCode::NOT_FOUND, format!("No run {}", id));
+    };
+    if matches!(run.state, RunState::Queued | RunState::Running) {
+        return error(StatusCode::CONFLICT, format!("Run {} has not finished", id));
+    }
+
+    let mut files = Vec::new();
+    for path in run.files {
+        match tokio::fs::read_to_string(&path).await {
+            Ok(content) => files.push(OutputFile { path, content }),
+            Err(e) => warn!("Could not read {}: {}", path.display(), e),
+        }
+    }
+    Json(files).into_response()
+}
+
+impl Server {
+    fn next_id(&self) -> u64 {
+        self.last_id.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    /// Queue a run over `input` and answer with its status
+    fn start(self: &Arc<Self>, id: u64, input: PathBuf) -> Response {
+        let run = Run::new(id, input.clone());
+        self.runs.lock().unwrap().insert(id, run.clone());
+        info!("Queued run {} over {}", id, input.display());
+
+        let server = Arc::clone(self);
+        tokio::spawn(async move {
+            let _turn = server.queue.lock().await;
+            let state = match server.execute(id, &input).await {
+                Ok(true) => RunState::Finished,
+                Ok(false) => RunState::Failed,
+                Err(e) => {
+                    server.update(id, |run| run.errors.push(e.to_string()));
+                    RunState::Failed
+                }
+            };
+            server.update(id, |run| run.state = state);
+            server.forget_old_runs();
+            info!("Run {} {:?}", id, state);
+        });
+        (StatusCode::ACCEPTED, Json(run)).into_response()
+    }
+
+    fn update(&self, id: u64, change: impl FnOnce(&mut Run)) {
+        if let Some(run) = self.runs.lock().unwrap().get_mut(&id) {
+            change(run);
+        }
+    }
+
+    /// Drop the oldest finished and failed runs beyond `KEPT_RUNS`
+    fn forget_old_runs(&self) {
+        let mut runs = self.runs.lock().unwrap();
+        let done: Vec<u64> = runs
+            .values()
+            .filter(|run| matches!(run.state, RunState::Finished | RunState::Failed))
+            .map(|run| run.id)
+            .collect();
+        for id in done.iter().take(done.len().saturating_sub(KEPT_RUNS)) {
+            runs.remove(id);
+        }
+    }
+
+    /// Run the pipeline and follow its progress events; true if it succeeded
+    async fn execute(&self, id: u64, input: &Path) -> std::io::Result<bool> {
+        self.update(id, |run| run.state = RunState::Running);
+        let status = run_pipeline(&self.exe, input, &self.options, |event| {
+            self.update(id, |run| run.follow(event))
+        })
+        .await?;
+        if !status.success() {
+            let message = format!("notex exited with {}", status);
+            self.update(id, |run| run.errors.push(message));
+        }
+        Ok(status.success())
+    }
+}
+
+impl Run {
+    pub fn new(id: u64, input: PathBuf) -> Self {
+        Self {
+            id,
+            input,
+            state: RunState::Queued,
+            phase: None,
+            files: vec![],
+            failures: 0,
+            errors: vec![],
+        }
+    }
+
+    /// Apply one `--progress json` event to the run's status
+    pub fn follow(&mut self, event: &serde_json::Value) {
+        let text = |key: &str| event.get(key).and_then(|v| v.as_str()).map(str::to_string);
+        match event.get("event").and_then(|e| e.as_str()) {
+            Some("phase_started") => self.phase = text("name"),
+            Some("file_written") => self.files.extend(text("path").map(PathBuf::from)),
+            Some("error") => self.errors.extend(text("message")),
+            Some("finished") => {
+                self.phase = None;
+                self.failures = event["failures"].as_u64().unwrap_or(0) as usize;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Save a submitted note in `dir` under its file name (`note.md` by default,
+/// `.md` added when it has no extension), returning its path
+pub async fn save_note(dir: &Path, name: Option<&str>, content: &str) -> std::io::Result<PathBuf> {
+    // Only the file name is kept, so notes stay inside the inbox
+    let name = name
+        .and_then(|name| Path::new(name).file_name())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("note.md"));
+    let name = match name.extension() {
+        Some(_) => name,
+        None => name.with_extension("md"),
+    };
+    tokio::fs::create_dir_all(dir).await?;
+    let path = dir.join(name);
+    tokio::fs::write(&path, content).await?;
+    Ok(path)
+}
+
+/// Run `notex <input> <options> --progress json`, handing every progress
+/// event to `on_event`
+pub async fn run_pipeline(
+    exe: &Path,
+    input: &Path,
+    options: &[String],
+    mut on_event: impl FnMut(&serde_json::Value),
+) -> std::io::Result<ExitStatus> {
+    let mut child = tokio::process::Command::new(exe)
+        .arg(input)
+        .args(options)
+        .args(["--progress", "json"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .spawn()?;
+
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let mut lines = BufReader::new(stdout).lines();
+    while let Some(line) = lines.next_line().await? {
+        if let Ok(event) = serde_json::from_str::<serde_json::Value>(&line) {
+            on_event(&event);
+        }
+    }
+    child.wait().await
+}
@@ -0,0 +1,145 @@
+use crate::categorizer::extract_json;
+use crate::client::{ClientError, LlmClient};
+use crate::types::{EnhancedSegment, OutputFormat, ReviewCard};
+use serde::Deserialize;
+use thiserror::Error;
+
+const SRS_START: &str = "<!-- srs -->";
+const SRS_END: &str = "<!-- /srs -->";
+
+/// Most review prompts kept per segment
+const MAX_CARDS: usize = 8;
+
+/// Longest excerpt of a segment sent for review prompts
+const SRS_EXCERPT_CHARS: usize = 6000;
+
+const SRS_SYSTEM_PROMPT: &str = r#"You are a study assistant writing spaced-repetition review prompts. Given a note, write 3 to 8 prompts that test its key facts, definitions and ideas.
+
+Respond with JSON in this exact format:
+{
+  "cards": [
+    {"type": "qa", "question": "What does gradient descent minimize?", "answer": "A differentiable loss function."},
+    {"type": "cloze", "text": "The learning rate sets the {{c1::step size}} of each update."}
+  ]
+}
+
+Rules:
+- Use "qa" for questions and "cloze" for sentences with one or more {{c1::...}} deletions (number them c1, c2, ...)
+- Each prompt tests one thing and can be answered without seeing the note
+- Keep answers short; put equations in LaTeX ($...$)
+- Only use facts stated in the note
+- Write the prompts in the language of the note
+- Do NOT use emojis"#;
+
+#[derive(Error, Debug)]
+pub enum SrsError {
+    #[error("LLM client error: {0}")]
+    Client(#[from] ClientError),
+    #[error("Failed to parse LLM response: {0}")]
+    Parse(#[from] serde_json::Error),
+}
+
+#[derive(Debug, Deserialize)]
+struct SrsResponse {
+    cards: Vec<ReviewCard>,
+}
+
+/// Ask the LLM for review prompts covering a segment
+pub async fn review_cards(
+    client: &LlmClient,
+    segment: &EnhancedSegment,
+) -> Result<Vec<ReviewCard>, SrsError> {
+    let excerpt: String = segment.content.chars().take(SRS_EXCERPT_CHARS).collect();
+    let user_prompt = format!("Category: {}\n\nNote:\n{}", segment.category, excerpt);
+    let response = client.chat_json(SRS_SYSTEM_PROMPT, &user_prompt).await?;
+    let response: SrsResponse = serde_json::from_str(extract_json(&response))?;
+
+    let mut cards: Vec<ReviewCard> = response
+        .cards
+        .into_iter()
+        .map(|card| match card {
+            ReviewCard::Qa { question, answer } => ReviewCard::Qa {
+                question: one_line(&question),
+                answer: one_line(&answer),
+            },
+            ReviewCard::Cloze { text } => ReviewCard::Cloze {
+                text: one_line(&text),
+            },
+        })
+        .filter(|card| match card {
+            ReviewCard::Qa { question, answer } => !question.is_empty() && !answer.is_empty(),
+            ReviewCard::Cloze { text } => text.contains("{{c"),
+        })
+        .collect();
+    cards.truncate(MAX_CARDS);
+    Ok(cards)
+}
+
+/// Collapse whitespace so every field fits on its line
+fn one_line(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// The "Review questions" section closing a file, empty if its segments have no prompts.
+///
+/// Logseq gets native `#card` blocks; other formats get `Q:`/`A:` and `C:` lines
+/// between `<!-- srs -->` markers, one prompt per line, to follow the trimmed content.
+pub fn review_section(segments: &[EnhancedSegment], format: OutputFormat) -> String {
+    let cards: Vec<&ReviewCard> = segments.iter().flat_map(|s| &s.cards).collect();
+    if cards.is_empty() {
+        return String::new();
+    }
+
+    if matches!(format, OutputFormat::Logseq) {
+        let mut out = String::from("\n- ## Review questions\n");
+        for card in cards {
+            match card {
+                ReviewCard::Qa { question, answer } => {
+                    out.push_str(&format!("\t- {} #card\n\t\t- {}\n", question, answer))
+                }
+                ReviewCard::Cloze { text } => {
+                    out.push_str(&format!("\t- {} #card\n", logseq_cloze(text)))
+                }
+            }
+        }
+        return out;
+    }
+
+    let heading = match format {
+        OutputFormat::Plain => "Review questions\n----------------",
+        _ => "## Review questions",
+    };
+    let mut out = format!("\n\n{}\n{}\n", SRS_START, heading);
+    for card in cards {
+        match card {
+            ReviewCard::Qa { question, answer } => {
+                out.push_str(&format!("\nQ: {}\nA: {}\n", question, answer))
+            }
+            ReviewCard::Cloze { text } => out.push_str(&format!("\nC: {}\n", text)),
+        }
+    }
+    out.push_str(SRS_END);
+    out
+}
+
+/// Rewrite `{{c1::text}}` deletions as Logseq's `{{cloze text}}`
+fn logseq_cloze(text: &str) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("{{c") {
+        let Some(len) = rest[start..].find("}}") else {
+            break;
+        };
+        let inner = &rest[start + 2..start + len];
+        let Some((_, answer)) = inner.split_once("::") else {
+            break;
+        };
+        // Drop an Anki hint (`{{c1::answer::hint}}`)
+        let answer = answer.split("::").next().unwrap_or(answer);
+        out.push_str(&rest[..start]);
+        out.push_str(&format!("{{{{cloze {}}}}}", answer));
+        rest = &rest[start + len + 2..];
+    }
+    out.push_str(rest);
+    out
+}
@@ -0,0 +1,225 @@
+use crate::graph::{build_graph, EdgeKind};
+use crate::indexer::collect_indexable;
+use crate::manifest;
+use crate::questions;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write as _;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use tracing::warn;
+
+/// Run history kept in the output directory (hidden, so indexing skips it)
+pub const RUNS_FILE: &str = ".runs.jsonl";
+
+/// What one processing run did, appended to `.runs.jsonl` when it finishes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunRecord {
+    /// When the run finished, local time
+    pub finished: String,
+    pub notes: usize,
+    pub segments: usize,
+    pub segments_per_category: BTreeMap<String, usize>,
+    /// Characters of the categorized segments that went into enhancement
+    pub input_chars: usize,
+    /// Characters of the enhanced segments that came out
+    pub output_chars: usize,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+impl RunRecord {
+    /// Output characters per input character; below 1 means the notes got shorter
+    pub fn compression_ratio(&self) -> Option<f64> {
+        (self.input_chars > 0).then(|| self.output_chars as f64 / self.input_chars as f64)
+    }
+}
+
+/// Append a run to the output directory's history
+pub fn record_run(output_dir: &Path, record: &RunRecord) -> Result<(), std::io::Error> {
+    let line = serde_json::to_string(record).map_err(std::io::Error::other)?;
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(output_dir.join(RUNS_FILE))?;
+    writeln!(file, "{}", line)
+}
+
+/// Every run recorded in the output directory, oldest first
+pub fn read_runs(output_dir: &Path) -> Vec<RunRecord> {
+    let Ok(content) = fs::read_to_string(output_dir.join(RUNS_FILE)) else {
+        return vec![];
+    };
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .filter_map(|(i, line)| match serde_json::from_str(line) {
+            Ok(record) => Some(record),
+            Err(e) => {
+                warn!("Skipping line {} of {}: {}", i + 1, RUNS_FILE, e);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Size of one output file
+#[derive(Debug, Clone, Serialize)]
+pub struct FileSize {
+    pub path: String,
+    pub chars: usize,
+}
+
+/// The `notex stats` report for an output directory
+#[derive(Debug, Clone, Serialize)]
+pub struct Stats {
+    pub files: usize,
+    pub output_chars: usize,
+    /// Notes processed by the last run
+    pub notes: Option<usize>,
+    /// Segments per category in the last run
+    pub segments_per_category: BTreeMap<String, usize>,
+    /// Compression ratio of the last run
+    pub compression_ratio: Option<f64>,
+    pub largest_files: Vec<FileSize>,
+    /// Files no other note links to (index files don't count as links)
+    pub orphaned: Vec<String>,
+    pub unanswered_questions: usize,
+    /// Notes and segments that failed in the last run, from its manifest
+    pub failures: Option<usize>,
+    /// Files changed or removed since the last run wrote them
+    pub edited: Vec<String>,
+    pub runs: Vec<RunRecord>,
+}
+
+/// Gather statistics for an output directory, listing the `top` largest files
+pub fn collect(output_dir: &Path, top: usize) -> Stats {
+    let files = collect_indexable(output_dir);
+    let mut sizes = Vec::new();
+    let mut unanswered_questions = 0;
+    for file in &files {
+        let Ok(content) = fs::read_to_string(output_dir.join(file)) else {
+            continue;
+        };
+        unanswered_questions += questions::unanswered(&content);
+        sizes.push(FileSize {
+            path: file.clone(),
+            chars: content.chars().count(),
+        });
+    }
+    let output_chars = sizes.iter().map(|f| f.chars).sum();
+    sizes.sort_by(|a, b| b.chars.cmp(&a.chars).then_with(|| a.path.cmp(&b.path)));
+    sizes.truncate(top);
+
+    let graph = build_graph(output_dir, None);
+    let linked: BTreeSet<&str> = graph
+        .edges
+        .iter()
+        .filter(|e| e.kind == EdgeKind::CrossReference)
+        .map(|e| e.target.as_str())
+        .collect();
+    let orphaned = files
+        .iter()
+        .filter(|f| !linked.contains(f.as_str()))
+        .cloned()
+        .collect();
+
+    let manifest = manifest::read(output_dir);
+    let runs = read_runs(output_dir);
+    let last = runs.last();
+    Stats {
+        files: files.len(),
+        output_chars,
+        notes: last.map(|r| r.notes),
+        segments_per_category: last
+            .map(|r| r.segments_per_category.clone())
+            .unwrap_or_default(),
+        compression_ratio: last.and_then(RunRecord::compression_ratio),
+        largest_files: sizes,
+        orphaned,
+        unanswered_questions,
+        failures: manifest.as_ref().map(|m| m.errors.len()),
+        edited: manifest.map(|m| m.edited(output_dir)).unwrap_or_default(),
+        runs,
+    }
+}
+
+/// Render the report as plain-text tables
+pub fn render_table(stats: &Stats) -> String {
+    let mut out = String::new();
+    let ratio = |r: Option<f64>| r.map(|r| format!("{:.2}", r)).unwrap_or("-".into());
+
+    let _ = writeln!(out, "Output files:          {}", stats.files);
+    let _ = writeln!(out, "Output characters:     {}", stats.output_chars);
+    let _ = writeln!(
+        out,
+        "Notes (last run):      {}",
+        stats.notes.map(|n| n.to_string()).unwrap_or("-".into())
+    );
+    let _ = writeln!(
+        out,
+        "Compression ratio:     {}",
+        ratio(stats.compression_ratio)
+    );
+    let _ = writeln!(out, "Unanswered questions:  {}", stats.unanswered_questions);
+    let _ = writeln!(out, "Orphaned outputs:      {}", stats.orphaned.len());
+    let _ = writeln!(
+        out,
+        "Failures (last run):   {}",
+        stats.failures.map(|n| n.to_string()).unwrap_or("-".into())
+    );
+    let _ = writeln!(out, "Edited since run:      {}", stats.edited.len());
+
+    if !stats.segments_per_category.is_empty() {
+        let _ = writeln!(out, "\n{:<30} {:>8}", "Category", "Segments");
+        for (category, count) in &stats.segments_per_category {
+            let _ = writeln!(out, "{:<30} {:>8}", category, count);
+        }
+    }
+
+    if !stats.largest_files.is_empty() {
+        let _ = writeln!(out, "\n{:<60} {:>10}", "Largest files", "Chars");
+        for file in &stats.largest_files {
+            let _ = writeln!(out, "{:<60} {:>10}", file.path, file.chars);
+        }
+    }
+
+    if !stats.orphaned.is_empty() {
+        let _ = writeln!(out, "\nOrphaned outputs");
+        for file in &stats.orphaned {
+            let _ = writeln!(out, "  {}", file);
+        }
+    }
+
+    if !stats.edited.is_empty() {
+        let _ = writeln!(out, "\nEdited since the last run");
+        for file in &stats.edited {
+            let _ = writeln!(out, "  {}", file);
+        }
+    }
+
+    if stats.runs.is_empty() {
+        let _ = writeln!(out, "\nNo runs recorded in {}", RUNS_FILE);
+    } else {
+        let _ = writeln!(
+            out,
+            "\n{:<20} {:>6} {:>9} {:>8} {:>14} {:>18}",
+            "Run", "Notes", "Segments", "Ratio", "Prompt tokens", "Completion tokens"
+        );
+        for run in &stats.runs {
+            let _ = writeln!(
+                out,
+                "{:<20} {:>6} {:>9} {:>8} {:>14} {:>18}",
+                run.finished,
+                run.notes,
+                run.segments,
+                ratio(run.compression_ratio()),
+                run.prompt_tokens,
+                run.completion_tokens
+            );
+        }
+    }
+    out
+}
@@ -0,0 +1,118 @@
+use crate::categorizer::extract_json;
+use crate::client::{ClientError, LlmClient};
+use crate::indexer::write_index;
+use crate::links::relative_path;
+use crate::types::{EnhancedSegment, OutputFormat};
+use crate::writer::{output_rel_path, rekey};
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Tag index, relative to the output directory (before format mapping)
+pub const TAG_INDEX: &str = "tags/index.md";
+
+/// Most tags kept per segment
+const MAX_TAGS: usize = 7;
+
+/// Longest excerpt of a segment sent for tagging
+const TAG_EXCERPT_CHARS: usize = 4000;
+
+const TAG_SYSTEM_PROMPT: &str = r#"You are a note tagging assistant. Given a note, choose 3 to 7 tags that describe its topics.
+
+Respond with JSON in this exact format:
+{"tags": ["gradient-descent", "optimization", "calculus"]}
+
+Rules:
+- Tags are lowercase, with hyphens instead of spaces
+- Prefer specific topics over broad fields; the note's category is already recorded
+- Reuse common, well-known terms so related notes share tags"#;
+
+#[derive(Error, Debug)]
+pub enum TagError {
+    #[error("LLM client error: {0}")]
+    Client(#[from] ClientError),
+    #[error("Failed to parse LLM response: {0}")]
+    Parse(#[from] serde_json::Error),
+}
+
+#[derive(Debug, Deserialize)]
+struct TagResponse {
+    tags: Vec<String>,
+}
+
+/// Ask the LLM for 3-7 tags describing a segment
+pub async fn suggest_tags(
+    client: &LlmClient,
+    segment: &EnhancedSegment,
+) -> Result<Vec<String>, TagError> {
+    let excerpt: String = segment.content.chars().take(TAG_EXCERPT_CHARS).collect();
+    let user_prompt = format!("Category: {}\n\nNote:\n{}", segment.category, excerpt);
+    let response = client.chat_json(TAG_SYSTEM_PROMPT, &user_prompt).await?;
+    let response: TagResponse = serde_json::from_str(extract_json(&response))?;
+
+    let mut tags: Vec<String> = Vec::new();
+    for tag in response.tags.iter().map(|t| normalize(t)) {
+        if !tag.is_empty() && !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    tags.truncate(MAX_TAGS);
+    Ok(tags)
+}
+
+/// Lower-case a tag and replace spaces with hyphens
+fn normalize(tag: &str) -> String {
+    tag.trim()
+        .trim_start_matches('#')
+        .to_lowercase()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Tags of a file's segments, in order of appearance
+pub fn file_tags(segments: &[EnhancedSegment]) -> Vec<String> {
+    let mut tags: Vec<String> = Vec::new();
+    for tag in segments.iter().flat_map(|s| &s.tags) {
+        if !tags.contains(tag) {
+            tags.push(tag.clone());
+        }
+    }
+    tags
+}
+
+/// Write `tags/index.md`, listing for every tag the output files that carry it
+pub fn write_tag_index(
+    output_dir: &Path,
+    grouped: &HashMap<String, Vec<EnhancedSegment>>,
+    format: OutputFormat,
+) -> Result<PathBuf, std::io::Error> {
+    let mut by_tag: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    for (file, segments) in rekey(grouped.clone(), format) {
+        for tag in file_tags(&segments) {
+            by_tag.entry(tag).or_default().insert(file.clone());
+        }
+    }
+
+    let rel_path = output_rel_path(TAG_INDEX, format);
+    let mut md = String::from("# Tags\n");
+    for (tag, files) in &by_tag {
+        md.push_str(&format!("\n## {}\n\n", tag));
+        for file in files {
+            md.push_str(&format!(
+                "- [{}](<{}>)\n",
+                file,
+                relative_path(&rel_path, file)
+            ));
+        }
+    }
+
+    let path = output_dir.join(&rel_path);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    write_index(&path, "Tags", &md, format)?;
+    Ok(path)
+}
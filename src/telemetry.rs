@@ -0,0 +1,123 @@
+use crate::config::{Command, Config};
+use crate::types::ProgressMode;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use opentelemetry_sdk::Resource;
+use std::ffi::OsStr;
+use std::path::Path;
+use tracing::{warn, Level};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_subscriber::filter::{LevelFilter, Targets};
+use tracing_subscriber::fmt::{self, writer::BoxMakeWriter};
+use tracing_subscriber::prelude::*;
+
+/// Logging and tracing state that must be flushed before the process exits
+pub struct Telemetry {
+    _log_guard: Option<WorkerGuard>,
+    tracer_provider: Option<SdkTracerProvider>,
+}
+
+impl Telemetry {
+    /// Flush buffered log lines and export any pending spans
+    pub fn shutdown(self) {
+        if let Some(provider) = self.tracer_provider {
+            if let Err(e) = provider.shutdown() {
+                warn!("Could not flush OTLP spans: {}", e);
+            }
+        }
+    }
+}
+
+/// Log to the terminal at the chosen verbosity; with `--log-file`, also write
+/// everything at DEBUG as JSON lines to a daily-rotated file, and with
+/// `--otlp-endpoint`, export a span for every LLM call
+pub fn init(config: &Config) -> Telemetry {
+    let log_level = if config.verbose {
+        Level::DEBUG
+    } else {
+        Level::INFO
+    };
+
+    // Keep stdout clean for JSON progress events and MCP messages
+    let mcp = matches!(config.command, Some(Command::Mcp { .. }));
+    let writer = match config.progress {
+        ProgressMode::Json => BoxMakeWriter::new(std::io::stderr),
+        ProgressMode::Bars if mcp => BoxMakeWriter::new(std::io::stderr),
+        ProgressMode::Bars => BoxMakeWriter::new(std::io::stdout),
+    };
+    let console = fmt::layer()
+        .with_target(false)
+        .with_thread_ids(false)
+        .compact()
+        .with_writer(writer)
+        .with_filter(LevelFilter::from_level(log_level));
+
+    let (file, log_guard) = match &config.log_file {
+        Some(path) => {
+            let dir = path
+                .parent()
+                .filter(|p| !p.as_os_str().is_empty())
+                .unwrap_or(Path::new("."));
+            let name = path.file_name().unwrap_or(OsStr::new("notex.log"));
+            let (writer, guard) =
+                tracing_appender::non_blocking(tracing_appender::rolling::daily(dir, name));
+            let layer = fmt::layer().json().with_writer(writer).with_filter(
+                Targets::new()
+                    .with_target("notex", Level::DEBUG)
+                    .with_default(Level::INFO),
+            );
+            (Some(layer), Some(guard))
+        }
+        None => (None, None),
+    };
+
+    let (tracer_provider, otlp_error) = match &config.otlp_endpoint {
+        Some(endpoint) => match tracer_provider(endpoint) {
+            Ok(provider) => (Some(provider), None),
+            Err(e) => (None, Some(e)),
+        },
+        None => (None, None),
+    };
+    let otel = tracer_provider.as_ref().map(|provider| {
+        tracing_opentelemetry::layer()
+            .with_tracer(provider.tracer("notex"))
+            .with_filter(Targets::new().with_target("notex", Level::INFO))
+    });
+
+    tracing_subscriber::registry()
+        .with(console)
+        .with(file)
+        .with(otel)
+        .init();
+
+    if let Some(e) = otlp_error {
+        warn!("OTLP tracing disabled: {}", e);
+    }
+
+    Telemetry {
+        _log_guard: log_guard,
+        tracer_provider,
+    }
+}
+
+/// Build a provider that batches spans to an OTLP/HTTP collector
+fn tracer_provider(endpoint: &str) -> Result<SdkTracerProvider, String> {
+    // Accept either the collector's base URL or the full traces URL
+    let endpoint = if endpoint.ends_with("/v1/traces") {
+        endpoint.to_string()
+    } else {
+        format!("{}/v1/traces", endpoint.trim_end_matches('/'))
+    };
+
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(endpoint)
+        .build()
+        .map_err(|e| e.to_string())?;
+
+    Ok(SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(Resource::builder().with_service_name("notex").build())
+        .build())
+}
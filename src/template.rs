@@ -0,0 +1,108 @@
+use crate::types::{EnhancedSegment, OutputFormat};
+use handlebars::{no_escape, Handlebars};
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+const TEMPLATE_NAME: &str = "file";
+
+#[derive(Error, Debug)]
+pub enum TemplateError {
+    #[error("could not read template {0}: {1}")]
+    Io(PathBuf, std::io::Error),
+    #[error("invalid template {0}: {1}")]
+    Parse(PathBuf, Box<handlebars::TemplateError>),
+    #[error("could not render {0}: {1}")]
+    Render(String, Box<handlebars::RenderError>),
+}
+
+/// A Handlebars template for output files (`--template`)
+pub struct Template {
+    registry: Handlebars<'static>,
+}
+
+/// What a template sees for one output file
+#[derive(Serialize)]
+pub struct FileContext<'a> {
+    /// Output path relative to the output directory
+    pub path: &'a str,
+    /// File name without extension, underscores as spaces
+    pub title: String,
+    pub format: &'static str,
+    /// True when segments are added to a file written by an earlier run
+    pub append: bool,
+    /// The frontmatter or page properties notex would write, empty when appending
+    pub frontmatter: String,
+    pub categories: BTreeSet<String>,
+    pub tags: Vec<String>,
+    pub sources: BTreeSet<String>,
+    pub segments: Vec<SegmentContext<'a>>,
+    /// The `--srs` review questions section, empty without it
+    pub review: String,
+}
+
+#[derive(Serialize)]
+pub struct SegmentContext<'a> {
+    /// Enhanced text, already converted to the output format
+    pub content: String,
+    pub category: String,
+    pub subcategory: Option<&'a str>,
+    pub source: String,
+    pub position: usize,
+    /// Lines of the source note, e.g. `12-30`, when they were found
+    pub lines: Option<String>,
+    /// Other notes the content was also found in (`--dedupe`)
+    pub duplicates: Vec<String>,
+    pub tags: &'a [String],
+}
+
+impl<'a> SegmentContext<'a> {
+    pub fn new(segment: &'a EnhancedSegment, content: String) -> Self {
+        Self {
+            content,
+            category: segment.category.to_string(),
+            subcategory: segment.subcategory.as_deref(),
+            source: segment.original_path.to_string_lossy().to_string(),
+            position: segment.position,
+            lines: segment.lines.map(|l| l.to_string()),
+            duplicates: segment
+                .duplicates
+                .iter()
+                .map(|p| p.to_string_lossy().to_string())
+                .collect(),
+            tags: &segment.tags,
+        }
+    }
+}
+
+impl Template {
+    pub fn load(path: &Path) -> Result<Self, TemplateError> {
+        let text =
+            std::fs::read_to_string(path).map_err(|e| TemplateError::Io(path.to_path_buf(), e))?;
+        let mut registry = Handlebars::new();
+        // Output is Markdown or plain text, not HTML
+        registry.register_escape_fn(no_escape);
+        registry
+            .register_template_string(TEMPLATE_NAME, text)
+            .map_err(|e| TemplateError::Parse(path.to_path_buf(), Box::new(e)))?;
+        Ok(Self { registry })
+    }
+
+    pub fn render(&self, context: &FileContext) -> Result<String, TemplateError> {
+        self.registry
+            .render(TEMPLATE_NAME, context)
+            .map_err(|e| TemplateError::Render(context.path.to_string(), Box::new(e)))
+    }
+}
+
+/// Name of a format as templates see it
+pub fn format_name(format: OutputFormat) -> &'static str {
+    match format {
+        OutputFormat::Markdown => "markdown",
+        OutputFormat::Plain => "plain",
+        OutputFormat::Obsidian => "obsidian",
+        OutputFormat::Html => "html",
+        OutputFormat::Logseq => "logseq",
+    }
+}
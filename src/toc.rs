@@ -0,0 +1,151 @@
+use crate::atomic;
+use crate::frontmatter;
+use crate::types::OutputFormat;
+use std::collections::HashMap;
+use std::path::Path;
+
+const TOC_START: &str = "<!-- toc -->";
+const TOC_END: &str = "<!-- /toc -->";
+
+/// When a file is long enough to get a table of contents (`--toc`)
+#[derive(Debug, Clone, Copy)]
+pub struct TocRule {
+    pub min_headers: usize,
+    pub min_lines: usize,
+}
+
+/// Insert, refresh or drop the table of contents of a written file.
+///
+/// The table sits between `<!-- toc -->` markers after the title, so running
+/// again replaces it instead of adding another. Returns the new content if it
+/// changed.
+pub fn update(content: &str, format: OutputFormat, rule: TocRule) -> Option<String> {
+    let (front, body) = match frontmatter::split(content) {
+        Some((_, body)) => content.split_at(content.len() - body.len()),
+        None => ("", content),
+    };
+    let body = remove_toc(body);
+    let headings = headings(&body);
+
+    let long = headings.len() >= rule.min_headers || body.lines().count() >= rule.min_lines;
+    let updated = if long && !headings.is_empty() {
+        format!("{}{}", front, insert_toc(&body, &render(&headings, format)))
+    } else {
+        format!("{}{}", front, body)
+    };
+    (updated != content).then_some(updated)
+}
+
+/// Update the table of contents of every markdown file in a list
+pub fn update_files(files: &[impl AsRef<Path>], format: OutputFormat, rule: TocRule) -> usize {
+    files
+        .iter()
+        .filter(|f| f.as_ref().extension().is_some_and(|e| e == "md"))
+        .filter(|f| {
+            let Ok(content) = std::fs::read_to_string(f) else {
+                return false;
+            };
+            match update(&content, format, rule) {
+                Some(updated) => atomic::write(f.as_ref(), updated).is_ok(),
+                None => false,
+            }
+        })
+        .count()
+}
+
+/// Section headings below the title, as (level, text), skipping code blocks
+fn headings(body: &str) -> Vec<(usize, String)> {
+    let mut in_code = false;
+    body.lines()
+        .filter_map(|line| {
+            if line.trim_start().starts_with("```") {
+                in_code = !in_code;
+            }
+            if in_code {
+                return None;
+            }
+            let level = line.chars().take_while(|c| *c == '#').count();
+            let text = line[level..]
+                .strip_prefix(' ')?
+                .trim()
+                .trim_end_matches('#')
+                .trim();
+            ((2..=4).contains(&level) && !text.is_empty()).then(|| (level, text.to_string()))
+        })
+        .collect()
+}
+
+/// A nested list linking to each heading
+fn render(headings: &[(usize, String)], format: OutputFormat) -> String {
+    let top = headings.iter().map(|(level, _)| *level).min().unwrap_or(2);
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    let mut out = format!("{}\n## Contents\n\n", TOC_START);
+    for (level, text) in headings {
+        let indent = "  ".repeat(level - top);
+        let link = match format {
+            OutputFormat::Obsidian => {
+                let target = text.replace(['#', '|', '[', ']', '^'], " ");
+                format!("[[#{}|{}]]", target.trim(), text)
+            }
+            _ => {
+                let slug = anchor(text);
+                let count = seen.entry(slug.clone()).or_insert(0);
+                let slug = match *count {
+                    0 => slug,
+                    n => format!("{}-{}", slug, n),
+                };
+                *count += 1;
+                format!("[{}](#{})", text, slug)
+            }
+        };
+        out.push_str(&format!("{}- {}\n", indent, link));
+    }
+    out.push_str(TOC_END);
+    out.push('\n');
+    out
+}
+
+/// GitHub-style heading anchor: lower case, punctuation dropped, spaces as hyphens
+fn anchor(text: &str) -> String {
+    text.to_lowercase()
+        .chars()
+        .filter(|c| c.is_alphanumeric() || matches!(c, ' ' | '-' | '_'))
+        .map(|c| if c == ' ' { '-' } else { c })
+        .collect()
+}
+
+/// Put the table after the `# Title` line, or at the top if there is none
+fn insert_toc(body: &str, toc: &str) -> String {
+    let title_end = body
+        .lines()
+        .find(|l| !l.trim().is_empty())
+        .filter(|l| l.starts_with("# "))
+        .and_then(|title| body.find(title).map(|i| i + title.len()));
+    match title_end {
+        Some(end) => format!(
+            "{}\n\n{}\n{}",
+            &body[..end],
+            toc,
+            body[end..].trim_start_matches('\n')
+        ),
+        None => format!("{}\n{}", toc, body),
+    }
+}
+
+/// The body without an earlier table of contents
+fn remove_toc(body: &str) -> String {
+    let Some(start) = body.find(TOC_START) else {
+        return body.to_string();
+    };
+    let Some(len) = body[start..].find(TOC_END) else {
+        return body.to_string();
+    };
+    let end = start + len + TOC_END.len();
+    let before = body[..start].trim_end_matches('\n');
+    let after = body[end..].trim_start_matches('\n');
+    if before.is_empty() {
+        after.to_string()
+    } else {
+        format!("{}\n\n{}", before, after)
+    }
+}
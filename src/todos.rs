@@ -0,0 +1,241 @@
+use crate::categorizer::extract_json;
+use crate::client::{ClientError, LlmClient};
+use crate::indexer::{collect_indexable, write_index};
+use crate::links::relative_path;
+use crate::questions::is_question_list;
+use crate::types::OutputFormat;
+use crate::writer::output_rel_path;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use tracing::{debug, warn};
+
+/// Aggregated task list, relative to the output directory (before format mapping)
+pub const TODO_INDEX: &str = "todo/index.md";
+
+/// How an unchecked `- [ ]` item is rendered in HTML pages
+const HTML_UNCHECKED: &str = "<input disabled=\"\" type=\"checkbox\"/>\n";
+
+/// Tasks sent to the LLM per scheduling request
+pub const SCHEDULE_BATCH: usize = 50;
+
+const SCHEDULE_SYSTEM_PROMPT: &str = r#"You are a task planning assistant. You are given numbered tasks found in someone's notes, each with the note it came from. For every task, infer a due date and a priority.
+
+Respond with JSON in this exact format:
+{
+  "tasks": [
+    {"id": 1, "due": "2024-03-15", "priority": "high"}
+  ]
+}
+
+Rules:
+- "due" is a YYYY-MM-DD date, or null if nothing in the task hints at one
+- Resolve relative dates ("tomorrow", "next Friday", "end of the month") against today's date
+- "priority" is "high", "medium" or "low"; deadlines, blockers and promises to other people are high
+- Include every task id exactly once"#;
+
+#[derive(Error, Debug)]
+pub enum TodoError {
+    #[error("LLM client error: {0}")]
+    Client(#[from] ClientError),
+    #[error("Failed to parse LLM response: {0}")]
+    Parse(#[from] serde_json::Error),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    High,
+    #[default]
+    Medium,
+    Low,
+}
+
+impl Priority {
+    fn title(self) -> &'static str {
+        match self {
+            Priority::High => "High priority",
+            Priority::Medium => "Medium priority",
+            Priority::Low => "Low priority",
+        }
+    }
+}
+
+/// An open task found in an output file
+#[derive(Debug, Clone)]
+pub struct Task {
+    pub text: String,
+    /// Output file the task is in, relative to the output directory
+    pub file: String,
+    pub due: Option<String>,
+    pub priority: Priority,
+}
+
+#[derive(Debug, Deserialize)]
+struct ScheduleResponse {
+    tasks: Vec<Schedule>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Schedule {
+    id: usize,
+    #[serde(default)]
+    due: Option<String>,
+    #[serde(default)]
+    priority: Priority,
+}
+
+/// Open tasks in a note: unchecked `- [ ]` items, `TODO` lines and "remind me" requests
+pub fn extract(content: &str, format: OutputFormat) -> Vec<String> {
+    let content = match format {
+        // Rendered task lists put an unchecked box in front of the item's text
+        OutputFormat::Html => strip_tags(&content.replace(HTML_UNCHECKED, "[ ] ")),
+        _ => content.to_string(),
+    };
+
+    let mut tasks: Vec<String> = Vec::new();
+    for line in content.lines() {
+        let item = line.trim().trim_start_matches(['-', '*', '+']).trim_start();
+
+        let task = if let Some(rest) = item.strip_prefix("[ ]") {
+            rest
+        } else if let Some(rest) = item.strip_prefix("TODO") {
+            if !rest.starts_with([':', ' ']) {
+                continue;
+            }
+            rest.trim_start_matches(':')
+        } else if let Some(pos) = item.find("TODO:") {
+            &item[pos + "TODO:".len()..]
+        } else if item.to_lowercase().contains("remind me") {
+            item
+        } else {
+            continue;
+        };
+
+        let task = task.trim();
+        if !task.is_empty() && !tasks.iter().any(|t| t == task) {
+            tasks.push(task.to_string());
+        }
+    }
+    tasks
+}
+
+/// Collect the open tasks of every note in the output directory, skipping
+/// generated lists
+pub fn collect(output_dir: &Path, format: OutputFormat) -> Vec<Task> {
+    let index = output_rel_path(TODO_INDEX, format);
+    collect_indexable(output_dir)
+        .into_iter()
+        .filter(|file| *file != index && !is_question_list(file, format))
+        .flat_map(|file| {
+            let content = fs::read_to_string(output_dir.join(&file)).unwrap_or_else(|e| {
+                warn!("Could not read {}: {}", file, e);
+                String::new()
+            });
+            extract(&content, format)
+                .into_iter()
+                .map(|text| Task {
+                    text,
+                    file: file.clone(),
+                    due: None,
+                    priority: Priority::default(),
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// Ask the LLM for the due date and priority of a batch of tasks
+pub async fn schedule(
+    client: &LlmClient,
+    tasks: &mut [Task],
+    today: &str,
+) -> Result<(), TodoError> {
+    let listed: String = tasks
+        .iter()
+        .enumerate()
+        .map(|(i, task)| format!("{}. [{}] {}\n", i + 1, task.file, task.text))
+        .collect();
+    let user_prompt = format!("Today is {}.\n\nTasks:\n{}", today, listed);
+
+    let response = client
+        .chat_json(SCHEDULE_SYSTEM_PROMPT, &user_prompt)
+        .await?;
+    let response: ScheduleResponse = serde_json::from_str(extract_json(&response))?;
+
+    let mut by_id: HashMap<usize, Schedule> =
+        response.tasks.into_iter().map(|s| (s.id, s)).collect();
+    for (i, task) in tasks.iter_mut().enumerate() {
+        let Some(schedule) = by_id.remove(&(i + 1)) else {
+            debug!("No schedule for task {} in {}", i + 1, task.file);
+            continue;
+        };
+        task.due = schedule
+            .due
+            .filter(|due| chrono::NaiveDate::parse_from_str(due, "%Y-%m-%d").is_ok());
+        task.priority = schedule.priority;
+    }
+    Ok(())
+}
+
+/// Write the aggregated `todo/index.md`, grouped by priority and sorted by due date
+pub fn write_todo_index(
+    output_dir: &Path,
+    tasks: &mut [Task],
+    format: OutputFormat,
+) -> Result<PathBuf, std::io::Error> {
+    tasks.sort_by(|a, b| {
+        a.priority
+            .cmp(&b.priority)
+            // Dated tasks first, soonest first
+            .then_with(|| a.due.is_none().cmp(&b.due.is_none()))
+            .then_with(|| a.due.cmp(&b.due))
+            .then_with(|| a.file.cmp(&b.file))
+    });
+
+    let rel_path = output_rel_path(TODO_INDEX, format);
+    let mut md = String::from("# TODO\n");
+    let mut current = None;
+    for task in tasks.iter() {
+        if current != Some(task.priority) {
+            current = Some(task.priority);
+            md.push_str(&format!("\n## {}\n\n", task.priority.title()));
+        }
+        let due = task
+            .due
+            .as_ref()
+            .map(|due| format!(" (due {})", due))
+            .unwrap_or_default();
+        md.push_str(&format!(
+            "- [ ] {}{} - [{}](<{}>)\n",
+            task.text,
+            due,
+            task.file,
+            relative_path(&rel_path, &task.file)
+        ));
+    }
+
+    let path = output_dir.join(&rel_path);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    write_index(&path, "TODO", &md, format)?;
+    Ok(path)
+}
+
+/// Text of HTML without its tags
+fn strip_tags(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            _ if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    text
+}
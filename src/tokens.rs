@@ -0,0 +1,25 @@
+use std::sync::OnceLock;
+use tiktoken_rs::CoreBPE;
+
+static TOKENIZER: OnceLock<Option<CoreBPE>> = OnceLock::new();
+
+/// Select the tokenizer for a model.
+///
+/// Models tiktoken doesn't know (e.g. local GGUF models) fall back to
+/// cl100k, which is a close enough approximation for budgeting.
+pub fn init(model: &str) {
+    TOKENIZER.get_or_init(|| {
+        tiktoken_rs::get_bpe_from_model(model)
+            .or_else(|_| tiktoken_rs::cl100k_base())
+            .ok()
+    });
+}
+
+/// Count the tokens in a piece of text
+pub fn count_tokens(text: &str) -> usize {
+    match TOKENIZER.get_or_init(|| tiktoken_rs::cl100k_base().ok()) {
+        Some(bpe) => bpe.encode_ordinary(text).len(),
+        // About four characters per token if no tokenizer could be loaded
+        None => text.chars().count().div_ceil(4),
+    }
+}
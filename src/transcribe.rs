@@ -0,0 +1,16 @@
+use crate::client::{ClientError, LlmClient};
+use std::path::Path;
+
+/// Audio extensions accepted by OpenAI-compatible transcription endpoints
+const AUDIO_EXTENSIONS: &[&str] = &["m4a", "mp3", "wav", "mpga", "ogg", "flac"];
+
+/// Whether an extension is an audio format we can transcribe
+pub fn is_audio(ext: &str) -> bool {
+    AUDIO_EXTENSIONS.contains(&ext)
+}
+
+/// Transcribe a voice memo into note text
+pub async fn transcribe_audio(client: &LlmClient, path: &Path) -> Result<String, ClientError> {
+    let text = client.transcribe(path).await?;
+    Ok(text.trim().to_string())
+}
@@ -0,0 +1,170 @@
+use crate::chunking::chunk_text;
+use crate::client::{ClientError, LlmClient};
+use thiserror::Error;
+
+const TRANSLATE_SYSTEM_PROMPT: &str = r#"You are a translator. Translate the note you are given into {language}.
+
+Rules:
+- Translate all prose, headings, list items, table cells and link text
+- Keep every placeholder like ⟦1⟧ exactly as written and in the matching place; they stand for code, equations, links and URLs
+- Keep the formatting: headings, lists, tables, emphasis and blank lines
+- Leave text that is already in {language} as it is
+- Respond with the translated note only, with no preamble or commentary"#;
+
+#[derive(Error, Debug)]
+pub enum TranslateError {
+    #[error("LLM client error: {0}")]
+    Client(#[from] ClientError),
+    #[error("Translation dropped {0} protected spans (code, equations or links)")]
+    LostSpans(usize),
+}
+
+/// Directory of the parallel translated tree, e.g. `german/` or `pt-br/`
+pub fn language_dir(language: &str) -> String {
+    language
+        .trim()
+        .to_lowercase()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Translate a segment's content into `language` (`--translate-to`).
+///
+/// Code, equations, links and URLs are swapped for `⟦N⟧` placeholders before
+/// the text is sent and put back afterwards; a translation that loses any of
+/// them is rejected. Long content is translated in parts of at most `max_tokens`.
+pub async fn translate(
+    client: &LlmClient,
+    content: &str,
+    language: &str,
+    max_tokens: usize,
+) -> Result<String, TranslateError> {
+    let (masked, kept) = protect(content);
+    let system_prompt = TRANSLATE_SYSTEM_PROMPT.replace("{language}", language);
+
+    let mut parts = Vec::new();
+    for chunk in chunk_text(&masked, max_tokens, 0) {
+        let translated = client.chat(&system_prompt, &chunk.body).await?;
+        parts.push(translated.trim().to_string());
+    }
+
+    let mut translated = restore(&parts.join("\n\n"), &kept)?;
+    if content.ends_with('\n') {
+        translated.push('\n');
+    }
+    Ok(translated)
+}
+
+/// Replace code blocks, display math, inline code and math, wikilinks, link
+/// targets, URLs and HTML comments with numbered placeholders
+fn protect(text: &str) -> (String, Vec<String>) {
+    let mut kept = Vec::new();
+    let mut out = String::new();
+    let mut lines = text.split_inclusive('\n');
+
+    while let Some(line) = lines.next() {
+        let trimmed = line.trim();
+        let fence = ["```", "~~~", "$$"]
+            .into_iter()
+            .find(|f| trimmed.starts_with(f));
+        // `$$x$$` on one line is inline math, not the start of a block
+        let one_line_math = fence == Some("$$") && trimmed.len() > 4 && trimmed.ends_with("$$");
+
+        match fence {
+            Some(fence) if !one_line_math => {
+                let mut block = line.to_string();
+                for next in lines.by_ref() {
+                    block.push_str(next);
+                    if next.trim_start().starts_with(fence) {
+                        break;
+                    }
+                }
+                out.push_str(&keep(&mut kept, block.trim_end_matches('\n')));
+                if block.ends_with('\n') {
+                    out.push('\n');
+                }
+            }
+            _ => out.push_str(&protect_inline(line, &mut kept)),
+        }
+    }
+    (out, kept)
+}
+
+fn protect_inline(line: &str, kept: &mut Vec<String>) -> String {
+    let mut out = String::new();
+    let mut rest = line;
+
+    while let Some(c) = rest.chars().next() {
+        let end = match c {
+            '`' => {
+                let ticks = &rest[..rest.len() - rest.trim_start_matches('`').len()];
+                rest[ticks.len()..].find(ticks).map(|i| 2 * ticks.len() + i)
+            }
+            '$' if rest.starts_with("$$") => rest[2..].find("$$").map(|i| i + 4),
+            '$' if !rest[1..].starts_with(char::is_whitespace) => {
+                rest[1..].find('$').map(|i| i + 2)
+            }
+            '[' if rest.starts_with("[[") => rest.find("]]").map(|i| i + 2),
+            '(' if out.ends_with(']') => link_target_len(rest),
+            '<' if rest.starts_with("<!--") => rest.find("-->").map(|i| i + 3),
+            'h' if rest.starts_with("http://") || rest.starts_with("https://") => {
+                let url = rest
+                    .split(|c: char| c.is_whitespace() || matches!(c, '<' | '>' | ')'))
+                    .next()
+                    .unwrap_or(rest);
+                Some(url.trim_end_matches(['.', ',', ';', ':', '!', '?']).len())
+            }
+            _ => None,
+        };
+
+        match end {
+            Some(end) => {
+                out.push_str(&keep(kept, &rest[..end]));
+                rest = &rest[end..];
+            }
+            None => {
+                out.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    out
+}
+
+/// Length of a `(target)` after a link's text, allowing balanced parentheses inside
+fn link_target_len(rest: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in rest.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth == 1 => return Some(i + 1),
+            ')' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+fn keep(kept: &mut Vec<String>, span: &str) -> String {
+    kept.push(span.to_string());
+    format!("⟦{}⟧", kept.len())
+}
+
+/// Put protected spans back, failing if the translation lost any placeholder
+fn restore(text: &str, kept: &[String]) -> Result<String, TranslateError> {
+    let mut text = text.to_string();
+    let mut lost = 0;
+    for (i, span) in kept.iter().enumerate() {
+        let placeholder = format!("⟦{}⟧", i + 1);
+        if text.contains(&placeholder) {
+            text = text.replace(&placeholder, span);
+        } else {
+            lost += 1;
+        }
+    }
+    match lost {
+        0 => Ok(text),
+        n => Err(TranslateError::LostSpans(n)),
+    }
+}
@@ -1,4 +1,6 @@
-use serde::{Deserialize, Serialize};
+use crate::frontmatter::{Routing, SourceMeta};
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize};
 use std::path::PathBuf;
 
 /// Broad categories for notes - LLM can suggest subcategories dynamically
@@ -57,19 +59,251 @@ impl std::fmt::Display for Category {
     }
 }
 
+impl Category {
+    /// Category for a name, matching the built-in ones regardless of case and
+    /// separators (`Machine Learning` and `machine-learning` are `machine_learning`)
+    pub fn from_name(name: &str) -> Self {
+        let name = name.trim().to_lowercase().replace([' ', '-'], "_");
+        serde_json::from_value(serde_json::Value::String(name.clone()))
+            .unwrap_or(Category::Custom(name))
+    }
+
+    /// Whether this is one of the built-in categories
+    pub fn is_builtin(&self) -> bool {
+        !matches!(self, Category::Custom(_))
+    }
+
+    /// Name used in output paths, e.g. `machine_learning`
+    pub fn dir_name(&self) -> String {
+        match serde_json::to_value(self) {
+            Ok(serde_json::Value::String(name)) => name,
+            _ => self.to_string(),
+        }
+    }
+}
+
+/// How much enhancement rewrites a segment
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum EnhanceLevel {
+    /// Written as-is, no LLM call
+    Off,
+    /// Typos and formatting only
+    Light,
+    /// Fixes, answers to `?` markers, equations and a few resources
+    #[default]
+    Standard,
+    /// Restructures, answers questions in depth and adds resources
+    Aggressive,
+}
+
+/// How output files are arranged in the output directory
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Layout {
+    /// `category/subcategory/topic.md`
+    #[default]
+    ByCategory,
+    /// `YYYY/MM/topic.md`, with the category in the file's metadata
+    ByDate,
+    /// `category/YYYY/MM/topic.md`
+    Hybrid,
+}
+
 /// Output format for processed notes
 #[derive(Debug, Clone, Copy, Default, clap::ValueEnum)]
 pub enum OutputFormat {
     #[default]
     Markdown,
     Plain,
+    /// Markdown with wikilinks, tags and vault-safe file names
+    Obsidian,
+    /// Standalone HTML pages rendered from markdown
+    Html,
+    /// Logseq graph with outliner blocks, page properties and journals
+    Logseq,
+}
+
+/// Output of the publish command
+#[derive(Debug, Clone, Copy, Default, clap::ValueEnum)]
+pub enum PublishTarget {
+    /// mdBook source tree with a generated SUMMARY.md
+    #[default]
+    Mdbook,
+    /// Plain static HTML site with an index page
+    Site,
+}
+
+/// How progress is reported while processing
+#[derive(Debug, Clone, Copy, Default, clap::ValueEnum)]
+pub enum ProgressMode {
+    /// Progress bars on the terminal
+    #[default]
+    Bars,
+    /// Newline-delimited JSON events on stdout (logs go to stderr)
+    Json,
+}
+
+/// What to do when different suggested paths normalize to the same file
+#[derive(Debug, Clone, Copy, Default, clap::ValueEnum)]
+pub enum CollisionPolicy {
+    /// Write their segments to the one file
+    #[default]
+    Merge,
+    /// Give each later path a numbered suffix (`topic-2.md`)
+    Suffix,
+    /// Stop before writing anything
+    Error,
+}
+
+/// Copies kept of output files a run replaces
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum BackupMode {
+    /// No copies
+    #[default]
+    Off,
+    /// `file.md.bak` next to the file
+    Bak,
+    /// A `.backups/<timestamp>/` snapshot per run
+    Tree,
+}
+
+/// How requests ask the provider to cache the repeated system prompt
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum PromptCache {
+    /// `anthropic` for Claude models, `openai` for api.openai.com, otherwise `off`
+    #[default]
+    Auto,
+    /// Plain requests; servers that cache prefixes on their own still do
+    Off,
+    /// Mark the system prompt with `cache_control` (Anthropic, via OpenRouter or LiteLLM)
+    Anthropic,
+    /// Send a `prompt_cache_key` per phase so requests land on the same cache
+    Openai,
+    /// Send `cache_prompt: true` (llama.cpp builds where it is off by default)
+    Llama,
+}
+
+/// What happens when the categorizer asks about an ambiguous note
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ClarifyMode {
+    /// The categorizer may not ask; it guesses
+    #[default]
+    Off,
+    /// Answer from the note's folder, its neighbours and the config profile
+    Auto,
+    /// Ask on the terminal, answering automatically when left blank
+    Ask,
+}
+
+/// What happens to an enhanced segment that drifts too far from its original
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum DriftGuard {
+    /// Keep it
+    #[default]
+    Off,
+    /// Write the original instead
+    Fallback,
+    /// Enhance again with a stricter prompt, writing the original if that drifts too
+    Retry,
+}
+
+/// How notes are split into pieces before categorization (`--split-on`)
+#[derive(Debug, Clone, Default)]
+pub enum SplitOn {
+    /// The categorizer splits notes itself
+    #[default]
+    None,
+    /// At every markdown header
+    Headers,
+    /// At every line matching the pattern
+    Regex(regex::Regex),
+}
+
+/// Which segment a segment that is too short, or one too many, is merged into
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum SegmentMerge {
+    /// The neighbour in the same category, else the shorter neighbour
+    #[default]
+    Neighbor,
+    /// The closest segment in the same category, else a neighbour
+    Category,
+}
+
+/// Output of the graph command
+#[derive(Debug, Clone, Copy, Default, clap::ValueEnum)]
+pub enum GraphFormat {
+    /// Graphviz DOT
+    #[default]
+    Dot,
+    /// JSON with `nodes` and `edges` arrays
+    Json,
+}
+
+/// How files with a given extension are read during discovery
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReaderKind {
+    /// Plain text, read as-is
+    Text,
+    /// Document with a text extractor (PDF, DOCX, ODT)
+    Extract,
+    /// Image transcribed by the vision model
+    Ocr,
+    /// Delimited table (CSV, TSV) converted to a markdown table
+    Table,
+    /// Saved web page: boilerplate stripped, main content converted to markdown
+    Html,
+    /// Ignored during discovery
+    Skip,
 }
 
 /// A raw note loaded from disk
 #[derive(Debug, Clone)]
 pub struct RawNote {
     pub path: PathBuf,
+    /// Content without the frontmatter
     pub content: String,
+    /// Line of the file the content starts on, after the frontmatter
+    pub first_line: usize,
+    /// Notes whose copies of this note's paragraphs `--dedupe` dropped, with
+    /// the lines of the paragraph each one repeated
+    pub duplicates: Vec<(LineRange, PathBuf)>,
+    /// The note's YAML frontmatter, empty if it has none
+    pub frontmatter: serde_yaml::Mapping,
+    /// Directives from the note's `notex:` frontmatter
+    pub routing: Option<Routing>,
+}
+
+/// Lines of a source note, 1-based and inclusive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LineRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl LineRange {
+    /// The range covering both
+    pub fn union(self, other: Self) -> Self {
+        Self {
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+        }
+    }
+
+    /// Whether the ranges share a line
+    pub fn overlaps(self, other: Self) -> bool {
+        self.start <= other.end && other.start <= self.end
+    }
+}
+
+impl std::fmt::Display for LineRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.start == self.end {
+            write!(f, "{}", self.start)
+        } else {
+            write!(f, "{}-{}", self.start, self.end)
+        }
+    }
 }
 
 /// A segment extracted from a note by the LLM
@@ -82,12 +316,90 @@ pub struct Segment {
     pub paths: Vec<String>,
     #[serde(default)]
     pub cross_file_to: Vec<String>,
+    /// How sure the categorizer is about the category, from 0 to 1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<f32>,
+    /// Write the content as-is instead of enhancing it
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub skip_enhance: bool,
+    /// Tags, aliases and dates from the source note's frontmatter
+    #[serde(default, skip_serializing_if = "SourceMeta::is_empty")]
+    pub source: SourceMeta,
+    /// Where in the source note the content came from, found by matching its
+    /// lines after categorization
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lines: Option<LineRange>,
+    /// Other notes the content was also found in (`--dedupe`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub duplicates: Vec<PathBuf>,
 }
 
-/// Response from the categorization LLM call
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Response from the categorization LLM call.
+///
+/// Parsed leniently: a segment object that is malformed or has no content is
+/// left out and described in `rejected`, so one bad segment doesn't cost the
+/// whole note. A response without a single usable segment is still an error,
+/// unless it asks a question instead (`--clarify`).
+#[derive(Debug, Clone, Serialize)]
 pub struct CategorizationResponse {
     pub segments: Vec<Segment>,
+    #[serde(skip)]
+    pub rejected: Vec<String>,
+    /// The categorizer's question about an ambiguous note, when it returned no segments
+    #[serde(skip)]
+    pub needs_clarification: Option<String>,
+}
+
+impl<'de> Deserialize<'de> for CategorizationResponse {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        struct Raw {
+            segments: Option<Vec<serde_json::Value>>,
+            needs_clarification: Option<String>,
+        }
+
+        let raw = Raw::deserialize(deserializer)?;
+        let question = raw
+            .needs_clarification
+            .map(|q| q.trim().to_string())
+            .filter(|q| !q.is_empty());
+        let raw_segments = match (raw.segments, question) {
+            (Some(segments), _) if !segments.is_empty() => segments,
+            (_, Some(question)) => {
+                return Ok(Self {
+                    segments: vec![],
+                    rejected: vec![],
+                    needs_clarification: Some(question),
+                })
+            }
+            (Some(segments), None) => segments,
+            (None, None) => return Err(D::Error::missing_field("segments")),
+        };
+
+        let mut segments = Vec::new();
+        let mut rejected = Vec::new();
+        for (i, value) in raw_segments.into_iter().enumerate() {
+            match serde_json::from_value::<Segment>(value) {
+                Ok(segment) if segment.content.trim().is_empty() => {
+                    rejected.push(format!("segment {}: no content", i + 1))
+                }
+                Ok(segment) => segments.push(segment),
+                Err(e) => rejected.push(format!("segment {}: {}", i + 1, e)),
+            }
+        }
+
+        if segments.is_empty() && !rejected.is_empty() {
+            return Err(D::Error::custom(format!(
+                "no usable segments ({})",
+                rejected.join("; ")
+            )));
+        }
+        Ok(Self {
+            segments,
+            rejected,
+            needs_clarification: None,
+        })
+    }
 }
 
 /// An enhanced segment ready for output
@@ -98,6 +410,32 @@ pub struct EnhancedSegment {
     pub category: Category,
     pub subcategory: Option<String>,
     pub output_paths: Vec<String>,
+    /// Index of the segment within its source note
+    pub position: usize,
+    /// Lines of the source note the segment came from
+    pub lines: Option<LineRange>,
+    /// Other notes the content was also found in (`--dedupe`)
+    pub duplicates: Vec<PathBuf>,
+    /// Tags from the source note's frontmatter, then topic tags from `--tags`
+    pub tags: Vec<String>,
+    /// Aliases and dates from the source note's frontmatter
+    pub source: SourceMeta,
+    /// Review prompts from `--srs`
+    pub cards: Vec<ReviewCard>,
+    /// Facts of the original that `--verify` found missing
+    pub missing_facts: Vec<String>,
+    /// Word diff against the original from `--show-changes`
+    pub changes: Option<String>,
+}
+
+/// A spaced-repetition prompt generated for a segment (`--srs`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ReviewCard {
+    /// A question and its answer
+    Qa { question: String, answer: String },
+    /// A sentence with `{{c1::...}}` deletions
+    Cloze { text: String },
 }
 
 /// Suggestion for reorganizing file structure
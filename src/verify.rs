@@ -0,0 +1,105 @@
+use crate::categorizer::extract_json;
+use crate::client::{ClientError, LlmClient};
+use crate::types::EnhancedSegment;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Report of segments that lost information, written to the output directory
+pub const VERIFY_REPORT_FILE: &str = "verification.json";
+
+const VERIFY_SYSTEM_PROMPT: &str = r#"You are a fact checker comparing an original note with an edited version of it. List every fact in the original that is missing from the edited version.
+
+A fact is a definition, claim, number, equation, name, date, step, example, link or reference. It counts as present if the edited version states it in other words, in another order or in more detail.
+
+Respond with JSON in this exact format:
+{
+  "missing": ["The learning rate was 0.01 in the second experiment", "Link to the Adam paper"]
+}
+
+Rules:
+- Each item is one missing fact, stated briefly in the language of the original
+- Ignore fixed typos, formatting, and text the edited version adds
+- Return an empty list if nothing is missing"#;
+
+#[derive(Error, Debug)]
+pub enum VerifyError {
+    #[error("LLM client error: {0}")]
+    Client(#[from] ClientError),
+    #[error("Failed to parse LLM response: {0}")]
+    Parse(#[from] serde_json::Error),
+}
+
+#[derive(Debug, Deserialize)]
+struct VerifyResponse {
+    missing: Vec<String>,
+}
+
+/// Ask the LLM which facts of the original are missing from the enhanced text
+pub async fn missing_facts(
+    client: &LlmClient,
+    original: &str,
+    enhanced: &str,
+) -> Result<Vec<String>, VerifyError> {
+    let user_prompt = format!(
+        "Original note:\n{}\n\n---\n\nEdited version:\n{}",
+        original, enhanced
+    );
+    let response = client.chat_json(VERIFY_SYSTEM_PROMPT, &user_prompt).await?;
+    let response: VerifyResponse = serde_json::from_str(extract_json(&response))?;
+
+    Ok(response
+        .missing
+        .into_iter()
+        .map(|fact| fact.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|fact| !fact.is_empty())
+        .collect())
+}
+
+/// Extra rule for re-enhancing a segment that dropped facts
+pub fn retention_instruction(missing: &[String]) -> String {
+    let facts: Vec<String> = missing.iter().map(|fact| format!("- {}", fact)).collect();
+    format!(
+        "\n\nAn earlier version of this note dropped the facts below. Keep every one of them:\n{}",
+        facts.join("\n")
+    )
+}
+
+/// A segment flagged by `--verify`
+#[derive(Debug, Serialize)]
+struct Flagged<'a> {
+    path: &'a Path,
+    position: usize,
+    output_paths: &'a [String],
+    missing: &'a [String],
+}
+
+/// Write the segments that are still missing facts to `verification.json`,
+/// returning how many there are. An earlier report is removed when none are.
+pub fn write_report(
+    output_dir: &Path,
+    segments: &[EnhancedSegment],
+) -> Result<usize, std::io::Error> {
+    let flagged: Vec<Flagged> = segments
+        .iter()
+        .filter(|s| !s.missing_facts.is_empty())
+        .map(|s| Flagged {
+            path: &s.original_path,
+            position: s.position,
+            output_paths: &s.output_paths,
+            missing: &s.missing_facts,
+        })
+        .collect();
+
+    let path: PathBuf = output_dir.join(VERIFY_REPORT_FILE);
+    if flagged.is_empty() {
+        if path.exists() {
+            std::fs::remove_file(&path)?;
+        }
+        return Ok(0);
+    }
+    let json = serde_json::to_string_pretty(&flagged).map_err(std::io::Error::other)?;
+    std::fs::create_dir_all(output_dir)?;
+    std::fs::write(&path, json)?;
+    Ok(flagged.len())
+}
@@ -0,0 +1,206 @@
+use crate::links::resolve_wikilink;
+use crate::logseq;
+use crate::roots::OutputRoots;
+use crate::types::{EnhancedSegment, OutputFormat};
+use crate::writer::segment_output_path;
+use std::collections::HashMap;
+use std::path::Path;
+use tracing::{debug, warn};
+
+/// Where the content of each source note was written, for pointing the
+/// `[[Note Title]]` links between source notes at the output files
+pub struct NoteLinks<'a> {
+    format: OutputFormat,
+    roots: &'a OutputRoots,
+    /// Source notes relative to the input directory, the way links name them
+    sources: Vec<String>,
+    /// Source note to the output file holding its first segment, as its root
+    /// and its path under it
+    outputs: HashMap<String, (&'a Path, String)>,
+}
+
+/// Counts from relinking a run's output
+#[derive(Debug, Default)]
+pub struct Relinked {
+    pub resolved: usize,
+    pub unresolved: usize,
+}
+
+impl<'a> NoteLinks<'a> {
+    /// Map every source note in `grouped` to the file its first segment goes to
+    pub fn new(
+        input_dir: &Path,
+        grouped: &HashMap<String, Vec<EnhancedSegment>>,
+        format: OutputFormat,
+        roots: &'a OutputRoots,
+    ) -> Self {
+        let mut first: HashMap<String, (usize, String, &Path)> = HashMap::new();
+        for (rel_path, segments) in grouped {
+            let (root, rel_path) = roots.locate(rel_path, segments);
+            for segment in segments {
+                let file = segment_output_path(&rel_path, segment, format);
+                let entry = (segment.position, file, root);
+                first
+                    .entry(source_name(input_dir, &segment.original_path))
+                    .and_modify(|current| *current = current.clone().min(entry.clone()))
+                    .or_insert(entry);
+            }
+        }
+
+        let mut sources: Vec<String> = first.keys().cloned().collect();
+        sources.sort();
+        Self {
+            format,
+            roots,
+            sources,
+            outputs: first
+                .into_iter()
+                .map(|(source, (_, file, root))| (source, (root, file)))
+                .collect(),
+        }
+    }
+
+    /// Rewrite the wikilinks to other notes in every segment. Links to notes
+    /// that were not written this run are left as they are and logged.
+    pub fn relink(&self, grouped: &mut HashMap<String, Vec<EnhancedSegment>>) -> Relinked {
+        let mut counts = Relinked::default();
+        for (rel_path, segments) in grouped.iter_mut() {
+            let (root, rel_path) = self.roots.locate(rel_path, segments);
+            for segment in segments.iter_mut() {
+                let file = segment_output_path(&rel_path, segment, self.format);
+                let mut unresolved = Vec::new();
+                segment.content = rewrite_wikilinks(&segment.content, |target, label| {
+                    let output = resolve_wikilink(target, &self.sources)
+                        .and_then(|source| self.outputs.get(source));
+                    match output {
+                        Some((output_root, output)) => {
+                            counts.resolved += 1;
+                            Some(self.link((root, &file), (output_root, output), label))
+                        }
+                        None => {
+                            unresolved.push(target.to_string());
+                            None
+                        }
+                    }
+                });
+                if !unresolved.is_empty() {
+                    warn!(
+                        "Links in {} to notes that are not in the output: {}",
+                        segment.original_path.display(),
+                        unresolved
+                            .iter()
+                            .map(|t| format!("[[{}]]", t))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    );
+                    counts.unresolved += unresolved.len();
+                }
+            }
+        }
+        debug!(
+            "Relinked {} links between notes, {} unresolved",
+            counts.resolved, counts.unresolved
+        );
+        counts
+    }
+
+    /// A link from `file` to `output` in the output format, each given as its
+    /// root and its path under it
+    fn link(&self, file: (&Path, &str), output: (&Path, &str), label: &str) -> String {
+        let target = self.roots.link(file, output);
+        let output = output.1;
+        match self.format {
+            OutputFormat::Obsidian => {
+                let target = output.strip_suffix(".md").unwrap_or(output);
+                format!("[[{}|{}]]", target, label)
+            }
+            OutputFormat::Logseq => {
+                let page = logseq::page_name(output);
+                if page == label {
+                    format!("[[{}]]", page)
+                } else {
+                    format!("[{}]([[{}]])", label, page)
+                }
+            }
+            // HTML pages are rendered from markdown
+            OutputFormat::Markdown | OutputFormat::Html => {
+                if target.contains(' ') {
+                    format!("[{}](<{}>)", label, target)
+                } else {
+                    format!("[{}]({})", label, target)
+                }
+            }
+            OutputFormat::Plain => format!("{} ({})", label, target),
+        }
+    }
+}
+
+/// A source note as wikilinks name it: its path under the input directory
+fn source_name(input_dir: &Path, path: &Path) -> String {
+    path.strip_prefix(input_dir)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .replace('\\', "/")
+}
+
+/// Whether a wikilink target names a note rather than an attachment or a
+/// heading in the same note
+fn is_note_target(target: &str) -> bool {
+    let name = target.split('#').next().unwrap_or(target).trim();
+    let ext = name.rsplit_once('.').map(|(_, ext)| ext.to_lowercase());
+    !name.is_empty() && matches!(ext.as_deref(), None | Some("md"))
+}
+
+/// Replace each `[[target]]` or `[[target|label]]` link to a note outside
+/// fenced code blocks with what `replace` returns for its target and label.
+/// Embeds (`![[...]]`) and links `replace` returns None for are kept.
+fn rewrite_wikilinks(
+    content: &str,
+    mut replace: impl FnMut(&str, &str) -> Option<String>,
+) -> String {
+    let mut out = String::with_capacity(content.len());
+    let mut in_fence = false;
+
+    for line in content.split_inclusive('\n') {
+        let fence = line.trim_start().starts_with("```");
+        if fence {
+            in_fence = !in_fence;
+        }
+        if fence || in_fence {
+            out.push_str(line);
+            continue;
+        }
+
+        let mut rest = line;
+        while let Some(pos) = rest.find("[[") {
+            let after = &rest[pos + 2..];
+            let Some(end) = after.find("]]") else {
+                break;
+            };
+            let inner = &after[..end];
+            let (target, alias) = match inner.split_once('|') {
+                Some((target, alias)) => (target.trim(), Some(alias.trim())),
+                None => (inner.trim(), None),
+            };
+            let embed = rest[..pos].ends_with('!');
+            let label = alias.unwrap_or_else(|| {
+                let note = target.split('#').next().unwrap_or(target);
+                note.rsplit('/').next().unwrap_or(note)
+            });
+
+            out.push_str(&rest[..pos]);
+            let link = if embed || !is_note_target(target) {
+                None
+            } else {
+                replace(target, label)
+            };
+            match link {
+                Some(link) => out.push_str(&link),
+                None => out.push_str(&rest[pos..pos + 2 + end + 2]),
+            }
+            rest = &after[end + 2..];
+        }
+        out.push_str(rest);
+    }
+    out
+}
@@ -1,16 +1,236 @@
-use crate::types::{EnhancedSegment, OutputFormat};
-use std::collections::HashMap;
+use crate::atomic;
+use crate::dedupe;
+use crate::figures;
+use crate::frontmatter::SourceMeta;
+use crate::html;
+use crate::logseq;
+use crate::obsidian;
+use crate::originals;
+use crate::srs;
+use crate::tags;
+use crate::template::{format_name, FileContext, SegmentContext, Template, TemplateError};
+use crate::types::{CollisionPolicy, EnhancedSegment, OutputFormat};
+use deunicode::deunicode;
+use similar::{ChangeTag, TextDiff};
+use std::collections::{BTreeSet, HashMap};
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 use thiserror::Error;
+use tracing::{debug, warn};
+
+/// Longest file or directory name written, in bytes (before the extension)
+const MAX_NAME_LEN: usize = 100;
+
+/// Share of a segment's words a block of an existing file must hold, in
+/// order, for the segment to count as already written there
+const WRITTEN_SIMILARITY: f64 = 0.8;
+
+/// Longest the comparison with one block may take
+const DIFF_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Names Windows reserves for devices, with or without an extension
+const RESERVED_NAMES: &[&str] = &[
+    "con", "prn", "aux", "nul", "com1", "com2", "com3", "com4", "com5", "com6", "com7", "com8",
+    "com9", "lpt1", "lpt2", "lpt3", "lpt4", "lpt5", "lpt6", "lpt7", "lpt8", "lpt9",
+];
 
 #[derive(Error, Debug)]
 pub enum WriterError {
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
+    #[error("Template error: {0}")]
+    Template(#[from] TemplateError),
+    #[error("refusing to write outside the output directory: {0}")]
+    UnsafePath(String),
+    #[error("output paths {first} and {second} both map to {path}")]
+    Collision {
+        path: String,
+        first: String,
+        second: String,
+    },
+}
+
+/// Normalize the output paths of every segment, resolving paths that end up
+/// naming the same file according to `collisions`. With `max_depth`, folders
+/// below that depth are folded into the file name (`a/b/c.md` → `a/b-c.md`
+/// at depth 1).
+///
+/// Paths that would leave the output directory are rejected; a segment left
+/// without a path is filed under its category by source file name instead.
+pub fn normalize_output_paths(
+    segments: &mut [EnhancedSegment],
+    collisions: CollisionPolicy,
+    max_depth: Option<usize>,
+) -> Result<(), WriterError> {
+    for segment in segments.iter_mut() {
+        let suggested = segment.output_paths.len();
+        segment.output_paths.retain(|path| {
+            let safe = is_safe_path(path);
+            if !safe {
+                warn!(
+                    "Rejected output path {:?} for a segment of {}",
+                    path,
+                    segment.original_path.display()
+                );
+            }
+            safe
+        });
+        if suggested > 0 && segment.output_paths.is_empty() {
+            let name = segment
+                .original_path
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_default();
+            segment
+                .output_paths
+                .push(format!("{}/{}.md", segment.category.dir_name(), name));
+        }
+    }
+
+    // Sorted, so numbered suffixes do not depend on enhancement order
+    let suggested: BTreeSet<String> = segments
+        .iter()
+        .flat_map(|s| s.output_paths.iter().cloned())
+        .collect();
+
+    let mut targets: HashMap<String, String> = HashMap::new();
+    let mut claimed: HashMap<String, String> = HashMap::new();
+    for path in suggested {
+        let normalized = match max_depth {
+            Some(depth) => normalize_path(&limit_depth(&path, depth)),
+            None => normalize_path(&path),
+        };
+        let target = match (claimed.get(&normalized), collisions) {
+            (None, _) | (Some(_), CollisionPolicy::Merge) => normalized,
+            (Some(_), CollisionPolicy::Suffix) => (2..)
+                .map(|n| with_suffix(&normalized, n))
+                .find(|p| !claimed.contains_key(p))
+                .unwrap_or(normalized),
+            (Some(first), CollisionPolicy::Error) => {
+                return Err(WriterError::Collision {
+                    path: normalized,
+                    first: first.clone(),
+                    second: path,
+                })
+            }
+        };
+        if target != path {
+            debug!("Output path {} → {}", path, target);
+        }
+        claimed
+            .entry(target.clone())
+            .or_insert_with(|| path.clone());
+        targets.insert(path, target);
+    }
+
+    for segment in segments {
+        let mut paths: Vec<String> = Vec::new();
+        for path in &segment.output_paths {
+            let target = &targets[path];
+            if !paths.contains(target) {
+                paths.push(target.clone());
+            }
+        }
+        segment.output_paths = paths;
+    }
+    Ok(())
+}
+
+/// Whether a path from the LLM stays inside the output directory: relative,
+/// with no `..` component, and not starting at a root or drive
+pub fn is_safe_path(path: &str) -> bool {
+    let path = path.trim();
+    !path.is_empty()
+        && !path.starts_with(['/', '\\', '~'])
+        && path.chars().nth(1) != Some(':')
+        && path.split(['/', '\\']).all(|part| part.trim() != "..")
+}
+
+/// Make a suggested path portable: ASCII, lower case, underscores for spaces
+/// and punctuation, names of at most `MAX_NAME_LEN` bytes and no Windows device names
+pub fn normalize_path(path: &str) -> String {
+    path.split('/')
+        .filter(|part| !part.is_empty())
+        .map(normalize_name)
+        .collect::<Vec<_>>()
+        .join("/")
 }
 
-/// Group enhanced segments by their output paths
+fn normalize_name(name: &str) -> String {
+    let (stem, extension) = match name.rsplit_once('.') {
+        Some((stem, ext))
+            if !stem.is_empty()
+                && (1..=5).contains(&ext.len())
+                && ext.chars().all(|c| c.is_ascii_alphanumeric()) =>
+        {
+            (stem, Some(ext.to_ascii_lowercase()))
+        }
+        _ => (name, None),
+    };
+
+    let mut slug = String::new();
+    for c in deunicode(stem.trim()).chars() {
+        let c = c.to_ascii_lowercase();
+        if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+            slug.push(c);
+        } else if !slug.ends_with('_') {
+            slug.push('_');
+        }
+    }
+    slug.truncate(MAX_NAME_LEN);
+    // Leading dots would hide the file; trailing ones are dropped by Windows
+    let mut slug = slug
+        .trim_start_matches('.')
+        .trim_end_matches(['_', '.'])
+        .to_string();
+    if slug.is_empty() {
+        slug = "untitled".to_string();
+    }
+    if RESERVED_NAMES.contains(&slug.as_str()) {
+        slug.push('_');
+    }
+
+    match extension {
+        Some(ext) => format!("{}.{}", slug, ext),
+        None => slug,
+    }
+}
+
+/// A path with the folders below `depth` folded into the file name, joined by `-`
+fn limit_depth(path: &str, depth: usize) -> String {
+    let parts: Vec<&str> = path.split('/').filter(|p| !p.is_empty()).collect();
+    let Some((name, dirs)) = parts.split_last() else {
+        return path.to_string();
+    };
+    if dirs.len() <= depth {
+        return parts.join("/");
+    }
+    let (kept, folded) = dirs.split_at(depth);
+    let mut name_parts = folded.to_vec();
+    name_parts.push(name);
+    let name = name_parts.join("-");
+    kept.iter()
+        .copied()
+        .chain(std::iter::once(name.as_str()))
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// `dir/topic.md` with a numbered suffix, `dir/topic-2.md`
+fn with_suffix(path: &str, n: usize) -> String {
+    let (dir, name) = match path.rsplit_once('/') {
+        Some((dir, name)) => (format!("{}/", dir), name),
+        None => (String::new(), path),
+    };
+    match name.rsplit_once('.') {
+        Some((stem, ext)) => format!("{}{}-{}.{}", dir, stem, n, ext),
+        None => format!("{}{}-{}", dir, name, n),
+    }
+}
+
+/// Group enhanced segments by their output paths, each file's segments ordered
+/// by source path and position in the source so reruns produce the same file
 pub fn group_by_output_path(
     segments: Vec<EnhancedSegment>,
 ) -> HashMap<String, Vec<EnhancedSegment>> {
@@ -24,65 +244,360 @@ pub fn group_by_output_path(
                 .push(segment.clone());
         }
     }
+    for segments in grouped.values_mut() {
+        segments.sort_by(source_order);
+    }
 
     grouped
 }
 
-/// Write all grouped segments to output directory
+/// Order of segments by source path, then position within the source
+fn source_order(a: &EnhancedSegment, b: &EnhancedSegment) -> std::cmp::Ordering {
+    a.original_path
+        .cmp(&b.original_path)
+        .then(a.position.cmp(&b.position))
+}
+
+/// Write all grouped segments to output directory.
+///
+/// With `append`, segments are added to the end of files that already exist
+/// instead of replacing them (used when retrying failures from an earlier run),
+/// leaving out segments whose content the file already holds.
+/// With `originals` (the input directory), each segment links to its source
+/// archived under `_originals/`. With `category_in_header`, Markdown frontmatter
+/// (or a first line in HTML and plain text) records the category the path no
+/// longer shows (`--layout by-date`). With `template`, files are rendered
+/// through it instead of the built-in layout.
 pub fn write_outputs(
     output_dir: &Path,
     grouped: HashMap<String, Vec<EnhancedSegment>>,
     format: OutputFormat,
+    append: bool,
+    originals: Option<&Path>,
+    category_in_header: bool,
+    template: Option<&Template>,
 ) -> Result<Vec<PathBuf>, WriterError> {
     let mut written_files = Vec::new();
 
+    let mut grouped: Vec<_> = rekey(grouped, format).into_iter().collect();
+    grouped.sort_by(|a, b| a.0.cmp(&b.0));
+
     for (rel_path, segments) in grouped {
+        if !is_safe_path(&rel_path) {
+            return Err(WriterError::UnsafePath(rel_path));
+        }
         let file_path = output_dir.join(&rel_path);
+        let segments = match originals {
+            Some(input_dir) => originals::link_sources(segments, &rel_path, input_dir, format),
+            None => segments,
+        };
+        let segments = figures::relink(segments, &rel_path);
+        let segments = dedupe::note_copies(segments, format);
 
         // Create parent directories
         if let Some(parent) = file_path.parent() {
             fs::create_dir_all(parent)?;
         }
 
+        let existing = if append && file_path.exists() {
+            Some(fs::read_to_string(&file_path)?)
+        } else {
+            None
+        };
+
         // Build file content
-        let content = build_file_content(&segments, format);
+        let content = match existing {
+            Some(existing) => {
+                // Segments an earlier run already wrote are not added again
+                let blocks = written_blocks(&existing, format);
+                let segments: Vec<EnhancedSegment> = segments
+                    .into_iter()
+                    .filter(|s| !already_written(&blocks, s, format))
+                    .collect();
+                if segments.is_empty() {
+                    debug!("{} already holds every segment", rel_path);
+                    continue;
+                }
+                let body = build_file_content(&rel_path, &segments, format, None, template)?;
+                append_content(&existing, &body, format)
+            }
+            None => {
+                let header = file_header(&segments, format, category_in_header);
+                let content =
+                    build_file_content(&rel_path, &segments, format, Some(header), template)?;
+                match format {
+                    OutputFormat::Html => {
+                        let title = file_path
+                            .file_stem()
+                            .map(|s| s.to_string_lossy().replace('_', " "))
+                            .unwrap_or_default();
+                        html::render_page(&title, &content)
+                    }
+                    _ => content,
+                }
+            }
+        };
 
         // Write file
-        fs::write(&file_path, content)?;
+        atomic::write(&file_path, content)?;
         written_files.push(file_path);
     }
 
     Ok(written_files)
 }
 
-fn build_file_content(segments: &[EnhancedSegment], format: OutputFormat) -> String {
-    let mut content = String::new();
+/// Add segments to the end of an already written file
+fn append_content(existing: &str, body: &str, format: OutputFormat) -> String {
+    match format {
+        OutputFormat::Html => html::append_to_page(existing, &format!("<hr>\n\n{}", body)),
+        OutputFormat::Markdown | OutputFormat::Obsidian => {
+            format!("{}\n---\n\n{}", existing, body)
+        }
+        OutputFormat::Plain => format!("{}\n{}\n\n{}", existing, "=".repeat(80), body),
+        OutputFormat::Logseq => format!("{}{}", existing, body),
+    }
+}
 
+/// Map an LLM-suggested output path to the path actually written for a format
+pub fn output_rel_path(path: &str, format: OutputFormat) -> String {
     match format {
-        OutputFormat::Markdown => {
-            for (i, segment) in segments.iter().enumerate() {
-                if i > 0 {
-                    content.push_str("\n\n---\n\n");
+        OutputFormat::Obsidian => obsidian::vault_safe_path(path),
+        OutputFormat::Html => html::html_path(path),
+        OutputFormat::Logseq => logseq::page_path(path),
+        OutputFormat::Markdown | OutputFormat::Plain => path.to_string(),
+    }
+}
+
+/// Rewrite output paths for the target format, merging any that collide
+pub fn rekey(
+    grouped: HashMap<String, Vec<EnhancedSegment>>,
+    format: OutputFormat,
+) -> HashMap<String, Vec<EnhancedSegment>> {
+    let mut rekeyed: HashMap<String, Vec<EnhancedSegment>> = HashMap::new();
+
+    // Sorted, so files that merge keep the same segment order
+    let mut grouped: Vec<_> = grouped.into_iter().collect();
+    grouped.sort_by(|a, b| a.0.cmp(&b.0));
+
+    for (rel_path, segments) in grouped {
+        let mapped = output_rel_path(&rel_path, format);
+
+        for segment in segments {
+            match journal_path(&segment, format) {
+                Some(path) => {
+                    let entries = rekeyed.entry(path).or_default();
+                    let duplicate = entries.iter().any(|s| {
+                        s.original_path == segment.original_path && s.content == segment.content
+                    });
+                    if !duplicate {
+                        entries.push(segment);
+                    }
                 }
-                content.push_str(&segment.content);
+                None => rekeyed.entry(mapped.clone()).or_default().push(segment),
             }
         }
-        OutputFormat::Plain => {
-            for (i, segment) in segments.iter().enumerate() {
-                if i > 0 {
-                    content.push_str("\n\n");
-                    content.push_str(&"=".repeat(80));
-                    content.push_str("\n\n");
-                }
-                content.push_str(&segment.content);
-            }
+    }
+
+    rekeyed
+}
+
+/// Date-stamped journal entries go to Logseq's journals/ instead of a page
+fn journal_path(segment: &EnhancedSegment, format: OutputFormat) -> Option<String> {
+    match format {
+        OutputFormat::Logseq => logseq::journal_path(segment),
+        _ => None,
+    }
+}
+
+/// The file a segment grouped under `rel_path` is written to
+pub fn segment_output_path(
+    rel_path: &str,
+    segment: &EnhancedSegment,
+    format: OutputFormat,
+) -> String {
+    journal_path(segment, format).unwrap_or_else(|| output_rel_path(rel_path, format))
+}
+
+/// The frontmatter or page properties a new file starts with
+fn file_header(
+    segments: &[EnhancedSegment],
+    format: OutputFormat,
+    category_in_header: bool,
+) -> String {
+    match format {
+        OutputFormat::Markdown => markdown_frontmatter(segments, category_in_header),
+        OutputFormat::Obsidian => obsidian::frontmatter(segments),
+        OutputFormat::Logseq => logseq::page_properties(segments),
+        // No frontmatter to hold it, so the category opens the text
+        OutputFormat::Html | OutputFormat::Plain if category_in_header => {
+            format!("Category: {}\n\n", category_names(segments))
         }
+        OutputFormat::Html | OutputFormat::Plain => String::new(),
     }
+}
+
+/// Build a file's content, starting with `header` unless segments are being
+/// appended (`None`), through `template` if one is set
+fn build_file_content(
+    rel_path: &str,
+    segments: &[EnhancedSegment],
+    format: OutputFormat,
+    header: Option<String>,
+    template: Option<&Template>,
+) -> Result<String, WriterError> {
+    let mut content = match template {
+        Some(template) => render_template(template, rel_path, segments, format, header)?,
+        None => {
+            let mut content = header.unwrap_or_default();
+            content.push_str(&join_segments(segments, format));
+            let review = srs::review_section(segments, format);
+            if !review.is_empty() {
+                content.truncate(content.trim_end().len());
+                content.push_str(&review);
+            }
+            content
+        }
+    };
 
     // Ensure file ends with newline
     if !content.ends_with('\n') {
         content.push('\n');
     }
 
-    content
+    Ok(content)
+}
+
+/// Segment contents with the format's separator between them
+fn join_segments(segments: &[EnhancedSegment], format: OutputFormat) -> String {
+    let separator = match format {
+        OutputFormat::Markdown | OutputFormat::Obsidian | OutputFormat::Html => {
+            "\n\n---\n\n".to_string()
+        }
+        OutputFormat::Plain => format!("\n\n{}\n\n", "=".repeat(80)),
+        // Every segment's outline is its own run of blocks
+        OutputFormat::Logseq => String::new(),
+    };
+    segments
+        .iter()
+        .map(|segment| segment_content(segment, format))
+        .collect::<Vec<_>>()
+        .join(&separator)
+}
+
+/// Whether an earlier run already wrote a version of a segment to a file: some
+/// block of the file shares most of the segment's words, in order, so a copy
+/// that was enhanced again or rewrapped still counts
+fn already_written(blocks: &[&str], segment: &EnhancedSegment, format: OutputFormat) -> bool {
+    let content = match format {
+        OutputFormat::Html => html::markdown_to_html(&segment.content),
+        _ => segment_content(segment, format),
+    };
+    let words = content.split_whitespace().count();
+    if words == 0 {
+        return false;
+    }
+    blocks.iter().any(|block| {
+        let diff = TextDiff::configure()
+            .timeout(DIFF_TIMEOUT)
+            .diff_words(content.as_str(), block);
+        let shared = diff
+            .iter_all_changes()
+            .filter(|c| c.tag() == ChangeTag::Equal && !c.value().trim().is_empty())
+            .count();
+        shared as f64 >= words as f64 * WRITTEN_SIMILARITY
+    })
+}
+
+/// A written file cut at the separators between its segments
+fn written_blocks(existing: &str, format: OutputFormat) -> Vec<&str> {
+    let is_separator = |line: &str| match format {
+        OutputFormat::Markdown | OutputFormat::Obsidian => line.trim() == "---",
+        OutputFormat::Html => line.trim_start().starts_with("<hr"),
+        OutputFormat::Plain => line.len() >= 80 && line.trim().chars().all(|c| c == '='),
+        // Outlines have no separator; the whole page is compared
+        OutputFormat::Logseq => false,
+    };
+
+    let mut blocks = Vec::new();
+    let mut start = 0;
+    let mut offset = 0;
+    for line in existing.split_inclusive('\n') {
+        if is_separator(line) {
+            blocks.push(&existing[start..offset]);
+            start = offset + line.len();
+        }
+        offset += line.len();
+    }
+    blocks.push(&existing[start..]);
+    blocks.retain(|block| !block.trim().is_empty());
+    blocks
+}
+
+/// A segment's text as it is written in the format
+fn segment_content(segment: &EnhancedSegment, format: OutputFormat) -> String {
+    match format {
+        OutputFormat::Logseq => logseq::to_outline(&segment.content),
+        _ => segment.content.clone(),
+    }
+}
+
+fn render_template(
+    template: &Template,
+    rel_path: &str,
+    segments: &[EnhancedSegment],
+    format: OutputFormat,
+    header: Option<String>,
+) -> Result<String, WriterError> {
+    let context = FileContext {
+        path: rel_path,
+        title: Path::new(rel_path)
+            .file_stem()
+            .map(|s| s.to_string_lossy().replace('_', " "))
+            .unwrap_or_default(),
+        format: format_name(format),
+        append: header.is_none(),
+        frontmatter: header.unwrap_or_default(),
+        categories: segments.iter().map(|s| s.category.to_string()).collect(),
+        tags: tags::file_tags(segments),
+        sources: segments
+            .iter()
+            .flat_map(|s| std::iter::once(&s.original_path).chain(&s.duplicates))
+            .map(|p| p.to_string_lossy().to_string())
+            .collect(),
+        segments: segments
+            .iter()
+            .map(|s| SegmentContext::new(s, segment_content(s, format)))
+            .collect(),
+        review: srs::review_section(segments, format)
+            .trim_start()
+            .to_string(),
+    };
+    Ok(template.render(&context)?)
+}
+
+/// The categories of a file's segments, each once
+fn category_names(segments: &[EnhancedSegment]) -> String {
+    let categories: BTreeSet<String> = segments.iter().map(|s| s.category.to_string()).collect();
+    categories.into_iter().collect::<Vec<_>>().join(", ")
+}
+
+/// YAML frontmatter with a file's category (if `with_category`), tags and the
+/// aliases and dates of its sources, empty if there is nothing to record
+fn markdown_frontmatter(segments: &[EnhancedSegment], with_category: bool) -> String {
+    let mut fields = String::new();
+    if with_category {
+        fields.push_str(&format!("category: {}\n", category_names(segments)));
+    }
+    let tags = tags::file_tags(segments);
+    if !tags.is_empty() {
+        fields.push_str("tags:\n");
+        for tag in tags {
+            fields.push_str(&format!("  - {}\n", tag));
+        }
+    }
+    fields.push_str(&SourceMeta::merge(segments.iter().map(|s| &s.source)).yaml_fields());
+    if fields.is_empty() {
+        return fields;
+    }
+    format!("---\n{}---\n\n", fields)
 }